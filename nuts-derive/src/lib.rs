@@ -0,0 +1,176 @@
+//! Procedural macros for [nuts](https://docs.rs/nuts), re-exported by it with the `derive` feature.
+//!
+//! Do not depend on this crate directly, use `nuts::subscriber` instead.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{Error, FnArg, ImplItem, ItemImpl, Meta, ReceiverKind, Signature, Type};
+
+/// Turns the methods of an impl block that are marked with `#[subscribe]` into subscriptions, registered with `ActivityId::register_all`.
+///
+/// A marked method with the signature `fn(&mut self, msg: &MSG)` subscribes to `MSG` like `ActivityId::subscribe`,
+/// a marked method with the signature `fn(&mut self, msg: &mut MSG)` like `ActivityId::subscribe_mut`.
+/// Marking a method with any other signature is a compile error. Methods without the marker are left alone.
+///
+/// Only one impl block per type can be annotated.
+#[proc_macro_attribute]
+pub fn subscriber(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr.into(), item.into())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(attr: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    if !attr.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "nuts::subscriber takes no arguments",
+        ));
+    }
+    let mut item: ItemImpl = syn::parse2(item)?;
+    if let Some((path, _)) = &item.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "nuts::subscriber must be used on an inherent impl block",
+        ));
+    }
+    let mut subscriptions = vec![];
+    for impl_item in &mut item.items {
+        if let ImplItem::Fn(f) = impl_item {
+            // The marker is consumed here, the compiler does not know it
+            let markers: Vec<_> = f
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("subscribe"))
+                .cloned()
+                .collect();
+            f.attrs.retain(|attr| !attr.path().is_ident("subscribe"));
+            let marker = match markers.as_slice() {
+                [] => continue,
+                [marker] => marker,
+                [_, marker, ..] => {
+                    return Err(Error::new_spanned(marker, "duplicate #[subscribe]"))
+                }
+            };
+            if !matches!(marker.meta, Meta::Path(_)) {
+                return Err(Error::new_spanned(
+                    marker,
+                    "#[subscribe] takes no arguments",
+                ));
+            }
+            let method = &f.sig.ident;
+            match message_mutability(&f.sig) {
+                Some(false) => subscriptions.push(quote! { id.subscribe(Self::#method); }),
+                Some(true) => subscriptions.push(quote! { id.subscribe_mut(Self::#method); }),
+                None => {
+                    return Err(Error::new_spanned(
+                        &f.sig,
+                        "#[subscribe] requires the signature `fn(&mut self, msg: &MSG)` or `fn(&mut self, msg: &mut MSG)`",
+                    ))
+                }
+            }
+        }
+    }
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+        impl #impl_generics ::nuts::Subscriber for #self_ty #where_clause {
+            fn register_all(id: ::nuts::ActivityId<Self>) {
+                #( #subscriptions )*
+            }
+        }
+    })
+}
+
+/// `Some(is_mut)` for handler signatures, `None` for all other methods.
+fn message_mutability(sig: &Signature) -> Option<bool> {
+    if !sig.generics.params.is_empty() || sig.inputs.len() != 2 {
+        return None;
+    }
+    let mut inputs = sig.inputs.iter();
+    match inputs.next()? {
+        FnArg::Receiver(receiver) => match receiver.kind {
+            ReceiverKind::Reference(_, _, Some(_)) => {}
+            _ => return None,
+        },
+        FnArg::Typed(_) => return None,
+    }
+    match inputs.next()? {
+        FnArg::Typed(arg) => match &*arg.ty {
+            Type::Reference(reference) => Some(reference.mutability.is_some()),
+            _ => None,
+        },
+        FnArg::Receiver(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+    use quote::quote;
+
+    #[test]
+    fn only_marked_methods_subscribe() {
+        let item = quote! {
+            impl Player {
+                #[subscribe]
+                fn on_move(&mut self, msg: &Move) {}
+                #[inline]
+                #[subscribe]
+                fn on_teleport(&mut self, msg: &mut Teleport) {}
+                fn distance(&mut self, other: &Player) -> u32 { 0 }
+            }
+        };
+        let expected = quote! {
+            impl Player {
+                fn on_move(&mut self, msg: &Move) {}
+                #[inline]
+                fn on_teleport(&mut self, msg: &mut Teleport) {}
+                fn distance(&mut self, other: &Player) -> u32 { 0 }
+            }
+            impl ::nuts::Subscriber for Player {
+                fn register_all(id: ::nuts::ActivityId<Self>) {
+                    id.subscribe(Self::on_move);
+                    id.subscribe_mut(Self::on_teleport);
+                }
+            }
+        };
+        let expanded = expand(quote!(), item).expect("valid input");
+        assert_eq!(expected.to_string(), expanded.to_string());
+    }
+
+    #[test]
+    fn marked_method_with_wrong_signature() {
+        let item = quote! {
+            impl Player {
+                #[subscribe]
+                fn on_move(&self, msg: &Move) {}
+            }
+        };
+        let err = expand(quote!(), item).expect_err("&self is not a handler");
+        assert!(err
+            .to_string()
+            .starts_with("#[subscribe] requires the signature"));
+    }
+
+    #[test]
+    fn rejects_arguments() {
+        let item = quote! { impl Player {} };
+        assert!(expand(quote!(Move), item).is_err());
+        let item = quote! {
+            impl Player {
+                #[subscribe(Move)]
+                fn on_move(&mut self, msg: &Move) {}
+            }
+        };
+        assert!(expand(quote!(), item).is_err());
+    }
+
+    #[test]
+    fn rejects_trait_impls() {
+        let item = quote! { impl Default for Player {} };
+        assert!(expand(quote!(), item).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Stable names for all types that appear in the signatures of public functions and subscription handlers.
+//!
+//! Everything in here is also available at the crate root.
+//! Importing from `nuts::api` instead makes it explicit that code depends only on this stable surface:
+//! Items in this module are only removed or changed in incompatible ways together with a bump of the major version
+//! (or the minor version, while nuts is at 0.x).
+//! Internal types, such as the state backing domains, can be redesigned without affecting this module.
+//!
+//! The conversion traits [`AsDomainState`](trait.AsDomainState.html) and [`AsMessageMeta`](trait.AsMessageMeta.html)
+//! are implemented for every handler argument that carries a domain or message information.
+//! Helper functions written against them keep compiling when a handler moves from one kind of subscription to another,
+//! for example from `subscribe_domained` to `subscribe_ctx`.
+//!
+//! ### Example
+//! ```rust
+//! use nuts::api::{AsDomainState, DomainState, HandlerCtx};
+//!
+//! struct Score(u32);
+//! struct Player;
+//! struct Goal;
+//!
+//! // Works with a `&mut DomainState` as well as with a `HandlerCtx`
+//! fn count_goal(mut domain: impl AsDomainState) {
+//!     if let Some(domain) = domain.as_domain_state() {
+//!         domain.get_mut::<Score>().0 += 1;
+//!     }
+//! }
+//!
+//! nuts::store_to_domain(&nuts::DefaultDomain, Score(0));
+//! let player = nuts::new_domained_activity_checked(Player, &nuts::DefaultDomain);
+//! player.subscribe_domained(|_, domain: &mut DomainState, _: &Goal| count_goal(domain));
+//! player.subscribe_ctx(|ctx: &mut HandlerCtx<Player>, _: &Goal| count_goal(ctx));
+//! nuts::publish(Goal);
+//! ```
+
+pub use crate::nut::activity::{
+    AboutToDelete, Activity, ActivityBuilder, ActivityGroup, ActivityId, ActivityScope,
+    ActivityStateMachine, ActivityTemplate, LifecycleStatus, LifecycleTransition, NoDomain,
+    RestartPolicy, Subscriber, TransitionCause, UncheckedActivityId, WithDomain,
+};
+pub use crate::nut::bus::Bus;
+#[cfg(debug_assertions)]
+pub use crate::nut::diagnostics::PanicContext;
+pub use crate::nut::exec::heartbeat::NutsHeartbeat;
+#[cfg(feature = "metrics")]
+pub use crate::nut::exec::metrics::{HandlerMetrics, MessageMetrics, NutsMetrics};
+pub use crate::nut::exec::trace::TraceEvent;
+pub use crate::nut::exec::{
+    BudgetExceeded, ExecError, FrameDraw, FrameUpdate, MainLoopBuilder, OnOverflow, PanicPolicy,
+    Phase, Priority, QueuePolicy, SubscriberPanicked,
+};
+pub use crate::nut::iac::context::HandlerCtx;
+pub use crate::nut::iac::entity::EntityId;
+pub use crate::nut::iac::filter::SubscriptionFilter;
+pub use crate::nut::iac::managed_state::{
+    DefaultDomain, DomainEnumeration, DomainState, TypedDomain,
+};
+pub use crate::nut::iac::order::DeliveryOrder;
+pub use crate::nut::iac::publish::{
+    InterceptDecision, MailboxStats, MessageMeta, NutsProxy, PublishError, Recording, RequestError,
+};
+pub use crate::nut::iac::signal::{Changed, DomainWatch, Signal};
+pub use crate::nut::iac::subscription::{
+    HandlerError, SubscriptionHandle, SubscriptionId, TopicOrphaned,
+};
+pub use crate::nut::init::Setup;
+pub use crate::nut::inspect::{ActivityInspection, NutsInspection};
+#[cfg(feature = "serde")]
+pub use crate::nut::snapshot::SnapshotError;
+
+/// Access to the domain of the receiving activity, from any handler argument that carries it.
+pub trait AsDomainState {
+    /// The domain, or `None` if the activity has been registered without a domain.
+    fn as_domain_state(&mut self) -> Option<&mut DomainState>;
+}
+
+/// Access to the information about the delivered message, from any handler argument that carries it.
+pub trait AsMessageMeta {
+    /// Information about the message that is currently being delivered.
+    fn as_message_meta(&self) -> &MessageMeta;
+}
+
+impl AsDomainState for DomainState {
+    fn as_domain_state(&mut self) -> Option<&mut DomainState> {
+        Some(self)
+    }
+}
+impl<T: AsDomainState + ?Sized> AsDomainState for &mut T {
+    fn as_domain_state(&mut self) -> Option<&mut DomainState> {
+        (**self).as_domain_state()
+    }
+}
+impl<A: Activity> AsDomainState for HandlerCtx<'_, A> {
+    fn as_domain_state(&mut self) -> Option<&mut DomainState> {
+        self.domain()
+    }
+}
+
+impl AsMessageMeta for MessageMeta {
+    fn as_message_meta(&self) -> &MessageMeta {
+        self
+    }
+}
+impl<T: AsMessageMeta + ?Sized> AsMessageMeta for &T {
+    fn as_message_meta(&self) -> &MessageMeta {
+        (**self).as_message_meta()
+    }
+}
+impl<T: AsMessageMeta + ?Sized> AsMessageMeta for &mut T {
+    fn as_message_meta(&self) -> &MessageMeta {
+        (**self).as_message_meta()
+    }
+}
+impl<A: Activity> AsMessageMeta for HandlerCtx<'_, A> {
+    fn as_message_meta(&self) -> &MessageMeta {
+        self.meta()
+    }
+}
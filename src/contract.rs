@@ -0,0 +1,125 @@
+//! Executable checks of the execution-order guarantees nuts makes.
+//!
+//! Libraries built on top of nuts can run these checks against the version they compile with,
+//! to make sure the semantics they rely on still hold.
+//! Only available with the `test-utils` feature.
+//!
+//! Each check resets the nut of the current thread before and after it runs.
+//! Run them in a test of their own, not in the middle of a test that has activities registered.
+//!
+//! ### Example
+//! In a test of a downstream crate:
+//! ```rust
+//! # #[cfg(feature = "test-utils")] {
+//! nuts::assert_nuts_semantics!();
+//! # }
+//! ```
+//!
+//! The checks cover these guarantees:
+//! * Messages published inside a subscription handler are delivered after all handlers of the current message have finished, in the order they were published.
+//!   See [`nested_publish_is_fifo`](fn.nested_publish_is_fifo.html).
+//! * Subscriptions registered inside a subscription handler are not called for the message that is being delivered, only for later messages.
+//!   See [`subscription_inside_handler_is_deferred`](fn.subscription_inside_handler_is_deferred.html).
+//! * Activities created inside a subscription handler receive all messages published after their creation, including those published by the same handler.
+//!   See [`inchoate_activity_receives_later_messages`](fn.inchoate_activity_receives_later_messages.html).
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Runs all checks of this module and panics if any guarantee is violated.
+///
+/// Calling `nuts::assert_nuts_semantics!()` is equivalent.
+pub fn check_all() {
+    nested_publish_is_fifo();
+    subscription_inside_handler_is_deferred();
+    inchoate_activity_receives_later_messages();
+}
+
+/// Asserts that nuts upholds all guarantees checked in [`nuts::contract`](contract/index.html).
+///
+/// Panics with a description of the first violated guarantee.
+/// This resets the nut of the current thread.
+#[macro_export]
+macro_rules! assert_nuts_semantics {
+    () => {
+        $crate::contract::check_all()
+    };
+}
+
+type Log = Rc<RefCell<Vec<&'static str>>>;
+
+struct Outer;
+struct Inner(&'static str);
+
+/// Checks that nested publishes are queued and delivered in FIFO order.
+pub fn nested_publish_is_fifo() {
+    crate::reset();
+    let log: Log = Default::default();
+
+    let l = log.clone();
+    crate::new_activity(()).subscribe(move |_, _: &Outer| {
+        l.borrow_mut().push("outer 1");
+        crate::publish(Inner("first"));
+        crate::publish(Inner("second"));
+    });
+    let l = log.clone();
+    crate::new_activity(()).subscribe(move |_, _: &Outer| l.borrow_mut().push("outer 2"));
+    let l = log.clone();
+    crate::new_activity(()).subscribe(move |_, msg: &Inner| l.borrow_mut().push(msg.0));
+
+    crate::publish(Outer);
+    assert_eq!(
+        vec!["outer 1", "outer 2", "first", "second"],
+        *log.borrow(),
+        "nuts contract violated: messages published inside a handler must be delivered after the current message, in publish order"
+    );
+    crate::reset();
+}
+
+/// Checks that subscriptions registered inside a handler only see later messages.
+pub fn subscription_inside_handler_is_deferred() {
+    crate::reset();
+    let log: Log = Default::default();
+
+    let l = log.clone();
+    let id = crate::new_activity(());
+    id.subscribe(move |_, _: &Outer| {
+        let l = l.clone();
+        crate::new_activity(()).subscribe(move |_, _: &Outer| l.borrow_mut().push("late"));
+    });
+
+    crate::publish(Outer);
+    assert!(
+        log.borrow().is_empty(),
+        "nuts contract violated: a subscription registered inside a handler must not receive the message being delivered"
+    );
+    crate::publish(Outer);
+    assert_eq!(
+        vec!["late"],
+        *log.borrow(),
+        "nuts contract violated: a subscription registered inside a handler must receive later messages"
+    );
+    crate::reset();
+}
+
+/// Checks that activities created inside a handler receive messages published after their creation.
+pub fn inchoate_activity_receives_later_messages() {
+    crate::reset();
+    let log: Log = Default::default();
+
+    let l = log.clone();
+    crate::new_activity(()).subscribe(move |_, _: &Outer| {
+        let l = l.clone();
+        crate::new_activity(()).subscribe(move |_, msg: &Inner| l.borrow_mut().push(msg.0));
+        crate::publish(Inner("same handler"));
+    });
+
+    crate::publish(Outer);
+    crate::publish(Inner("later"));
+    assert_eq!(
+        vec!["same handler", "later"],
+        *log.borrow(),
+        "nuts contract violated: an activity created inside a handler must receive all messages published after its creation"
+    );
+    crate::reset();
+}
@@ -1,3 +1,21 @@
+//! Tools for debugging applications built with nuts.
+//!
+//! The flow graph records which messages are delivered to which activities and which activities publish which messages, while the application runs.
+//! Exporting it shows the real message flow, including paths that are not obvious from the subscriptions alone.
+//!
+//! ### Example
+//! ```rust
+//! struct Input;
+//! struct Jump;
+//! struct Player;
+//! struct Sound;
+//!
+//! nuts::debug::start_flow_capture();
+//! nuts::new_activity(Player).subscribe(|_, _: &Input| nuts::publish(Jump));
+//! nuts::new_activity(Sound).subscribe(|_, _: &Jump| {});
+//! nuts::publish(Input);
+//! println!("{}", nuts::debug::export_flow_graph().to_mermaid());
+//! ```
 #![allow(unused_macros)]
 /* log_print, to println! or web console log (nothing in release mode) */
 
@@ -68,3 +86,29 @@ impl std::fmt::Debug for DebugTypeName {
         write!(f, "{}", self.0)
     }
 }
+
+pub use crate::nut::exec::flow::{FlowEdge, FlowGraph, FlowNode};
+
+/// Starts recording the message flow, see [`export_flow_graph`](fn.export_flow_graph.html).
+///
+/// Edges observed before are kept. Recording uses the same machinery as [`nuts::enable_tracing`](../fn.enable_tracing.html), both can be used at the same time.
+pub fn start_flow_capture() {
+    crate::nut::update_tracer(|tracer| {
+        if tracer.flow().is_none() {
+            tracer.set_flow(Some(Default::default()))
+        }
+    })
+}
+
+/// Stops recording the message flow and discards all recorded edges.
+pub fn stop_flow_capture() {
+    crate::nut::update_tracer(|tracer| tracer.set_flow(None))
+}
+
+/// Returns the message flow recorded since [`start_flow_capture`](fn.start_flow_capture.html).
+///
+/// Render it with [`FlowGraph::to_mermaid`](struct.FlowGraph.html#method.to_mermaid) or [`FlowGraph::to_dot`](struct.FlowGraph.html#method.to_dot).
+/// Activity names are only resolved outside of subscription handlers, inside they are replaced by the activity index.
+pub fn export_flow_graph() -> FlowGraph {
+    crate::nut::flow_graph()
+}
@@ -39,21 +39,504 @@
 #![allow(clippy::needless_doctest_main)]
 
 #[macro_use]
-pub(crate) mod debug;
+pub mod debug;
 
 mod nut;
 
+pub mod api;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod contract;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_support;
+
 #[cfg(test)]
 mod test;
+// Lets `#[nuts::subscriber]` expand to paths starting with `::nuts` inside the tests of this crate
+#[cfg(all(test, feature = "derive"))]
+extern crate self as nuts;
 
-pub use crate::nut::iac::managed_state::{DefaultDomain, DomainEnumeration, DomainState};
+pub use crate::nut::iac::managed_state::{
+    DefaultDomain, DomainEnumeration, DomainState, TypedDomain,
+};
+pub use api::{AsDomainState, AsMessageMeta};
 use core::any::Any;
+use core::hash::Hash;
 pub use nut::activity::*;
+pub use nut::bus::Bus;
+#[cfg(debug_assertions)]
+pub use nut::diagnostics::PanicContext;
+pub use nut::exec::heartbeat::NutsHeartbeat;
+#[cfg(feature = "metrics")]
+pub use nut::exec::metrics::{HandlerMetrics, MessageMetrics, NutsMetrics};
+pub use nut::exec::trace::TraceEvent;
+pub use nut::exec::{
+    BudgetExceeded, ExecError, FrameDraw, FrameUpdate, MainLoopBuilder, OnOverflow, PanicPolicy,
+    Phase, Priority, QueuePolicy, SubscriberPanicked,
+};
+pub use nut::iac::context::HandlerCtx;
+pub use nut::iac::entity::EntityId;
 pub use nut::iac::filter::*;
+pub use nut::iac::order::DeliveryOrder;
+pub use nut::iac::publish::{
+    InterceptDecision, MailboxStats, MessageMeta, NutsProxy, PublishError, Recording, RequestError,
+};
+pub use nut::iac::signal::{Changed, DomainWatch, Signal};
+pub use nut::iac::subscription::{HandlerError, SubscriptionHandle, SubscriptionId, TopicOrphaned};
+pub use nut::init::Setup;
+pub use nut::inspect::{ActivityInspection, NutsInspection};
+#[cfg(feature = "serde")]
+pub use nut::snapshot::SnapshotError;
+#[cfg(feature = "derive")]
+pub use nuts_derive::subscriber;
 
 use nut::iac::managed_state::*;
 use nut::iac::topic::*;
 
+/// Runs the initialization closure with direct access to the nut and marks it as initialized.
+///
+/// Activities and domain values created through [`Setup`](struct.Setup.html) are available immediately after each call.
+/// The return value of the closure is passed through, which is a good place to collect all created ids.
+///
+/// # Panics
+/// Panics if called inside a subscription handler or if called more than once.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{ActivityId, DefaultDomain, WithDomain};
+///
+/// struct Player;
+/// struct Audio;
+/// struct Ids {
+///     player: ActivityId<Player, WithDomain>,
+///     audio: ActivityId<Audio>,
+/// }
+///
+/// let ids = nuts::init_with(|setup| {
+///     setup.store_to_domain(&DefaultDomain, 100u32);
+///     Ids {
+///         player: setup.new_domained_activity_checked(Player, &DefaultDomain),
+///         audio: setup.new_activity(Audio),
+///     }
+/// });
+/// assert!(nuts::is_initialized());
+/// ```
+pub fn init_with<R>(f: impl FnOnce(&mut Setup) -> R) -> R {
+    nut::init_with(f)
+}
+
+/// Drops all activities, subscriptions, domains, and queued events, leaving nuts in the same state as on a fresh thread.
+///
+/// No `on_delete` or `on_leave` handlers are called, see [`shutdown`](fn.shutdown.html) for that.
+/// Futures returned by [`publish_awaiting_response`](fn.publish_awaiting_response.html) that have not resolved, yet, resolve without a reply after a reset.
+/// `ActivityId`s of old activities must not be used anymore, using them panics.
+///
+/// # Panics
+/// Panics if called inside a subscription handler.
+pub fn reset() {
+    nut::reset()
+}
+
+/// Deletes all activities, newest first, then calls [`reset`](fn.reset.html).
+///
+/// The usual handlers for deleted activities are called, such as `on_leave` and `on_delete`,
+/// with [`TransitionCause::Shutdown`](enum.TransitionCause.html#variant.Shutdown).
+/// All events caused by them are executed before the reset.
+///
+/// # Panics
+/// Panics if called inside a subscription handler.
+pub fn shutdown() {
+    nut::shutdown()
+}
+
+/// Registers an app-global object of type `T`, for objects that belong to no particular domain, such as an asset database.
+///
+/// The object is created by `init` on first access through [`with_singleton`](fn.with_singleton.html).
+/// Since no internal state of nuts is borrowed while `init` runs, it may call any nuts function, including accessing other singletons.
+/// Returns false, and drops `init`, if a singleton of type `T` is already registered.
+///
+/// Singletons live until [`reset`](fn.reset.html) or [`shutdown`](fn.shutdown.html).
+/// They are dropped in reverse order of registration, after all activities have been deleted in case of a shutdown.
+/// Thus, a singleton may safely depend on the singletons registered before it.
+///
+/// ### Example
+/// ```rust
+/// struct Assets { loaded: Vec<String> }
+///
+/// nuts::singleton(|| Assets { loaded: vec![] });
+/// nuts::with_singleton(|assets: &mut Assets| assets.loaded.push("player.png".to_owned()));
+/// let n = nuts::with_singleton(|assets: &mut Assets| assets.loaded.len());
+/// assert_eq!(Some(1), n);
+/// ```
+pub fn singleton<T: Any>(init: impl FnOnce() -> T + 'static) -> bool {
+    nut::singleton::register(init)
+}
+
+/// Calls `f` with the singleton of type `T`, initializing it first if necessary.
+///
+/// Returns `None` if no singleton of type `T` has been registered with [`singleton`](fn.singleton.html).
+/// Unlike domains, singletons can be accessed anywhere, including inside subscription handlers.
+///
+/// # Panics
+/// Panics when accessing a singleton inside `f` for the same type, or inside its own initialization.
+pub fn with_singleton<T: Any, R>(f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    nut::singleton::with(f)
+}
+
+/// Includes domain values of type `T` in [`snapshot_domains`](fn.snapshot_domains.html) and [`restore_domains`](fn.restore_domains.html).
+///
+/// Only available with the `serde` feature.
+/// Values are identified by `name` inside a snapshot, so the type can be renamed or moved without breaking older snapshots, as long as the name stays the same.
+/// Returns false if `T` or `name` has already been registered.
+#[cfg(feature = "serde")]
+pub fn register_serializable<T: Any + serde::Serialize + serde::de::DeserializeOwned>(
+    name: &'static str,
+) -> bool {
+    nut::snapshot::register::<T>(name)
+}
+
+/// Serializes the values of all registered types in all domains, for example to store a save game.
+///
+/// Only available with the `serde` feature.
+/// Register the types to include with [`register_serializable`](fn.register_serializable.html) first.
+/// Values of other types are not part of the snapshot.
+///
+/// ### Example
+/// ```rust
+/// # #[cfg(feature = "serde")] {
+/// use nuts::DefaultDomain;
+///
+/// nuts::register_serializable::<u32>("score");
+/// nuts::store_to_domain(&DefaultDomain, 7u32);
+/// let save_game = nuts::snapshot_domains().expect("not inside a handler");
+///
+/// nuts::store_to_domain(&DefaultDomain, 100u32);
+/// nuts::restore_domains(&save_game).expect("valid snapshot");
+/// # }
+/// ```
+///
+/// # Errors
+/// Fails inside subscription handlers and if a value cannot be serialized.
+#[cfg(feature = "serde")]
+pub fn snapshot_domains() -> Result<Vec<u8>, SnapshotError> {
+    nut::snapshot::snapshot()
+}
+
+/// Stores all values of a snapshot created by [`snapshot_domains`](fn.snapshot_domains.html) in their domains, overwriting the current values.
+///
+/// Only available with the `serde` feature.
+/// Values of types that are stored in a domain but not in the snapshot are left as they are.
+///
+/// # Errors
+/// Fails inside subscription handlers, if the snapshot cannot be deserialized,
+/// and if it contains values under a name that has not been registered with [`register_serializable`](fn.register_serializable.html).
+/// Nothing is restored in case of an error.
+#[cfg(feature = "serde")]
+pub fn restore_domains(snapshot: &[u8]) -> Result<(), SnapshotError> {
+    nut::snapshot::restore(snapshot)
+}
+
+/// Forwards published messages of type `T` to the Web Worker connected with [`connect_worker`](fn.connect_worker.html) or [`connect_main_thread`](fn.connect_main_thread.html).
+///
+/// Only available with the `web-worker` feature.
+/// Both sides must register the same types, messages are identified by `std::any::type_name::<T>()`.
+/// Messages received from the other side are published locally, without being sent back.
+/// Private messages are never forwarded.
+/// Returns false if `T` has already been registered.
+#[cfg(feature = "web-worker")]
+pub fn bridge<T: Any + serde::Serialize + serde::de::DeserializeOwned>() -> bool {
+    nut::worker::register::<T>()
+}
+
+/// Connects the nut of the main thread to a Web Worker, see [`bridge`](fn.bridge.html).
+///
+/// Only available with the `web-worker` feature.
+/// This replaces the `onmessage` handler of the worker and any previous connection.
+/// Inside the worker, call [`connect_main_thread`](fn.connect_main_thread.html) to complete the bridge.
+///
+/// ### Example
+/// ```rust,no_run
+/// # #[cfg(feature = "web-worker")] {
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct FindPath { from: (u32, u32), to: (u32, u32) }
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct PathFound { steps: Vec<(u32, u32)> }
+///
+/// nuts::bridge::<FindPath>();
+/// nuts::bridge::<PathFound>();
+/// let worker = web_sys::Worker::new("./pathfinder.js").expect("worker script");
+/// nuts::connect_worker(worker);
+/// // Handled inside the worker, which publishes `PathFound` when it is done
+/// nuts::publish(FindPath { from: (0, 0), to: (10, 5) });
+/// # }
+/// ```
+#[cfg(feature = "web-worker")]
+pub fn connect_worker(worker: web_sys::Worker) {
+    nut::worker::connect_worker(worker)
+}
+
+/// Connects the nut inside a Web Worker to the main thread, the counterpart of [`connect_worker`](fn.connect_worker.html).
+///
+/// Only available with the `web-worker` feature.
+/// This replaces the `onmessage` handler of the worker scope and any previous connection.
+#[cfg(feature = "web-worker")]
+pub fn connect_main_thread(scope: web_sys::DedicatedWorkerGlobalScope) {
+    nut::worker::connect_main_thread(scope)
+}
+
+/// Releases memory that nuts still holds for deleted activities.
+///
+/// Deleting an activity removes its subscriptions right away. But subscriptions registered for an activity while its deletion was pending stay around,
+/// as do its mailbox statistics and spare capacity of internal collections. Applications that create and delete many activities can call this from time to time.
+///
+/// Activity ids are never reused, so ids of deleted activities stay invalid and all other ids stay valid.
+/// Inside activities, compaction is delayed until all events queued before have been executed.
+pub fn compact() {
+    nut::compact()
+}
+
+/// True iff [`init_with`](fn.init_with.html) has been called.
+pub fn is_initialized() -> bool {
+    nut::is_initialized()
+}
+
+/// Advances the frame counter by one and returns the new value.
+///
+/// Nuts never advances the counter on its own, call this once per frame or update cycle.
+/// Messages published afterwards carry the new value in their [`MessageMeta::tick`](struct.MessageMeta.html#method.tick).
+pub fn tick() -> u64 {
+    nut::tick()
+}
+
+/// The current value of the frame counter, see [`tick`](fn.tick.html).
+///
+/// Starts at 0 and is set back to 0 by [`reset`](fn.reset.html).
+pub fn current_tick() -> u64 {
+    nut::current_tick()
+}
+
+/// Drives nuts with [`FrameUpdate`](struct.FrameUpdate.html) and [`FrameDraw`](struct.FrameDraw.html) messages at the given rate,
+/// until [`exit_main_loop`](fn.exit_main_loop.html) is called.
+///
+/// Shorthand for `MainLoopBuilder::new(fps).run()`, see [`MainLoopBuilder`](struct.MainLoopBuilder.html).
+/// Not available on wasm, where the browser owns the loop.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_blocking(fps: u32) {
+    MainLoopBuilder::new(fps).run()
+}
+
+/// Makes [`run_blocking`](fn.run_blocking.html) or [`MainLoopBuilder::run`](struct.MainLoopBuilder.html#method.run) return after the current frame.
+pub fn exit_main_loop() {
+    nut::request_exit()
+}
+
+/// Returns a snapshot of all registered activities, subscriptions, and domains, for debugging tools.
+///
+/// Returns `None` if called inside a subscription handler, where activities and domains are not accessible.
+///
+/// ### Example
+/// ```rust
+/// struct Overlay;
+/// nuts::new_activity(Overlay);
+/// let inspection = nuts::inspect().expect("not inside a handler");
+/// for activity in &inspection.activities {
+///     println!("{} is {:?}", activity.type_name, activity.status);
+/// }
+/// ```
+pub fn inspect() -> Option<NutsInspection> {
+    nut::inspect()
+}
+
+/// Lists the activities that react to messages of type `MSG`, for tooling and debug UIs.
+///
+/// Activities with a subscription to published messages come first, in the order they receive them,
+/// followed by activities with a private channel for `MSG`, in the order they have been created. Each activity is listed once, subscriptions without activity are not listed.
+///
+/// Returns `None` if called inside a subscription handler, where activities are not accessible.
+///
+/// ### Example
+/// ```rust
+/// struct Overlay;
+/// struct Resize;
+/// nuts::new_activity(Overlay).subscribe(|_, _: &Resize| {});
+/// let subscribers = nuts::subscribers_of::<Resize>().expect("not inside a handler");
+/// assert!(subscribers[0].type_name.ends_with("Overlay"));
+/// ```
+pub fn subscribers_of<MSG: Any>() -> Option<Vec<ActivityInspection>> {
+    nut::subscribers_of::<MSG>()
+}
+
+/// Returns how many messages have been broadcast per message type, and how often and how long the handlers of each activity have been executed.
+///
+/// Only available with the `metrics` feature.
+/// Handler durations are measured with `std::time::Instant`, or with `performance.now()` when compiled to WebAssembly.
+/// On WebAssembly, this requires the `web-sys` dependency, which is enabled for example by the `web-debug` feature. Without it, all durations are zero.
+///
+/// ### Example
+/// ```rust
+/// # #[cfg(feature = "metrics")] {
+/// let metrics = nuts::metrics();
+/// for handler in metrics.handlers.values() {
+///     println!("{}: {} calls, {:?}", handler.type_name, handler.calls, handler.total_time);
+/// }
+/// # }
+/// ```
+#[cfg(feature = "metrics")]
+pub fn metrics() -> NutsMetrics {
+    nut::metrics()
+}
+
+/// Caches the last `n` published messages of type `MSG` and delivers them to every subscriber of `MSG` registered afterwards.
+///
+/// This lets activities that are created late, such as a debug panel opened mid-game, catch up on state-bearing events.
+/// Cached messages are delivered only to the new subscription, oldest first, before any message published after the subscription.
+/// Messages sent with `send_to` are not cached.
+///
+/// Calling it again changes the size of the cache, `n = 0` removes the cache.
+///
+/// ### Example
+/// ```rust
+/// #[derive(Clone)]
+/// struct Score(u32);
+/// struct Hud;
+///
+/// nuts::retain_last::<Score>(1);
+/// nuts::publish(Score(10));
+/// nuts::publish(Score(20));
+/// // The new subscriber immediately receives `Score(20)`
+/// nuts::new_activity(Hud).subscribe(|_, score: &Score| assert_eq!(20, score.0));
+/// ```
+pub fn retain_last<MSG: Any + Clone>(n: usize) {
+    nut::retain_last::<MSG>(n)
+}
+
+/// Allows messages of type `MSG` to be held for activities with status [`LifecycleStatus::Buffering`](enum.LifecycleStatus.html#variant.Buffering).
+///
+/// Messages of other types are missed by buffering activities, as if they were inactive.
+///
+/// ### Example
+/// ```rust
+/// use nuts::LifecycleStatus;
+///
+/// #[derive(Clone)]
+/// struct Chat(&'static str);
+/// struct ChatWindow;
+///
+/// nuts::make_bufferable::<Chat>();
+/// let window = nuts::new_activity(ChatWindow);
+/// window.subscribe(|_, msg: &Chat| println!("{}", msg.0));
+/// window.set_status(LifecycleStatus::Buffering);
+/// nuts::publish(Chat("Hello"));
+/// // Prints "Hello" now
+/// window.set_status(LifecycleStatus::Active);
+/// ```
+pub fn make_bufferable<MSG: Any + Clone>() {
+    nut::make_bufferable::<MSG>()
+}
+
+/// Registers an interceptor that is called before each message is delivered, for cross-cutting concerns such as recording or validation.
+///
+/// The interceptor receives the metadata and a mutable reference to the message and decides with the returned [`InterceptDecision`](enum.InterceptDecision.html)
+/// whether the message is delivered, dropped, or rerouted to a single activity.
+/// Interceptors run in registration order, each one sees the changes of the previous ones.
+/// They are called for all messages, including private and builtin messages, and run like subscription handlers, so messages published inside of them are queued.
+///
+/// ### Example
+/// ```rust
+/// use nuts::InterceptDecision;
+/// struct Damage(u32);
+///
+/// // Cap damage from any source
+/// nuts::add_interceptor(|_meta, msg| {
+///     if let Some(damage) = msg.downcast_mut::<Damage>() {
+///         damage.0 = damage.0.min(100);
+///     }
+///     InterceptDecision::Deliver
+/// });
+/// nuts::new_activity(()).subscribe(|_, damage: &Damage| assert_eq!(100, damage.0));
+/// nuts::publish(Damage(9000));
+/// ```
+pub fn add_interceptor<F>(f: F)
+where
+    F: Fn(&MessageMeta, &mut dyn Any) -> InterceptDecision + 'static,
+{
+    nut::add_interceptor(f)
+}
+
+/// Allows messages of type `MSG` to be captured by [`start_recording`](fn.start_recording.html).
+///
+/// Messages of types that have not been registered are not recorded.
+pub fn record_type<MSG: Any + Clone>() {
+    nut::record_type::<MSG>()
+}
+
+/// Starts capturing published messages, to reproduce a session later with [`replay`](fn.replay.html).
+///
+/// Only messages published with `nuts::publish`, `nuts::publish_iter`, `nuts::publish_phased` or `nuts::publish_when` outside of subscription handlers are recorded,
+/// and only if their type has been registered with [`record_type`](fn.record_type.html).
+/// Messages published inside handlers are not recorded because the handlers publish them again during the replay.
+///
+/// Calling it while already recording discards the messages recorded so far.
+///
+/// ### Example
+/// ```rust
+/// #[derive(Clone)]
+/// struct Click { x: i32, y: i32 }
+///
+/// nuts::record_type::<Click>();
+/// nuts::start_recording();
+/// nuts::publish(Click { x: 1, y: 2 });
+/// let recording = nuts::stop_recording().expect("recording has been started");
+///
+/// // Later, possibly after rebuilding all activities
+/// nuts::reset();
+/// nuts::new_activity(()).subscribe(|_, click: &Click| assert_eq!((1, 2), (click.x, click.y)));
+/// nuts::replay(&recording);
+/// ```
+pub fn start_recording() {
+    nut::start_recording()
+}
+
+/// Ends the recording started with [`start_recording`](fn.start_recording.html) and returns it.
+///
+/// Returns `None` if no recording is in progress.
+pub fn stop_recording() -> Option<Recording> {
+    nut::stop_recording()
+}
+
+/// Publishes a copy of each message in the recording, in the original order.
+///
+/// Outside of subscription handlers, each message is fully processed before the next one is published, like it was when it was recorded.
+/// Messages recorded from a single call to `publish_iter` are queued together again, phased messages are held for their phase again.
+/// Messages held with `publish_when` are recorded when they are held and replayed without their condition.
+/// Since nuts executes deterministically on a single thread, an activity graph built the same way as during the recording processes the messages identically.
+pub fn replay(recording: &Recording) {
+    nut::replay(recording)
+}
+
+/// Registers a low-priority handler for housekeeping work, such as cache eviction or autosaving.
+///
+/// Idle handlers are never called while messages are being delivered.
+/// They only run when [`run_idle`](fn.run_idle.html) is called while no events are waiting.
+pub fn on_idle<F>(f: F)
+where
+    F: Fn() + 'static,
+{
+    nut::on_idle(f)
+}
+
+/// Calls all idle handlers registered with [`on_idle`](fn.on_idle.html), in registration order.
+///
+/// Returns false without calling anything if events are waiting to be processed or if called inside a subscription handler.
+/// Messages published by idle handlers are delivered before the next idle handler is called.
+///
+/// Call this when the application has time to spare.
+/// On the web, a good place is a callback registered with `window.requestIdleCallback`.
+pub fn run_idle() -> bool {
+    nut::run_idle()
+}
+
 /// Consumes a struct and registers it as an Activity.
 ///
 /// `nuts::new_activity(...)` is the simplest method to create a new activity.
@@ -97,7 +580,11 @@ pub fn new_activity<A>(activity: A) -> ActivityId<A>
 where
     A: Activity,
 {
-    let a = nut::new_activity(activity, DomainId::default(), LifecycleStatus::Active);
+    let a = nut::new_activity(
+        NewActivity::Constructed(activity),
+        DomainId::default(),
+        LifecycleStatus::Active,
+    );
     #[cfg(feature = "verbose-debug-log")]
     debug_print!(
         "New activity {:?}({})",
@@ -107,9 +594,104 @@ where
     a
 }
 
+/// Registers an activity under a key that stays the same across sessions, unlike its `ActivityId`.
+///
+/// The id of an activity depends on the order of registration, hence a serialized id may refer to another activity in the next session.
+/// The key can be stored instead and resolved with [`lookup_key`](fn.lookup_key.html), for example in save files or scripting bindings.
+/// The key is freed when the activity is deleted.
+///
+/// # Panics
+/// Panics if the key is already used by a living activity.
+///
+/// ### Example
+/// ```rust
+/// struct PlayerController;
+/// let id = nuts::new_activity_keyed("player_controller", PlayerController);
+/// assert_eq!(Some(id.into()), nuts::lookup_key("player_controller"));
+/// assert_eq!(None, nuts::lookup_key("enemy_controller"));
+/// ```
+pub fn new_activity_keyed<A>(key: impl Into<String>, activity: A) -> ActivityId<A>
+where
+    A: Activity,
+{
+    nut::activity::keys::new_keyed_activity(key.into(), activity)
+}
+
+/// Returns the activity registered with [`new_activity_keyed`](fn.new_activity_keyed.html) under the key, if it has not been deleted.
+pub fn lookup_key(key: &str) -> Option<UncheckedActivityId> {
+    nut::activity::keys::lookup(key)
+}
+
+/// Returns the ids of all living activities of type `A`, in the order they have been created.
+///
+/// Each activity is addressed through its own id, for example with [`send_to_instance`](fn.send_to_instance.html).
+/// Activities created inside a subscription handler are listed only after the handler has finished.
+/// Returns `None` if called inside a subscription handler, where activities are not accessible.
+pub fn instances_of<A: Activity>() -> Option<Vec<UncheckedActivityId>> {
+    nut::instances_of::<A>()
+}
+
+/// Starts an [`ActivityBuilder`](struct.ActivityBuilder.html), to register an activity with its status, domain, and subscriptions in one expression.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{DefaultDomain, SubscriptionFilter};
+///
+/// struct Player;
+/// struct Update;
+/// struct Draw;
+///
+/// let player = nuts::activity(Player)
+///     .domain(&DefaultDomain)
+///     .start_inactive()
+///     .subscribe(|_, _: &Update| println!("update"))
+///     .subscribe_masked(SubscriptionFilter::no_filter(), |_, _: &Draw| println!("draw"))
+///     .subscribe_domained(|_, domain, _: &Update| domain.store(1u32))
+///     .build();
+/// player.set_status(nuts::LifecycleStatus::Active);
+/// ```
+pub fn activity<A: Activity>(activity: A) -> ActivityBuilder<A> {
+    ActivityBuilder::new(activity)
+}
+
+/// Same as [`new_activity`](fn.new_activity.html) but the activity is only constructed when it is first needed.
+///
+/// Use this for heavyweight activities, for example those that hold textures or large buffers, that may never receive a message.
+/// The factory is called before the first subscription handler of the activity runs,
+/// or when the activity is accessed otherwise, for example with [`read_activity`](fn.read_activity.html).
+/// Subscriptions can be registered right away, as with any other activity.
+///
+/// The factory is called while nuts is accessing its activities, it must not call nuts functions itself.
+///
+/// ### Example
+/// ```rust
+/// struct Renderer { textures: Vec<Vec<u8>> }
+/// struct Draw;
+///
+/// let renderer = nuts::new_lazy_activity(|| Renderer { textures: vec![vec![0; 1024]; 16] });
+/// // Nothing has been allocated, yet
+/// renderer.subscribe(|renderer, _: &Draw| assert_eq!(16, renderer.textures.len()));
+/// // The renderer is constructed right before the handler is called
+/// nuts::publish(Draw);
+/// ```
+pub fn new_lazy_activity<A, F>(factory: F) -> ActivityId<A>
+where
+    A: Activity,
+    F: FnOnce() -> A + 'static,
+{
+    nut::new_activity(
+        NewActivity::Lazy(Box::new(factory)),
+        DomainId::default(),
+        LifecycleStatus::Active,
+    )
+}
+
 /// Consumes a struct that is registered as an Activity that has access to the specified domain.
 /// Use the returned `ActivityId` to register callbacks on the activity.
 ///
+/// Handlers with domain access are registered on the `ActivityId<A, WithDomain>` returned by
+/// [`new_domained_activity_checked`](fn.new_domained_activity_checked.html), which is used in the example.
+///
 // @ START-DOC NEW_ACTIVITY_WITH_DOMAIN
 /// ```rust
 /// use nuts::{domain_enum, DomainEnumeration};
@@ -129,7 +711,7 @@ where
 /// nuts::store_to_domain(&MyDomain::DomainA, 42usize);
 ///
 /// // Register activity
-/// let activity_id = nuts::new_domained_activity(MyActivity, &MyDomain::DomainA);
+/// let activity_id = nuts::new_domained_activity_checked(MyActivity, &MyDomain::DomainA);
 ///
 /// // Add event listener that listens to published `MyMessage` types and has also access to the domain data
 /// activity_id.subscribe_domained(
@@ -149,7 +731,11 @@ where
     A: Activity,
     D: DomainEnumeration,
 {
-    let a = nut::new_activity(activity, DomainId::new(domain), LifecycleStatus::Active);
+    let a = nut::new_activity(
+        NewActivity::Constructed(activity),
+        DomainId::new(domain),
+        LifecycleStatus::Active,
+    );
     #[cfg(feature = "verbose-debug-log")]
     debug_print!(
         "New activity {:?}({})",
@@ -160,6 +746,87 @@ where
     a
 }
 
+/// Same as [`new_domained_activity`](fn.new_domained_activity.html) but returns an `ActivityId<A, WithDomain>`.
+///
+/// Handlers with domain access registered through this id cannot fail at runtime for lack of a domain.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{ActivityId, DefaultDomain, WithDomain};
+///
+/// struct Score(u32);
+/// struct Goal;
+///
+/// nuts::store_to_domain(&DefaultDomain, Score(0));
+/// let id: ActivityId<(), WithDomain> = nuts::new_domained_activity_checked((), &DefaultDomain);
+/// id.subscribe_domained(|_, domain, _: &Goal| domain.get_mut::<Score>().0 += 1);
+/// nuts::publish(Goal);
+/// ```
+pub fn new_domained_activity_checked<A, D>(activity: A, domain: &D) -> ActivityId<A, WithDomain>
+where
+    A: Activity,
+    D: DomainEnumeration,
+{
+    new_domained_activity(activity, domain).assume_domain()
+}
+
+/// Same as [`new_domained_activity`](fn.new_domained_activity.html) but returns a [`TypedDomain`](struct.TypedDomain.html),
+/// which gives handlers direct access to the domain value of type `T`.
+///
+/// If the domain has no value of type `T`, yet, the default value is stored.
+///
+/// ### Example
+/// ```rust
+/// use nuts::DefaultDomain;
+///
+/// #[derive(Default)]
+/// struct Score(u32);
+/// struct Goal;
+///
+/// let typed = nuts::new_domained_activity_typed::<_, _, Score>((), &DefaultDomain);
+/// typed.subscribe(|_activity, score, _msg: &Goal| {
+///     score.0 += 1;
+/// });
+/// nuts::publish(Goal);
+/// ```
+pub fn new_domained_activity_typed<A, D, T>(activity: A, domain: &D) -> TypedDomain<A, T>
+where
+    A: Activity,
+    D: DomainEnumeration,
+    T: Any + Default,
+{
+    TypedDomain::new(new_domained_activity_checked(activity, domain))
+}
+
+/// Registers a prototype for activities that are spawned many times, such as enemies or rows of a list.
+///
+/// The factory creates the activity from the arguments passed to [`spawn`](struct.ActivityTemplate.html#method.spawn).
+/// Afterwards, `wire` is called with the new ID to register subscriptions, identically for every instance.
+///
+/// ### Example
+/// ```rust
+/// struct Enemy { hp: u32 }
+/// struct Damage(u32);
+///
+/// let enemies = nuts::template(
+///     |hp| Enemy { hp },
+///     |id| {
+///         id.subscribe(|enemy, dmg: &Damage| enemy.hp = enemy.hp.saturating_sub(dmg.0));
+///     },
+/// );
+/// let weak = enemies.spawn(10);
+/// let strong = enemies.spawn(100);
+/// nuts::publish(Damage(20));
+/// ```
+pub fn template<ARGS, A, F, W>(factory: F, wire: W) -> ActivityTemplate<ARGS, A>
+where
+    A: Activity,
+    F: Fn(ARGS) -> A + 'static,
+    W: Fn(ActivityId<A>) + 'static,
+{
+    ActivityTemplate::new(factory, wire)
+}
+
 /// Puts the data object to the domain, which can be accessed by all associated activities.
 ///
 /// This function stores the data to the domain immediately if called outside of activities.
@@ -173,21 +840,355 @@ where
     nut::write_domain(domain, data)
 }
 
-/// Registers a callback closure with a specific topic to listen to.
+/// Puts the data object to the domain immediately, or fails if that is not possible.
 ///
-/// This variant of subscription has no activity. See [`ActivityId::subscribe`](struct.ActivityId.html#method.subscribe) and friends for other subscription options.
-pub fn subscribe<F, MSG>(f: F)
+/// Unlike [`store_to_domain`](fn.store_to_domain.html), this never falls back to delaying the store.
+///
+/// # Errors
+/// Inside activities, the domain is not accessible and `ExecError::InsideHandler` is returned. The data is dropped in that case.
+pub fn store_to_domain_now<D, T>(domain: &D, data: T) -> Result<(), ExecError>
 where
-    F: Fn(&MSG) + 'static,
-    MSG: Any,
+    D: DomainEnumeration,
+    T: core::any::Any,
 {
-    crate::nut::register_no_activity(f)
+    nut::write_domain_now(domain, data)
 }
 
-/// Send the message to all subscribed activities
+/// Queues storing the data object to the domain, behind all events that are already waiting.
 ///
-// @ START-DOC PUBLISH
-/// Any instance of a struct or primitive can be published, as long as its type is known at compile-time. (The same constraint as for Activities.)
+/// Unlike [`store_to_domain`](fn.store_to_domain.html), this always goes through the queue, also outside of activities.
+/// Outside of activities, the queue is processed before this function returns.
+pub fn store_to_domain_deferred<D, T>(domain: &D, data: T)
+where
+    D: DomainEnumeration,
+    T: core::any::Any,
+{
+    nut::write_domain_deferred(domain, data)
+}
+
+/// Same as [`store_to_domain`](fn.store_to_domain.html) but returns a future that resolves once the data has been stored.
+///
+/// Outside of activities, the data is stored immediately and the future is ready right away.
+/// Inside activities, the future resolves once the delayed store and all events queued before it have been executed.
+/// Until then, [`DomainState::pending`](struct.DomainState.html#method.pending) counts the store.
+///
+/// The future does not need to be polled for the data to be stored.
+pub fn store_to_domain_and_notify<D, T>(
+    domain: &D,
+    data: T,
+) -> impl core::future::Future<Output = ()>
+where
+    D: DomainEnumeration,
+    T: core::any::Any,
+{
+    nut::write_domain_and_notify(DomainId::new(domain), data)
+}
+
+/// Looks up the activity of type `A` and returns what `f` computes from it.
+///
+/// Nothing is registered, this only peeks at the current state of the activity.
+/// Returns `None` if there is no such activity or if called inside a subscription handler, where activities are not accessible.
+///
+/// The closure must not call other nuts functions.
+///
+/// ### Example
+/// ```rust
+/// struct Score(u32);
+/// nuts::new_activity(Score(7));
+/// assert_eq!(Some(7), nuts::read_activity(|score: &Score| score.0));
+/// ```
+pub fn read_activity<A, R, F>(f: F) -> Option<R>
+where
+    A: Activity,
+    F: FnOnce(&A) -> R,
+{
+    nut::read_activity(f)
+}
+
+/// Gives mutable access to the activity of type `A` and returns what `f` computes from it.
+///
+/// Unlike [`read_activity`](fn.read_activity.html), the activity can be modified and messages published inside `f` are delivered once it returns.
+/// This is useful in functions that are called from outside, such as functions exported to JavaScript, to query or update an activity without defining a message type.
+///
+/// ### Example
+/// ```rust
+/// struct Counter(u32);
+/// nuts::new_activity(Counter(0));
+/// let value = nuts::with_activity(|counter: &mut Counter| {
+///     counter.0 += 1;
+///     counter.0
+/// });
+/// assert_eq!(Ok(1), value);
+/// ```
+///
+/// # Errors
+/// Fails with `ExecError::InsideHandler` if called while nuts is executing, for example inside a subscription handler,
+/// and with `ExecError::MissingActivity` if there is no activity of type `A`.
+pub fn with_activity<A, R>(f: impl FnOnce(&mut A) -> R) -> Result<R, ExecError>
+where
+    A: Activity,
+{
+    nut::with_activity(f)
+}
+
+/// Removes the value of type `T` from the domain and returns it.
+///
+/// Outside of activities, the value is removed immediately and returned, if it exists.
+/// Inside activities, the domain is not accessible and the removal is delayed, like [`store_to_domain`](fn.store_to_domain.html).
+/// In that case, the value is dropped and `None` is returned.
+/// To take ownership of domain data inside a handler, use [`DomainState::remove`](struct.DomainState.html#method.remove) in a domained subscription.
+pub fn take_from_domain<D, T>(domain: &D) -> Option<T>
+where
+    D: DomainEnumeration,
+    T: Any,
+{
+    nut::take_from_domain(domain)
+}
+
+/// Applies a closure to the value of type `T` stored in the domain.
+///
+/// Outside of activities, the closure is called immediately.
+/// Inside activities, the domain is not accessible and the call is delayed, like [`store_to_domain`](fn.store_to_domain.html).
+/// The returned future resolves once the closure has been called and all events caused by it have been executed.
+/// It resolves to the return value of the closure, or to `None` if the domain holds no value of type `T`.
+///
+/// The future does not need to be polled for the closure to be called.
+///
+/// ### Example
+/// ```rust
+/// use nuts::DefaultDomain;
+///
+/// struct Volume(f32);
+/// nuts::store_to_domain(&DefaultDomain, Volume(1.0));
+///
+/// // For example inside a JS callback
+/// drop(nuts::mutate(&DefaultDomain, |volume: &mut Volume| volume.0 = 0.5));
+/// ```
+pub fn mutate<D, T, R, F>(domain: &D, f: F) -> impl core::future::Future<Output = Option<R>>
+where
+    D: DomainEnumeration,
+    T: Any,
+    R: Any,
+    F: FnOnce(&mut T) -> R + 'static,
+{
+    nut::mutate_domain(DomainId::new(domain), f)
+}
+
+/// Creates a [`Signal`](struct.Signal.html), a value that publishes [`Changed<T>`](struct.Changed.html) only when it actually changes.
+///
+/// The initial value is stored to the domain immediately (or deferred, if called inside a handler), without publishing.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{Changed, DefaultDomain};
+///
+/// let score = nuts::signal(&DefaultDomain, 0u32);
+/// nuts::subscribe(|change: &Changed<u32>| {
+///     println!("Score changed from {} to {}", change.old, change.new);
+/// });
+/// score.set(1); // prints "Score changed from 0 to 1"
+/// score.set(1); // prints nothing
+/// ```
+pub fn signal<D, T>(domain: &D, initial: T) -> Signal<T>
+where
+    D: DomainEnumeration,
+    T: PartialEq + Clone + Any,
+{
+    Signal::new(DomainId::new(domain), initial)
+}
+
+/// Watches a domain value that is modified without publishing anything, and publishes [`Changed<T>`](struct.Changed.html) when it differs from before.
+///
+/// The value is compared every `period` frames, on [`FrameUpdate`](struct.FrameUpdate.html) messages with a frame counter divisible by `period`.
+/// The first comparison only records the value, as does a comparison while the domain holds no value of type `T`.
+/// On a change, `on_change` is called with the old and the new value, before `Changed<T>` is published.
+///
+/// The watch is a builtin activity in the domain, delete it to stop watching.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{Changed, DefaultDomain, MainLoopBuilder};
+///
+/// #[derive(Clone, PartialEq)]
+/// struct Volume(f32);
+///
+/// nuts::store_to_domain(&DefaultDomain, Volume(1.0));
+/// nuts::watch_domain(&DefaultDomain, 10, |old: &Volume, new: &Volume| {
+///     println!("Volume changed from {} to {}", old.0, new.0);
+/// });
+/// nuts::subscribe(|_: &Changed<Volume>| println!("Update the volume slider"));
+/// let mut main_loop = MainLoopBuilder::new(60);
+/// for _ in 0..20 {
+///     main_loop.step(Default::default());
+///     nuts::store_to_domain(&DefaultDomain, Volume(0.5));
+/// }
+/// ```
+///
+/// # Panics
+/// Panics if `period` is zero.
+pub fn watch_domain<D, T>(
+    domain: &D,
+    period: u64,
+    on_change: impl Fn(&T, &T) + 'static,
+) -> ActivityId<DomainWatch<T>, WithDomain>
+where
+    D: DomainEnumeration,
+    T: PartialEq + Clone + Any,
+{
+    nut::iac::signal::watch_domain(domain, period, on_change)
+}
+
+/// Registers a callback closure with a specific topic to listen to.
+///
+/// This variant of subscription has no activity. See [`ActivityId::subscribe`](struct.ActivityId.html#method.subscribe) and friends for other subscription options.
+/// Use the returned handle to remove the subscription again.
+///
+/// ### Example
+/// ```rust
+/// struct Ping;
+///
+/// let handle = nuts::subscribe(|_: &Ping| println!("Pong"));
+/// nuts::publish(Ping); // prints "Pong"
+/// handle.unsubscribe();
+/// nuts::publish(Ping); // prints nothing
+/// ```
+pub fn subscribe<F, MSG>(f: F) -> SubscriptionHandle
+where
+    F: Fn(&MSG) + 'static,
+    MSG: Any,
+{
+    subscribe_masked(SubscriptionFilter::default(), f)
+}
+
+/// Same as [`subscribe`](fn.subscribe.html) but with a filter, to set the priority or phase of the subscription.
+///
+/// Without an activity, there is no lifecycle status to filter on.
+pub fn subscribe_masked<F, MSG>(mask: SubscriptionFilter, f: F) -> SubscriptionHandle
+where
+    F: Fn(&MSG) + 'static,
+    MSG: Any,
+{
+    SubscriptionHandle::new(crate::nut::register_no_activity(f, mask))
+}
+
+/// Same as [`subscribe`](fn.subscribe.html) but the subscription only lives as long as the guard.
+///
+/// The handler receives the guard along with the message.
+/// Once all strong references to the guard have been dropped, the subscription is removed the next time a message would be delivered to it.
+/// This prevents leaking subscriptions of objects that are removed, such as DOM elements owning callbacks.
+///
+/// ### Example
+/// ```rust
+/// use std::rc::Rc;
+/// struct Button { label: &'static str }
+/// struct Click;
+///
+/// let button = Rc::new(Button { label: "OK" });
+/// nuts::subscribe_weak(&button, |button, _: &Click| println!("{} clicked", button.label));
+/// nuts::publish(Click); // prints "OK clicked"
+/// drop(button);
+/// nuts::publish(Click); // prints nothing and removes the subscription
+/// ```
+pub fn subscribe_weak<G, F, MSG>(guard: &std::rc::Rc<G>, f: F) -> SubscriptionHandle
+where
+    G: 'static,
+    F: Fn(&G, &MSG) + 'static,
+    MSG: Any,
+{
+    SubscriptionHandle::new(crate::nut::register_weak(guard, f))
+}
+
+/// Turns all subscriptions with the tag on or off, see [`ActivityId::subscribe_tagged`](struct.ActivityId.html#method.subscribe_tagged).
+///
+/// This applies to all subscriptions in the subscription group of the same name, including those registered later.
+/// Disabled subscriptions are skipped as if they did not exist, independent of the lifecycle status of their activities.
+/// Tags are enabled by default.
+pub fn set_tag_enabled(tag: &str, enabled: bool) {
+    nut::set_tag_enabled(tag, enabled)
+}
+
+/// Defines in which order subscriptions of equal priority receive messages of type `MSG`.
+///
+/// By default, subscriptions are called in the order they have been registered.
+/// The order applies to existing and future subscriptions of the message type.
+///
+/// ### Example
+/// ```rust
+/// use nuts::DeliveryOrder;
+/// struct Beta;
+/// struct Alpha;
+/// struct Tick;
+///
+/// nuts::new_activity(Beta).subscribe(|_, _: &Tick| println!("Beta"));
+/// nuts::new_activity(Alpha).subscribe(|_, _: &Tick| println!("Alpha"));
+/// nuts::set_topic_order::<Tick>(DeliveryOrder::TypeName);
+/// nuts::publish(Tick); // prints "Alpha", then "Beta"
+/// ```
+pub fn set_topic_order<MSG: Any>(order: DeliveryOrder) {
+    nut::set_topic_order::<MSG>(order)
+}
+
+/// Registers the message type `MSG` as implementation of the trait object type `T`, so that subscribers of the trait receive it.
+///
+/// Rust cannot cast a message to a trait object at runtime, hence the cast is passed in. Usually, this is just `|msg| msg`.
+/// Subscribe to the trait with [`ActivityId::subscribe_trait`](struct.ActivityId.html#method.subscribe_trait).
+/// This also works for boxed trait objects, by registering `Box<dyn Trait>` with `|msg| msg.as_ref()`.
+///
+/// ### Example
+/// ```rust
+/// trait GameEvent {
+///     fn describe(&self) -> String;
+/// }
+/// struct Explosion;
+/// struct Victory;
+/// impl GameEvent for Explosion {
+///     fn describe(&self) -> String { "Boom".to_owned() }
+/// }
+/// impl GameEvent for Victory {
+///     fn describe(&self) -> String { "Yay".to_owned() }
+/// }
+/// struct EventLog;
+///
+/// nuts::register_trait::<dyn GameEvent, Explosion>(|msg| msg);
+/// nuts::register_trait::<dyn GameEvent, Victory>(|msg| msg);
+/// nuts::new_activity(EventLog)
+///     .subscribe_trait::<dyn GameEvent, _>(|_, event| println!("{}", event.describe()));
+/// nuts::publish(Explosion); // prints "Boom"
+/// nuts::publish(Victory); // prints "Yay"
+/// ```
+pub fn register_trait<T, MSG>(cast: fn(&MSG) -> &T)
+where
+    T: ?Sized + 'static,
+    MSG: Any,
+{
+    nut::iac::trait_topic::register(cast)
+}
+
+/// Registers a callback closure that receives every message, regardless of its type.
+///
+/// This includes published messages, private messages, and builtin events.
+/// The closure is called before any regular subscriber, hence it also sees messages that are consumed by private channels.
+/// Use the [`MessageMeta`](struct.MessageMeta.html) to tell messages apart. This is mostly useful for logging and debugging.
+///
+/// ### Example
+/// ```rust
+/// nuts::subscribe_any(|meta, _msg| {
+///     println!("Message of type {} delivered", meta.type_name());
+/// });
+/// nuts::publish(17usize);
+/// // prints "Message of type usize delivered"
+/// ```
+pub fn subscribe_any<F>(f: F) -> SubscriptionHandle
+where
+    F: Fn(&MessageMeta, &dyn Any) + 'static,
+{
+    SubscriptionHandle::new(crate::nut::register_any(f))
+}
+
+/// Send the message to all subscribed activities
+///
+// @ START-DOC PUBLISH
+/// Any instance of a struct or primitive can be published, as long as its type is known at compile-time. (The same constraint as for Activities.)
 /// Upon calling `nuts::publish`, all active subscriptions for the same type are executed and the published object will be shared with all of them.
 ///
 /// ### Example
@@ -232,12 +1233,494 @@ pub fn publish<A: Any>(a: A) {
     nut::publish_custom(a)
 }
 
+/// Same as [`publish`](fn.publish.html) but avoids allocating for every message, for high-frequency streams of small messages.
+///
+/// Messages published with `publish` are put in a new box each time.
+/// With this function, the boxes of delivered messages are kept and reused for the next message of the same type.
+///
+/// ### Example
+/// ```rust
+/// #[derive(Clone, Copy)]
+/// struct MouseMove { x: i32, y: i32 }
+///
+/// nuts::subscribe(|m: &MouseMove| println!("{} {}", m.x, m.y));
+/// for x in 0..100 {
+///     // Only the first message allocates
+///     nuts::publish_copy(MouseMove { x, y: 0 });
+/// }
+/// ```
+pub fn publish_copy<MSG: Any + Copy>(msg: MSG) {
+    nut::publish_copy(msg)
+}
+
+/// Same as [`publish`](fn.publish.html) but guarantees that all subscribers have been called when it returns.
+///
+/// Instead of queuing the message when nuts is already executing, for example inside a subscription handler, this fails and the message is dropped.
+/// Use this in top-level code that reads back state right after publishing.
+///
+/// ### Example
+/// ```rust
+/// struct Counter(u32);
+/// struct Increment;
+///
+/// nuts::new_activity(Counter(0)).subscribe(|counter, _: &Increment| counter.0 += 1);
+/// nuts::publish_sync(Increment).expect("not inside a handler");
+/// assert_eq!(Some(1), nuts::read_activity(|counter: &Counter| counter.0));
+/// ```
+///
+/// # Errors
+/// Fails with `ExecError::InsideHandler` if nuts is already executing,
+/// with `ExecError::EventsPending` if events are left over from a catch-up that used up the [execution budget](fn.set_execution_budget.html),
+/// and with `ExecError::Stepping` while [`test_support`](test_support/index.html) step mode is on.
+/// The message is dropped in all these cases.
+pub fn publish_sync<A: Any>(a: A) -> Result<(), ExecError> {
+    nut::publish_sync(a)
+}
+
+/// Same as [`publish`](fn.publish.html) but the handlers are called grouped by the domain of their activity, as preparation for running them on multiple threads.
+///
+/// All handlers of activities in the first domain are called before those of the second domain, and so on.
+/// Activities without a domain come last. Within a domain, the usual order by priority and registration is kept.
+///
+/// While the handlers of a domain are running, accessing any other domain panics.
+/// This asserts that handlers of different domains never alias, which is required before they can run in parallel.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{domain_enum, DomainEnumeration};
+///
+/// #[derive(Clone, Copy)]
+/// enum Domain {
+///     Left,
+///     Right,
+/// }
+/// domain_enum!(Domain);
+/// struct Tick;
+/// struct Side;
+///
+/// nuts::new_domained_activity_checked(Side, &Domain::Right)
+///     .subscribe_domained(|_, domain, _: &Tick| domain.store(2u32));
+/// nuts::new_domained_activity_checked(Side, &Domain::Left)
+///     .subscribe_domained(|_, domain, _: &Tick| domain.store(1u32));
+/// // The handler of the left domain is called first
+/// nuts::publish_parallel_ready(Tick);
+/// ```
+pub fn publish_parallel_ready<A: Any>(a: A) {
+    nut::publish_parallel_ready(a)
+}
+
+/// Same as [`publish`](fn.publish.html) but the message may overtake or fall behind other queued messages.
+///
+/// Messages of higher priority are delivered before all queued messages of lower priority, messages of equal priority keep their order.
+/// This also applies to internal events queued with the default priority, such as new subscriptions or lifecycle changes.
+/// To prevent starvation, at most 16 events of higher priorities are delivered in a row while events of a lower priority are waiting.
+/// After that, the oldest event of the next lower priority is delivered.
+///
+/// When the queue policy drops old messages, it drops messages of low priority first.
+///
+/// ### Example
+/// ```rust
+/// use nuts::Priority;
+/// struct Log(&'static str);
+/// struct Emergency;
+/// struct App;
+///
+/// let app = nuts::new_activity(App);
+/// app.subscribe(|_, log: &Log| println!("{}", log.0));
+/// app.subscribe(|_, _: &Emergency| println!("Shutting down"));
+/// app.subscribe(|_, _: &u32| {
+///     nuts::publish(Log("Everything is fine"));
+///     // Printed before the log message
+///     nuts::publish_with_priority(Emergency, Priority::High);
+/// });
+/// nuts::publish(0u32);
+/// ```
+pub fn publish_with_priority<A: Any>(a: A, priority: Priority) {
+    nut::publish_with_priority(a, priority)
+}
+
+/// Fallible variant of [`publish`](fn.publish.html) for library code that should degrade gracefully.
+///
+/// The message is only published if at least one subscription would receive it and the queue has space left.
+/// Subscriptions to the message type, to all messages, and to traits the type has been [registered for](fn.register_trait.html) count,
+/// including subscriptions that have been added inside a handler and are still queued.
+/// Otherwise, the message is dropped and the reason is returned.
+///
+/// # Errors
+/// `PublishError::NoReceiver` if nothing subscribes to the message type,
+/// `PublishError::QueueFull` if the [`QueuePolicy`](struct.QueuePolicy.html) limit is reached.
+///
+/// ### Example
+/// ```rust
+/// use nuts::PublishError;
+/// struct Ping;
+///
+/// assert_eq!(Err(PublishError::NoReceiver), nuts::try_publish(Ping));
+/// nuts::new_activity(()).subscribe(|_, _: &Ping| println!("Pong"));
+/// assert_eq!(Ok(()), nuts::try_publish(Ping));
+/// ```
+pub fn try_publish<A: Any>(a: A) -> Result<(), PublishError> {
+    nut::try_publish_custom(a)
+}
+
+/// Publishes a message that is only delivered to subscribers whose activity belongs to the given domain.
+///
+/// Subscribers of activities in other domains, or without a domain, do not receive the message.
+/// Handlers registered with [`subscribe_any`](fn.subscribe_any.html) still receive it.
+///
+/// ### Example
+/// ```rust
+/// #[macro_use] extern crate nuts;
+/// use nuts::{domain_enum, DomainEnumeration};
+/// #[derive(Clone, Copy)]
+/// enum Screen {
+///     Menu,
+///     InGame,
+/// }
+/// domain_enum!(Screen);
+/// struct Button;
+/// struct Click;
+///
+/// nuts::new_domained_activity(Button, &Screen::Menu).subscribe(|_, _: &Click| println!("menu"));
+/// nuts::new_domained_activity(Button, &Screen::InGame).subscribe(|_, _: &Click| println!("game"));
+/// // Only prints "menu"
+/// nuts::publish_to_domain(&Screen::Menu, Click);
+/// ```
+pub fn publish_to_domain<D: DomainEnumeration, MSG: Any>(domain: &D, msg: MSG) {
+    nut::publish_to_domain(DomainId::new(domain), msg)
+}
+
+/// Publishes a message to the subscriptions of a single entity, registered with [`ActivityId::subscribe_entity`](struct.ActivityId.html#method.subscribe_entity).
+///
+/// Subscriptions are looked up by message type and entity, other entities never see the message.
+/// Regular subscribers of the message type do not receive it either, only those registered with [`subscribe_any`](fn.subscribe_any.html).
+///
+/// ### Example
+/// ```rust
+/// use nuts::EntityId;
+/// struct Enemy(u32);
+/// struct Hit(u32);
+///
+/// for i in 0..100 {
+///     nuts::new_activity(Enemy(10)).subscribe_entity(EntityId(i), |enemy, hit: &Hit| {
+///         enemy.0 = enemy.0.saturating_sub(hit.0)
+///     });
+/// }
+/// // Only one handler is called
+/// nuts::publish_to_entity(EntityId(42), Hit(3));
+/// ```
+pub fn publish_to_entity<MSG: Any>(entity: EntityId, msg: MSG) {
+    nut::publish_to_entity(entity, msg)
+}
+
+/// Publishes a message identified by a name chosen at runtime, with a JSON value as payload.
+///
+/// It is received by subscriptions registered with [`ActivityId::subscribe_dynamic`](struct.ActivityId.html#method.subscribe_dynamic) and the same name.
+/// This is meant for scripting bindings, console commands, and mods, where message types are not known at compile-time.
+/// Dynamic messages are queued and delivered like all other messages, but typed messages remain the faster and safer choice otherwise.
+/// A message under a name that has never been subscribed to is dropped right away, without appearing in diagnostics.
+///
+/// Only available with the `dynamic-messages` feature.
+///
+/// ### Example
+/// ```rust
+/// # #[cfg(feature = "dynamic-messages")] {
+/// struct Health(i64);
+/// let player = nuts::new_activity(Health(100));
+/// player.subscribe_dynamic("damage_event", |health, value| {
+///     health.0 -= value["amount"].as_i64().unwrap_or(0);
+/// });
+/// nuts::publish_dynamic("damage_event", serde_json::json!({ "amount": 30 }));
+/// assert_eq!(Some(70), nuts::read_activity(|health: &Health| health.0));
+/// # }
+/// ```
+#[cfg(feature = "dynamic-messages")]
+pub fn publish_dynamic(name: &str, value: serde_json::Value) {
+    nut::publish_dynamic(name, value)
+}
+
+/// Publishes all messages of an iterator.
+///
+/// All messages are queued before the first of them is delivered.
+/// Together with [`drain_topic`](fn.drain_topic.html), this allows to hand off pending messages in bulk.
+pub fn publish_iter<MSG: Any>(msgs: impl IntoIterator<Item = MSG>) {
+    nut::publish_iter(msgs)
+}
+
+/// Keeps messages of type `MSG` that are published while no activity subscribes to them, instead of dropping them.
+///
+/// The kept messages can be taken with [`drain_topic`](fn.drain_topic.html), for example to hand them off to another nuts instance.
+/// Only messages published with [`publish`](fn.publish.html) and its variants are kept, messages sent privately are not.
+pub fn keep_undelivered<MSG: Any>() {
+    nut::keep_undelivered::<MSG>()
+}
+
+/// Removes all messages of type `MSG` that have been published but not delivered, yet, and returns them in order.
+///
+/// These are the messages kept because nobody subscribed to them, see [`keep_undelivered`](fn.keep_undelivered.html),
+/// followed by those still queued because a broadcast is ongoing.
+/// The removed messages are never delivered to subscribers, unless they are published again.
+///
+/// ### Example
+/// ```rust
+/// struct Work(usize);
+///
+/// nuts::keep_undelivered::<Work>();
+/// nuts::publish(Work(1));
+/// nuts::publish(Work(2));
+/// // Hand off the work to an activity created later
+/// let pending: Vec<Work> = nuts::drain_topic();
+/// nuts::new_activity(()).subscribe(|_, work: &Work| println!("Working on {}", work.0));
+/// nuts::publish_iter(pending);
+/// ```
+pub fn drain_topic<MSG: Any>() -> Vec<MSG> {
+    nut::drain_topic()
+}
+
 /// Returns a future of type `NutsResponse` which will resolve after the
 /// message has been published and all subscribers have finished processing it.
+///
+/// Deferred events caused by the subscribers, such as messages published inside the handlers
+/// or calls to [`store_to_domain`](fn.store_to_domain.html), are also awaited, transitively.
+/// Once the future resolves, all of these have completed.
+///
+/// The message is published on the first poll.
+/// If that happens inside a subscription handler, the future stays pending at least until the handler has returned,
+/// so it must not be awaited by blocking inside a handler.
+/// With the `wasm-futures` feature, [`spawn`](fn.spawn.html) polls such futures at the right moments.
 pub async fn publish_awaiting_response<A: Any>(a: A) {
     nut::publish_custom_and_await(a).await;
 }
 
+/// Publishes a request and returns a future that resolves to the reply.
+///
+/// Replies are returned by subscription handlers registered with [`ActivityId::subscribe_responder`](struct.ActivityId.html#method.subscribe_responder).
+/// Like for [`publish_awaiting_response`](fn.publish_awaiting_response.html), the future resolves once the message
+/// and all deferred events caused by it have been processed.
+/// It resolves to `None` if no responder has answered or if the reply is not of type `REPLY`.
+///
+/// ### Example
+/// ```rust
+/// struct Calculator;
+/// struct Add(u32, u32);
+///
+/// let id = nuts::new_activity(Calculator);
+/// id.subscribe_responder(|_, msg: &Add| msg.0 + msg.1);
+///
+/// async fn compute() {
+///     let sum: Option<u32> = nuts::request(Add(2, 3)).await;
+///     assert_eq!(sum, Some(5));
+/// }
+/// ```
+pub async fn request<MSG: Any, REPLY: Any>(msg: MSG) -> Option<REPLY> {
+    nut::request(msg).await
+}
+
+/// Runs a future on the current thread, polled by nuts at points of quiescence.
+///
+/// The future is polled right away if no subscription handler is executing, otherwise after the handlers are done.
+/// Afterwards, all pending futures are polled again whenever nuts reaches a point of quiescence, for example after a call to `nuts::publish` has been processed.
+/// Thus, futures returned by [`publish_awaiting_response`](fn.publish_awaiting_response.html) or [`request`](fn.request.html) make progress without an external executor,
+/// and the code between two `.await` points never runs in the middle of a handler, where activities and domains are not accessible.
+///
+/// On the web, futures woken by anything other than nuts, such as a JS promise, are also polled in a microtask after they are woken.
+///
+/// Only available with the `wasm-futures` feature.
+///
+/// ### Example
+/// ```rust
+/// # #[cfg(feature = "wasm-futures")] {
+/// struct Calculator;
+/// struct Add(u32, u32);
+/// struct Show(u32);
+///
+/// let id = nuts::new_activity(Calculator);
+/// id.subscribe_responder(|_, msg: &Add| msg.0 + msg.1);
+/// id.subscribe(|_, msg: &Show| assert_eq!(msg.0, 5));
+///
+/// nuts::spawn(async {
+///     let sum: Option<u32> = nuts::request(Add(2, 3)).await;
+///     nuts::publish(Show(sum.unwrap()));
+/// });
+/// # }
+/// ```
+#[cfg(feature = "wasm-futures")]
+pub fn spawn(f: impl std::future::Future<Output = ()> + 'static) {
+    nut::exec::spawn::spawn(f)
+}
+
+/// Returns a handle that can be sent to other threads to publish messages from there.
+///
+/// Messages published through the [`NutsProxy`](struct.NutsProxy.html) are delivered on the current thread.
+/// They are picked up whenever nuts reaches a point of quiescence on this thread, for example after the next call to `nuts::publish`,
+/// or explicitly with [`drain_remote`](fn.drain_remote.html).
+///
+/// ### Example
+/// ```rust
+/// struct Loaded(String);
+/// let activity = nuts::new_activity(());
+/// activity.subscribe(|_, msg: &Loaded| println!("Loaded {}", msg.0));
+///
+/// let proxy = nuts::remote_handle();
+/// std::thread::spawn(move || {
+///     proxy.publish(Loaded("file.txt".to_owned()));
+/// })
+/// .join()
+/// .unwrap();
+///
+/// // prints "Loaded file.txt"
+/// nuts::drain_remote();
+/// ```
+pub fn remote_handle() -> NutsProxy {
+    nut::remote_handle()
+}
+
+/// Limits the number of queued events, to detect runaway feedback loops of recursively published messages.
+///
+/// Events are queued when they are published (or otherwise caused) while nuts is executing, for example inside a subscription handler.
+/// Without a policy, the queue grows without bounds. Pass `None` to remove the policy.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{OnOverflow, QueuePolicy};
+/// nuts::set_queue_policy(Some(QueuePolicy {
+///     max_len: 10_000,
+///     on_overflow: OnOverflow::DropOldest,
+/// }));
+/// ```
+pub fn set_queue_policy(policy: Option<QueuePolicy>) {
+    nut::set_queue_policy(policy)
+}
+
+/// Publishes a message for a frame phase.
+///
+/// The message is held back until all regular messages and all messages of earlier phases have been delivered, including everything they cause.
+/// For example, all `Update` handlers complete before the first `Draw` handler is called, regardless of their domains.
+/// Subscriptions registered with [`SubscriptionFilter::in_phase`](struct.SubscriptionFilter.html#method.in_phase) only receive messages of their phase.
+///
+/// Phases order the messages of a single pump, that is until nuts returns control to the caller.
+/// Outside of subscription handlers, the message is therefore delivered right away.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{Phase, SubscriptionFilter};
+/// struct NextFrame;
+/// struct Frame;
+/// struct Game;
+/// struct Renderer;
+///
+/// nuts::new_activity(Renderer)
+///     .subscribe_masked(SubscriptionFilter::default().in_phase(Phase::Draw), |_, _: &Frame| {
+///         println!("draw")
+///     });
+/// let game = nuts::new_activity(Game);
+/// game.subscribe_masked(SubscriptionFilter::default().in_phase(Phase::Update), |_, _: &Frame| {
+///     println!("update")
+/// });
+/// game.subscribe(|_, _: &NextFrame| {
+///     // Prints "update" before "draw", even though draw is published first
+///     nuts::publish_phased(Phase::Draw, Frame);
+///     nuts::publish_phased(Phase::Update, Frame);
+/// });
+/// nuts::publish(NextFrame);
+/// ```
+pub fn publish_phased<MSG: Any>(phase: Phase, msg: MSG) {
+    nut::publish_phased(phase, msg)
+}
+
+/// Holds the message back until the predicate over the domain is true, then publishes it.
+///
+/// The predicate is checked right away and then each time all queued messages have been delivered, as well as after storing to a domain outside of activities.
+/// Held messages whose predicate passes at the same time are published in the order they have been held.
+/// The predicate must not call any nuts functions.
+///
+/// This replaces polling, such as re-publishing a retry message every frame until a resource has been loaded.
+///
+/// ### Example
+/// ```rust
+/// use nuts::DefaultDomain;
+///
+/// struct Texture;
+/// struct StartLevel;
+///
+/// nuts::subscribe(|_: &StartLevel| println!("Starting"));
+/// nuts::publish_when(&DefaultDomain, |domain| domain.try_get::<Texture>().is_some(), StartLevel);
+/// // Prints "Starting"
+/// nuts::store_to_domain(&DefaultDomain, Texture);
+/// ```
+pub fn publish_when<D, MSG>(
+    domain: &D,
+    predicate: impl Fn(&DomainState) -> bool + 'static,
+    msg: MSG,
+) where
+    D: DomainEnumeration,
+    MSG: Any,
+{
+    nut::publish_when(DomainId::new(domain), predicate, msg)
+}
+
+/// Defines in which order phases are executed, see [`publish_phased`](fn.publish_phased.html).
+///
+/// The default order is `[Phase::Update, Phase::Draw]`.
+/// Phases that are not listed run after all listed phases, in the order they have first been published to.
+pub fn set_phase_order(order: &[Phase]) {
+    nut::set_phase_order(order)
+}
+
+/// Defines what happens when a subscription handler panics.
+///
+/// By default, panics unwind through nuts and leave it in a state where it can only be reset.
+/// With [`PanicPolicy::RecoverAndReport`](enum.PanicPolicy.html#variant.RecoverAndReport), nuts catches the panic,
+/// sets the activity to inactive and publishes a [`SubscriberPanicked`](struct.SubscriberPanicked.html) message.
+///
+/// Catching panics requires `panic = "unwind"`, which is the default on most targets but not on the web.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{PanicPolicy, SubscriberPanicked};
+/// struct Faulty;
+/// struct Logger;
+///
+/// nuts::set_panic_policy(PanicPolicy::RecoverAndReport);
+/// nuts::new_activity(Faulty).subscribe(|_, _: &u32| panic!("oops"));
+/// nuts::new_activity(Logger).subscribe(|_, report: &SubscriberPanicked| {
+///     assert_eq!(Some("oops"), report.panic_message());
+/// });
+/// nuts::publish(7u32);
+/// ```
+pub fn set_panic_policy(policy: PanicPolicy) {
+    nut::set_panic_policy(policy)
+}
+
+/// Limits how many published messages of type `MSG` are delivered per pump.
+///
+/// A pump is the execution of queued events that starts with a call to nuts outside of handlers, for example `nuts::publish`, and ends when nothing is queued anymore.
+/// Messages above the budget are set aside until the next pump, which can be started explicitly with [`pump`](fn.pump.html).
+/// Set-aside messages do not keep [`run_idle`](fn.run_idle.html) from running the idle handlers.
+/// This keeps a flood of cheap messages from delaying all other messages.
+pub fn set_topic_budget<MSG: Any>(max_per_pump: usize) {
+    nut::set_topic_budget::<MSG>(Some(max_per_pump))
+}
+
+/// Removes the budget set with [`set_topic_budget`](fn.set_topic_budget.html).
+pub fn clear_topic_budget<MSG: Any>() {
+    nut::set_topic_budget::<MSG>(None)
+}
+
+/// Executes all queued events, including messages postponed by a [topic budget](fn.set_topic_budget.html).
+///
+/// Call this once per frame when using topic budgets. Inside subscription handlers, this does nothing.
+pub fn pump() {
+    nut::pump()
+}
+
+/// Delivers all messages that have been published from other threads through a [`NutsProxy`](struct.NutsProxy.html).
+///
+/// Inside subscription handlers, this does nothing because the messages will be picked up anyway before the current publish call returns.
+pub fn drain_remote() {
+    nut::drain_remote()
+}
+
 /// Publish a message to a specific activity. The same as `id.private_message()` but works without an `ActivityId`.
 ///
 /// The first type parameter must always be specified.
@@ -263,6 +1746,142 @@ pub fn send_to<RECEIVER: Any, MSG: Any>(msg: MSG) {
     nut::send_custom::<RECEIVER, MSG>(msg)
 }
 
+/// Like [`send_to`](fn.send_to.html) but for one of several activities of the same type, selected by its id.
+///
+/// The ids of all instances of a type are listed by [`instances_of`](fn.instances_of.html), the id of a keyed activity is returned by [`lookup_key`](fn.lookup_key.html).
+/// This is the same as [`UncheckedActivityId::private_message`](struct.UncheckedActivityId.html#method.private_message).
+/// The message is ignored silently if the activity has been deleted or if it has no private channel for this message.
+///
+/// ### Example
+/// ```rust
+/// struct Timer { ticks: u32 }
+/// struct Tick;
+///
+/// nuts::new_activity(Timer { ticks: 0 }).private_channel(|timer, _: Tick| timer.ticks += 1);
+/// nuts::new_activity(Timer { ticks: 100 }).private_channel(|timer, _: Tick| timer.ticks += 1);
+/// let second = nuts::instances_of::<Timer>().expect("not inside a handler")[1];
+/// nuts::send_to_instance(second, Tick);
+/// ```
+pub fn send_to_instance<MSG: Any>(instance: UncheckedActivityId, msg: MSG) {
+    nut::send_custom_by_id(msg, instance)
+}
+
+/// Fallible variant of [`send_to`](fn.send_to.html) for library code that should degrade gracefully.
+///
+/// The message is only sent if an activity of type `RECEIVER` exists, it has a private channel for the message type, and the queue has space left.
+/// Otherwise, the message is dropped and the reason is returned.
+///
+/// Inside subscription handlers, activities cannot be looked up. The message is queued if any activity has a private channel for `MSG`,
+/// and dropped silently on delivery if that is not the `RECEIVER`, like with [`send_to`](fn.send_to.html).
+///
+/// # Errors
+/// `PublishError::NoReceiver` if no activity of type `RECEIVER` exists,
+/// `PublishError::NoPrivateChannel` if it has no private channel for `MSG`,
+/// and `PublishError::QueueFull` if the [`QueuePolicy`](struct.QueuePolicy.html) limit is reached.
+pub fn try_send_to<RECEIVER: Any, MSG: Any>(msg: MSG) -> Result<(), PublishError> {
+    nut::try_send_custom::<RECEIVER, MSG>(msg)
+}
+
+/// Sends a message to the keyed private channel of an activity.
+///
+/// The message is received by the channel that has been registered with [`ActivityId::private_channel_keyed`](struct.ActivityId.html#method.private_channel_keyed) and an equal key of the same type.
+/// It is ignored silently if no such channel exists.
+pub fn send_to_keyed<RECEIVER: Any, K: Hash + Eq + Any, MSG: Any>(key: K, msg: MSG) {
+    nut::send_custom_keyed::<RECEIVER, MSG>(key, msg)
+}
+
+/// Limits the time nuts spends executing queued events before it returns control to the caller.
+///
+/// When handlers keep publishing, a single call to `nuts::publish` from outside of handlers can run for a long time.
+/// With a budget, nuts stops after the first event that finishes when the budget has been used up,
+/// publishes a [`BudgetExceeded`](struct.BudgetExceeded.html) notification and leaves the remaining events queued.
+/// They are executed the next time nuts catches up, for example on the next frame. On the web, this keeps frames responsive.
+///
+/// Like with the heartbeat, nuts has no clock of its own, the current time is read with the `clock` function.
+///
+/// ### Example
+/// ```rust
+/// use std::time::{Duration, Instant};
+/// use nuts::BudgetExceeded;
+///
+/// thread_local!(static START: Instant = Instant::now());
+/// fn now() -> Duration {
+///     START.with(|start| start.elapsed())
+/// }
+///
+/// nuts::set_execution_budget(Duration::from_millis(8), now);
+/// nuts::subscribe(|msg: &BudgetExceeded| {
+///     println!("{} events postponed to the next frame", msg.remaining);
+/// });
+/// ```
+pub fn set_execution_budget(budget: std::time::Duration, clock: fn() -> std::time::Duration) {
+    nut::set_execution_budget(Some(nut::exec::time_slice::ExecutionBudget::new(
+        budget, clock,
+    )))
+}
+
+/// Removes the limit set with [`set_execution_budget`](fn.set_execution_budget.html).
+pub fn clear_execution_budget() {
+    nut::set_execution_budget(None)
+}
+
+/// Starts publishing a [`NutsHeartbeat`](struct.NutsHeartbeat.html) message periodically.
+///
+/// The heartbeat summarizes how many messages have been delivered per topic and how many handlers have been triggered per activity since the last heartbeat.
+/// This helps to spot feedback loops (message storms) while they happen.
+///
+/// Nuts has no clock of its own, the current time is read with the `clock` function.
+/// The heartbeat is only published when nuts reaches a point of quiescence after at least `interval` has passed.
+/// Calling this again resets all counters.
+///
+/// ### Example
+/// ```rust
+/// use std::time::{Duration, Instant};
+/// use nuts::NutsHeartbeat;
+///
+/// thread_local!(static START: Instant = Instant::now());
+/// fn now() -> Duration {
+///     START.with(|start| start.elapsed())
+/// }
+///
+/// nuts::enable_heartbeat(Duration::from_secs(1), now);
+/// nuts::subscribe(|beat: &NutsHeartbeat| {
+///     for (topic, count) in &beat.topics {
+///         println!("{}: {} per {:?}", topic, count, beat.elapsed);
+///     }
+/// });
+/// ```
+pub fn enable_heartbeat(interval: std::time::Duration, clock: fn() -> std::time::Duration) {
+    nut::set_heartbeat(Some(nut::exec::heartbeat::Heartbeat::new(interval, clock)))
+}
+
+/// Stops publishing [`NutsHeartbeat`](struct.NutsHeartbeat.html) messages and stops counting events.
+pub fn disable_heartbeat() {
+    nut::set_heartbeat(None)
+}
+
+/// Starts passing a [`TraceEvent`](enum.TraceEvent.html) to the sink for every published message, delivery, and lifecycle change.
+///
+/// Each event names the event that caused it, which allows to reconstruct why a cascade of messages happened.
+/// The sink must not call any nuts functions. Calling this again replaces the previous sink.
+///
+/// ### Example
+/// ```rust
+/// nuts::enable_tracing(|event| println!("{:?}", event));
+/// nuts::publish(17usize);
+/// ```
+pub fn enable_tracing<F>(sink: F)
+where
+    F: Fn(&TraceEvent) + 'static,
+{
+    nut::update_tracer(|tracer| tracer.set_sink(Some(Box::new(sink))))
+}
+
+/// Stops passing events to the sink registered with [`enable_tracing`](fn.enable_tracing.html).
+pub fn disable_tracing() {
+    nut::update_tracer(|tracer| tracer.set_sink(None))
+}
+
 #[cfg(debug_assertions)]
 /// Read some information about currently processing activities.
 /// This should be called inside a panic hook.
@@ -289,3 +1908,23 @@ pub fn panic_info() -> String {
     nut::nuts_panic_info()
         .unwrap_or_else(|| "NUTS panic hook: Failed to read panic info.".to_owned())
 }
+
+#[cfg(debug_assertions)]
+/// Same as [`panic_info`](fn.panic_info.html) but structured, for error reporting services that attach metadata to reports.
+///
+/// This should be called inside a panic hook. Like `panic_info`, it is only available in debug mode.
+///
+/// # Example
+/// ```
+/// #[cfg(debug_assertions)]
+/// std::panic::set_hook(Box::new(|_| {
+///     let context = nuts::panic_context();
+///     eprintln!(
+///         "activity: {:?}, message: {:?}, recent: {:?}",
+///         context.activity, context.message, context.recent_topics
+///     );
+/// }));
+/// ```
+pub fn panic_context() -> PanicContext {
+    nut::panic_context().unwrap_or_default()
+}
@@ -4,25 +4,48 @@
 //! library developers as well as users if they want to understand more how this library works.
 
 pub(crate) mod activity;
+pub(crate) mod bus;
+pub(crate) mod diagnostics;
 pub(crate) mod exec;
 pub(crate) mod iac;
+pub(crate) mod init;
+pub(crate) mod inspect;
+pub(crate) mod singleton;
+#[cfg(feature = "serde")]
+pub(crate) mod snapshot;
+#[cfg(feature = "web-worker")]
+pub(crate) mod worker;
 
-use crate::nut::exec::Deferred;
-use crate::nut::iac::subscription::OnDelete;
+use crate::nut::diagnostics::OrReport;
+use crate::nut::exec::{Deferred, QueuedEvent};
+use crate::nut::iac::subscription::{OnDelete, SubscriptionId};
+use crate::nut::inspect::NutsInspection;
 use crate::*;
 use crate::{debug::DebugTypeName, nut::exec::inchoate::InchoateActivityContainer};
-use core::any::Any;
+use core::any::{Any, TypeId};
+use core::future::Future;
+use core::hash::Hash;
 use core::sync::atomic::AtomicBool;
+use exec::budget::TopicBudgets;
 use exec::fifo::ThreadLocalFifo;
+use exec::flow::FlowGraph;
+use exec::heartbeat::Heartbeat;
+use exec::trace::Tracer;
 use iac::managed_state::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use self::iac::{
-    publish::{BroadcastInfo, ResponseTracker},
+    publish::{
+        BroadcastInfo, MailboxStats, Mailboxes, NutsProxy, NutsResponse, RemoteInbox, ResponseSlot,
+        ResponseTracker,
+    },
     subscription::Subscriptions,
 };
 
-thread_local!(static NUT: Nut = Nut::new());
+/// The nut in use on this thread, see `nuts::Bus`
+static NUT: bus::CurrentNut = bus::CurrentNut;
 
 pub(crate) const IMPOSSIBLE_ERR_MSG: &str =
     "Bug in nuts. It should be impossible to trigger this panic through any combinations of library calls.";
@@ -32,6 +55,8 @@ pub(crate) const IMPOSSIBLE_ERR_MSG: &str =
 /// To allow nested access to the nut, it is a read-only structure.
 /// The field of it can be accessed separately. The library is designed carefully to
 /// ensure single-write/multiple-reader is enforced at all times.
+///
+/// When adding fields, also update `Nut::reset`.
 #[derive(Default)]
 struct Nut {
     /// Stores the data for activities, the semi-isolated components of this library.
@@ -47,10 +72,13 @@ struct Nut {
     subscriptions: Subscriptions,
     /// FIFO queue for published messages and other events that cannot be processed immediately.
     /// Atomically accessed mutably between closure dispatches.
-    deferred_events: ThreadLocalFifo<Deferred>,
+    deferred_events: ThreadLocalFifo<QueuedEvent>,
+    /// Responses blocked by the deferred event that is currently executing.
+    /// Events queued during that time inherit them, see `push_deferred`.
+    current_barriers: RefCell<Vec<ResponseSlot>>,
     /// Tracks awaited responses, which are pending futures.
-    /// Used when creating new futures (NutsResponse) and when polling the same.
-    /// Atomically accessed in with_response_tracker_mut() only.
+    /// Used when creating new futures (`NutsResponse`) and when polling the same.
+    /// Atomically accessed in `with_response_tracker_mut()` only.
     response_tracker: RefCell<ResponseTracker>,
     /// A flag that marks if a broadcast is currently on-going
     executing: AtomicBool,
@@ -60,22 +88,219 @@ struct Nut {
     /// (Note: Adding subscriptions does not require additional structure because they will
     /// be queued and only executed after the activity is available anyway)
     inchoate_activities: RefCell<InchoateActivityContainer>,
+    /// Delivery statistics of private messages
+    mailboxes: RefCell<Mailboxes>,
+    /// Messages sent from other threads through a `NutsProxy`, only present after the first proxy has been created.
+    remote_inbox: RefCell<Option<RemoteInbox>>,
+    /// Set once `init_with` has been called
+    initialized: std::cell::Cell<bool>,
+    /// Message counters for `NutsHeartbeat`, only present when enabled.
+    heartbeat: RefCell<Option<Heartbeat>>,
+    /// Receives trace events, only present when enabled.
+    tracer: RefCell<Option<Tracer>>,
+    /// Limits on deliveries per topic and pump, and the messages postponed because of them
+    topic_budgets: RefCell<TopicBudgets>,
+    /// Limit on the length of `deferred_events`, set with `nuts::set_queue_policy`
+    queue_policy: std::cell::Cell<Option<QueuePolicy>>,
+    /// Set with `nuts::set_execution_budget`
+    execution_budget: std::cell::Cell<Option<exec::time_slice::ExecutionBudget>>,
+    /// The budget of the ongoing catch-up, until it has been used up
+    time_slice: std::cell::Cell<Option<exec::time_slice::TimeSlice>>,
+    /// Frame counter, advanced by `nuts::tick`
+    tick: std::cell::Cell<u64>,
+    /// Set by `nuts::exit_main_loop`
+    exit_requested: std::cell::Cell<bool>,
+    /// Number of delayed domain stores per domain and type, see `DomainState::pending`
+    pending_stores: RefCell<HashMap<(DomainId, TypeId), usize>>,
+    /// Subscription groups turned off with `nuts::set_tag_enabled`
+    disabled_tags: RefCell<HashSet<String>>,
+    /// Low-priority handlers registered with `nuts::on_idle`
+    idle_handlers: RefCell<Vec<Rc<dyn Fn()>>>,
+    /// Called before each broadcast, registered with `nuts::add_interceptor`
+    interceptors: RefCell<Vec<iac::publish::Interceptor>>,
+    /// Recordable message types and the recording started with `nuts::start_recording`
+    recorder: RefCell<iac::publish::Recorder>,
+    /// Latest messages of the topics configured with `nuts::retain_last`
+    replay_buffers: RefCell<iac::publish::ReplayBuffers>,
+    /// Set with `nuts::set_panic_policy`
+    panic_policy: std::cell::Cell<PanicPolicy>,
+    /// Published messages without receiver, of the types configured with `nuts::keep_undelivered`
+    undelivered: RefCell<iac::publish::UndeliveredMessages>,
+    /// Boxes of delivered messages, reused by `nuts::publish_copy`
+    message_pool: RefCell<iac::publish::MessagePool>,
+    /// Message types registered with `nuts::register_trait`
+    trait_registry: RefCell<iac::trait_topic::TraitRegistry>,
+    /// Messages published with `nuts::publish_when`, waiting for their condition
+    watch_list: RefCell<exec::watch::WatchList>,
+    /// Messages published with `nuts::publish_phased`, waiting for their phase
+    phases: RefCell<exec::phase::PhaseQueues>,
+    /// Messages held for activities with status `LifecycleStatus::Buffering`
+    activity_buffers: RefCell<iac::publish::ActivityBuffers>,
+    /// Restart policies registered with `ActivityId::supervise`
+    supervisions: RefCell<activity::Supervisions>,
+    /// Stable names of activities registered with `nuts::new_activity_keyed`
+    activity_keys: RefCell<activity::ActivityKeys>,
+    /// App-global objects registered with `nuts::singleton`
+    singletons: RefCell<singleton::Singletons>,
+    /// Domain value types registered with `nuts::register_serializable`
+    #[cfg(feature = "serde")]
+    serializable_types: RefCell<snapshot::SerializableTypes>,
+    /// Message types and the connection of `nuts::connect_worker`
+    #[cfg(feature = "web-worker")]
+    bridge: RefCell<worker::Bridge>,
+    /// Description of the deferred event that is executing right now, for diagnostics
+    #[cfg(debug_assertions)]
+    executing_event: RefCell<Option<String>>,
+    /// Message types delivered most recently, for `nuts::panic_context`
+    #[cfg(debug_assertions)]
+    recent_topics: RefCell<diagnostics::RecentTopics>,
+    /// Cumulative statistics returned by `nuts::metrics`
+    #[cfg(feature = "metrics")]
+    metrics: RefCell<exec::metrics::NutsMetrics>,
+    /// Set with `nuts::test_support::set_stepping`, keeps queued events until they are stepped through
+    #[cfg(any(test, feature = "test-utils"))]
+    stepping: std::cell::Cell<bool>,
+    /// Futures spawned with `nuts::spawn`
+    #[cfg(feature = "wasm-futures")]
+    spawned: RefCell<exec::spawn::Tasks>,
     /// For debugging messages
     #[allow(dead_code)]
     active_activity_name: std::cell::Cell<Option<DebugTypeName>>,
+    /// The bus this nut belongs to, all activity ids created here are tagged with it.
+    /// A reset assigns a fresh one, so ids from before the reset are recognized as stale.
+    bus: Cell<bus::BusId>,
 }
 
 /// A method that can be called by the `ActivityManager`.
 /// These handlers are created by the library and not part of the public interface.
 pub(crate) type Handler = Box<dyn Fn(&mut ActivityContainer, &mut ManagedState)>;
+/// Like a `Handler` but only called once.
+pub(crate) type OnceHandler = Box<dyn FnOnce(&mut ActivityContainer, &mut ManagedState)>;
 
 impl Nut {
-    fn new() -> Self {
+    fn new(bus: bus::BusId) -> Self {
         Self {
-            activities: RefCell::new(ActivityContainer::new()),
+            activities: RefCell::new(ActivityContainer::new(bus)),
+            inchoate_activities: RefCell::new(InchoateActivityContainer::new(bus)),
+            bus: Cell::new(bus),
             ..Default::default()
         }
     }
+    /// Drops all state and starts over with a fresh nut.
+    fn reset(&self) {
+        // Destructure to make sure no field is forgotten
+        let Nut {
+            activities,
+            managed_state,
+            subscriptions,
+            deferred_events,
+            current_barriers,
+            response_tracker,
+            executing,
+            inchoate_activities,
+            mailboxes,
+            remote_inbox,
+            initialized,
+            heartbeat,
+            tracer,
+            topic_budgets,
+            queue_policy,
+            execution_budget,
+            time_slice,
+            tick,
+            exit_requested,
+            pending_stores,
+            disabled_tags,
+            idle_handlers,
+            interceptors,
+            recorder,
+            replay_buffers,
+            panic_policy,
+            undelivered,
+            message_pool,
+            trait_registry,
+            watch_list,
+            phases,
+            activity_buffers,
+            supervisions,
+            activity_keys,
+            singletons,
+            #[cfg(feature = "serde")]
+            serializable_types,
+            #[cfg(feature = "web-worker")]
+            bridge,
+            #[cfg(debug_assertions)]
+            executing_event,
+            #[cfg(debug_assertions)]
+            recent_topics,
+            #[cfg(feature = "metrics")]
+            metrics,
+            #[cfg(any(test, feature = "test-utils"))]
+            stepping,
+            #[cfg(feature = "wasm-futures")]
+            spawned,
+            active_activity_name,
+            bus,
+        } = self;
+        bus.set(bus::BusId::next());
+        // Old values are dropped only after the borrow has been released
+        activities.replace(ActivityContainer::new(bus.get()));
+        managed_state.take();
+        subscriptions.clear();
+        deferred_events.clear();
+        current_barriers.take();
+        let old_responses = response_tracker.take();
+        *response_tracker.borrow_mut() = old_responses.successor();
+        drop(old_responses);
+        executing.store(false, std::sync::atomic::Ordering::Relaxed);
+        inchoate_activities.replace(InchoateActivityContainer::new(bus.get()));
+        mailboxes.take();
+        remote_inbox.take();
+        initialized.set(false);
+        heartbeat.take();
+        tracer.take();
+        topic_budgets.take();
+        queue_policy.set(None);
+        execution_budget.set(None);
+        time_slice.set(None);
+        tick.set(0);
+        exit_requested.set(false);
+        pending_stores.take();
+        disabled_tags.take();
+        idle_handlers.take();
+        interceptors.take();
+        recorder.take();
+        replay_buffers.take();
+        panic_policy.take();
+        undelivered.take();
+        message_pool.take();
+        trait_registry.take();
+        watch_list.take();
+        phases.take();
+        activity_buffers.take();
+        supervisions.take();
+        activity_keys.take();
+        singletons.take();
+        #[cfg(feature = "serde")]
+        serializable_types.take();
+        #[cfg(feature = "web-worker")]
+        bridge.take();
+        #[cfg(debug_assertions)]
+        executing_event.take();
+        #[cfg(debug_assertions)]
+        recent_topics.take();
+        #[cfg(feature = "metrics")]
+        metrics.take();
+        #[cfg(any(test, feature = "test-utils"))]
+        stepping.set(false);
+        #[cfg(feature = "wasm-futures")]
+        spawned.take();
+        active_activity_name.set(None);
+    }
+    /// Panics if the id has been created on another bus.
+    fn check_bus(&self, id: UncheckedActivityId) {
+        assert_eq!(self.bus.get(), id.bus, "{}", bus::STALE_ID_ERR_MSG);
+    }
     fn quiescent(&self) -> bool {
         !self.executing.load(std::sync::atomic::Ordering::Relaxed)
     }
@@ -83,26 +308,30 @@ impl Nut {
         if self.quiescent() {
             self.activities
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
+                .or_report()
                 .add_on_delete(id, subscription);
         } else {
-            self.deferred_events
-                .push(Deferred::OnDeleteSubscription(id, subscription))
+            self.push_deferred(Deferred::OnDeleteSubscription(id, subscription))
         }
     }
     pub(crate) fn with_response_tracker_mut<T>(f: impl FnOnce(&mut ResponseTracker) -> T) -> T {
         NUT.with(|nut| {
-            let mut response_tracker = nut
-                .response_tracker
-                .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG);
-            f(&mut *response_tracker)
+            let mut response_tracker = nut.response_tracker.try_borrow_mut().or_report();
+            f(&mut response_tracker)
         })
     }
+    /// Same as `with_response_tracker_mut` but does nothing if the nut has already been destroyed. (Used in `Drop` implementations)
+    pub(crate) fn try_with_response_tracker_mut(f: impl FnOnce(&mut ResponseTracker)) {
+        let _ = NUT.try_with(|nut| {
+            if let Ok(mut response_tracker) = nut.response_tracker.try_borrow_mut() {
+                f(&mut response_tracker)
+            }
+        });
+    }
 }
 
 pub(crate) fn new_activity<A>(
-    activity: A,
+    activity: NewActivity<A>,
     domain_index: DomainId,
     status: LifecycleStatus,
 ) -> ActivityId<A>
@@ -119,42 +348,392 @@ where
             // On the other hand, performance of creating new activities is only secondary priority.
             nut.managed_state
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
+                .or_report()
                 .prepare(domain_index);
             // Make sure that length of activities is available without locking activities.
             // Again, a bit ugly but performance is secondary in this call.
             nut.inchoate_activities
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
+                .or_report()
                 .inc_offset();
             nut.activities
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
+                .or_report()
                 .add(activity, domain_index, status)
         } else {
-            nut.deferred_events.push(Deferred::FlushInchoateActivities);
+            nut.push_deferred(Deferred::FlushInchoateActivities);
             let a = nut
                 .inchoate_activities
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
+                .or_report()
                 .add(activity, domain_index, status);
             #[cfg(feature = "verbose-debug-log")]
             #[cfg(debug_assertions)]
             debug_print!(
                 "Added activity as inchoate. ID = {} ({} complete activities, {} inchoate activities exist)", 
                 a.id.index,
-                nut.inchoate_activities.try_borrow().expect(IMPOSSIBLE_ERR_MSG).offset(),
-                nut.inchoate_activities.try_borrow().expect(IMPOSSIBLE_ERR_MSG).len() - 1
+                nut.inchoate_activities.try_borrow().or_report().offset(),
+                nut.inchoate_activities.try_borrow().or_report().len() - 1
             );
             a
         }
     })
 }
 
+pub(crate) fn init_with<R>(f: impl FnOnce(&mut init::Setup) -> R) -> R {
+    NUT.with(|nut| {
+        assert!(
+            nut.quiescent(),
+            "nuts::init_with cannot be called inside subscription handlers."
+        );
+        assert!(
+            !nut.initialized.replace(true),
+            "nuts::init_with has been called twice."
+        );
+    });
+    f(&mut init::Setup::new())
+}
+
+pub(crate) fn reset() {
+    NUT.with(|nut| {
+        assert!(
+            nut.quiescent(),
+            "nuts::reset cannot be called inside subscription handlers."
+        );
+        nut.reset();
+    })
+}
+
+pub(crate) fn shutdown() {
+    NUT.with(|nut| {
+        assert!(
+            nut.quiescent(),
+            "nuts::shutdown cannot be called inside subscription handlers."
+        );
+        nut.delete_all_activities();
+        nut.reset();
+    })
+}
+
+pub(crate) fn is_initialized() -> bool {
+    NUT.with(|nut| nut.initialized.get())
+}
+
+pub(crate) fn tick() -> u64 {
+    NUT.with(|nut| {
+        let tick = nut.tick.get() + 1;
+        nut.tick.set(tick);
+        tick
+    })
+}
+
+pub(crate) fn current_tick() -> u64 {
+    NUT.with(|nut| nut.tick.get())
+}
+
+pub(crate) fn request_exit() {
+    NUT.with(|nut| nut.exit_requested.set(true))
+}
+
+pub(crate) fn take_exit_request() -> bool {
+    NUT.with(|nut| nut.exit_requested.take())
+}
+
+pub(crate) fn inspect() -> Option<NutsInspection> {
+    NUT.with(|nut| nut.inspect())
+}
+
+pub(crate) fn subscribers_of<MSG: Any>() -> Option<Vec<ActivityInspection>> {
+    NUT.with(|nut| {
+        nut.subscribers_of(
+            &Topic::public_message::<MSG>(),
+            &Topic::private_message::<MSG>(),
+        )
+    })
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn metrics() -> exec::metrics::NutsMetrics {
+    NUT.with(|nut| nut.metrics.try_borrow().or_report().clone())
+}
+
+pub(crate) fn keep_undelivered<MSG: Any>() {
+    NUT.with(|nut| nut.undelivered.try_borrow_mut().or_report().keep::<MSG>())
+}
+
+pub(crate) fn retain_last<MSG: Any + Clone>(n: usize) {
+    NUT.with(|nut| {
+        nut.replay_buffers
+            .try_borrow_mut()
+            .or_report()
+            .retain_last::<MSG>(n)
+    })
+}
+
+pub(crate) fn make_bufferable<MSG: Any + Clone>() {
+    NUT.with(|nut| {
+        nut.activity_buffers
+            .try_borrow_mut()
+            .or_report()
+            .make_bufferable::<MSG>()
+    })
+}
+
+pub(crate) fn set_buffer_capacity(id: UncheckedActivityId, capacity: usize) {
+    NUT.with_bus(id.bus, |nut| {
+        nut.activity_buffers
+            .try_borrow_mut()
+            .or_report()
+            .set_capacity(id, capacity)
+    })
+}
+
+pub(crate) fn add_interceptor(
+    f: impl Fn(&MessageMeta, &mut dyn Any) -> InterceptDecision + 'static,
+) {
+    NUT.with(|nut| {
+        nut.interceptors
+            .try_borrow_mut()
+            .or_report()
+            .push(Rc::new(f))
+    })
+}
+
+pub(crate) fn record_type<MSG: Any + Clone>() {
+    NUT.with(|nut| nut.recorder.try_borrow_mut().or_report().register::<MSG>())
+}
+
+pub(crate) fn start_recording() {
+    NUT.with(|nut| nut.recorder.try_borrow_mut().or_report().start())
+}
+
+pub(crate) fn stop_recording() -> Option<Recording> {
+    NUT.with(|nut| nut.recorder.try_borrow_mut().or_report().stop())
+}
+
+pub(crate) fn replay(recording: &Recording) {
+    NUT.with(|nut| nut.replay(recording))
+}
+
+pub(crate) fn set_tag_enabled(tag: &str, enabled: bool) {
+    NUT.with(|nut| {
+        let mut disabled = nut.disabled_tags.try_borrow_mut().or_report();
+        if enabled {
+            disabled.remove(tag);
+        } else {
+            disabled.insert(tag.to_owned());
+        }
+    })
+}
+
+pub(crate) fn set_execution_budget(budget: Option<exec::time_slice::ExecutionBudget>) {
+    NUT.with(|nut| nut.execution_budget.set(budget))
+}
+
+pub(crate) fn set_topic_order<MSG: Any>(order: DeliveryOrder) {
+    NUT.with(|nut| nut.set_delivery_order(Topic::public_message::<MSG>(), order))
+}
+
+pub(crate) fn on_idle(f: impl Fn() + 'static) {
+    NUT.with(|nut| {
+        nut.idle_handlers
+            .try_borrow_mut()
+            .or_report()
+            .push(Rc::new(f))
+    })
+}
+
+pub(crate) fn run_idle() -> bool {
+    let handlers = NUT.with(|nut| {
+        if nut.quiescent() && nut.deferred_events.is_empty() {
+            Some(nut.idle_handlers.try_borrow().or_report().clone())
+        } else {
+            None
+        }
+    });
+    // Called outside of `NUT.with`, handlers usually publish messages
+    match handlers {
+        Some(handlers) => {
+            for f in handlers {
+                f();
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Queues an event and processes it immediately if nuts is idle.
+pub(crate) fn push_deferred(event: Deferred) {
+    NUT.with(|nut| {
+        nut.push_deferred(event);
+        nut.catch_up_deferred_to_quiescence();
+    })
+}
+
 pub(crate) fn publish_custom<MSG: Any>(a: MSG) {
     NUT.with(|nut| nut.broadcast(BroadcastInfo::global(a, Topic::public_message::<MSG>())))
 }
 
+pub(crate) fn publish_copy<MSG: Any + Copy>(msg: MSG) {
+    NUT.with(|nut| {
+        let boxed = nut.message_pool.try_borrow_mut().or_report().boxed(msg);
+        nut.broadcast(BroadcastInfo::global_boxed::<MSG>(
+            boxed,
+            Topic::public_message::<MSG>(),
+        ))
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn pooled_boxes<MSG: Any>() -> usize {
+    NUT.with(|nut| {
+        nut.message_pool
+            .try_borrow()
+            .or_report()
+            .free_boxes::<MSG>()
+    })
+}
+
+pub(crate) fn publish_with_priority<MSG: Any>(a: MSG, priority: Priority) {
+    NUT.with(|nut| {
+        nut.broadcast_with_priority(
+            BroadcastInfo::global(a, Topic::public_message::<MSG>()),
+            priority,
+        )
+    })
+}
+
+pub(crate) fn publish_parallel_ready<MSG: Any>(a: MSG) {
+    NUT.with(|nut| {
+        nut.broadcast(BroadcastInfo::global(a, Topic::public_message::<MSG>()).parallel_ready())
+    })
+}
+
+pub(crate) fn publish_sync<MSG: Any>(a: MSG) -> Result<(), ExecError> {
+    NUT.with(|nut| {
+        if !nut.quiescent() {
+            return Err(ExecError::InsideHandler);
+        }
+        #[cfg(any(test, feature = "test-utils"))]
+        if nut.stepping.get() {
+            return Err(ExecError::Stepping);
+        }
+        if !nut.deferred_events.is_empty() {
+            return Err(ExecError::EventsPending);
+        }
+        nut.broadcast(BroadcastInfo::global(a, Topic::public_message::<MSG>()));
+        Ok(())
+    })
+}
+
+pub(crate) fn try_publish_custom<MSG: Any>(a: MSG) -> Result<(), PublishError> {
+    NUT.with(|nut| {
+        nut.check_publish::<MSG>()?;
+        nut.broadcast(BroadcastInfo::global(a, Topic::public_message::<MSG>()));
+        Ok(())
+    })
+}
+
+pub(crate) fn publish_to_domain<MSG: Any>(domain: DomainId, msg: MSG) {
+    NUT.with(|nut| {
+        nut.broadcast(BroadcastInfo::domain(
+            msg,
+            domain,
+            Topic::public_message::<MSG>(),
+        ))
+    })
+}
+
+pub(crate) fn publish_to_entity<MSG: Any>(entity: EntityId, msg: MSG) {
+    NUT.with(|nut| {
+        nut.broadcast(BroadcastInfo::global(
+            msg,
+            Topic::entity_message::<MSG>(entity),
+        ))
+    })
+}
+
+#[cfg(feature = "dynamic-messages")]
+pub(crate) fn publish_dynamic(name: &str, value: serde_json::Value) {
+    if let Some(topic) = Topic::published_dynamic_message(name) {
+        NUT.with(|nut| nut.broadcast(BroadcastInfo::global(value, topic)))
+    }
+}
+
+pub(crate) fn drain_topic<MSG: Any>() -> Vec<MSG> {
+    NUT.with(|nut| nut.drain_topic())
+}
+
+pub(crate) fn publish_iter<MSG: Any>(msgs: impl IntoIterator<Item = MSG>) {
+    NUT.with(|nut| nut.publish_iter(msgs))
+}
+
+pub(crate) fn remote_handle() -> NutsProxy {
+    NUT.with(|nut| nut.remote_handle())
+}
+
+pub(crate) fn set_topic_budget<MSG: Any>(limit: Option<usize>) {
+    NUT.with(|nut| {
+        nut.topic_budgets
+            .try_borrow_mut()
+            .or_report()
+            .set(Topic::public_message::<MSG>(), limit)
+    })
+}
+
+pub(crate) fn publish_phased<MSG: Any>(phase: Phase, msg: MSG) {
+    NUT.with(|nut| {
+        let broadcast = BroadcastInfo::global(msg, Topic::public_message::<MSG>()).in_phase(phase);
+        nut.accept(&broadcast, false);
+        nut.phases
+            .try_borrow_mut()
+            .or_report()
+            .hold(phase, broadcast);
+        nut.catch_up_deferred_to_quiescence();
+    })
+}
+
+pub(crate) fn publish_when<MSG: Any>(
+    domain: DomainId,
+    predicate: impl Fn(&DomainState) -> bool + 'static,
+    msg: MSG,
+) {
+    NUT.with(|nut| {
+        let broadcast = BroadcastInfo::global(msg, Topic::public_message::<MSG>());
+        nut.accept(&broadcast, false);
+        nut.watch_list
+            .try_borrow_mut()
+            .or_report()
+            .hold(domain, Box::new(predicate), broadcast);
+        nut.catch_up_deferred_to_quiescence();
+    })
+}
+
+pub(crate) fn set_phase_order(order: &[Phase]) {
+    NUT.with(|nut| nut.phases.try_borrow_mut().or_report().set_order(order))
+}
+
+pub(crate) fn set_panic_policy(policy: PanicPolicy) {
+    NUT.with(|nut| nut.panic_policy.set(policy))
+}
+
+pub(crate) fn set_queue_policy(policy: Option<QueuePolicy>) {
+    NUT.with(|nut| nut.queue_policy.set(policy))
+}
+
+pub(crate) fn pump() {
+    NUT.with(|nut| nut.catch_up_deferred_to_quiescence())
+}
+
+pub(crate) fn drain_remote() {
+    NUT.with(|nut| {
+        if nut.quiescent() && nut.queue_remote_messages() {
+            nut.catch_up_deferred_to_quiescence();
+        }
+    })
+}
+
 pub(crate) fn send_custom<RECV: Any, MSG: Any>(a: MSG) {
     NUT.with(|nut| {
         nut.broadcast(BroadcastInfo::local_by_type::<RECV, MSG>(
@@ -164,8 +743,36 @@ pub(crate) fn send_custom<RECV: Any, MSG: Any>(a: MSG) {
     })
 }
 
-pub(crate) fn send_custom_by_id<MSG: Any>(msg: MSG, id: UncheckedActivityId) {
+pub(crate) fn try_send_custom<RECV: Any, MSG: Any>(a: MSG) -> Result<(), PublishError> {
+    NUT.with(|nut| {
+        nut.check_send::<RECV, MSG>()?;
+        nut.broadcast(BroadcastInfo::local_by_type::<RECV, MSG>(
+            a,
+            Topic::private_message::<MSG>(),
+        ));
+        Ok(())
+    })
+}
+
+pub(crate) fn instances_of<A: Activity>() -> Option<Vec<UncheckedActivityId>> {
     NUT.with(|nut| {
+        let activities = nut.activities.try_borrow().ok()?;
+        Some(activities.instances(TypeId::of::<A>()).collect())
+    })
+}
+
+pub(crate) fn send_custom_keyed<RECV: Any, MSG: Any>(key: impl Hash + Eq + Any, a: MSG) {
+    NUT.with(|nut| {
+        nut.broadcast(BroadcastInfo::local_by_type::<RECV, MSG>(
+            a,
+            Topic::keyed_private_message::<MSG>(key),
+        ))
+    })
+}
+
+pub(crate) fn send_custom_by_id<MSG: Any>(msg: MSG, id: UncheckedActivityId) {
+    NUT.with_bus(id.bus, |nut| {
+        nut.check_bus(id);
         nut.broadcast(BroadcastInfo::local(
             msg,
             id,
@@ -178,7 +785,31 @@ pub(crate) async fn publish_custom_and_await<A: Any>(a: A) {
     NUT.with(move |nut| nut.publish_and_await(a)).await;
 }
 
-pub(crate) fn register_no_activity<F, MSG>(f: F)
+pub(crate) async fn request<MSG: Any, REPLY: Any>(msg: MSG) -> Option<REPLY> {
+    let reply = NUT.with(move |nut| nut.request(msg)).await?;
+    reply.downcast().ok().map(|reply| *reply)
+}
+
+pub(crate) fn request_from<MSG: Any, REPLY: Any>(
+    msg: MSG,
+    id: UncheckedActivityId,
+    timeout: Option<u64>,
+) -> impl Future<Output = Result<REPLY, RequestError>> {
+    // The request is sent right away, not on the first poll
+    let mut response = NUT.with_bus(id.bus, move |nut| nut.request_from(msg, id, timeout));
+    async move {
+        let reply = (&mut response).await;
+        if response.timed_out() {
+            return Err(RequestError::Timeout);
+        }
+        reply
+            .and_then(|reply| reply.downcast().ok())
+            .map(|reply| *reply)
+            .ok_or(RequestError::NoReply)
+    }
+}
+
+pub(crate) fn register_no_activity<F, MSG>(f: F, filter: SubscriptionFilter) -> SubscriptionId
 where
     F: Fn(&MSG) + 'static,
     MSG: Any,
@@ -186,45 +817,353 @@ where
     NUT.with(|nut| {
         let closure = ManagedState::pack_closure_no_activity::<_, MSG>(f);
         let topic = Topic::public_message::<MSG>();
-        let id = NotAnActivity::id();
-        nut.push_closure(topic, id, closure);
-    });
+        let id = NotAnActivity::id(nut.bus.get());
+        nut.push_closure(topic, id, closure, filter.into_options())
+    })
+}
+/// The subscription removes itself when it receives a message after the guard has been dropped.
+pub(crate) fn register_weak<G, F, MSG>(guard: &Rc<G>, f: F) -> SubscriptionId
+where
+    G: 'static,
+    F: Fn(&G, &MSG) + 'static,
+    MSG: Any,
+{
+    let guard = Rc::downgrade(guard);
+    let own_id: Rc<RefCell<Option<SubscriptionId>>> = Default::default();
+    let own_id_in_handler = own_id.clone();
+    let handler = move |msg: &MSG| match guard.upgrade() {
+        Some(guard) => f(&guard, msg),
+        None => {
+            if let Some(id) = own_id_in_handler.borrow_mut().take() {
+                unsubscribe(id);
+            }
+        }
+    };
+    let id = register_no_activity(handler, SubscriptionFilter::default());
+    *own_id.borrow_mut() = Some(id.clone());
+    id
+}
+pub(crate) fn register_any<F>(f: F) -> SubscriptionId
+where
+    F: Fn(&MessageMeta, &dyn Any) + 'static,
+{
+    NUT.with(|nut| {
+        let closure = ManagedState::pack_closure_any(f);
+        nut.push_closure(
+            Topic::Wildcard,
+            NotAnActivity::id(nut.bus.get()),
+            closure,
+            Default::default(),
+        )
+    })
 }
-pub(crate) fn register<A, F, MSG>(id: ActivityId<A>, f: F, filter: SubscriptionFilter)
+pub(crate) fn register<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
 where
     A: Activity,
     F: Fn(&mut A, &MSG) + 'static,
     MSG: Any,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_closure::<_, _, MSG>(f, id, filter);
         let topic = Topic::public_message::<MSG>();
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_entity<A, F, MSG>(
+    id: ActivityId<A>,
+    entity: EntityId,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &MSG) + 'static,
+    MSG: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure::<_, _, MSG>(f, id, filter);
+        let topic = Topic::entity_message::<MSG>(entity);
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+#[cfg(feature = "dynamic-messages")]
+pub(crate) fn register_dynamic<A, F>(
+    id: ActivityId<A>,
+    name: &str,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &serde_json::Value) + 'static,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure::<_, _, serde_json::Value>(f, id, filter);
+        nut.push_closure(Topic::dynamic_message(name), id, closure, options)
+    })
+}
+/// Registration state of a one-shot subscription, shared with its handler so it can remove itself.
+#[derive(Default)]
+enum OnceState {
+    #[default]
+    Pending,
+    Registered(SubscriptionId),
+    Fired,
+}
+type OnceSlot = Rc<RefCell<OnceState>>;
+
+/// Called by the handler of a one-shot subscription after its first call.
+fn once_fired(slot: &OnceSlot) {
+    if let OnceState::Registered(id) = slot.replace(OnceState::Fired) {
+        unsubscribe(id);
+    }
+}
+/// Called after a one-shot subscription has been registered, the handler may have fired already.
+fn once_registered(slot: &OnceSlot, id: &SubscriptionId) {
+    let fired = matches!(*slot.borrow(), OnceState::Fired);
+    if fired {
+        unsubscribe(id.clone());
+    } else {
+        *slot.borrow_mut() = OnceState::Registered(id.clone());
+    }
+}
+pub(crate) fn register_once<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: FnOnce(&mut A, &MSG) + 'static,
+    MSG: Any,
+{
+    let slot = OnceSlot::default();
+    let handler_slot = slot.clone();
+    let f = std::cell::Cell::new(Some(f));
+    let sub = register(
+        id,
+        move |a: &mut A, msg: &MSG| {
+            if let Some(f) = f.take() {
+                f(a, msg);
+                once_fired(&handler_slot);
+            }
+        },
+        filter,
+    );
+    once_registered(&slot, &sub);
+    sub
+}
+pub(crate) fn register_domained_once<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: FnOnce(&mut A, &mut DomainState, &MSG) + 'static,
+    MSG: Any,
+{
+    let slot = OnceSlot::default();
+    let handler_slot = slot.clone();
+    let f = std::cell::Cell::new(Some(f));
+    let sub = register_domained(
+        id,
+        move |a: &mut A, domain: &mut DomainState, msg: &MSG| {
+            if let Some(f) = f.take() {
+                f(a, domain, msg);
+                once_fired(&handler_slot);
+            }
+        },
+        filter,
+    );
+    once_registered(&slot, &sub);
+    sub
+}
+pub(crate) fn register_filtered<A, F, P, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    predicate: P,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &MSG) + 'static,
+    P: Fn(&MSG) -> bool + 'static,
+    MSG: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure_filtered::<_, _, _, MSG>(f, predicate, id, filter);
+        let topic = Topic::public_message::<MSG>();
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_paired<A, F, G, MSG>(
+    id: ActivityId<A>,
+    active: F,
+    inactive: G,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &MSG) + 'static,
+    G: Fn(&mut A, &MSG) + 'static,
+    MSG: Any,
+{
+    // The lifecycle status selects the handler, so no status filter applies
+    let options = SubscriptionFilter::no_filter().options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure_paired::<_, _, _, MSG>(active, inactive, id);
+        let topic = Topic::public_message::<MSG>();
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_dyn<A, F>(
+    id: ActivityId<A>,
+    type_id: TypeId,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &dyn Any) + 'static,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure_dyn(f, id, filter);
+        let topic = Topic::public_message_dyn(type_id);
+        nut.push_closure(topic, id, closure, options)
+    })
 }
-pub(crate) fn register_mut<A, F, MSG>(id: ActivityId<A>, f: F, filter: SubscriptionFilter)
+pub(crate) fn register_responder<A, F, MSG, REPLY>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &MSG) -> REPLY + 'static,
+    MSG: Any,
+    REPLY: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_responder::<_, _, MSG, REPLY>(f, id, filter);
+        let topic = Topic::public_message::<MSG>();
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_ctx<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut HandlerCtx<A>, &MSG) + 'static,
+    MSG: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure_ctx::<_, _, MSG>(f, id, filter);
+        let topic = Topic::public_message::<MSG>();
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_mut<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
 where
     A: Activity,
     F: Fn(&mut A, &mut MSG) + 'static,
     MSG: Any,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_closure_mut::<_, _, MSG>(f, id, filter);
         let topic = Topic::public_message::<MSG>();
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
 }
-pub(crate) fn register_owned<A, F, MSG>(id: ActivityId<A>, f: F, filter: SubscriptionFilter)
+pub(crate) fn register_owned<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
 where
     A: Activity,
     F: Fn(&mut A, MSG) + 'static,
     MSG: Any,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_closure_owned::<_, _, MSG>(f, id, filter);
         let topic = Topic::private_message::<MSG>();
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_owned_responder<A, F, MSG, REPLY>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, MSG) -> REPLY + 'static,
+    MSG: Any,
+    REPLY: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_owned_responder::<_, _, MSG, REPLY>(f, id, filter);
+        let topic = Topic::private_message::<MSG>();
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+pub(crate) fn register_owned_keyed<A, F, MSG>(
+    id: ActivityId<A>,
+    key: impl Hash + Eq + Any,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, MSG) + 'static,
+    MSG: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure_owned::<_, _, MSG>(f, id, filter);
+        let topic = Topic::keyed_private_message::<MSG>(key);
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+
+/// For subscriptions to builtin events with payload
+pub(crate) fn register_builtin<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    topic: Topic,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
+where
+    A: Activity,
+    F: Fn(&mut A, &MSG) + 'static,
+    MSG: Any,
+{
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = ManagedState::pack_closure::<_, _, MSG>(f, id, filter);
+        nut.push_closure(topic, id, closure, options)
+    })
 }
 
 /// For subscriptions without payload
@@ -233,54 +1172,68 @@ pub(crate) fn register_no_payload<A, F>(
     f: F,
     topic: Topic,
     filter: SubscriptionFilter,
-) where
+) -> SubscriptionId
+where
     A: Activity,
     F: Fn(&mut A) + 'static,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_closure_no_payload(f, id, filter);
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
 }
 
-pub(crate) fn register_domained<A, F, MSG>(id: ActivityId<A>, f: F, filter: SubscriptionFilter)
+pub(crate) fn register_domained<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
 where
     A: Activity,
     F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
     MSG: Any,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_domained_closure(f, id, filter);
         let topic = Topic::public_message::<MSG>();
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
 }
-pub(crate) fn register_domained_mut<A, F, MSG>(id: ActivityId<A>, f: F, filter: SubscriptionFilter)
+pub(crate) fn register_domained_mut<A, F, MSG>(
+    id: ActivityId<A>,
+    f: F,
+    filter: SubscriptionFilter,
+) -> SubscriptionId
 where
     A: Activity,
     F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
     MSG: Any,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_domained_closure_mut(f, id, filter);
         let topic = Topic::public_message::<MSG>();
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
 }
 pub(crate) fn register_domained_owned<A, F, MSG>(
     id: ActivityId<A>,
     f: F,
     filter: SubscriptionFilter,
-) where
+) -> SubscriptionId
+where
     A: Activity,
     F: Fn(&mut A, &mut DomainState, MSG) + 'static,
     MSG: Any,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_domained_closure_owned(f, id, filter);
         let topic = Topic::private_message::<MSG>();
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
 }
 
 /// For subscriptions without payload but with domain access
@@ -289,14 +1242,36 @@ pub(crate) fn register_domained_no_payload<A, F>(
     f: F,
     topic: Topic,
     filter: SubscriptionFilter,
-) where
+) -> SubscriptionId
+where
     A: Activity,
     F: Fn(&mut A, &mut DomainState) + 'static,
 {
-    NUT.with(|nut| {
+    let options = filter.options();
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = ManagedState::pack_closure_domained_no_payload(f, id, filter);
-        nut.push_closure(topic, id, closure);
-    });
+        nut.push_closure(topic, id, closure, options)
+    })
+}
+
+pub(crate) fn register_on_start<A, F>(id: ActivityId<A>, f: F)
+where
+    A: Activity,
+    F: FnOnce(&mut A, Option<&mut DomainState>) + 'static,
+{
+    bus::on_bus(id.id.bus, || {
+        push_deferred(Deferred::ActivityCall(Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                if activities.is_alive(id.into()) {
+                    let domain = activities.domain(id.into());
+                    let a = activities[id]
+                        .downcast_mut::<A>()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    f(a, managed_state.get_mut(domain))
+                }
+            },
+        )));
+    })
 }
 
 pub(crate) fn register_on_delete<A, F>(id: ActivityId<A>, f: F)
@@ -304,7 +1279,7 @@ where
     A: Activity,
     F: FnOnce(A) + 'static,
 {
-    NUT.with(|nut| {
+    NUT.with_bus(id.id.bus, |nut| {
         let closure = Box::new(|a: Box<dyn Any>| {
             let activity = a.downcast().expect(IMPOSSIBLE_ERR_MSG);
             f(*activity);
@@ -318,21 +1293,133 @@ where
     A: Activity,
     F: FnOnce(A, &mut DomainState) + 'static,
 {
-    NUT.with(|nut| {
-        let closure = Box::new(move |a: Box<dyn Any>, managed_state: &mut ManagedState| {
-            let activity = a.downcast().expect(IMPOSSIBLE_ERR_MSG);
-            let domain = managed_state
-                .get_mut(id.domain_index)
-                .expect("missing domain");
-            f(*activity, domain);
-        });
+    NUT.with_bus(id.id.bus, |nut| {
+        let closure = Box::new(
+            move |a: Box<dyn Any>, domain: DomainId, managed_state: &mut ManagedState| {
+                let activity = a.downcast().expect(IMPOSSIBLE_ERR_MSG);
+                let domain = managed_state.get_mut(domain).expect("missing domain");
+                f(*activity, domain);
+            },
+        );
         let subscription = OnDelete::WithDomain(closure);
         nut.add_on_delete(id.into(), subscription);
     })
 }
 
-pub(crate) fn set_status(id: UncheckedActivityId, status: LifecycleStatus) {
-    NUT.with(|nut| nut.set_status(id, status));
+pub(crate) fn unsubscribe(id: SubscriptionId) {
+    NUT.with_bus(id.activity.bus, |nut| {
+        nut.check_bus(id.activity);
+        nut.unsubscribe(id)
+    });
+}
+
+pub(crate) fn current_bus() -> bus::BusId {
+    NUT.with(|nut| nut.bus.get())
+}
+
+pub(crate) fn move_to_domain(id: UncheckedActivityId, domain: DomainId) {
+    NUT.with_bus(id.bus, |nut| nut.check_bus(id));
+    push_deferred(Deferred::ActivityCall(Box::new(
+        move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+            managed_state.prepare(domain);
+            activities.set_domain(id, domain);
+        },
+    )));
+}
+
+pub(crate) fn replace_activity(
+    id: UncheckedActivityId,
+    data: Box<dyn Any>,
+    keep_groups: Vec<String>,
+) {
+    bus::on_bus(id.bus, || {
+        push_deferred(Deferred::ReplaceActivity(id, data, keep_groups));
+    })
+}
+
+pub(crate) fn compact() {
+    push_deferred(Deferred::Compact);
+}
+
+pub(crate) fn supervise(
+    supervisor: UncheckedActivityId,
+    child: UncheckedActivityId,
+    policy: RestartPolicy,
+    factory: activity::RestartFactory,
+) {
+    NUT.with_bus(supervisor.bus, |nut| {
+        nut.supervisions
+            .try_borrow_mut()
+            .or_report()
+            .add(supervisor, child, policy, factory)
+    })
+}
+
+pub(crate) fn request_delete<A: Activity>(id: ActivityId<A>) {
+    bus::on_bus(id.id.bus, || {
+        let vetoed = Rc::new(std::cell::Cell::new(false));
+        publish_custom(AboutToDelete::new(id, vetoed.clone()));
+        push_deferred(Deferred::ActivityCall(Box::new(
+            move |activities: &mut ActivityContainer, _: &mut ManagedState| {
+                let id = id.into();
+                if !vetoed.get() && activities.status(id) != LifecycleStatus::Deleted {
+                    set_status(id, LifecycleStatus::Deleted, TransitionCause::Manual);
+                }
+            },
+        )));
+    })
+}
+
+pub(crate) fn mailbox_stats<A: Any>(id: UncheckedActivityId) -> MailboxStats {
+    NUT.with_bus(id.bus, |nut| nut.mailbox_stats::<A>(id))
+}
+
+pub(crate) fn mailbox_len<A: Any, MSG: Any>(id: UncheckedActivityId) -> usize {
+    NUT.with_bus(id.bus, |nut| nut.mailbox_len::<A, MSG>(id))
+}
+
+pub(crate) fn set_heartbeat(heartbeat: Option<Heartbeat>) {
+    NUT.with(|nut| *nut.heartbeat.try_borrow_mut().or_report() = heartbeat);
+}
+
+/// Modifies the tracer, which is created on demand and removed again once it has nothing attached.
+pub(crate) fn update_tracer(f: impl FnOnce(&mut Tracer)) {
+    NUT.with(|nut| {
+        let mut tracer = nut.tracer.try_borrow_mut().or_report();
+        f(tracer.get_or_insert_with(Default::default));
+        if tracer.as_ref().is_some_and(Tracer::is_idle) {
+            *tracer = None;
+        }
+    })
+}
+
+pub(crate) fn flow_graph() -> FlowGraph {
+    NUT.with(|nut| {
+        let tracer = nut.tracer.try_borrow().or_report();
+        let activities = nut.activities.try_borrow().ok();
+        tracer
+            .as_ref()
+            .and_then(Tracer::flow)
+            .map(|flow| flow.graph(activities.as_deref()))
+            .unwrap_or_default()
+    })
+}
+
+pub(crate) fn set_status(id: UncheckedActivityId, status: LifecycleStatus, cause: TransitionCause) {
+    NUT.with_bus(id.bus, |nut| {
+        nut.check_bus(id);
+        nut.set_status(id, status, cause)
+    });
+}
+
+pub(crate) fn set_group_status(
+    members: &[UncheckedActivityId],
+    status: LifecycleStatus,
+    name: &str,
+) {
+    if let Some(first) = members.first() {
+        NUT.with_bus(first.bus, |nut| nut.set_group_status(members, status, name));
+    }
 }
 
 pub(crate) fn write_domain<D, T>(domain: &D, data: T)
@@ -340,19 +1427,242 @@ where
     D: DomainEnumeration,
     T: core::any::Any,
 {
+    write_domain_by_id(DomainId::new(domain), data)
+}
+
+pub(crate) fn write_domain_by_id<T: core::any::Any>(id: DomainId, data: T) {
     NUT.with(|nut| {
-        let id = DomainId::new(domain);
         if let Ok(mut managed_state) = nut.managed_state.try_borrow_mut() {
             managed_state.prepare(id);
             let storage = managed_state.get_mut(id).expect("No domain");
             storage.store(data);
+            drop(managed_state);
+            if !nut.watch_list.try_borrow().or_report().is_empty() {
+                // The store may fulfill the condition of a held message
+                nut.catch_up_deferred_to_quiescence();
+            }
+        } else {
+            nut.push_domain_store(DomainStoreData::new(id, data), None);
+        }
+    })
+}
+
+pub(crate) fn write_domain_now<D, T>(domain: &D, data: T) -> Result<(), ExecError>
+where
+    D: DomainEnumeration,
+    T: core::any::Any,
+{
+    NUT.with(|nut| {
+        let id = DomainId::new(domain);
+        let mut managed_state = nut
+            .managed_state
+            .try_borrow_mut()
+            .map_err(|_| ExecError::InsideHandler)?;
+        managed_state.prepare(id);
+        managed_state.get_mut(id).expect("No domain").store(data);
+        drop(managed_state);
+        if !nut.watch_list.try_borrow().or_report().is_empty() {
+            // The store may fulfill the condition of a held message
+            nut.catch_up_deferred_to_quiescence();
+        }
+        Ok(())
+    })
+}
+
+pub(crate) fn write_domain_deferred<D, T>(domain: &D, data: T)
+where
+    D: DomainEnumeration,
+    T: core::any::Any,
+{
+    let id = DomainId::new(domain);
+    NUT.with(|nut| {
+        nut.push_domain_store(DomainStoreData::new(id, data), None);
+        nut.catch_up_deferred_to_quiescence();
+    })
+}
+
+pub(crate) fn write_domain_and_notify<T: Any>(id: DomainId, data: T) -> impl Future<Output = ()> {
+    let response = NUT.with(|nut| {
+        let ticket = Nut::with_response_tracker_mut(|rt| rt.allocate());
+        let response = NutsResponse::new(&ticket);
+        nut.push_domain_store(DomainStoreData::new(id, data), Some(ticket));
+        nut.catch_up_deferred_to_quiescence();
+        response
+    });
+    async move {
+        response.await;
+    }
+}
+
+pub(crate) fn pending_domain_stores(domain: DomainId, type_id: TypeId) -> usize {
+    NUT.with(|nut| {
+        nut.pending_stores
+            .try_borrow()
+            .or_report()
+            .get(&(domain, type_id))
+            .copied()
+            .unwrap_or(0)
+    })
+}
+
+/// Number of domain values with stores in the queue
+#[cfg(test)]
+pub(crate) fn pending_store_entries() -> usize {
+    NUT.with(|nut| nut.pending_stores.try_borrow().or_report().len())
+}
+
+pub(crate) fn mutate_domain<T, R, F>(id: DomainId, f: F) -> impl Future<Output = Option<R>>
+where
+    T: Any,
+    R: Any,
+    F: FnOnce(&mut T) -> R + 'static,
+{
+    let response = NUT.with(|nut| {
+        let ticket = Nut::with_response_tracker_mut(|rt| rt.allocate());
+        let response = NutsResponse::new(&ticket);
+        let event = Deferred::ActivityCall(Box::new(
+            move |_: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                managed_state.prepare(id);
+                let value = managed_state
+                    .get_mut(id)
+                    .and_then(DomainState::try_get_mut::<T>);
+                if let Some(value) = value {
+                    let result = f(value);
+                    Nut::with_response_tracker_mut(|rt| rt.set_reply(ticket, Box::new(result)));
+                }
+            },
+        ));
+        nut.push_deferred_with_barrier(event, ticket);
+        nut.catch_up_deferred_to_quiescence();
+        response
+    });
+    async move {
+        let result = response.await?;
+        result.downcast().ok().map(|result| *result)
+    }
+}
+
+pub(crate) fn read_activity<A, R, F>(f: F) -> Option<R>
+where
+    A: Activity,
+    F: FnOnce(&A) -> R,
+{
+    NUT.with(|nut| {
+        // Mutable access, to construct lazy activities
+        let mut activities = nut.activities.try_borrow_mut().ok()?;
+        let id = activities.id_lookup(TypeId::of::<A>())?;
+        let activity = activities.get_mut(id)?.downcast_ref()?;
+        Some(f(activity))
+    })
+}
+
+/// Gives exclusive access to the activities, which is impossible while nuts is executing.
+/// Events queued meanwhile are executed afterwards.
+fn with_activities_exclusive<R>(
+    f: impl FnOnce(&mut ActivityContainer) -> Option<R>,
+) -> Result<R, ExecError> {
+    NUT.with(|nut| {
+        if nut
+            .executing
+            .swap(true, std::sync::atomic::Ordering::Relaxed)
+        {
+            return Err(ExecError::InsideHandler);
+        }
+        let _guard = ExclusiveAccess(nut);
+        let result = f(&mut nut.activities.try_borrow_mut().or_report());
+        result.ok_or(ExecError::MissingActivity)
+    })
+}
+
+/// Ends the exclusive access of `with_activities_exclusive`, also if the closure panics.
+struct ExclusiveAccess<'a>(&'a Nut);
+
+impl Drop for ExclusiveAccess<'_> {
+    fn drop(&mut self) {
+        self.0
+            .executing
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        // While unwinding, queued events wait for the next call to nuts instead
+        if !std::thread::panicking() {
+            self.0.catch_up_deferred_to_quiescence();
+        }
+    }
+}
+
+pub(crate) fn borrow_scope<A, R>(
+    id: ActivityId<A>,
+    f: impl FnOnce(&mut ActivityScope<A>) -> R,
+) -> Result<R, ExecError>
+where
+    A: Activity,
+{
+    bus::on_bus(id.id.bus, || {
+        with_activities_exclusive(|activities| {
+            activities
+                .get_mut(id.into())
+                .and_then(|a| a.downcast_mut())
+                .map(|activity| f(&mut ActivityScope::new(activity)))
+        })
+    })
+}
+
+pub(crate) fn swap_activity<A: Activity>(
+    id: ActivityId<A>,
+    new_activity: A,
+) -> Result<A, ExecError> {
+    bus::on_bus(id.id.bus, || {
+        with_activities_exclusive(|activities| {
+            activities
+                .get_mut(id.into())
+                .and_then(|a| a.downcast_mut())
+                .map(|activity| std::mem::replace(activity, new_activity))
+        })
+    })
+}
+
+pub(crate) fn with_activity<A, R>(f: impl FnOnce(&mut A) -> R) -> Result<R, ExecError>
+where
+    A: Activity,
+{
+    with_activities_exclusive(|activities| {
+        activities
+            .id_lookup(TypeId::of::<A>())
+            .and_then(|id| activities.get_mut(id))
+            .and_then(|a| a.downcast_mut())
+            .map(f)
+    })
+}
+
+pub(crate) fn take_from_domain<D, T>(domain: &D) -> Option<T>
+where
+    D: DomainEnumeration,
+    T: core::any::Any,
+{
+    NUT.with(|nut| {
+        let id = DomainId::new(domain);
+        if let Ok(mut managed_state) = nut.managed_state.try_borrow_mut() {
+            managed_state.prepare(id);
+            managed_state.get_mut(id).and_then(DomainState::remove)
         } else {
-            let event = Deferred::DomainStore(DomainStoreData::new(id, data));
-            nut.deferred_events.push(event);
+            let event = Deferred::ActivityCall(Box::new(
+                move |_: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                    managed_state.prepare(id);
+                    if let Some(storage) = managed_state.get_mut(id) {
+                        storage.remove::<T>();
+                    }
+                },
+            ));
+            nut.push_deferred(event);
+            None
         }
     })
 }
 
+#[cfg(debug_assertions)]
+pub(crate) fn panic_context() -> Option<PanicContext> {
+    NUT.try_with(Nut::panic_context).ok()
+}
+
 #[cfg(debug_assertions)]
 pub(crate) fn nuts_panic_info() -> Option<String> {
     NUT.try_with(|nut| {
@@ -1,12 +1,30 @@
 mod activity_container;
+mod builder;
+mod compact;
+mod group;
+pub(crate) mod keys;
 mod lifecycle;
+mod scope;
+mod state_machine;
+mod supervisor;
+mod template;
 
 pub(crate) use activity_container::*;
+pub use builder::ActivityBuilder;
+pub use group::ActivityGroup;
+pub(crate) use keys::ActivityKeys;
 pub use lifecycle::*;
+pub use scope::ActivityScope;
+pub use state_machine::ActivityStateMachine;
+pub use supervisor::RestartPolicy;
+pub(crate) use supervisor::{RestartFactory, Supervisions};
+pub use template::ActivityTemplate;
 
+use crate::nut::bus::BusId;
 use crate::nut::iac::{filter::SubscriptionFilter, managed_state::DomainId};
 use crate::*;
 use core::any::Any;
+use core::hash::Hash;
 use std::ops::{Index, IndexMut};
 
 // @ START-DOC ACTIVITY
@@ -19,24 +37,44 @@ use std::ops::{Index, IndexMut};
 ///
 /// To create an activity, simply register the object that should be used as activity, using `nuts::new_activity` or one of its variants.
 ///
-/// It is important to understand that Activities are uniquely defined by their type.
-/// You cannot create two activities from the same type. (But you can, for example, create a wrapper type around it.)
+/// It is important to understand that Activities are usually identified by their type.
 /// This allows activities to be referenced by their type, which must be known at run-time.
+/// Several activities of the same type can be created, each is then addressed through its own `ActivityId`, or through a key given with `nuts::new_activity_keyed`.
+/// Functions that look up an activity by its type, like `nuts::send_to`, refer to the first instance that is still alive.
 // @ END-DOC ACTIVITY
 pub trait Activity: Any {}
 impl<T: Any> Activity for T {}
 
+/// An activity that registers all its subscriptions at once, with [`ActivityId::register_all`](struct.ActivityId.html#method.register_all).
+///
+/// With the `derive` feature, `#[nuts::subscriber]` on an impl block implements it for all methods in the block that are marked with `#[subscribe]`.
+/// It can also be implemented manually.
+pub trait Subscriber: Activity + Sized {
+    /// Registers the subscriptions of the activity.
+    fn register_all(id: ActivityId<Self>);
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 /// Handle to an `Activity` that has been registered, with a type parameter to track the activity's type.
 /// Can be used to add type-checked closures to the activity, which will be used as event listeners.
 ///
 /// Implements `Copy` and `Clone`
-pub struct ActivityId<A> {
+///
+/// The second type parameter tracks whether the activity has a domain.
+/// Activities created with `nuts::new_domained_activity_checked` have an `ActivityId<A, WithDomain>`,
+/// which offers methods with domain access, such as [`subscribe_domained`](#method.subscribe_domained-1), that cannot fail at runtime.
+pub struct ActivityId<A, D = NoDomain> {
     pub(crate) id: UncheckedActivityId,
-    pub(crate) domain_index: DomainId,
-    phantom: std::marker::PhantomData<A>,
+    phantom: std::marker::PhantomData<(A, D)>,
 }
 
+/// Marker for an [`ActivityId`](struct.ActivityId.html) of an activity that is not known to have a domain.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct NoDomain;
+/// Marker for an [`ActivityId`](struct.ActivityId.html) of an activity that has been registered with a domain.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct WithDomain;
+
 /// This type is used for subscriptions without activity. It is zero sized, hence should be a zero-cost abstraction.
 pub(crate) struct NotAnActivity;
 
@@ -48,35 +86,305 @@ pub(crate) struct NotAnActivity;
 /// Therefore, this id cannot be used to register closures.
 pub struct UncheckedActivityId {
     pub(crate) index: usize,
+    pub(crate) bus: BusId,
 }
 
 impl<A: Activity> ActivityId<A> {
-    pub(crate) fn new(index: usize, domain_index: DomainId) -> Self {
+    pub(crate) fn new(id: UncheckedActivityId) -> Self {
         Self {
-            id: UncheckedActivityId { index },
-            domain_index,
+            id,
             phantom: Default::default(),
         }
     }
-    /// Registers a callback closure that is called when an activity changes from inactive to active.
-    /// Multiple handlers can be registered.
-    pub fn on_enter<F>(&self, f: F)
+    /// Treats the activity as having a domain, which is only checked when a handler with domain access is called.
+    pub(crate) fn assume_domain(&self) -> ActivityId<A, WithDomain> {
+        ActivityId {
+            id: self.id,
+            phantom: Default::default(),
+        }
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn on_enter_domained<F>(&self, f: F)
     where
-        F: Fn(&mut A) + 'static,
+        F: Fn(&mut A, &mut DomainState) + 'static,
+    {
+        self.assume_domain().on_enter_domained(f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn on_leave_domained<F>(&self, f: F)
+    where
+        F: Fn(&mut A, &mut DomainState) + 'static,
+    {
+        self.assume_domain().on_leave_domained(f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn on_start_domained<F>(&self, f: F)
+    where
+        F: FnOnce(&mut A, &mut DomainState) + 'static,
+    {
+        self.assume_domain().on_start_domained(f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn on_delete_domained<F>(&self, f: F)
+    where
+        F: FnOnce(A, &mut DomainState) + 'static,
+    {
+        self.assume_domain().on_delete_domained(f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn subscribe_domained<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.assume_domain().subscribe_domained(f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn subscribe_domained_mut<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
+        MSG: Any,
+    {
+        self.assume_domain().subscribe_domained_mut(f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn private_domained_channel<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, MSG) + 'static,
+        MSG: Any,
     {
-        crate::nut::register_no_payload(*self, f, Topic::enter(), SubscriptionFilter::no_filter())
+        self.assume_domain().private_domained_channel(f)
     }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn private_domained_channel_masked<F, MSG>(
+        &self,
+        mask: SubscriptionFilter,
+        f: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, MSG) + 'static,
+        MSG: Any,
+    {
+        self.assume_domain()
+            .private_domained_channel_masked(mask, f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn subscribe_domained_masked<F, MSG>(
+        &self,
+        mask: SubscriptionFilter,
+        f: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.assume_domain().subscribe_domained_masked(mask, f)
+    }
+    /// Same as the method of an `ActivityId<A, WithDomain>`, as returned by `nuts::new_domained_activity_checked`.
+    ///
+    /// # Panics
+    /// Panics if the activity has not been registered with a domain.
+    #[deprecated(note = "use new_domained_activity_checked")]
+    pub fn subscribe_domained_masked_mut<F, MSG>(
+        &self,
+        mask: SubscriptionFilter,
+        f: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
+        MSG: Any,
+    {
+        self.assume_domain().subscribe_domained_masked_mut(mask, f)
+    }
+}
+
+impl<A: Activity> ActivityId<A, WithDomain> {
     /// Same as `on_enter` but with domain access in closure
     pub fn on_enter_domained<F>(&self, f: F)
     where
         F: Fn(&mut A, &mut DomainState) + 'static,
     {
         crate::nut::register_domained_no_payload(
-            *self,
+            self.without_domain(),
             f,
             Topic::enter(),
             SubscriptionFilter::no_filter(),
-        )
+        );
+    }
+    /// Same as `on_leave` but with domain access in closure
+    pub fn on_leave_domained<F>(&self, f: F)
+    where
+        F: Fn(&mut A, &mut DomainState) + 'static,
+    {
+        crate::nut::register_domained_no_payload(
+            self.without_domain(),
+            f,
+            Topic::leave(),
+            SubscriptionFilter::no_filter(),
+        );
+    }
+    /// Same as `on_start` but with domain access in closure
+    pub fn on_start_domained<F>(&self, f: F)
+    where
+        F: FnOnce(&mut A, &mut DomainState) + 'static,
+    {
+        crate::nut::register_on_start(self.without_domain(), move |a, domain| {
+            f(a, domain.expect("Activity has no domain"))
+        });
+    }
+    /// Same as `on_delete` but with domain access in closure
+    pub fn on_delete_domained<F>(&self, f: F)
+    where
+        F: FnOnce(A, &mut DomainState) + 'static,
+    {
+        crate::nut::register_domained_on_delete(self.without_domain(), f);
+    }
+    /// Registers a callback closure on an activity with a specific topic to listen to.
+    /// Has mutable access to the `DomainState` object.
+    ///
+    /// By default, the activity will only receive calls when it is active.
+    /// Use `subscribe_domained_masked` for more control over this behavior.
+    ///
+    /// Make sure to use the correct signature for the function, the Rust compiler may give strange error messages otherwise.
+    /// For example, the message must be borrowed by the subscription handler.
+    pub fn subscribe_domained<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained(self.without_domain(), f, Default::default())
+    }
+    /// Same as [`subscribe_once`](#method.subscribe_once) but with domain access in the closure.
+    pub fn subscribe_domained_once<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: FnOnce(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained_once(self.without_domain(), f, Default::default())
+    }
+    /// Same as [`subscribe_domained`](#method.subscribe_domained) but gives mutable access to the message object.
+    pub fn subscribe_domained_mut<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained_mut(self.without_domain(), f, Default::default())
+    }
+    /// Variant of `private_channel` with access to the domain state.
+    pub fn private_domained_channel<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained_owned(self.without_domain(), f, Default::default())
+    }
+    /// Variant of `private_channel` with access to the domain state and subscription mask.
+    pub fn private_domained_channel_masked<F, MSG>(
+        &self,
+        mask: SubscriptionFilter,
+        f: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained_owned(self.without_domain(), f, mask)
+    }
+    /// Registers a callback closure on an activity with a specific topic to listen to with filtering options.
+    /// Has mutable access to the `DomainState` object.
+    pub fn subscribe_domained_masked<F, MSG>(
+        &self,
+        mask: SubscriptionFilter,
+        f: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained(self.without_domain(), f, mask)
+    }
+    /// Same as [`subscribe_domained_masked`](#method.subscribe_domained_masked) but gives mutable access to the message object.
+    pub fn subscribe_domained_masked_mut<F, MSG>(
+        &self,
+        mask: SubscriptionFilter,
+        f: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_domained_mut(self.without_domain(), f, mask)
+    }
+}
+
+impl<A: Activity, D> ActivityId<A, D> {
+    /// Drops the information whether the activity has a domain, for example to store ids of domained and other activities together.
+    pub fn without_domain(&self) -> ActivityId<A> {
+        ActivityId {
+            id: self.id,
+            phantom: Default::default(),
+        }
+    }
+    /// Registers a callback closure that is called when an activity changes from inactive to active.
+    /// Multiple handlers can be registered.
+    pub fn on_enter<F>(&self, f: F)
+    where
+        F: Fn(&mut A) + 'static,
+    {
+        crate::nut::register_no_payload(
+            self.without_domain(),
+            f,
+            Topic::enter(),
+            SubscriptionFilter::no_filter(),
+        );
+    }
+    /// Same as `on_enter` but the closure also receives the cause of the transition.
+    pub fn on_enter_with_cause<F>(&self, f: F)
+    where
+        F: Fn(&mut A, &TransitionCause) + 'static,
+    {
+        crate::nut::register_builtin(
+            self.without_domain(),
+            f,
+            Topic::enter(),
+            SubscriptionFilter::no_filter(),
+        );
     }
     /// Registers a callback closure that is called when an activity changes from active to inactive.
     /// Multiple handlers can be registered.
@@ -84,19 +392,65 @@ impl<A: Activity> ActivityId<A> {
     where
         F: Fn(&mut A) + 'static,
     {
-        crate::nut::register_no_payload(*self, f, Topic::leave(), SubscriptionFilter::no_filter())
+        crate::nut::register_no_payload(
+            self.without_domain(),
+            f,
+            Topic::leave(),
+            SubscriptionFilter::no_filter(),
+        );
     }
-    /// Same as `on_leave` but with domain access in closure
-    pub fn on_leave_domained<F>(&self, f: F)
+    /// Same as `on_leave` but the closure also receives the cause of the transition.
+    pub fn on_leave_with_cause<F>(&self, f: F)
     where
-        F: Fn(&mut A, &mut DomainState) + 'static,
+        F: Fn(&mut A, &TransitionCause) + 'static,
     {
-        crate::nut::register_domained_no_payload(
-            *self,
+        crate::nut::register_builtin(
+            self.without_domain(),
             f,
             Topic::leave(),
             SubscriptionFilter::no_filter(),
-        )
+        );
+    }
+    /// Registers a callback closure that is called on every change of the lifecycle status, with the old and new status and the cause.
+    ///
+    /// The closure is called regardless of the status, also when the activity is about to be deleted.
+    /// It is also called when a supervisor restarts the activity, with the new instance and an unchanged status.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use nuts::LifecycleStatus;
+    /// struct Level;
+    ///
+    /// let level = nuts::new_activity(Level);
+    /// level.on_transition(|_, transition| match transition.to {
+    ///     LifecycleStatus::Deleted => println!("unloading"),
+    ///     LifecycleStatus::Inactive => println!("paused"),
+    ///     _ => {}
+    /// });
+    /// level.set_status(LifecycleStatus::Inactive); // prints "paused"
+    /// level.set_status(LifecycleStatus::Deleted); // prints "unloading"
+    /// ```
+    pub fn on_transition<F>(&self, f: F)
+    where
+        F: Fn(&mut A, &LifecycleTransition) + 'static,
+    {
+        crate::nut::register_builtin(
+            self.without_domain(),
+            f,
+            Topic::transition(),
+            SubscriptionFilter::no_filter(),
+        );
+    }
+    /// Registers a callback closure that is called once, after the activity has been fully registered.
+    ///
+    /// Outside of subscription handlers, the closure is called immediately.
+    /// Inside handlers, it is called after all events queued up to this point, which includes adding activities created by the handler.
+    /// Unlike `on_enter`, this is independent of the lifecycle status, as long as the activity has not been deleted.
+    pub fn on_start<F>(&self, f: F)
+    where
+        F: FnOnce(&mut A) + 'static,
+    {
+        crate::nut::register_on_start(self.without_domain(), move |a, _| f(a));
     }
     /// Registers a callback closure that is called when an activity is deleted.
     /// Only one handler can be registered because it takes ownership of the data.
@@ -105,14 +459,7 @@ impl<A: Activity> ActivityId<A> {
     where
         F: FnOnce(A) + 'static,
     {
-        crate::nut::register_on_delete(*self, f);
-    }
-    /// Same as `on_delete` but with domain access in closure
-    pub fn on_delete_domained<F>(&self, f: F)
-    where
-        F: FnOnce(A, &mut DomainState) + 'static,
-    {
-        crate::nut::register_domained_on_delete(*self, f);
+        crate::nut::register_on_delete(self.without_domain(), f);
     }
 
     /// Registers a callback closure on an activity with a specific topic to listen to.
@@ -158,50 +505,313 @@ impl<A: Activity> ActivityId<A> {
     /// }
     /// ```
     // @ END-DOC SUBSCRIBE_EXAMPLE
-    pub fn subscribe<F, MSG>(&self, f: F)
+    pub fn subscribe<F, MSG>(&self, f: F) -> SubscriptionId
     where
         F: Fn(&mut A, &MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register(*self, f, Default::default())
+        crate::nut::register(self.without_domain(), f, Default::default())
+    }
+    /// Same as [`subscribe`](#method.subscribe) but the subscription is tagged, so it can be disabled with [`nuts::set_tag_enabled`](fn.set_tag_enabled.html).
+    ///
+    /// The tag is the subscription group, see [`SubscriptionFilter::in_group`](struct.SubscriptionFilter.html#method.in_group).
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Game;
+    /// struct Tick;
+    ///
+    /// let game = nuts::new_activity(Game);
+    /// game.subscribe(|_, _: &Tick| println!("Update"));
+    /// game.subscribe_tagged("debug-ui", |_, _: &Tick| println!("Draw debug overlay"));
+    /// nuts::set_tag_enabled("debug-ui", false);
+    /// nuts::publish(Tick); // prints only "Update"
+    /// ```
+    pub fn subscribe_tagged<F, MSG>(&self, tag: &str, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        let filter = SubscriptionFilter::default().in_group(tag);
+        crate::nut::register(self.without_domain(), f, filter)
+    }
+    /// Registers all subscriptions of a [`Subscriber`](trait.Subscriber.html).
+    ///
+    /// ### Example
+    /// ```rust
+    /// # #[cfg(feature = "derive")] {
+    /// struct Player { x: i32, y: i32 }
+    /// struct Move(i32, i32);
+    /// struct Teleport(i32, i32);
+    ///
+    /// #[nuts::subscriber]
+    /// impl Player {
+    ///     #[subscribe]
+    ///     fn on_move(&mut self, msg: &Move) {
+    ///         self.x += msg.0;
+    ///         self.y += msg.1;
+    ///     }
+    ///     #[subscribe]
+    ///     fn on_teleport(&mut self, msg: &Teleport) {
+    ///         self.x = msg.0;
+    ///         self.y = msg.1;
+    ///     }
+    ///     // Not a subscription, even though the signature would fit
+    ///     fn follow(&mut self, leader: &Player) {
+    ///         self.x = leader.x;
+    ///         self.y = leader.y;
+    ///     }
+    /// }
+    ///
+    /// let player = nuts::new_activity(Player { x: 0, y: 0 });
+    /// player.register_all();
+    /// nuts::publish(Teleport(5, 5));
+    /// nuts::publish(Move(1, -1));
+    /// assert_eq!(Some((6, 4)), nuts::read_activity(|p: &Player| (p.x, p.y)));
+    /// # }
+    /// ```
+    pub fn register_all(&self)
+    where
+        A: Subscriber,
+    {
+        A::register_all(self.without_domain())
+    }
+    /// Same as [subscribe](#method.subscribe) but the subscription is removed after the first call.
+    ///
+    /// While the activity is inactive, messages are ignored and the subscription stays in place.
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Game { ready: bool }
+    /// struct AssetsLoaded;
+    ///
+    /// let game = nuts::new_activity(Game { ready: false });
+    /// game.subscribe_once(|game, _: &AssetsLoaded| game.ready = true);
+    /// nuts::publish(AssetsLoaded);
+    /// // Not received anymore
+    /// nuts::publish(AssetsLoaded);
+    /// ```
+    pub fn subscribe_once<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: FnOnce(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_once(self.without_domain(), f, Default::default())
     }
     /// Same as [subscribe](#method.subscribe) but gives mutable access to the message object.
     ///
     /// Make sure to use the correct signature for the function, the Rust compiler may give strange error messages otherwise.
     /// For example, the message must be borrowed by the subscription handler.
-    pub fn subscribe_mut<F, MSG>(&self, f: F)
+    pub fn subscribe_mut<F, MSG>(&self, f: F) -> SubscriptionId
     where
         F: Fn(&mut A, &mut MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_mut(*self, f, Default::default())
+        crate::nut::register_mut(self.without_domain(), f, Default::default())
     }
-
-    /// Registers a callback closure on an activity with a specific topic to listen to.
-    /// Has mutable access to the `DomainState` object.
+    /// Same as [subscribe](#method.subscribe) but only messages that satisfy the predicate are delivered.
     ///
-    /// By default, the activity will only receive calls when it is active.
-    /// Use `subscribe_domained_masked` for more control over this behavior.
+    /// The predicate is checked before the activity is looked up, which makes it cheap to skip messages addressed to other entities.
     ///
-    /// Make sure to use the correct signature for the function, the Rust compiler may give strange error messages otherwise.
-    /// For example, the message must be borrowed by the subscription handler.
+    /// ### Example
+    /// ```rust
+    /// struct Entity { id: u32, x: i32 }
+    /// struct EntityMoved { entity_id: u32, dx: i32 }
     ///
-    /// # Panics
-    /// Panics if the activity has not been registered with a domain.    
-    pub fn subscribe_domained<F, MSG>(&self, f: F)
+    /// let entity = nuts::new_activity(Entity { id: 7, x: 0 });
+    /// entity.subscribe_filtered(
+    ///     |msg: &EntityMoved| msg.entity_id == 7,
+    ///     |entity, msg: &EntityMoved| entity.x += msg.dx,
+    /// );
+    /// nuts::publish(EntityMoved { entity_id: 7, dx: 1 });
+    /// nuts::publish(EntityMoved { entity_id: 8, dx: 100 });
+    /// ```
+    pub fn subscribe_filtered<P, F, MSG>(&self, predicate: P, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        P: Fn(&MSG) -> bool + 'static,
+        F: Fn(&mut A, &MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_domained(*self, f, Default::default())
+        crate::nut::register_filtered(self.without_domain(), f, predicate, Default::default())
     }
-    /// Same as [`subscribe_domained`](#method.subscribe_domained) but gives mutable access to the message object.
-    pub fn subscribe_domained_mut<F, MSG>(&self, f: F)
+    /// Subscribes to a single variant of an enum message.
+    ///
+    /// The extractor returns the content of the variant, or `None` for all other variants.
+    /// Like with [`subscribe_filtered`](#method.subscribe_filtered), non-matching messages are skipped before the activity is looked up.
+    ///
+    /// ### Example
+    /// ```rust
+    /// enum GameEvent {
+    ///     Moved { dx: i32 },
+    ///     Jumped,
+    /// }
+    /// struct Player { x: i32 }
+    ///
+    /// let player = nuts::new_activity(Player { x: 0 });
+    /// player.subscribe_variant(
+    ///     |event: &GameEvent| match event {
+    ///         GameEvent::Moved { dx } => Some(dx),
+    ///         _ => None,
+    ///     },
+    ///     |player, dx: &i32| player.x += dx,
+    /// );
+    /// nuts::publish(GameEvent::Moved { dx: 2 });
+    /// nuts::publish(GameEvent::Jumped);
+    /// ```
+    pub fn subscribe_variant<X, F, MSG, V>(&self, extract: X, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
+        X: Fn(&MSG) -> Option<&V> + 'static,
+        F: Fn(&mut A, &V) + 'static,
         MSG: Any,
+        V: ?Sized,
     {
-        crate::nut::register_domained_mut(*self, f, Default::default())
+        let extract = std::rc::Rc::new(extract);
+        let predicate = extract.clone();
+        crate::nut::register_filtered(
+            self.without_domain(),
+            move |a, msg: &MSG| {
+                if let Some(variant) = extract(msg) {
+                    f(a, variant)
+                }
+            },
+            move |msg: &MSG| predicate(msg).is_some(),
+            Default::default(),
+        )
+    }
+    /// Registers two handlers for the same message, one that is called while the activity is active and one that is called while it is inactive.
+    ///
+    /// This lets an inactive activity, such as a hidden menu, still take note of messages with cheaper logic than while it is active.
+    /// Both handlers belong to a single subscription, the returned id removes both.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use nuts::LifecycleStatus;
+    /// struct PauseMenu { backlog: Vec<u32>, shown: Vec<u32> }
+    /// struct Score(u32);
+    ///
+    /// let menu = nuts::new_activity(PauseMenu { backlog: vec![], shown: vec![] });
+    /// menu.subscribe_paired(
+    ///     |menu, score: &Score| menu.shown.push(score.0),
+    ///     |menu, score: &Score| menu.backlog.push(score.0),
+    /// );
+    /// menu.set_status(LifecycleStatus::Inactive);
+    /// nuts::publish(Score(10));
+    /// ```
+    pub fn subscribe_paired<F, G, MSG>(&self, active: F, inactive: G) -> SubscriptionId
+    where
+        F: Fn(&mut A, &MSG) + 'static,
+        G: Fn(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_paired(self.without_domain(), active, inactive)
+    }
+    /// Same as [subscribe](#method.subscribe) but the message type is chosen at runtime.
+    ///
+    /// The handler receives all published messages whose type has the given `TypeId`, as `&dyn Any`.
+    /// This is meant for generic tooling, such as inspectors or scripting integrations. Prefer the typed API otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use core::any::{Any, TypeId};
+    /// struct Inspector;
+    /// let inspector = nuts::new_activity(Inspector);
+    /// inspector.subscribe_dyn(TypeId::of::<u32>(), |_, msg: &dyn Any| {
+    ///     assert_eq!(Some(&7u32), msg.downcast_ref::<u32>());
+    /// });
+    /// nuts::publish(7u32);
+    /// ```
+    pub fn subscribe_dyn<F>(&self, type_id: core::any::TypeId, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &dyn Any) + 'static,
+    {
+        crate::nut::register_dyn(self.without_domain(), type_id, f, Default::default())
+    }
+    /// Subscribes to messages of type `MSG` published to the entity with [`nuts::publish_to_entity`](fn.publish_to_entity.html).
+    ///
+    /// Entity messages are routed by message type and entity directly, so that handlers of other entities are not called at all.
+    pub fn subscribe_entity<F, MSG>(&self, entity: EntityId, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_entity(self.without_domain(), entity, f, Default::default())
+    }
+    /// Subscribes to messages published by name with [`nuts::publish_dynamic`](fn.publish_dynamic.html).
+    ///
+    /// Only available with the `dynamic-messages` feature.
+    #[cfg(feature = "dynamic-messages")]
+    pub fn subscribe_dynamic<F>(&self, name: &str, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &serde_json::Value) + 'static,
+    {
+        crate::nut::register_dynamic(self.without_domain(), name, f, Default::default())
+    }
+    /// Subscribes to all published messages whose type has been registered for the trait object type `T`, see [`nuts::register_trait`](fn.register_trait.html).
+    ///
+    /// Trait subscriptions are called after all subscriptions to the concrete message type.
+    pub fn subscribe_trait<T, F>(&self, f: F) -> SubscriptionId
+    where
+        T: ?Sized + 'static,
+        F: Fn(&mut A, &T) + 'static,
+    {
+        crate::nut::register_dyn(
+            self.without_domain(),
+            core::any::TypeId::of::<T>(),
+            move |a, msg| crate::nut::iac::trait_topic::with_trait_object(msg, |obj| f(a, obj)),
+            Default::default(),
+        )
+    }
+    /// Same as [subscribe](#method.subscribe) but the handler may fail.
+    ///
+    /// Errors returned by the handler are published as [`HandlerError`](struct.HandlerError.html),
+    /// which other activities can subscribe to.
+    pub fn subscribe_fallible<F, MSG, E>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &MSG) -> Result<(), E> + 'static,
+        MSG: Any,
+        E: std::error::Error + 'static,
+    {
+        self.subscribe(move |a, msg: &MSG| {
+            if let Err(e) = f(a, msg) {
+                crate::publish(HandlerError::new::<A, MSG>(Box::new(e)));
+            }
+        })
+    }
+    /// Same as [subscribe](#method.subscribe) but the handler receives its own copy of the message.
+    ///
+    /// The published message is cloned once for each subscriber registered this way.
+    /// To receive a message without cloning it, send it to a [private channel](#method.private_channel) instead.
+    pub fn subscribe_owned<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, MSG) + 'static,
+        MSG: Any + Clone,
+    {
+        self.subscribe(move |a, msg: &MSG| f(a, msg.clone()))
+    }
+
+    /// Registers a callback closure which receives a [`HandlerCtx`](struct.HandlerCtx.html) instead of the bare activity.
+    ///
+    /// The context gives access to the activity, its domain (if any), information about the message,
+    /// and offers methods to influence the dispatch, such as stopping the propagation of the message to further subscribers.
+    pub fn subscribe_ctx<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut HandlerCtx<A>, &MSG) + 'static,
+        MSG: Any,
+    {
+        crate::nut::register_ctx(self.without_domain(), f, Default::default())
+    }
+
+    /// Registers a callback closure that answers requests sent with [`nuts::request`](fn.request.html).
+    ///
+    /// The returned value is the reply to the request. If multiple responders answer the same request, the first reply is used.
+    /// Messages published with `nuts::publish` are also delivered to responders, the reply is dropped in that case.
+    pub fn subscribe_responder<F, MSG, REPLY>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &MSG) -> REPLY + 'static,
+        MSG: Any,
+        REPLY: Any,
+    {
+        crate::nut::register_responder(self.without_domain(), f, Default::default())
     }
 
     /// Registers a callback closure on an activity with a specific topic to listen to.
@@ -213,83 +823,137 @@ impl<A: Activity> ActivityId<A> {
     ///
     /// Since the listener takes ownership, it is not possible to have more than one private channel active for the same activity at the same time.
     /// If multiple private channels are added to an activity, only the last listener is retained. (Older ones are replaced and deleted)
-    pub fn private_channel<F, MSG>(&self, f: F)
+    pub fn private_channel<F, MSG>(&self, f: F) -> SubscriptionId
     where
         F: Fn(&mut A, MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_owned(*self, f, Default::default())
+        crate::nut::register_owned(self.without_domain(), f, Default::default())
     }
 
-    /// Variant of `private_channel` with access to the domain state.
+    /// Variant of `private_channel` with a key, to receive several independent streams of the same message type.
     ///
-    /// # Panics
-    /// Panics if the activity has not been registered with a domain.   
-    pub fn private_domained_channel<F, MSG>(&self, f: F)
+    /// Each key has its own channel, which only receives messages sent with [`nuts::send_to_keyed`](fn.send_to_keyed.html) and an equal key.
+    /// Registering a second channel for the same key and message type replaces the first, like with `private_channel`.
+    /// Keys are compared with `Eq`, keys of different types never match.
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Server;
+    /// struct Packet(Vec<u8>);
+    ///
+    /// let server = nuts::new_activity(Server);
+    /// for connection in 0..2u32 {
+    ///     server.private_channel_keyed(connection, move |_, packet: Packet| {
+    ///         println!("{} bytes on connection {}", packet.0.len(), connection);
+    ///     });
+    /// }
+    /// nuts::send_to_keyed::<Server, _, _>(1u32, Packet(vec![1, 2, 3]));
+    /// ```
+    pub fn private_channel_keyed<K, F, MSG>(&self, key: K, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, &mut DomainState, MSG) + 'static,
+        K: Hash + Eq + Any,
+        F: Fn(&mut A, MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_domained_owned(*self, f, Default::default())
+        crate::nut::register_owned_keyed(self.without_domain(), key, f, Default::default())
     }
 
-    /// Variant of `private_channel` with subscription mask.
-    pub fn private_channel_masked<F, MSG>(&self, mask: SubscriptionFilter, f: F)
+    /// Variant of `private_channel` that answers requests sent with [`request`](#method.request).
+    ///
+    /// The returned value is the reply to the request. Messages sent with `nuts::send_to()` are also received, the reply is dropped in that case.
+    /// A private responder and a private channel of the same activity and message type replace each other.
+    pub fn private_responder<F, MSG, REPLY>(&self, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, MSG) + 'static,
+        F: Fn(&mut A, MSG) -> REPLY + 'static,
         MSG: Any,
+        REPLY: Any,
     {
-        crate::nut::register_owned(*self, f, mask)
+        crate::nut::register_owned_responder(self.without_domain(), f, Default::default())
     }
 
-    /// Variant of `private_channel` with access to the domain state and subscription mask.
+    /// Sends a private message to this activity and returns a future that resolves to the reply of its [`private_responder`](#method.private_responder).
     ///
-    /// # Panics
-    /// Panics if the activity has not been registered with a domain.   
-    pub fn private_domained_channel_masked<F, MSG>(&self, mask: SubscriptionFilter, f: F)
+    /// The message is queued immediately, not when the future is first polled.
+    /// Like for [`nuts::request`](fn.request.html), the future resolves once the message and all deferred events caused by it have been processed.
+    /// It resolves to [`RequestError::NoReply`](enum.RequestError.html) if the activity has not answered with a reply of type `REPLY`, for example because it is inactive.
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Inventory { items: Vec<String> }
+    /// struct CountItems;
+    ///
+    /// let inventory = nuts::new_activity(Inventory { items: vec!["sword".to_owned()] });
+    /// inventory.private_responder(|inventory, _: CountItems| inventory.items.len());
+    ///
+    /// async fn count(inventory: nuts::ActivityId<Inventory>) {
+    ///     let n: Result<usize, nuts::RequestError> = inventory.request(CountItems).await;
+    ///     assert_eq!(n, Ok(1));
+    /// }
+    /// ```
+    pub fn request<MSG, REPLY>(
+        &self,
+        msg: MSG,
+    ) -> impl core::future::Future<Output = Result<REPLY, RequestError>>
     where
-        F: Fn(&mut A, &mut DomainState, MSG) + 'static,
         MSG: Any,
+        REPLY: Any,
     {
-        crate::nut::register_domained_owned(*self, f, mask)
+        crate::nut::request_from(msg, self.id, None)
     }
 
-    /// Registers a callback closure on an activity with a specific topic to listen to with filtering options.
-    pub fn subscribe_masked<F, MSG>(&self, mask: SubscriptionFilter, f: F)
+    /// Same as [`request`](#method.request) but gives up waiting after `ticks` calls to [`nuts::tick`](fn.tick.html).
+    ///
+    /// A request that is still pending when the frame counter has advanced by `ticks` resolves to [`RequestError::Timeout`](enum.RequestError.html).
+    /// The message itself is still delivered later, but its reply is dropped.
+    pub fn request_with_timeout<MSG, REPLY>(
+        &self,
+        msg: MSG,
+        ticks: u64,
+    ) -> impl core::future::Future<Output = Result<REPLY, RequestError>>
     where
-        F: Fn(&mut A, &MSG) + 'static,
         MSG: Any,
+        REPLY: Any,
     {
-        crate::nut::register(*self, f, mask)
+        crate::nut::request_from(msg, self.id, Some(ticks))
     }
-    /// Same as [`subscribe_masked`](#method.subscribe_masked) but gives mutable access to the message object.
-    pub fn subscribe_masked_mut<F, MSG>(&self, mask: SubscriptionFilter, f: F)
+
+    /// Variant of `private_channel` with subscription mask.
+    pub fn private_channel_masked<F, MSG>(&self, mask: SubscriptionFilter, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, &mut MSG) + 'static,
+        F: Fn(&mut A, MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_mut(*self, f, mask)
+        crate::nut::register_owned(self.without_domain(), f, mask)
     }
 
     /// Registers a callback closure on an activity with a specific topic to listen to with filtering options.
-    /// Has mutable access to the `DomainState` object.
-    ///
-    /// # Panics
-    /// Panics if the activity has not been registered with a domain.
-    pub fn subscribe_domained_masked<F, MSG>(&self, mask: SubscriptionFilter, f: F)
+    pub fn subscribe_masked<F, MSG>(&self, mask: SubscriptionFilter, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        F: Fn(&mut A, &MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_domained(*self, f, mask)
+        crate::nut::register(self.without_domain(), f, mask)
     }
-    /// Same as [`subscribe_domained_masked`](#method.subscribe_domained_masked) but gives mutable access to the message object.
-    pub fn subscribe_domained_masked_mut<F, MSG>(&self, mask: SubscriptionFilter, f: F)
+    /// Same as [`subscribe_masked`](#method.subscribe_masked) but gives mutable access to the message object.
+    pub fn subscribe_masked_mut<F, MSG>(&self, mask: SubscriptionFilter, f: F) -> SubscriptionId
     where
-        F: Fn(&mut A, &mut DomainState, &mut MSG) + 'static,
+        F: Fn(&mut A, &mut MSG) + 'static,
         MSG: Any,
     {
-        crate::nut::register_domained_mut(*self, f, mask)
+        crate::nut::register_mut(self.without_domain(), f, mask)
+    }
+
+    /// Removes a subscription previously registered on this activity.
+    ///
+    /// If called while a broadcast is ongoing, the removal is deferred.
+    /// Messages published before the call to `unsubscribe` may thus still be delivered to the subscription.
+    pub fn unsubscribe(&self, subscription: SubscriptionId) {
+        debug_assert_eq!(
+            subscription.activity, self.id,
+            "Subscription belongs to another activity"
+        );
+        crate::nut::unsubscribe(subscription);
     }
 
     /// Changes the lifecycle status of the activity
@@ -297,7 +961,162 @@ impl<A: Activity> ActivityId<A> {
     /// # Panics
     /// If status is set to something other than Deleted after it has been Deleted
     pub fn set_status(&self, status: LifecycleStatus) {
-        crate::nut::set_status((*self).into(), status);
+        crate::nut::set_status(self.id, status, TransitionCause::Manual);
+    }
+
+    /// Sets how many messages are held for the activity while its status is [`LifecycleStatus::Buffering`](enum.LifecycleStatus.html#variant.Buffering).
+    ///
+    /// When the limit is reached, the oldest held message is dropped. The default is 64.
+    pub fn set_buffer_capacity(&self, capacity: usize) {
+        crate::nut::set_buffer_capacity(self.id, capacity);
+    }
+
+    /// Deletes the activity in two phases, giving other activities a chance to react or object.
+    ///
+    /// First, [`AboutToDelete<A>`](struct.AboutToDelete.html) is published.
+    /// Once it has been delivered to all subscribers, the activity is deleted, unless one of them has called `veto`.
+    /// Nothing happens if the activity has been deleted in the meantime.
+    pub fn request_delete(&self) {
+        crate::nut::request_delete(self.without_domain());
+    }
+
+    /// Number of private messages of type `MSG` addressed to the activity that are waiting in the queue.
+    ///
+    /// Messages sent with `nuts::send_to::<A, _>` are counted for all activities of type `A`.
+    pub fn mailbox_len<MSG: Any>(&self) -> usize {
+        crate::nut::mailbox_len::<A, MSG>(self.id)
+    }
+    /// Statistics about all private messages addressed to the activity.
+    pub fn mailbox_stats(&self) -> MailboxStats {
+        crate::nut::mailbox_stats::<A>(self.id)
+    }
+
+    /// Associates the activity with another domain.
+    ///
+    /// All existing and future subscriptions with domain access will receive the new domain.
+    /// If called inside a subscription handler, the change happens after the current message has been delivered.
+    pub fn move_to_domain<DOMAIN: DomainEnumeration>(&self, domain: &DOMAIN) {
+        crate::nut::move_to_domain(self.id, DomainId::new(domain));
+    }
+    /// Same as [`move_to_domain`](#method.move_to_domain) but returns the id with domain access,
+    /// which can be used to register handlers with access to the new domain.
+    pub fn move_to_domain_checked<DOMAIN: DomainEnumeration>(
+        &self,
+        domain: &DOMAIN,
+    ) -> ActivityId<A, WithDomain> {
+        self.move_to_domain(domain);
+        self.without_domain().assume_domain()
+    }
+
+    /// Borrows the activity once for a series of calls, for example from a high-frequency callback.
+    ///
+    /// Inside the scope, nuts behaves like inside a subscription handler: Published messages and other changes are queued.
+    /// They are executed when the scope ends.
+    ///
+    /// # Errors
+    /// Fails with `ExecError::InsideHandler` if nuts is already executing, for example inside a subscription handler or another scope.
+    /// Fails with `ExecError::MissingActivity` if the activity has been deleted.
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Mixer { volume: f32 }
+    /// let mixer = nuts::new_activity(Mixer { volume: 0.5 });
+    /// let mut samples = [1.0f32, -1.0, 0.5];
+    /// mixer.borrow_scope(|scope| {
+    ///     for sample in &mut samples {
+    ///         *sample = scope.with(|mixer| *sample * mixer.volume);
+    ///     }
+    /// }).expect("not inside a handler");
+    /// assert_eq!([0.5, -0.5, 0.25], samples);
+    /// ```
+    pub fn borrow_scope<R>(
+        &self,
+        f: impl FnOnce(&mut ActivityScope<A>) -> R,
+    ) -> Result<R, ExecError> {
+        crate::nut::borrow_scope(self.without_domain(), f)
+    }
+
+    /// Replaces the activity with a new instance of the same type.
+    ///
+    /// Subscriptions registered in one of the `keep_groups` (see [`SubscriptionFilter::in_group`](struct.SubscriptionFilter.html#method.in_group))
+    /// stay in place and are called with the new instance.
+    /// All other subscriptions of the activity are dropped, the new instance is expected to register them again.
+    /// Lifecycle status, domain, and on-delete handlers are kept. The old instance is dropped without calling on-delete.
+    ///
+    /// The replacement happens after all currently queued events have been executed.
+    pub fn replace(&self, new_activity: A, keep_groups: &[&str]) {
+        crate::nut::replace_activity(
+            self.id,
+            Box::new(new_activity),
+            keep_groups.iter().map(|g| g.to_string()).collect(),
+        );
+    }
+
+    /// Swaps in a new instance of the activity right away and returns the old one.
+    ///
+    /// Unlike [`replace`](#method.replace), everything else stays as it is: id, domain, lifecycle status, and all subscriptions, which are called with the new instance from now on.
+    /// This is useful for hot-reloading, where the state of an activity is rebuilt but its wiring should remain stable.
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Enemy { speed: f32 }
+    /// struct Tick;
+    ///
+    /// let enemy = nuts::new_activity(Enemy { speed: 1.0 });
+    /// enemy.subscribe(|enemy, _: &Tick| println!("moving at {}", enemy.speed));
+    /// let old = enemy.replace_activity(Enemy { speed: 2.0 }).expect("not inside a handler");
+    /// assert_eq!(1.0, old.speed);
+    /// nuts::publish(Tick); // prints "moving at 2"
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with `ExecError::InsideHandler` if called while nuts is executing, for example inside a subscription handler,
+    /// and with `ExecError::MissingActivity` if the activity has been deleted. The new instance is dropped in both cases.
+    pub fn replace_activity(&self, new_activity: A) -> Result<A, ExecError> {
+        crate::nut::swap_activity(self.without_domain(), new_activity)
+    }
+
+    /// Makes this activity the supervisor of `child`, which is restarted with a fresh instance from `factory` when it fails.
+    ///
+    /// Depending on the `policy`, a restart replaces setting the child to inactive after a handler panicked (see [`PanicPolicy::RecoverAndReport`](enum.PanicPolicy.html)),
+    /// or it replaces deleting the child.
+    /// A restart keeps the id, subscriptions, lifecycle status, domain, and on-delete handlers of the child. The old instance is dropped without calling on-delete.
+    /// When the supervisor is deleted, its children are deleted, too.
+    ///
+    /// Supervising the same child again replaces the previous supervision.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use nuts::{LifecycleStatus, PanicPolicy, RestartPolicy};
+    /// struct App;
+    /// struct Connection { retries: u32 }
+    /// struct Disconnected;
+    ///
+    /// nuts::set_panic_policy(PanicPolicy::RecoverAndReport);
+    /// let app = nuts::new_activity(App);
+    /// let connection = nuts::new_activity(Connection { retries: 0 });
+    /// app.supervise(connection, RestartPolicy::Always, || Connection { retries: 0 });
+    /// connection.subscribe(|conn, _: &Disconnected| {
+    ///     conn.retries += 1;
+    ///     if conn.retries > 3 {
+    ///         panic!("giving up");
+    ///     }
+    /// });
+    /// // A fresh connection takes over, with the same subscriptions
+    /// connection.set_status(LifecycleStatus::Deleted);
+    /// nuts::publish(Disconnected);
+    /// ```
+    pub fn supervise<C, CD, F>(&self, child: ActivityId<C, CD>, policy: RestartPolicy, factory: F)
+    where
+        C: Activity,
+        F: Fn() -> C + 'static,
+    {
+        crate::nut::supervise(
+            self.id,
+            child.id,
+            policy,
+            std::rc::Rc::new(move || Box::new(factory()) as Box<dyn Any>),
+        );
     }
 
     /// Publish a message to a specific activity.
@@ -305,7 +1124,7 @@ impl<A: Activity> ActivityId<A> {
     /// If you lack access to an `ActivityId`, use `nuts::send_to()` or `UncheckedActivityId::private_message`.
     /// Both are equivalent.
     pub fn private_message<MSG: Any>(&self, msg: MSG) {
-        let id: UncheckedActivityId = (*self).into();
+        let id: UncheckedActivityId = self.id;
         id.private_message(msg);
     }
 }
@@ -316,7 +1135,7 @@ impl UncheckedActivityId {
     /// # Panics
     /// If status is set to something other than Deleted after it has been Deleted
     pub fn set_status(&self, status: LifecycleStatus) {
-        crate::nut::set_status(*self, status);
+        crate::nut::set_status(*self, status, TransitionCause::Manual);
     }
     /// Publish a message to a specific activity.
     ///
@@ -333,26 +1152,42 @@ impl UncheckedActivityId {
     ///
     /// If used in any other way, you might experience panics.
     /// Right now, there should still be no UB but that might change in future versions.
+    ///
+    /// Indices depend on the order of registration. For references that must survive a restart, see `nuts::new_activity_keyed`.
+    ///
+    /// The id refers to an activity of the bus in use when it is forged, see [`Bus`](struct.Bus.html).
     pub fn forge_from_usize(index: usize) -> Self {
-        Self { index }
+        Self {
+            index,
+            bus: crate::nut::current_bus(),
+        }
     }
 }
 
 impl NotAnActivity {
-    pub fn id() -> ActivityId<NotAnActivity> {
-        ActivityId::<NotAnActivity>::new(0, DomainId::default())
+    pub fn id(bus: BusId) -> ActivityId<NotAnActivity> {
+        ActivityId::<NotAnActivity>::new(UncheckedActivityId { index: 0, bus })
     }
 }
 
-impl<A> Copy for ActivityId<A> {}
-impl<A> Clone for ActivityId<A> {
+impl<A, D> Copy for ActivityId<A, D> {}
+impl<A, D> Clone for ActivityId<A, D> {
     fn clone(&self) -> Self {
         *self
     }
 }
 
-impl<A> Into<UncheckedActivityId> for ActivityId<A> {
-    fn into(self) -> UncheckedActivityId {
-        self.id
+impl<A, D> From<ActivityId<A, D>> for UncheckedActivityId {
+    fn from(id: ActivityId<A, D>) -> Self {
+        id.id
+    }
+}
+
+impl<A> From<ActivityId<A, WithDomain>> for ActivityId<A> {
+    fn from(id: ActivityId<A, WithDomain>) -> Self {
+        ActivityId {
+            id: id.id,
+            phantom: Default::default(),
+        }
     }
 }
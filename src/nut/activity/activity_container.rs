@@ -1,4 +1,5 @@
 use super::*;
+use crate::nut::bus::STALE_ID_ERR_MSG;
 use crate::nut::iac::subscription::OnDelete;
 use core::any::TypeId;
 
@@ -9,51 +10,156 @@ use core::any::TypeId;
 #[derive(Default)]
 pub(crate) struct ActivityContainer {
     data: Vec<Option<Box<dyn Any>>>,
+    /// Factories of lazy activities that have not been constructed, yet. Their `data` holds `Unconstructed`.
+    factories: Vec<Option<ActivityFactory>>,
+    type_ids: Vec<TypeId>,
     active: Vec<LifecycleStatus>,
+    domains: Vec<DomainId>,
     on_delete: Vec<OnDelete>,
+    type_names: Vec<&'static str>,
+    /// The bus that all contained activities belong to
+    bus: BusId,
+}
+
+type ActivityFactory = Box<dyn FnOnce() -> Box<dyn Any>>;
+
+/// Placeholder for a lazy activity that has not been constructed, yet
+struct Unconstructed;
+
+/// An activity that is about to be added to a container
+pub(crate) enum NewActivity<A> {
+    Constructed(A),
+    /// Constructed on first access, see `nuts::new_lazy_activity`
+    Lazy(Box<dyn FnOnce() -> A>),
 }
 
 impl ActivityContainer {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(bus: BusId) -> Self {
         Self {
             data: vec![Some(Box::new(NotAnActivity))],
+            factories: vec![None],
+            type_ids: vec![TypeId::of::<NotAnActivity>()],
             active: vec![LifecycleStatus::Active],
+            domains: vec![DomainId::default()],
             on_delete: vec![OnDelete::None],
+            type_names: vec![std::any::type_name::<NotAnActivity>()],
+            bus,
         }
     }
+    /// Without the slot of `NotAnActivity`, for activities added during a broadcast
+    pub(crate) fn empty(bus: BusId) -> Self {
+        Self {
+            bus,
+            ..Default::default()
+        }
+    }
+    /// The id of the activity in the slot, which may not be alive.
+    pub(crate) fn id(&self, index: usize) -> UncheckedActivityId {
+        UncheckedActivityId {
+            index,
+            bus: self.bus,
+        }
+    }
+    /// The slot of an activity, after checking that the id belongs to the same bus.
+    fn slot(&self, id: UncheckedActivityId) -> usize {
+        assert_eq!(self.bus, id.bus, "{}", STALE_ID_ERR_MSG);
+        id.index
+    }
     pub(crate) fn add<A: Activity>(
         &mut self,
-        a: A,
+        a: NewActivity<A>,
         domain: DomainId,
         status: LifecycleStatus,
     ) -> ActivityId<A> {
         let i = self.data.len();
-        self.data.push(Some(Box::new(a)));
+        match a {
+            NewActivity::Constructed(a) => {
+                self.data.push(Some(Box::new(a)));
+                self.factories.push(None);
+            }
+            NewActivity::Lazy(factory) => {
+                self.data.push(Some(Box::new(Unconstructed)));
+                self.factories
+                    .push(Some(Box::new(move || Box::new(factory()))));
+            }
+        }
+        self.type_ids.push(TypeId::of::<A>());
         self.active.push(status);
+        self.domains.push(domain);
         self.on_delete.push(OnDelete::None);
-        ActivityId::new(i, domain)
+        self.type_names.push(std::any::type_name::<A>());
+        ActivityId::new(self.id(i))
     }
     pub(crate) fn status(&self, id: UncheckedActivityId) -> LifecycleStatus {
-        self.active[id.index]
+        self.active[self.slot(id)]
     }
     pub(crate) fn set_status(&mut self, id: UncheckedActivityId, status: LifecycleStatus) {
-        self.active[id.index] = status
+        let i = self.slot(id);
+        self.active[i] = status
+    }
+    pub(crate) fn domain(&self, id: UncheckedActivityId) -> DomainId {
+        self.domains[self.slot(id)]
+    }
+    pub(crate) fn set_domain(&mut self, id: UncheckedActivityId, domain: DomainId) {
+        let i = self.slot(id);
+        self.domains[i] = domain
     }
     pub(crate) fn add_on_delete(&mut self, id: UncheckedActivityId, f: OnDelete) {
-        self.on_delete[id.index] = f;
+        let i = self.slot(id);
+        self.on_delete[i] = f;
+    }
+    /// Calls the factory of a lazy activity that has not been constructed, yet.
+    fn construct(&mut self, index: usize) {
+        if let Some(factory) = self.factories.get_mut(index).and_then(Option::take) {
+            if let Some(slot) = self.data[index].as_mut() {
+                *slot = factory();
+            }
+        }
     }
     pub(crate) fn delete(&mut self, id: UncheckedActivityId, managed_state: &mut ManagedState) {
-        if let Some(activity) = self.data[id.index].take() {
+        let i = self.slot(id);
+        match self.on_delete[i] {
+            // The handler takes the activity, so a lazy activity must be constructed first
+            OnDelete::None => self.factories[i] = None,
+            _ => self.construct(i),
+        }
+        if let Some(activity) = self.data[i].take() {
             // Taking ownership to call FnOnce
             let mut on_delete = OnDelete::None;
-            std::mem::swap(&mut on_delete, &mut self.on_delete[id.index]);
+            std::mem::swap(&mut on_delete, &mut self.on_delete[i]);
             match on_delete {
                 OnDelete::None => { /* NOP  */ }
                 OnDelete::Simple(f) => f(activity),
-                OnDelete::WithDomain(f) => f(activity, managed_state),
+                OnDelete::WithDomain(f) => f(activity, self.domains[i], managed_state),
             }
         }
     }
+    /// Swaps the data of a living activity, the old data is dropped without calling on-delete handlers.
+    pub(crate) fn replace(&mut self, id: UncheckedActivityId, a: Box<dyn Any>) {
+        let i = self.slot(id);
+        if let Some(slot) = self.data[i].as_mut() {
+            *slot = a;
+            self.factories[i] = None;
+        }
+    }
+    /// Releases unused capacity. Slots of deleted activities stay in place, so that their ids are never reused.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        self.factories.shrink_to_fit();
+        self.type_ids.shrink_to_fit();
+        self.active.shrink_to_fit();
+        self.domains.shrink_to_fit();
+        self.on_delete.shrink_to_fit();
+        self.type_names.shrink_to_fit();
+    }
+    /// False for activities that have not been added, yet
+    pub(crate) fn is_deleted(&self, id: UncheckedActivityId) -> bool {
+        matches!(self.data.get(self.slot(id)), Some(None))
+    }
+    /// False if the activity has been deleted
+    pub(crate) fn is_alive(&self, id: UncheckedActivityId) -> bool {
+        matches!(self.data.get(self.slot(id)), Some(Some(_)))
+    }
     pub(crate) fn len(&self) -> usize {
         self.data.len()
     }
@@ -61,26 +167,43 @@ impl ActivityContainer {
     pub(crate) fn append(&mut self, other: &mut Self) {
         self.active.append(&mut other.active);
         self.data.append(&mut other.data);
+        self.factories.append(&mut other.factories);
+        self.type_ids.append(&mut other.type_ids);
+        self.domains.append(&mut other.domains);
         self.on_delete.append(&mut other.on_delete);
+        self.type_names.append(&mut other.type_names);
+    }
+    pub(crate) fn get_mut(&mut self, id: UncheckedActivityId) -> Option<&mut dyn Any> {
+        let i = self.slot(id);
+        self.construct(i);
+        self.data.get_mut(i)?.as_deref_mut()
+    }
+    pub(crate) fn type_name(&self, id: UncheckedActivityId) -> &'static str {
+        self.type_names[self.slot(id)]
     }
+    /// The first living activity of the type
     pub(crate) fn id_lookup(&self, t: TypeId) -> Option<UncheckedActivityId> {
+        self.instances(t).next()
+    }
+    /// All living activities of the type, in order of creation
+    pub(crate) fn instances(&self, t: TypeId) -> impl Iterator<Item = UncheckedActivityId> + '_ {
         // This is not the most efficient (if there are many activities) but it does the job to get something working.
         // If anyone ever find this to be a performance bottleneck in a real application, this can be fixed with some smarter implementation.
-        #[allow(clippy::unwrap_used)]
-        if let Some(index) = self.data.iter().position(|maybe_activity| {
-            maybe_activity.is_some() && (*maybe_activity.as_ref().unwrap().as_ref()).type_id() == t
-        }) {
-            Some(UncheckedActivityId { index })
-        } else {
-            None
-        }
+        self.type_ids
+            .iter()
+            .zip(&self.data)
+            .enumerate()
+            .filter(move |(_, (type_id, maybe_activity))| {
+                **type_id == t && maybe_activity.is_some()
+            })
+            .map(move |(index, _)| self.id(index))
     }
 }
 
 impl<A: Activity> Index<ActivityId<A>> for ActivityContainer {
     type Output = dyn Any;
     fn index(&self, id: ActivityId<A>) -> &Self::Output {
-        self.data[id.id.index]
+        self.data[self.slot(id.id)]
             .as_ref()
             .expect("Missing activity")
             .as_ref()
@@ -88,9 +211,8 @@ impl<A: Activity> Index<ActivityId<A>> for ActivityContainer {
 }
 impl<A: Activity> IndexMut<ActivityId<A>> for ActivityContainer {
     fn index_mut(&mut self, id: ActivityId<A>) -> &mut Self::Output {
-        self.data[id.id.index]
-            .as_mut()
-            .expect("Missing activity")
-            .as_mut()
+        let i = self.slot(id.id);
+        self.construct(i);
+        self.data[i].as_mut().expect("Missing activity").as_mut()
     }
 }
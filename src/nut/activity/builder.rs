@@ -0,0 +1,126 @@
+use super::*;
+use std::marker::PhantomData;
+
+type Wiring<A> = Box<dyn FnOnce(ActivityId<A, WithDomain>)>;
+
+/// Registers an activity together with its status, domain, and subscriptions in a single expression, created with [`nuts::activity`](fn.activity.html).
+///
+/// Domained subscriptions can only be added after [`domain`](#method.domain) has been called, otherwise the code does not compile.
+/// Nothing is registered before [`build`](#method.build) is called.
+pub struct ActivityBuilder<A, D = NoDomain> {
+    activity: A,
+    domain: DomainId,
+    status: LifecycleStatus,
+    wiring: Vec<Wiring<A>>,
+    phantom: PhantomData<D>,
+}
+
+impl<A: Activity> ActivityBuilder<A> {
+    pub(crate) fn new(activity: A) -> Self {
+        Self {
+            activity,
+            domain: DomainId::default(),
+            status: LifecycleStatus::Active,
+            wiring: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+    /// The activity joins the domain, as with [`nuts::new_domained_activity`](fn.new_domained_activity.html).
+    pub fn domain<DOMAIN: DomainEnumeration>(
+        self,
+        domain: &DOMAIN,
+    ) -> ActivityBuilder<A, WithDomain> {
+        ActivityBuilder {
+            activity: self.activity,
+            domain: DomainId::new(domain),
+            status: self.status,
+            wiring: self.wiring,
+            phantom: PhantomData,
+        }
+    }
+    /// Registers the activity and all subscriptions.
+    pub fn build(self) -> ActivityId<A> {
+        self.register().without_domain()
+    }
+}
+
+impl<A: Activity> ActivityBuilder<A, WithDomain> {
+    /// Adds a subscription, see [`ActivityId::subscribe_domained`](struct.ActivityId.html#method.subscribe_domained).
+    pub fn subscribe_domained<F, MSG>(self, f: F) -> Self
+    where
+        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.wire(move |id| {
+            id.subscribe_domained(f);
+        })
+    }
+    /// Adds a subscription, see [`ActivityId::subscribe_domained_masked`](struct.ActivityId.html#method.subscribe_domained_masked).
+    pub fn subscribe_domained_masked<F, MSG>(self, mask: SubscriptionFilter, f: F) -> Self
+    where
+        F: Fn(&mut A, &mut DomainState, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.wire(move |id| {
+            id.subscribe_domained_masked(mask, f);
+        })
+    }
+    /// Registers the activity and all subscriptions.
+    pub fn build(self) -> ActivityId<A, WithDomain> {
+        self.register()
+    }
+}
+
+impl<A: Activity, D> ActivityBuilder<A, D> {
+    /// The activity starts with status `Inactive`, subscriptions are only called once it has been set to `Active`.
+    pub fn start_inactive(mut self) -> Self {
+        self.status = LifecycleStatus::Inactive;
+        self
+    }
+    /// Adds a subscription, see [`ActivityId::subscribe`](struct.ActivityId.html#method.subscribe).
+    pub fn subscribe<F, MSG>(self, f: F) -> Self
+    where
+        F: Fn(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.wire(move |id| {
+            id.subscribe(f);
+        })
+    }
+    /// Adds a subscription, see [`ActivityId::subscribe_masked`](struct.ActivityId.html#method.subscribe_masked).
+    pub fn subscribe_masked<F, MSG>(self, mask: SubscriptionFilter, f: F) -> Self
+    where
+        F: Fn(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.wire(move |id| {
+            id.subscribe_masked(mask, f);
+        })
+    }
+    /// Adds a private channel, see [`ActivityId::private_channel`](struct.ActivityId.html#method.private_channel).
+    pub fn private_channel<F, MSG>(self, f: F) -> Self
+    where
+        F: Fn(&mut A, MSG) + 'static,
+        MSG: Any,
+    {
+        self.wire(move |id| {
+            id.private_channel(f);
+        })
+    }
+    fn wire(mut self, f: impl FnOnce(ActivityId<A, WithDomain>) + 'static) -> Self {
+        self.wiring.push(Box::new(f));
+        self
+    }
+    fn register(self) -> ActivityId<A, WithDomain> {
+        let id = crate::nut::new_activity(
+            NewActivity::Constructed(self.activity),
+            self.domain,
+            self.status,
+        )
+        .assume_domain();
+        for wire in self.wiring {
+            wire(id);
+        }
+        id
+    }
+}
@@ -0,0 +1,20 @@
+//! Releasing memory held for deleted activities, see `nuts::compact`.
+
+use crate::nut::{diagnostics::OrReport, Nut};
+
+impl Nut {
+    /// only access while no broadcast is ongoing
+    pub(crate) fn compact(&self) {
+        let orphaned = {
+            let mut activities = self.activities.try_borrow_mut().or_report();
+            activities.shrink_to_fit();
+            let dead = |id| activities.is_deleted(id);
+            self.mailboxes.try_borrow_mut().or_report().compact(dead);
+            // Subscriptions added after their activity has been deleted are never removed otherwise
+            self.subscriptions.compact(dead)
+        };
+        for topic in orphaned {
+            self.publish_orphaned(&topic);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+
+/// A named set of activities whose lifecycle status can be changed together.
+///
+/// [`set_status`](#method.set_status) transitions all members before any other event is processed.
+/// Enter and leave handlers are called in the order in which the members have been added to the group,
+/// with [`TransitionCause::GroupChange`](enum.TransitionCause.html#variant.GroupChange) as cause.
+///
+/// ### Example
+/// ```rust
+/// # use nuts::{ActivityGroup, LifecycleStatus};
+/// # struct Menu;
+/// # struct Sidebar;
+/// let mut screen = ActivityGroup::new("settings screen");
+/// screen.add(nuts::new_activity(Menu));
+/// screen.add(nuts::new_activity(Sidebar));
+/// screen.set_status(LifecycleStatus::Inactive);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ActivityGroup {
+    name: String,
+    members: Vec<UncheckedActivityId>,
+}
+
+impl ActivityGroup {
+    /// Creates an empty group. The name is included in the transition cause of members.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            members: vec![],
+        }
+    }
+    /// Adds an activity to the group. Adding the same activity twice has no effect.
+    pub fn add(&mut self, id: impl Into<UncheckedActivityId>) {
+        let id = id.into();
+        if !self.members.contains(&id) {
+            self.members.push(id);
+        }
+    }
+    /// Removes an activity from the group.
+    pub fn remove(&mut self, id: impl Into<UncheckedActivityId>) {
+        let id = id.into();
+        self.members.retain(|member| *member != id);
+    }
+    /// The name of the group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// All members, in the order they have been added.
+    pub fn members(&self) -> &[UncheckedActivityId] {
+        &self.members
+    }
+    /// Changes the lifecycle status of all members.
+    ///
+    /// Members that have been deleted in the meantime are skipped.
+    pub fn set_status(&self, status: LifecycleStatus) {
+        crate::nut::set_group_status(&self.members, status, &self.name);
+    }
+}
@@ -0,0 +1,47 @@
+//! Stable names of activities, see `nuts::new_activity_keyed`.
+
+use super::*;
+use crate::nut::{diagnostics::OrReport, NUT};
+use std::collections::HashMap;
+
+/// Keys of all living activities registered with `nuts::new_activity_keyed`
+#[derive(Default)]
+pub(crate) struct ActivityKeys {
+    keys: HashMap<String, UncheckedActivityId>,
+}
+
+impl ActivityKeys {
+    pub(crate) fn get(&self, key: &str) -> Option<UncheckedActivityId> {
+        self.keys.get(key).copied()
+    }
+    /// Frees the key of a deleted activity, so that it can be registered again.
+    pub(crate) fn forget(&mut self, id: UncheckedActivityId) {
+        self.keys.retain(|_, keyed| *keyed != id);
+    }
+}
+
+pub(crate) fn new_keyed_activity<A: Activity>(key: String, activity: A) -> ActivityId<A> {
+    if let Some(id) = lookup(&key) {
+        panic!(
+            "Activity key {:?} is already used by activity {}",
+            key, id.index
+        );
+    }
+    let id = crate::nut::new_activity(
+        NewActivity::Constructed(activity),
+        DomainId::default(),
+        LifecycleStatus::Active,
+    );
+    NUT.with(|nut| {
+        nut.activity_keys
+            .try_borrow_mut()
+            .or_report()
+            .keys
+            .insert(key, id.into())
+    });
+    id
+}
+
+pub(crate) fn lookup(key: &str) -> Option<UncheckedActivityId> {
+    NUT.with(|nut| nut.activity_keys.try_borrow().or_report().get(key))
+}
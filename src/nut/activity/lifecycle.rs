@@ -1,5 +1,5 @@
 use super::*;
-use crate::nut::{iac::publish::BroadcastInfo, Nut, IMPOSSIBLE_ERR_MSG};
+use crate::nut::{diagnostics::OrReport, iac::publish::BroadcastInfo, Nut};
 
 // @ START-DOC ACTIVITY_LIFECYCLE
 /// Each activity has a lifecycle status that can be changed using [`set_status`](struct.ActivityId.html#method.set_status).
@@ -11,6 +11,8 @@ use crate::nut::{iac::publish::BroadcastInfo, Nut, IMPOSSIBLE_ERR_MSG};
 /// A subscription filter can been used to change this behavior.
 /// (See [`subscribe_masked`](struct.ActivityId.html#method.subscribe_masked))
 ///
+/// The buffering status also puts activities to sleep, but published messages it misses are held back and delivered once it is active again.
+///
 /// If the status of a changes from active to inactive, the activity's [`on_leave`](struct.ActivityId.html#method.on_leave) and [`on_leave_domained`](struct.ActivityId.html#method.on_leave_domained) subscriptions will be called.
 ///
 /// If the status of a changes from inactive to active, the activity's [`on_enter`](struct.ActivityId.html#method.on_enter) and [`on_enter_domained`](struct.ActivityId.html#method.on_enter_domained) subscriptions will be called.
@@ -23,14 +25,93 @@ pub enum LifecycleStatus {
     Active,
     /// Inactive / Sleeping
     Inactive,
+    /// Inactive, but published messages that would be delivered to an active activity are held back
+    /// and delivered in order when it becomes active again.
+    ///
+    /// Only messages of types registered with [`nuts::make_bufferable`](fn.make_bufferable.html) are held.
+    /// The number of messages held per activity is bounded, see [`set_buffer_capacity`](struct.ActivityId.html#method.set_buffer_capacity).
+    Buffering,
     /// Mark for deletion, the activity will be removed and `on_delete` called on it.
     /// Setting to this state twice will cause panics.
     Deleted,
 }
 
+/// Describes why the lifecycle status of an activity has changed.
+///
+/// This is the payload of enter and leave events, which can be received with
+/// [`on_enter_with_cause`](struct.ActivityId.html#method.on_enter_with_cause)
+/// and [`on_leave_with_cause`](struct.ActivityId.html#method.on_leave_with_cause).
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum TransitionCause {
+    /// The status has been set explicitly, using `set_status`.
+    Manual,
+    /// The status of a group of activities has been changed. The name of the group is included.
+    GroupChange(String),
+    /// The status of the parent activity has been changed, which cascaded down to this activity.
+    ParentCascade(UncheckedActivityId),
+    /// The activity has been put to sleep after one of its handlers panicked.
+    PanicRecovery,
+    /// All activities are deleted by `nuts::shutdown`.
+    Shutdown,
+    /// A supervisor has replaced the activity with a fresh instance, instead of deleting it or putting it to sleep.
+    /// The status stays the same.
+    SupervisorRestart,
+}
+
+/// Payload of [`on_transition`](struct.ActivityId.html#method.on_transition) handlers, describes a single change of the lifecycle status.
+///
+/// Unlike enter and leave events, a transition is reported for every change, so that handlers can tell a pause from a pending deletion.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub struct LifecycleTransition {
+    /// The status before the change
+    pub from: LifecycleStatus,
+    /// The status after the change
+    pub to: LifecycleStatus,
+    /// Why the status has changed
+    pub cause: TransitionCause,
+}
+
+/// Published by [`request_delete`](struct.ActivityId.html#method.request_delete) before the activity is deleted.
+///
+/// Subscribers can detach references to the activity or prevent the deletion with [`veto`](#method.veto).
+pub struct AboutToDelete<A> {
+    id: ActivityId<A>,
+    vetoed: std::rc::Rc<std::cell::Cell<bool>>,
+}
+
+impl<A> AboutToDelete<A> {
+    pub(crate) fn new(id: ActivityId<A>, vetoed: std::rc::Rc<std::cell::Cell<bool>>) -> Self {
+        Self { id, vetoed }
+    }
+    /// The activity that is about to be deleted.
+    pub fn id(&self) -> ActivityId<A> {
+        self.id
+    }
+    /// Keeps the activity alive. Only has an effect while the message is being delivered.
+    pub fn veto(&self) {
+        self.vetoed.set(true);
+    }
+    /// True iff a subscriber has vetoed the deletion, so far.
+    pub fn is_vetoed(&self) -> bool {
+        self.vetoed.get()
+    }
+}
+
 pub(crate) struct LifecycleChange {
-    activity: UncheckedActivityId,
-    status: LifecycleStatus,
+    pub(super) activity: UncheckedActivityId,
+    pub(super) status: LifecycleStatus,
+    pub(super) cause: TransitionCause,
+}
+
+impl LifecycleChange {
+    pub(crate) fn activity(&self) -> UncheckedActivityId {
+        self.activity
+    }
+    pub(crate) fn status(&self) -> LifecycleStatus {
+        self.status
+    }
 }
 
 impl LifecycleStatus {
@@ -42,18 +123,61 @@ impl LifecycleStatus {
         match self {
             Self::Active => true,
             Self::Inactive => false,
+            Self::Buffering => false,
             Self::Deleted => false,
         }
     }
 }
 
 impl Nut {
-    pub(crate) fn set_status(&self, id: UncheckedActivityId, status: LifecycleStatus) {
+    pub(crate) fn set_status(
+        &self,
+        id: UncheckedActivityId,
+        status: LifecycleStatus,
+        cause: TransitionCause,
+    ) {
         let event = LifecycleChange {
             activity: id,
             status,
+            cause,
+        };
+        self.push_deferred(event.into());
+        self.catch_up_deferred_to_quiescence();
+    }
+    pub(crate) fn set_group_status(
+        &self,
+        members: &[UncheckedActivityId],
+        status: LifecycleStatus,
+        name: &str,
+    ) {
+        for activity in members {
+            let event = LifecycleChange {
+                activity: *activity,
+                status,
+                cause: TransitionCause::GroupChange(name.to_owned()),
+            };
+            self.push_deferred(event.into());
+        }
+        self.catch_up_deferred_to_quiescence();
+    }
+    /// Deletes all activities, newest first, and executes all events caused by that.
+    pub(crate) fn delete_all_activities(&self) {
+        let alive: Vec<UncheckedActivityId> = {
+            let activities = self.activities.try_borrow().or_report();
+            (1..activities.len())
+                .rev()
+                .map(|index| activities.id(index))
+                .filter(|id| activities.status(*id) != LifecycleStatus::Deleted)
+                .collect()
         };
-        self.deferred_events.push(event.into());
+        for activity in alive {
+            let event = LifecycleChange {
+                activity,
+                status: LifecycleStatus::Deleted,
+                cause: TransitionCause::Shutdown,
+            };
+            self.push_deferred(event.into());
+        }
         self.catch_up_deferred_to_quiescence();
     }
     /// only access after locking with executing flag
@@ -61,8 +185,24 @@ impl Nut {
         let before = self
             .activities
             .try_borrow()
-            .expect(IMPOSSIBLE_ERR_MSG)
+            .or_report()
             .status(lifecycle_change.activity);
+        if before == LifecycleStatus::Deleted
+            && matches!(
+                lifecycle_change.cause,
+                TransitionCause::GroupChange(_) | TransitionCause::ParentCascade(_)
+            )
+        {
+            // Groups and supervisors may still list members that have been deleted
+            return;
+        }
+        if before != LifecycleStatus::Deleted
+            && lifecycle_change.status == LifecycleStatus::Deleted
+            && lifecycle_change.cause != TransitionCause::Shutdown
+            && self.restart_supervised(lifecycle_change.activity, false)
+        {
+            return;
+        }
         if before != lifecycle_change.status {
             assert_ne!(
                 before,
@@ -72,47 +212,88 @@ impl Nut {
             );
             self.activities
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
+                .or_report()
                 .set_status(lifecycle_change.activity, lifecycle_change.status);
             if !before.is_active() && lifecycle_change.status.is_active() {
                 self.broadcast(BroadcastInfo::local(
-                    (),
+                    lifecycle_change.cause.clone(),
                     lifecycle_change.activity,
                     Topic::enter(),
                 ));
             } else if before.is_active() && !lifecycle_change.status.is_active() {
                 self.broadcast(BroadcastInfo::local(
-                    (),
+                    lifecycle_change.cause.clone(),
                     lifecycle_change.activity,
                     Topic::leave(),
                 ));
             }
+            self.broadcast_transition(
+                lifecycle_change.activity,
+                LifecycleTransition {
+                    from: before,
+                    to: lifecycle_change.status,
+                    cause: lifecycle_change.cause.clone(),
+                },
+            );
+            if before == LifecycleStatus::Buffering {
+                if lifecycle_change.status.is_active() {
+                    self.release_held(lifecycle_change.activity);
+                } else {
+                    self.activity_buffers
+                        .try_borrow_mut()
+                        .or_report()
+                        .forget_held(lifecycle_change.activity);
+                }
+            }
         }
         if lifecycle_change.status == LifecycleStatus::Deleted {
+            self.end_supervision(lifecycle_change.activity);
             // Delete must be deferred in case the on_leave is hanging.
-            self.deferred_events
-                .push(nut::exec::Deferred::RemoveActivity(
-                    lifecycle_change.activity,
-                ));
+            self.push_deferred(nut::exec::Deferred::RemoveActivity(
+                lifecycle_change.activity,
+            ));
+        }
+    }
+    /// Skipped for activities without `on_transition` handlers, which keeps status changes as cheap as before.
+    pub(crate) fn broadcast_transition(
+        &self,
+        id: UncheckedActivityId,
+        transition: LifecycleTransition,
+    ) {
+        let subscribed = self
+            .subscriptions
+            .get()
+            .get(&Topic::transition())
+            .is_some_and(|subs| {
+                subs.shared_subscriptions_of_single_activity(id)
+                    .next()
+                    .is_some()
+            });
+        if subscribed {
+            self.broadcast(BroadcastInfo::local(transition, id, Topic::transition()));
         }
     }
     pub(crate) fn delete_activity(&self, id: UncheckedActivityId) {
         self.activities
             .try_borrow_mut()
-            .expect(IMPOSSIBLE_ERR_MSG)
-            .delete(
-                id,
-                &mut self
-                    .managed_state
-                    .try_borrow_mut()
-                    .expect(IMPOSSIBLE_ERR_MSG),
-            );
+            .or_report()
+            .delete(id, &mut self.managed_state.try_borrow_mut().or_report());
+        self.unsubscribe_all(id);
+        self.activity_buffers
+            .try_borrow_mut()
+            .or_report()
+            .forget(id);
+        self.activity_keys.try_borrow_mut().or_report().forget(id);
     }
 }
 
 #[cfg(debug_assertions)]
 impl std::fmt::Debug for LifecycleChange {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Transition to state: {:?}", self.status)
+        write!(
+            f,
+            "Transition to state: {:?} ({:?})",
+            self.status, self.cause
+        )
     }
 }
@@ -0,0 +1,18 @@
+use super::*;
+
+/// Exclusive access to an activity, handed out by [`ActivityId::borrow_scope`](struct.ActivityId.html#method.borrow_scope).
+///
+/// Activities stay borrowed for the entire scope, each call to [`with`](#method.with) is just a function call.
+pub struct ActivityScope<'a, A> {
+    activity: &'a mut A,
+}
+
+impl<'a, A: Activity> ActivityScope<'a, A> {
+    pub(crate) fn new(activity: &'a mut A) -> Self {
+        Self { activity }
+    }
+    /// Calls the closure with the activity.
+    pub fn with<R>(&mut self, f: impl FnOnce(&mut A) -> R) -> R {
+        f(self.activity)
+    }
+}
@@ -0,0 +1,146 @@
+use super::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type TransitionHandler = Rc<dyn Fn()>;
+
+/// Maps the states of an application-defined enum onto the lifecycle status of activities.
+///
+/// Each activity is bound to one or more states and is active exactly while the machine is in one of them.
+/// Activities that are not bound to any state are not touched.
+/// In the same way, subscriptions tagged with [`ActivityId::subscribe_tagged`](struct.ActivityId.html#method.subscribe_tagged) can be bound to states with [`bind_tag`](#method.bind_tag).
+/// Status changes use [`TransitionCause::GroupChange`](enum.TransitionCause.html#variant.GroupChange) with the name of the machine as cause.
+///
+/// The machine is a handle, clones share the same state. This allows changing the state from inside of subscription handlers.
+///
+/// ### Example
+/// ```rust
+/// # use nuts::ActivityStateMachine;
+/// #[derive(Clone, PartialEq)]
+/// enum Screen { Menu, Game }
+/// struct MainMenu;
+/// struct World;
+///
+/// let screens = ActivityStateMachine::new("screens", Screen::Menu);
+/// screens.bind(Screen::Menu, nuts::new_activity(MainMenu));
+/// screens.bind(Screen::Game, nuts::new_activity(World));
+/// screens.on_transition(Screen::Menu, Screen::Game, || println!("Game started"));
+/// // Deactivates the menu, activates the world, and prints "Game started"
+/// screens.set(Screen::Game);
+/// ```
+pub struct ActivityStateMachine<S> {
+    inner: Rc<RefCell<Machine<S>>>,
+}
+
+struct Machine<S> {
+    name: String,
+    state: S,
+    bindings: Vec<(S, UncheckedActivityId)>,
+    tags: Vec<(S, String)>,
+    transitions: Vec<(S, S, TransitionHandler)>,
+}
+
+impl<S: Clone + PartialEq + 'static> ActivityStateMachine<S> {
+    /// Creates a machine in the initial state. The name is included in the transition cause of bound activities.
+    pub fn new(name: impl Into<String>, initial: S) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(Machine {
+                name: name.into(),
+                state: initial,
+                bindings: vec![],
+                tags: vec![],
+                transitions: vec![],
+            })),
+        }
+    }
+    /// Keeps the activity active while the machine is in this state.
+    ///
+    /// The status of the activity is updated right away to match the current state.
+    pub fn bind(&self, state: S, id: impl Into<UncheckedActivityId>) {
+        self.inner.borrow_mut().bindings.push((state, id.into()));
+        self.apply();
+    }
+    /// Keeps the subscriptions with this tag enabled while the machine is in this state, see [`nuts::set_tag_enabled`](fn.set_tag_enabled.html).
+    ///
+    /// A tag can be bound to several states. It is updated right away to match the current state.
+    pub fn bind_tag(&self, state: S, tag: impl Into<String>) {
+        self.inner.borrow_mut().tags.push((state, tag.into()));
+        self.apply();
+    }
+    /// Registers a handler that is called each time the machine changes from one state to the other.
+    ///
+    /// The handler is called after the lifecycle status of bound activities has been changed.
+    pub fn on_transition(&self, from: S, to: S, f: impl Fn() + 'static) {
+        self.inner
+            .borrow_mut()
+            .transitions
+            .push((from, to, Rc::new(f)));
+    }
+    /// The current state.
+    pub fn state(&self) -> S {
+        self.inner.borrow().state.clone()
+    }
+    /// Changes the state, which updates the lifecycle status of bound activities and calls matching transition handlers.
+    ///
+    /// Setting the current state again has no effect.
+    pub fn set(&self, state: S) {
+        let handlers: Vec<TransitionHandler> = {
+            let mut machine = self.inner.borrow_mut();
+            if machine.state == state {
+                return;
+            }
+            let from = std::mem::replace(&mut machine.state, state);
+            machine
+                .transitions
+                .iter()
+                .filter(|(f, t, _)| *f == from && *t == machine.state)
+                .map(|(_, _, handler)| handler.clone())
+                .collect()
+        };
+        self.apply();
+        for handler in handlers {
+            handler();
+        }
+    }
+    /// Activates the activities and tags bound to the current state and deactivates all others.
+    ///
+    /// Lifecycle handlers may change the state again, hence the machine is not borrowed while they run.
+    fn apply(&self) {
+        let (state, name, active, inactive) = {
+            let machine = self.inner.borrow();
+            for (_, tag) in &machine.tags {
+                let enabled = machine
+                    .tags
+                    .iter()
+                    .any(|(s, t)| t == tag && *s == machine.state);
+                crate::nut::set_tag_enabled(tag, enabled);
+            }
+            let (active, inactive): (Vec<_>, Vec<_>) =
+                machine.bindings.iter().map(|(_, id)| *id).partition(|id| {
+                    machine
+                        .bindings
+                        .iter()
+                        .any(|(s, bound)| bound == id && *s == machine.state)
+                });
+            (
+                machine.state.clone(),
+                machine.name.clone(),
+                active,
+                inactive,
+            )
+        };
+        crate::nut::set_group_status(&inactive, LifecycleStatus::Inactive, &name);
+        // A nested state change has already applied the newer state
+        if self.inner.borrow().state == state {
+            crate::nut::set_group_status(&active, LifecycleStatus::Active, &name);
+        }
+    }
+}
+
+impl<S> Clone for ActivityStateMachine<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
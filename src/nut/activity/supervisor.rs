@@ -0,0 +1,119 @@
+//! Supervised activities, which are restarted from a factory instead of failing or being deleted.
+
+use crate::nut::{diagnostics::OrReport, Nut};
+use crate::*;
+use core::any::Any;
+use std::rc::Rc;
+
+/// Defines when a supervised activity is restarted, see [`ActivityId::supervise`](struct.ActivityId.html#method.supervise).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RestartPolicy {
+    /// Restart when a handler panics while [`PanicPolicy::RecoverAndReport`](enum.PanicPolicy.html) is active, instead of setting the activity to inactive.
+    OnPanic,
+    /// Restart when the activity is deleted, instead of deleting it.
+    OnDelete,
+    /// Restart in both cases.
+    Always,
+}
+
+pub(crate) type RestartFactory = Rc<dyn Fn() -> Box<dyn Any>>;
+
+struct Supervision {
+    supervisor: UncheckedActivityId,
+    child: UncheckedActivityId,
+    policy: RestartPolicy,
+    factory: RestartFactory,
+}
+
+/// All supervisions registered with `ActivityId::supervise`
+#[derive(Default)]
+pub(crate) struct Supervisions {
+    list: Vec<Supervision>,
+}
+
+impl Supervisions {
+    /// Replaces the previous supervision of the child, if any.
+    pub(crate) fn add(
+        &mut self,
+        supervisor: UncheckedActivityId,
+        child: UncheckedActivityId,
+        policy: RestartPolicy,
+        factory: RestartFactory,
+    ) {
+        self.list.retain(|s| s.child != child);
+        self.list.push(Supervision {
+            supervisor,
+            child,
+            policy,
+            factory,
+        });
+    }
+    fn factory(&self, child: UncheckedActivityId, panicked: bool) -> Option<RestartFactory> {
+        self.list
+            .iter()
+            .find(|s| s.child == child)
+            .filter(|s| match s.policy {
+                RestartPolicy::OnPanic => panicked,
+                RestartPolicy::OnDelete => !panicked,
+                RestartPolicy::Always => true,
+            })
+            .map(|s| s.factory.clone())
+    }
+}
+
+impl Nut {
+    /// Swaps in a fresh instance of a supervised activity, if its restart policy covers the failure.
+    /// Subscriptions, lifecycle status, domain, and on-delete handlers are kept.
+    ///
+    /// Returns true iff the activity has been restarted.
+    pub(crate) fn restart_supervised(&self, child: UncheckedActivityId, panicked: bool) -> bool {
+        let factory = self
+            .supervisions
+            .try_borrow()
+            .or_report()
+            .factory(child, panicked);
+        match factory {
+            Some(factory) => {
+                let fresh = factory();
+                let status = {
+                    let mut activities = self.activities.try_borrow_mut().or_report();
+                    activities.replace(child, fresh);
+                    activities.status(child)
+                };
+                self.broadcast_transition(
+                    child,
+                    LifecycleTransition {
+                        from: status,
+                        to: status,
+                        cause: TransitionCause::SupervisorRestart,
+                    },
+                );
+                true
+            }
+            None => false,
+        }
+    }
+    /// Removes all supervisions of a deleted activity and deletes the activities it supervised.
+    pub(crate) fn end_supervision(&self, id: UncheckedActivityId) {
+        let mut children = Vec::new();
+        self.supervisions
+            .try_borrow_mut()
+            .or_report()
+            .list
+            .retain(|s| {
+                if s.supervisor == id {
+                    children.push(s.child);
+                }
+                s.supervisor != id && s.child != id
+            });
+        for child in children {
+            let event = LifecycleChange {
+                activity: child,
+                status: LifecycleStatus::Deleted,
+                cause: TransitionCause::ParentCascade(id),
+            };
+            self.push_deferred(event.into());
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use super::*;
+use std::rc::Rc;
+
+/// A prototype for spawning many similar activities, created with [`nuts::template`](fn.template.html).
+///
+/// Every spawned activity is created by the factory and then receives the same subscriptions.
+/// Clones of a template share the factory and the wiring.
+pub struct ActivityTemplate<ARGS, A> {
+    factory: Rc<dyn Fn(ARGS) -> A>,
+    wire: Rc<dyn Fn(ActivityId<A>)>,
+}
+
+impl<ARGS, A: Activity> ActivityTemplate<ARGS, A> {
+    pub(crate) fn new(
+        factory: impl Fn(ARGS) -> A + 'static,
+        wire: impl Fn(ActivityId<A>) + 'static,
+    ) -> Self {
+        Self {
+            factory: Rc::new(factory),
+            wire: Rc::new(wire),
+        }
+    }
+    /// Creates a new activity from the arguments and registers all subscriptions of the template for it.
+    pub fn spawn(&self, args: ARGS) -> ActivityId<A> {
+        let id = crate::new_activity((self.factory)(args));
+        (self.wire)(id);
+        id
+    }
+    /// Same as [`spawn`](#method.spawn) but the new activity joins a domain.
+    pub fn spawn_domained<D: DomainEnumeration>(
+        &self,
+        args: ARGS,
+        domain: &D,
+    ) -> ActivityId<A, WithDomain> {
+        let id = crate::new_domained_activity_checked((self.factory)(args), domain);
+        (self.wire)(id.without_domain());
+        id
+    }
+}
+
+impl<ARGS, A> Clone for ActivityTemplate<ARGS, A> {
+    fn clone(&self) -> Self {
+        Self {
+            factory: self.factory.clone(),
+            wire: self.wire.clone(),
+        }
+    }
+}
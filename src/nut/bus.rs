@@ -0,0 +1,203 @@
+//! Isolated nuts, see `nuts::Bus`.
+//!
+//! All internal code accesses state through `NUT`, which resolves to the innermost entered bus,
+//! or to the default nut of the thread if no bus is entered.
+//! Code that acts on an activity id enters the bus of the id first, see `CurrentNut::with_bus`.
+
+use super::Nut;
+use crate::*;
+use core::any::Any;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread::AccessError;
+
+pub(crate) const STALE_ID_ERR_MSG: &str =
+    "ActivityId used after its bus has been reset or dropped, or on another thread than the one it has been created on.";
+
+/// Nuts of this thread, the default nut, those of entered buses, and all buses that are alive
+struct ThreadNuts {
+    default: Nut,
+    /// `None` stands for the default nut, which is entered when an id of it is used inside a bus
+    entered: RefCell<Vec<Option<Rc<Nut>>>>,
+    buses: RefCell<Vec<Weak<Nut>>>,
+}
+
+thread_local!(static THREAD_NUTS: ThreadNuts = ThreadNuts {
+    default: Nut::new(BusId::default()),
+    entered: RefCell::new(Vec::new()),
+    buses: RefCell::new(Vec::new()),
+});
+
+/// Identifies the bus of an activity, `0` for the default nut of each thread until it is reset.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub(crate) struct BusId(u32);
+
+impl BusId {
+    pub(super) fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(1);
+        BusId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Handle to the nut that is currently in use on this thread, with the same interface as a `LocalKey`.
+pub(super) struct CurrentNut;
+
+impl CurrentNut {
+    pub(super) fn with<R>(&'static self, f: impl FnOnce(&Nut) -> R) -> R {
+        self.try_with(f)
+            .expect("cannot access a Thread Local Storage value during or after destruction")
+    }
+    /// Only inside a bus, the nut is cloned, so that the bus can be entered again from `f`.
+    pub(super) fn try_with<R>(&'static self, f: impl FnOnce(&Nut) -> R) -> Result<R, AccessError> {
+        THREAD_NUTS.try_with(|nuts| {
+            let entered = nuts.entered.borrow().last().cloned().flatten();
+            match entered {
+                Some(nut) => f(&nut),
+                None => f(&nuts.default),
+            }
+        })
+    }
+    /// Like `with`, but on the nut of the bus, which is entered for the duration of `f` if it is not the current one.
+    ///
+    /// Panics if the bus has been dropped.
+    pub(super) fn with_bus<R>(&'static self, bus: BusId, f: impl FnOnce(&Nut) -> R) -> R {
+        on_bus(bus, || self.with(f))
+    }
+}
+
+/// Calls `f` inside the bus, entering it unless it is the current one.
+pub(super) fn on_bus<R>(bus: BusId, f: impl FnOnce() -> R) -> R {
+    let target = THREAD_NUTS.with(|nuts| {
+        let entered = nuts.entered.borrow().last().cloned().flatten();
+        let current = entered.as_ref().map_or(&nuts.default, |nut| nut).bus.get();
+        if current == bus {
+            None
+        } else if bus == nuts.default.bus.get() {
+            Some(None)
+        } else {
+            let nut = nuts
+                .buses
+                .borrow()
+                .iter()
+                .filter_map(Weak::upgrade)
+                .find(|nut| nut.bus.get() == bus);
+            Some(Some(nut.expect(STALE_ID_ERR_MSG)))
+        }
+    });
+    match target {
+        None => f(),
+        Some(nut) => enter(nut, f),
+    }
+}
+
+/// Calls `f` with the nut in place of the default nut, or with the default nut for `None`.
+fn enter<R>(nut: Option<Rc<Nut>>, f: impl FnOnce() -> R) -> R {
+    THREAD_NUTS.with(|nuts| nuts.entered.borrow_mut().push(nut));
+    let _entered = Entered;
+    f()
+}
+
+/// Leaves the bus, also when unwinding.
+struct Entered;
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let _ = THREAD_NUTS.try_with(|nuts| nuts.entered.borrow_mut().pop());
+    }
+}
+
+/// A future that is always polled inside its bus.
+struct OnBus<F> {
+    nut: Rc<Nut>,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for OnBus<F> {
+    type Output = F::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let future = this.future.as_mut();
+        enter(Some(this.nut.clone()), || future.poll(cx))
+    }
+}
+
+/// An independent instance of nuts, with its own activities, subscriptions, domains, and message queue.
+///
+/// All free functions, such as `nuts::publish`, use the default bus of the thread.
+/// Libraries that use nuts internally can create their own bus, so their messages never collide with those of the application or other libraries.
+///
+/// Inside [`enter`](#method.enter), the free functions operate on the bus instead of the default bus.
+/// Handlers of activities created on the bus are always called inside it.
+/// Methods of `ActivityId`s always act on the bus the id has been created on, no matter if they are called inside or outside of `enter`.
+///
+/// ### Example
+/// ```rust
+/// use nuts::Bus;
+/// struct Counter(usize);
+///
+/// let bus = Bus::new();
+/// let counter = bus.new_activity(Counter(0));
+/// counter.subscribe(|counter, n: &usize| counter.0 += n);
+/// bus.publish(5usize);
+/// // The default bus has no activity receiving `usize`
+/// nuts::publish(100usize);
+/// assert_eq!(Some(5), bus.enter(|| nuts::read_activity(|c: &Counter| c.0)));
+/// ```
+pub struct Bus {
+    nut: Rc<Nut>,
+}
+
+impl Bus {
+    /// Creates an empty bus.
+    pub fn new() -> Self {
+        let nut = Rc::new(Nut::new(BusId::next()));
+        THREAD_NUTS.with(|nuts| {
+            let mut buses = nuts.buses.borrow_mut();
+            buses.retain(|nut| nut.strong_count() > 0);
+            buses.push(Rc::downgrade(&nut));
+        });
+        Self { nut }
+    }
+    /// Calls `f` with this bus in place of the default bus. Calls can be nested.
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        enter(Some(self.nut.clone()), f)
+    }
+    /// Wraps the future so that it is always polled inside this bus, such as the futures returned by `nuts::request` inside `enter`.
+    pub fn enter_future<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        OnBus {
+            nut: self.nut.clone(),
+            future: Box::pin(future),
+        }
+    }
+    /// Same as [`nuts::new_activity`](fn.new_activity.html), on this bus.
+    pub fn new_activity<A>(&self, activity: A) -> ActivityId<A>
+    where
+        A: Activity,
+    {
+        self.enter(|| crate::new_activity::<A>(activity))
+    }
+    /// Same as [`nuts::publish`](fn.publish.html), on this bus.
+    pub fn publish<A: Any>(&self, a: A) {
+        self.enter(|| crate::publish::<A>(a))
+    }
+    /// Same as [`nuts::reset`](fn.reset.html), on this bus.
+    pub fn reset(&self) {
+        self.enter(crate::reset)
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bus")
+    }
+}
@@ -0,0 +1,147 @@
+//! Diagnostic reports for failed borrows of the internal state.
+//!
+//! The internal state is split into many `RefCell`s and the library is designed such that borrows never overlap.
+//! If they do anyway, for example because nuts has been re-entered through an FFI callback in an unexpected way,
+//! a report with as much context as possible is more helpful than the usual one-line panic message.
+
+use super::{Nut, IMPOSSIBLE_ERR_MSG, NUT};
+use core::cell::{BorrowError, BorrowMutError};
+#[cfg(debug_assertions)]
+use std::collections::VecDeque;
+
+/// Maximum number of queued events listed in a report
+#[cfg(debug_assertions)]
+const MAX_LISTED_EVENTS: usize = 10;
+
+/// Number of message types kept for `PanicContext::recent_topics`
+#[cfg(debug_assertions)]
+const RECENT_TOPICS: usize = 16;
+
+/// What nuts was doing when a panic occurred, returned by [`nuts::panic_context`](fn.panic_context.html).
+///
+/// Unlike [`nuts::panic_info`](fn.panic_info.html), the information is not formatted, so that it can be attached to error reports as structured metadata.
+#[cfg(debug_assertions)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PanicContext {
+    /// Type name of the activity whose handler is running, if any
+    pub activity: Option<&'static str>,
+    /// Type name of the message that is being delivered, if any
+    pub message: Option<&'static str>,
+    /// Number of queued events, `None` if the queue could not be read
+    pub queue_len: Option<usize>,
+    /// Type names of the most recently delivered messages, the oldest first
+    pub recent_topics: Vec<&'static str>,
+}
+
+/// Ring buffer of the last delivered message types
+#[cfg(debug_assertions)]
+#[derive(Default)]
+pub(crate) struct RecentTopics {
+    topics: VecDeque<&'static str>,
+    delivering: bool,
+}
+
+/// Unwraps the result of `try_borrow` or `try_borrow_mut` on internal state.
+pub(crate) trait OrReport<T> {
+    /// Panics with a diagnostic report if the borrow failed.
+    fn or_report(self) -> T;
+}
+
+impl<T> OrReport<T> for Result<T, BorrowError> {
+    #[track_caller]
+    fn or_report(self) -> T {
+        match self {
+            Ok(t) => t,
+            Err(_) => borrow_conflict(),
+        }
+    }
+}
+
+impl<T> OrReport<T> for Result<T, BorrowMutError> {
+    #[track_caller]
+    fn or_report(self) -> T {
+        match self {
+            Ok(t) => t,
+            Err(_) => borrow_conflict(),
+        }
+    }
+}
+
+#[cold]
+#[track_caller]
+fn borrow_conflict() -> ! {
+    let location = std::panic::Location::caller();
+    let report = NUT
+        .try_with(Nut::diagnostic_report)
+        .unwrap_or_else(|_| "The nut is not accessible anymore.".to_owned());
+    panic!(
+        "{}\nConflicting borrow of internal state at {}.\n{}",
+        IMPOSSIBLE_ERR_MSG, location, report
+    )
+}
+
+#[cfg(debug_assertions)]
+impl Nut {
+    pub(crate) fn record_dispatch(&self, topic: &'static str) {
+        let mut recent = self.recent_topics.try_borrow_mut().or_report();
+        if recent.topics.len() == RECENT_TOPICS {
+            recent.topics.pop_front();
+        }
+        recent.topics.push_back(topic);
+        recent.delivering = true;
+    }
+    pub(crate) fn finish_dispatch(&self) {
+        self.recent_topics.try_borrow_mut().or_report().delivering = false;
+    }
+    /// Like the diagnostic report, this must not fail because of borrows that are still held by a panicking handler.
+    pub(crate) fn panic_context(&self) -> PanicContext {
+        let (message, recent_topics) = match self.recent_topics.try_borrow() {
+            Ok(recent) => (
+                recent.topics.back().copied().filter(|_| recent.delivering),
+                recent.topics.iter().copied().collect(),
+            ),
+            Err(_) => (None, Vec::new()),
+        };
+        PanicContext {
+            activity: self.active_activity_name.get().map(|name| name.0),
+            message,
+            queue_len: self.deferred_events.try_len(),
+            recent_topics,
+        }
+    }
+}
+
+impl Nut {
+    /// Describes what nuts is doing right now, without borrowing anything that might already be borrowed.
+    fn diagnostic_report(&self) -> String {
+        let mut report = format!(
+            "Executing deferred events: {}\n",
+            self.executing.load(std::sync::atomic::Ordering::Relaxed)
+        );
+        #[cfg(debug_assertions)]
+        {
+            let event = self
+                .executing_event
+                .try_borrow()
+                .ok()
+                .and_then(|e| e.clone());
+            report += &format!("Current event: {}\n", event.as_deref().unwrap_or("none"));
+            let activity = self.active_activity_name.get().map(|name| name.0);
+            report += &format!("Active activity: {}\n", activity.unwrap_or("none"));
+        }
+        match self.deferred_events.try_len() {
+            Some(len) => report += &format!("Queued events: {}\n", len),
+            None => report += "Queued events: unknown, the queue is borrowed\n",
+        }
+        #[cfg(debug_assertions)]
+        for event in self
+            .deferred_events
+            .debug_head(MAX_LISTED_EVENTS)
+            .unwrap_or_default()
+        {
+            report += &format!("    {}\n", event);
+        }
+        report
+    }
+}
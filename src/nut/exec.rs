@@ -1,30 +1,170 @@
 use crate::nut::activity::LifecycleChange;
+use crate::nut::diagnostics::OrReport;
 use crate::nut::iac::publish::{BroadcastInfo, ResponseSlot};
-use crate::nut::Nut;
+use crate::nut::iac::topic::Topic;
+use crate::nut::{Nut, OnceHandler};
 use crate::DomainStoreData;
 use crate::UncheckedActivityId;
+use core::any::Any;
+pub use error::ExecError;
+pub use fifo::Priority;
+use heartbeat::{Heartbeat, NutsHeartbeat};
+pub use main_loop::{FrameDraw, FrameUpdate, MainLoopBuilder};
+pub use panic_policy::{PanicPolicy, SubscriberPanicked};
+pub use phase::Phase;
+pub use queue_policy::{OnOverflow, QueuePolicy};
+pub use time_slice::BudgetExceeded;
+use time_slice::ExecutionBudget;
 
+pub(crate) mod budget;
+mod error;
 pub(crate) mod fifo;
+pub(crate) mod flow;
+pub(crate) mod heartbeat;
 pub(crate) mod inchoate;
+mod main_loop;
+#[cfg(feature = "metrics")]
+pub(crate) mod metrics;
+mod panic_policy;
+pub(crate) mod phase;
+mod queue_policy;
+#[cfg(feature = "wasm-futures")]
+pub(crate) mod spawn;
+#[cfg(any(test, feature = "test-utils"))]
+pub(crate) mod step;
+pub(crate) mod time_slice;
+pub(crate) mod trace;
+pub(crate) mod watch;
 
 pub(crate) enum Deferred {
     Broadcast(BroadcastInfo),
-    BroadcastAwaitingResponse(BroadcastInfo, ResponseSlot),
     Subscription(NewSubscription),
+    Unsubscribe(SubscriptionId),
     OnDeleteSubscription(UncheckedActivityId, OnDelete),
     LifecycleChange(LifecycleChange),
     RemoveActivity(UncheckedActivityId),
+    ReplaceActivity(UncheckedActivityId, Box<dyn Any>, Vec<String>),
     DomainStore(DomainStoreData),
     FlushInchoateActivities,
+    /// A closure scheduled by a handler, with access to activities and domains.
+    ActivityCall(OnceHandler),
+    ApplyDeliveryOrder(Topic),
+    Compact,
+}
+
+/// A deferred event together with the responses that are waiting for it.
+pub(crate) struct QueuedEvent {
+    event: Deferred,
+    /// Response futures that cannot resolve before this event has been executed.
+    barriers: Vec<ResponseSlot>,
+    /// Causality information, only set while tracing is enabled
+    trace: Option<u64>,
+    priority: Priority,
 }
 use core::sync::atomic::Ordering;
 
-use super::{
-    iac::subscription::{NewSubscription, OnDelete},
-    IMPOSSIBLE_ERR_MSG,
-};
+use super::iac::subscription::{NewSubscription, OnDelete, SubscriptionId};
+
+impl QueuedEvent {
+    pub(crate) fn event(&self) -> &Deferred {
+        &self.event
+    }
+}
 
 impl Nut {
+    /// Adds an event to the end of the deferred queue.
+    ///
+    /// If called while a deferred event is executing, the new event inherits all response barriers of it.
+    /// Thus, awaited responses only resolve after all events transitively caused by them have been executed.
+    pub(crate) fn push_deferred(&self, event: Deferred) {
+        let barriers = self.current_barriers.borrow().clone();
+        self.enqueue_deferred(event, barriers, Priority::Normal);
+    }
+    /// Same as `push_deferred` but the event is queued with the given priority.
+    pub(crate) fn push_deferred_with_priority(&self, event: Deferred, priority: Priority) {
+        let barriers = self.current_barriers.borrow().clone();
+        self.enqueue_deferred(event, barriers, priority);
+    }
+    /// Same as `push_deferred` but the event also blocks the given response.
+    pub(crate) fn push_deferred_with_barrier(&self, event: Deferred, slot: ResponseSlot) {
+        let mut barriers = self.current_barriers.borrow().clone();
+        barriers.push(slot);
+        self.enqueue_deferred(event, barriers, Priority::Normal);
+    }
+    fn enqueue_deferred(&self, event: Deferred, barriers: Vec<ResponseSlot>, priority: Priority) {
+        if !barriers.is_empty() {
+            let mut response_tracker = self.response_tracker.try_borrow_mut().or_report();
+            for slot in &barriers {
+                response_tracker.add_pending(*slot);
+            }
+        }
+        let trace = self
+            .tracer
+            .try_borrow_mut()
+            .or_report()
+            .as_mut()
+            .and_then(|tracer| tracer.queue(&event, !self.quiescent()));
+        let queued = QueuedEvent {
+            event,
+            barriers,
+            trace,
+            priority,
+        };
+        if let Some(queued) = self.apply_queue_policy(queued) {
+            self.deferred_events.push_with_priority(priority, queued);
+        }
+    }
+    /// Returns the event if it should be queued.
+    fn apply_queue_policy(&self, queued: QueuedEvent) -> Option<QueuedEvent> {
+        let policy = match self.queue_policy.get() {
+            Some(policy) if self.deferred_events.len() >= policy.max_len => policy,
+            _ => return Some(queued),
+        };
+        let is_message = |q: &QueuedEvent| matches!(q.event, Deferred::Broadcast(_));
+        match policy.on_overflow {
+            OnOverflow::Panic => panic!(
+                "Deferred queue of nuts exceeded the maximum of {} events",
+                policy.max_len
+            ),
+            OnOverflow::DropOldest => {
+                if let Some(dropped) = self.deferred_events.remove_first(is_message) {
+                    self.release_barriers(&dropped.barriers);
+                }
+            }
+            OnOverflow::DropNewest => {
+                if is_message(&queued) {
+                    self.release_barriers(&queued.barriers);
+                    return None;
+                }
+            }
+            OnOverflow::Callback(f) => f(self.deferred_events.len() + 1),
+        }
+        Some(queued)
+    }
+    /// Removes all queued events matching the predicate, without executing them.
+    pub(crate) fn take_deferred_where(&self, pred: impl Fn(&Deferred) -> bool) -> Vec<Deferred> {
+        self.deferred_events
+            .extract(|queued| pred(&queued.event))
+            .into_iter()
+            .map(|queued| {
+                self.release_barriers(&queued.barriers);
+                queued.event
+            })
+            .collect()
+    }
+    /// True if any queued event matches the predicate.
+    pub(crate) fn any_deferred(&self, pred: impl Fn(&Deferred) -> bool) -> bool {
+        self.deferred_events.count(|queued| pred(&queued.event)) > 0
+    }
+    fn release_barriers(&self, barriers: &[ResponseSlot]) {
+        if !barriers.is_empty() {
+            let mut response_tracker = self.response_tracker.try_borrow_mut().or_report();
+            for slot in barriers {
+                response_tracker.resolve_pending(*slot);
+            }
+        }
+    }
+
     /// Delivers all queue broadcasts (or other events) and all newly added broadcasts during that time period.
     ///
     /// If this is called in at a point of quiescence (no messages in flight)
@@ -33,95 +173,213 @@ impl Nut {
     /// No guarantee is given for calls while a broadcast is ongoing (messages are in flight).
     /// It is perfectly valid (and the intended behavior) to do nothing when called while a executing already.
     pub(crate) fn catch_up_deferred_to_quiescence(&self) {
+        #[cfg(any(test, feature = "test-utils"))]
+        if self.stepping.get() {
+            return;
+        }
         // A Nut only allows single-threaded access, relaxed ordering is fine.
         if !self.executing.swap(true, Ordering::Relaxed) {
             #[cfg(feature = "verbose-debug-log")]
             debug_print!("Start Executing from quiescent moment");
+            self.time_slice
+                .set(self.execution_budget.get().map(ExecutionBudget::start));
+            self.requeue_postponed();
             self.unchecked_catch_up_deferred_to_quiescence();
+            self.time_slice.set(None);
+            self.topic_budgets.try_borrow_mut().or_report().end_pump();
             self.executing.store(false, Ordering::Relaxed);
             #[cfg(feature = "verbose-debug-log")]
             debug_print!("Quiescence Reached");
+            #[cfg(feature = "wasm-futures")]
+            self.poll_spawned();
         }
     }
 
     /// only access after locking with executing flag
     fn unchecked_catch_up_deferred_to_quiescence(&self) {
-        while let Some(deferred) = self.deferred_events.pop() {
-            #[cfg(debug_assertions)]
-            let debug_message = format!("Executing: {:?}", deferred);
-
-            #[cfg(feature = "verbose-debug-log")]
-            #[cfg(debug_assertions)]
-            debug_print!("{}", debug_message);
-
-            #[cfg(feature = "verbose-debug-log")]
-            #[cfg(debug_assertions)]
-            if self.deferred_events.len() > 0 {
-                let events = self.deferred_events.events_debug_list();
-                debug_print!(
-                    "{} more events in queue: {}",
-                    self.deferred_events.len(),
-                    events
-                );
+        loop {
+            if !self.unchecked_exec_all_deferred() {
+                // Out of time, the remaining events wait for the next catch-up
+                break;
             }
+            let remote = self.queue_remote_messages();
+            let heartbeat = self.queue_heartbeat_if_due();
+            let watched = self.release_watched();
+            if remote || heartbeat || watched {
+                continue;
+            }
+            if !self.release_next_phase() {
+                break;
+            }
+        }
+    }
+    /// Starts a pump, messages that exceeded their topic budget in the previous pump are queued ahead of newer events.
+    fn requeue_postponed(&self) {
+        let postponed = self.topic_budgets.try_borrow_mut().or_report().start_pump();
+        for queued in postponed.into_iter().rev() {
+            self.deferred_events
+                .push_front_with_priority(queued.priority, queued);
+        }
+    }
+    /// Returns the event if it may execute now, otherwise it is set aside until the end of the pump.
+    fn admit(&self, queued: QueuedEvent) -> Option<QueuedEvent> {
+        if let Deferred::Broadcast(b) = &queued.event {
+            let mut budgets = self.topic_budgets.try_borrow_mut().or_report();
+            if !budgets.admit(b.topic()) {
+                budgets.postpone(queued);
+                return None;
+            }
+        }
+        Some(queued)
+    }
+    /// Publishes a `NutsHeartbeat` if it is enabled and the interval has passed.
+    /// Returns true iff a heartbeat has been queued.
+    fn queue_heartbeat_if_due(&self) -> bool {
+        let beat = self
+            .heartbeat
+            .try_borrow_mut()
+            .or_report()
+            .as_mut()
+            .and_then(Heartbeat::beat);
+        if let Some(msg) = beat {
+            let broadcast = BroadcastInfo::global(msg, Topic::public_message::<NutsHeartbeat>());
+            self.push_deferred(broadcast.into());
+            true
+        } else {
+            false
+        }
+    }
 
-            #[cfg(not(debug_assertions))]
-            self.exec_deferred(deferred);
-
-            // Catch panics inside executed closures
-            // Unfortunately, this currently does not seem to work on the web.
-            // To have good web debugging, the nuts panic hook should be used.
-            #[cfg(debug_assertions)]
-            if let Err(panic_info) =
-                std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
-                    self.exec_deferred(deferred)
-                }))
-            {
-                log_print!("Panic ocurred while nuts was executing. {}", debug_message);
-                log_print!(
-                    "Activity executing right now: {:?}",
-                    self.active_activity_name.get()
-                );
-                std::panic::resume_unwind(panic_info);
+    /// only access after locking with executing flag
+    /// Returns false iff execution stopped early because the execution budget has been used up.
+    fn unchecked_exec_all_deferred(&self) -> bool {
+        while let Some(queued) = self.deferred_events.pop() {
+            let QueuedEvent {
+                event: deferred,
+                barriers,
+                trace,
+                ..
+            } = match self.admit(queued) {
+                Some(queued) => queued,
+                None => continue,
+            };
+            self.exec_queued(deferred, barriers, trace);
+            if self.time_slice_used_up() {
+                self.finish_exec();
+                return false;
             }
         }
+        self.finish_exec();
+        true
+    }
+    /// Executes a single event that has been taken out of the queue.
+    ///
+    /// only access after locking with executing flag
+    fn exec_queued(&self, deferred: Deferred, barriers: Vec<ResponseSlot>, trace: Option<u64>) {
+        *self.current_barriers.borrow_mut() = barriers;
+        if let Some(tracer) = self.tracer.try_borrow_mut().or_report().as_mut() {
+            tracer.execute(trace, Some(&deferred));
+        }
+
+        #[cfg(debug_assertions)]
+        let debug_message = format!("Executing: {:?}", deferred);
+        #[cfg(debug_assertions)]
+        self.executing_event.replace(Some(debug_message.clone()));
+
+        #[cfg(feature = "verbose-debug-log")]
+        #[cfg(debug_assertions)]
+        debug_print!("{}", debug_message);
+
+        #[cfg(feature = "verbose-debug-log")]
+        #[cfg(debug_assertions)]
+        if !self.deferred_events.is_empty() {
+            let events = self.deferred_events.events_debug_list();
+            debug_print!(
+                "{} more events in queue: {}",
+                self.deferred_events.len(),
+                events
+            );
+        }
+
+        #[cfg(not(debug_assertions))]
+        self.exec_deferred(deferred);
+
+        // Catch panics inside executed closures
+        // Unfortunately, this currently does not seem to work on the web.
+        // To have good web debugging, the nuts panic hook should be used.
+        #[cfg(debug_assertions)]
+        if let Err(panic_info) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            self.exec_deferred(deferred)
+        })) {
+            log_print!("Panic ocurred while nuts was executing. {}", debug_message);
+            log_print!(
+                "Activity executing right now: {:?}",
+                self.active_activity_name.get()
+            );
+            std::panic::resume_unwind(panic_info);
+        }
+
+        let barriers = std::mem::take(&mut *self.current_barriers.borrow_mut());
+        self.release_barriers(&barriers);
+    }
+    fn finish_exec(&self) {
+        #[cfg(debug_assertions)]
+        self.executing_event.take();
+        if let Some(tracer) = self.tracer.try_borrow_mut().or_report().as_mut() {
+            tracer.execute(None, None);
+        }
     }
     fn exec_deferred(&self, deferred: Deferred) {
         match deferred {
             Deferred::Broadcast(b) => self.unchecked_broadcast(b),
-            Deferred::BroadcastAwaitingResponse(b, slot) => {
-                self.unchecked_broadcast(b);
-                Nut::with_response_tracker_mut(|rt| rt.done(&slot));
-            }
             Deferred::Subscription(sub) => {
-                self.subscriptions.exec_new_subscription(sub);
+                let id = self.subscriptions.exec_new_subscription(sub);
+                self.apply_delivery_order(&id.topic);
+                self.queue_replay(&id);
             }
+            Deferred::Unsubscribe(id) => self.unchecked_unsubscribe(&id),
             Deferred::OnDeleteSubscription(id, sub) => {
                 self.activities
                     .try_borrow_mut()
-                    .expect(IMPOSSIBLE_ERR_MSG)
+                    .or_report()
                     .add_on_delete(id, sub);
             }
             Deferred::LifecycleChange(lc) => self.unchecked_lifecycle_change(&lc),
             Deferred::RemoveActivity(id) => self.delete_activity(id),
+            Deferred::ReplaceActivity(id, data, keep_groups) => {
+                self.replace_activity(id, data, &keep_groups)
+            }
             Deferred::DomainStore(d) => self.exec_domain_store(d),
             Deferred::FlushInchoateActivities => self
                 .inchoate_activities
                 .try_borrow_mut()
-                .expect(IMPOSSIBLE_ERR_MSG)
-                .flush(&mut *self.activities.try_borrow_mut().expect(IMPOSSIBLE_ERR_MSG)),
+                .or_report()
+                .flush(&mut self.activities.try_borrow_mut().or_report()),
+            Deferred::ActivityCall(f) => f(
+                &mut self.activities.try_borrow_mut().or_report(),
+                &mut self.managed_state.try_borrow_mut().or_report(),
+            ),
+            Deferred::ApplyDeliveryOrder(topic) => self.apply_delivery_order(&topic),
+            Deferred::Compact => self.compact(),
         }
     }
 }
-impl Into<Deferred> for BroadcastInfo {
-    fn into(self) -> Deferred {
-        Deferred::Broadcast(self)
+impl From<BroadcastInfo> for Deferred {
+    fn from(b: BroadcastInfo) -> Self {
+        Deferred::Broadcast(b)
     }
 }
 
-impl Into<Deferred> for LifecycleChange {
-    fn into(self) -> Deferred {
-        Deferred::LifecycleChange(self)
+impl From<LifecycleChange> for Deferred {
+    fn from(lc: LifecycleChange) -> Self {
+        Deferred::LifecycleChange(lc)
+    }
+}
+
+#[cfg(debug_assertions)]
+impl std::fmt::Debug for QueuedEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.event.fmt(f)
     }
 }
 
@@ -130,15 +388,19 @@ impl std::fmt::Debug for Deferred {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Broadcast(b) => write!(f, "Broadcasting {:?}", b),
-            Self::BroadcastAwaitingResponse(b, _rs) => write!(f, "Broadcasting {:?}", b),
             Self::Subscription(sub) => write!(f, "{:?}", sub),
+            Self::Unsubscribe(_) => write!(f, "Removing subscription"),
             Self::OnDeleteSubscription(_id, _) => {
                 write!(f, "Adding new on delete listener {}", _id.index)
             }
             Self::LifecycleChange(lc) => write!(f, "{:?}", lc),
             Self::RemoveActivity(_id) => write!(f, "Delete activity {}.", _id.index),
+            Self::ReplaceActivity(_id, _, _) => write!(f, "Replace activity {}.", _id.index),
             Self::DomainStore(ds) => write!(f, "{:?}", ds),
             Self::FlushInchoateActivities => write!(f, "Adding new activities previously deferred"),
+            Self::ActivityCall(_) => write!(f, "Calling deferred closure"),
+            Self::ApplyDeliveryOrder(_) => write!(f, "Sorting subscriptions"),
+            Self::Compact => write!(f, "Compacting internal state"),
         }
     }
 }
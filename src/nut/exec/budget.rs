@@ -0,0 +1,50 @@
+//! Per-topic limits on how many messages are delivered in one pump.
+//!
+//! A pump starts when nuts begins executing from a quiescent moment and ends when the queue is empty.
+//! Messages above the budget of their topic are set aside and queued again when the next pump starts.
+//! Until then, they do not count as queued, so nuts is idle after a pump even if messages have been postponed.
+
+use crate::nut::exec::QueuedEvent;
+use crate::nut::iac::topic::Topic;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub(crate) struct TopicBudgets {
+    limits: HashMap<Topic, usize>,
+    used: HashMap<Topic, usize>,
+    postponed: Vec<QueuedEvent>,
+}
+
+impl TopicBudgets {
+    pub(crate) fn set(&mut self, topic: Topic, limit: Option<usize>) {
+        match limit {
+            Some(limit) => self.limits.insert(topic, limit),
+            None => self.limits.remove(&topic),
+        };
+    }
+    /// Counts a delivery of the topic, returns false if the budget has already been used up.
+    pub(crate) fn admit(&mut self, topic: &Topic) -> bool {
+        match self.limits.get(topic) {
+            None => true,
+            Some(limit) => {
+                let used = self.used.entry(topic.clone()).or_default();
+                if *used < *limit {
+                    *used += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+    pub(crate) fn postpone(&mut self, event: QueuedEvent) {
+        self.postponed.push(event);
+    }
+    /// Returns all events postponed in the previous pump, in their original order.
+    pub(crate) fn start_pump(&mut self) -> Vec<QueuedEvent> {
+        std::mem::take(&mut self.postponed)
+    }
+    pub(crate) fn end_pump(&mut self) {
+        self.used.clear();
+    }
+}
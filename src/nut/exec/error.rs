@@ -0,0 +1,30 @@
+/// Returned by functions that refuse to run when nuts cannot execute immediately.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum ExecError {
+    /// Called inside a subscription handler, where activities and domains are not accessible.
+    InsideHandler,
+    /// The activity has been deleted, or there is no activity of the requested type.
+    MissingActivity,
+    /// Events are still queued from an earlier catch-up that stopped when the [execution budget](fn.set_execution_budget.html) had been used up.
+    /// New events would have to wait behind them. Call [`pump`](fn.pump.html) until nothing is left before retrying.
+    EventsPending,
+    /// Step mode of [`test_support`](test_support/index.html) is on, so queued events only execute with `test_support::step`.
+    Stepping,
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InsideHandler => write!(
+                f,
+                "cannot execute immediately inside a subscription handler"
+            ),
+            Self::MissingActivity => write!(f, "the activity has been deleted"),
+            Self::EventsPending => write!(f, "events of an earlier catch-up are still queued"),
+            Self::Stepping => write!(f, "queued events only execute step by step"),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
@@ -1,34 +1,143 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 
+/// Priority of a message in the deferred queue, see [`nuts::publish_with_priority`](fn.publish_with_priority.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Delivered before all queued messages of normal and low priority.
+    High,
+    /// The priority of everything published without an explicit priority.
+    #[default]
+    Normal,
+    /// Delivered after all queued messages of high and normal priority.
+    Low,
+}
+
+/// Number of items that may be taken from higher priorities in a row, while a lower priority is waiting.
+/// After that, one item of the highest waiting lower priority is taken, so that no priority starves.
+pub(crate) const STARVATION_LIMIT: u32 = 16;
+
 /// FIFO queue that allows thread-local atomic pushing and popping.
 /// No borrowing of internal data is possible, only moving data in and out.
 /// No mutable access required for those operation.
 ///
+/// Items are kept in one FIFO per `Priority`, the highest non-empty priority is popped first (up to the `STARVATION_LIMIT`).
+///
 /// Note that the chosen limitation prevents an implementation of Iterator for
 /// this collection. `IntoIterator` would be possible but is mostly useless.
 pub(crate) struct ThreadLocalFifo<ITEM> {
-    fifo: RefCell<VecDeque<ITEM>>,
+    /// Indexed by priority, highest first
+    fifos: RefCell<[VecDeque<ITEM>; 3]>,
+    /// Items taken in a row while a lower priority was waiting
+    passed_over: Cell<u32>,
 }
 
 impl<ITEM> ThreadLocalFifo<ITEM> {
-    pub(crate) fn push(&self, i: ITEM) {
-        self.fifo.borrow_mut().push_back(i);
+    pub(crate) fn push_with_priority(&self, priority: Priority, i: ITEM) {
+        self.fifos.borrow_mut()[priority as usize].push_back(i);
+    }
+    /// Puts the item ahead of all queued items of the same priority.
+    pub(crate) fn push_front_with_priority(&self, priority: Priority, i: ITEM) {
+        self.fifos.borrow_mut()[priority as usize].push_front(i);
     }
     pub(crate) fn pop(&self) -> Option<ITEM> {
-        self.fifo.borrow_mut().pop_front()
+        let mut fifos = self.fifos.borrow_mut();
+        let first = fifos.iter().position(|fifo| !fifo.is_empty())?;
+        let waiting = fifos[first + 1..].iter().position(|fifo| !fifo.is_empty());
+        let level = match waiting {
+            None => {
+                self.passed_over.set(0);
+                first
+            }
+            Some(offset) if self.passed_over.get() >= STARVATION_LIMIT => {
+                self.passed_over.set(0);
+                first + 1 + offset
+            }
+            Some(_) => {
+                self.passed_over.set(self.passed_over.get() + 1);
+                first
+            }
+        };
+        fifos[level].pop_front()
+    }
+    /// Moves all items matching the predicate out of the queue, keeping the order of the remaining items.
+    pub(crate) fn extract(&self, mut pred: impl FnMut(&ITEM) -> bool) -> Vec<ITEM> {
+        let mut fifos = self.fifos.borrow_mut();
+        let mut extracted = vec![];
+        for fifo in fifos.iter_mut() {
+            let mut remaining = VecDeque::with_capacity(fifo.len());
+            for item in fifo.drain(..) {
+                if pred(&item) {
+                    extracted.push(item);
+                } else {
+                    remaining.push_back(item);
+                }
+            }
+            *fifo = remaining;
+        }
+        extracted
+    }
+    /// Moves the first item matching the predicate out of the queue, looking at the lowest priority first.
+    pub(crate) fn remove_first(&self, mut pred: impl FnMut(&ITEM) -> bool) -> Option<ITEM> {
+        let mut fifos = self.fifos.borrow_mut();
+        fifos.iter_mut().rev().find_map(|fifo| {
+            let index = fifo.iter().position(&mut pred)?;
+            fifo.remove(index)
+        })
+    }
+    /// Drops all items. They are dropped only after the queue has been released, so `Drop` implementations may push new items.
+    pub(crate) fn clear(&self) {
+        let old = self.fifos.take();
+        self.passed_over.set(0);
+        drop(old);
+    }
+    /// Number of items matching the predicate, without removing them.
+    pub(crate) fn count(&self, mut pred: impl FnMut(&ITEM) -> bool) -> usize {
+        self.fifos
+            .borrow()
+            .iter()
+            .flatten()
+            .filter(|item| pred(item))
+            .count()
+    }
+    /// Applies the function to all items, highest priority first.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn map_all<R>(&self, f: impl FnMut(&ITEM) -> R) -> Vec<R> {
+        self.fifos.borrow().iter().flatten().map(f).collect()
     }
-    #[cfg(feature = "verbose-debug-log")]
     pub(crate) fn len(&self) -> usize {
-        self.fifo.borrow().len()
+        self.fifos.borrow().iter().map(VecDeque::len).sum()
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fifos.borrow().iter().all(VecDeque::is_empty)
+    }
+    /// Number of items, or `None` if the queue is borrowed right now.
+    pub(crate) fn try_len(&self) -> Option<usize> {
+        self.fifos
+            .try_borrow()
+            .ok()
+            .map(|fifos| fifos.iter().map(VecDeque::len).sum())
     }
 }
 
 impl<ITEM: std::fmt::Debug> ThreadLocalFifo<ITEM> {
+    /// Debug output of the first `max` items, or `None` if the queue is borrowed right now.
+    #[cfg(debug_assertions)]
+    pub(crate) fn debug_head(&self, max: usize) -> Option<Vec<String>> {
+        let fifos = self.fifos.try_borrow().ok()?;
+        Some(
+            fifos
+                .iter()
+                .flatten()
+                .take(max)
+                .map(|e| format!("{:?}", e))
+                .collect(),
+        )
+    }
     #[cfg(feature = "verbose-debug-log")]
     pub(crate) fn events_debug_list(&self) -> String {
         let mut out = "(".to_owned();
-        for e in self.fifo.borrow().iter() {
+        for e in self.fifos.borrow().iter().flatten() {
             out += &format!("{:?}, ", e);
         }
         if out.len() > 2 {
@@ -42,7 +151,8 @@ impl<ITEM: std::fmt::Debug> ThreadLocalFifo<ITEM> {
 impl<ITEM> Default for ThreadLocalFifo<ITEM> {
     fn default() -> Self {
         ThreadLocalFifo {
-            fifo: RefCell::new(VecDeque::new()),
+            fifos: RefCell::default(),
+            passed_over: Cell::new(0),
         }
     }
 }
@@ -0,0 +1,142 @@
+//! Records which messages caused which handlers to publish which messages, for `nuts::debug::export_flow_graph`.
+
+use crate::nut::activity::ActivityContainer;
+use crate::nut::exec::trace::TraceEvent;
+use crate::UncheckedActivityId;
+use std::collections::HashMap;
+
+/// Message flow observed at runtime, created by [`export_flow_graph`](fn.export_flow_graph.html).
+///
+/// Nodes are activities and message types. An edge from a message to an activity means the message was delivered to a handler of the activity.
+/// An edge from an activity to a message means a handler of the activity published the message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlowGraph {
+    /// All activities and message types that appear in an edge
+    pub nodes: Vec<FlowNode>,
+    /// Observed edges, sorted by source and target
+    pub edges: Vec<FlowEdge>,
+}
+
+/// A node of a [`FlowGraph`](struct.FlowGraph.html)
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FlowNode {
+    /// Type name of the activity or the message, not stable, see [`MessageMeta::type_name`](../struct.MessageMeta.html#method.type_name).
+    pub name: String,
+    /// True for activities, false for message types
+    pub is_activity: bool,
+}
+
+/// A directed edge of a [`FlowGraph`](struct.FlowGraph.html), referencing nodes by their index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowEdge {
+    /// Index of the source node
+    pub from: usize,
+    /// Index of the target node
+    pub to: usize,
+    /// How many times the edge has been observed
+    pub count: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    Activity(UncheckedActivityId),
+    Message(&'static str),
+}
+
+#[derive(Default)]
+pub(crate) struct FlowRecorder {
+    edges: HashMap<(Node, Node), usize>,
+}
+
+impl FlowRecorder {
+    pub(crate) fn record(&mut self, event: &TraceEvent) {
+        let edge = match *event {
+            TraceEvent::Publish {
+                publisher: Some(publisher),
+                type_name,
+                ..
+            } => (Node::Activity(publisher), Node::Message(type_name)),
+            TraceEvent::Deliver {
+                receiver,
+                type_name,
+                ..
+            } => (Node::Message(type_name), Node::Activity(receiver)),
+            _ => return,
+        };
+        *self.edges.entry(edge).or_default() += 1;
+    }
+    /// Activity names are looked up in the container, if it is available.
+    pub(crate) fn graph(&self, activities: Option<&ActivityContainer>) -> FlowGraph {
+        let node = |n: Node| match n {
+            Node::Activity(id) => FlowNode {
+                name: match activities {
+                    Some(activities) => activities.type_name(id).to_owned(),
+                    None => format!("activity {}", id.index),
+                },
+                is_activity: true,
+            },
+            Node::Message(name) => FlowNode {
+                name: name.to_owned(),
+                is_activity: false,
+            },
+        };
+        let mut named: Vec<_> = self
+            .edges
+            .iter()
+            .map(|((from, to), count)| (node(*from), node(*to), *count))
+            .collect();
+        named.sort();
+        let mut nodes: Vec<FlowNode> = named
+            .iter()
+            .flat_map(|(from, to, _)| [from.clone(), to.clone()])
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+        let index = |n: &FlowNode| nodes.binary_search(n).unwrap_or_default();
+        let edges = named
+            .iter()
+            .map(|(from, to, count)| FlowEdge {
+                from: index(from),
+                to: index(to),
+                count: *count,
+            })
+            .collect();
+        FlowGraph { nodes, edges }
+    }
+}
+
+impl FlowGraph {
+    /// Renders the graph as a mermaid flowchart. Activities are drawn as rectangles, messages with rounded sides.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = "flowchart LR\n".to_owned();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let name = node.name.replace('"', "#quot;");
+            if node.is_activity {
+                out += &format!("    n{}[\"{}\"]\n", i, name);
+            } else {
+                out += &format!("    n{}([\"{}\"])\n", i, name);
+            }
+        }
+        for edge in &self.edges {
+            out += &format!("    n{} -->|{}| n{}\n", edge.from, edge.count, edge.to);
+        }
+        out
+    }
+    /// Renders the graph in the DOT language of Graphviz. Activities are drawn as boxes, messages as ellipses.
+    pub fn to_dot(&self) -> String {
+        let mut out = "digraph nuts {\n".to_owned();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let shape = if node.is_activity { "box" } else { "ellipse" };
+            let name = node.name.replace('"', "\\\"");
+            out += &format!("    n{} [label=\"{}\", shape={}];\n", i, name, shape);
+        }
+        for edge in &self.edges {
+            out += &format!(
+                "    n{} -> n{} [label=\"{}\"];\n",
+                edge.from, edge.to, edge.count
+            );
+        }
+        out += "}\n";
+        out
+    }
+}
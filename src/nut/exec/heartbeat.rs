@@ -0,0 +1,69 @@
+//! Opt-in rate counters, published periodically as `NutsHeartbeat`.
+
+use crate::nut::iac::topic::Topic;
+use crate::UncheckedActivityId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Builtin message that summarizes the events nuts has processed in the last interval.
+///
+/// Published periodically after calling [`nuts::enable_heartbeat`](fn.enable_heartbeat.html).
+/// Subscribe to it like to any other message, for example to graph message rates in an overlay.
+#[derive(Clone, Debug)]
+pub struct NutsHeartbeat {
+    /// Time passed since the last heartbeat, as measured by the clock passed to `enable_heartbeat`.
+    pub elapsed: Duration,
+    /// Number of messages delivered per topic, identified by the name of the message type.
+    /// Sorted by count, most frequent topic first.
+    pub topics: Vec<(&'static str, usize)>,
+    /// Number of subscription handlers triggered per activity.
+    /// Sorted by count, busiest activity first.
+    pub activities: Vec<(UncheckedActivityId, usize)>,
+}
+
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    clock: fn() -> Duration,
+    last: Duration,
+    topics: HashMap<Topic, (&'static str, usize)>,
+    activities: HashMap<UncheckedActivityId, usize>,
+}
+
+impl Heartbeat {
+    pub(crate) fn new(interval: Duration, clock: fn() -> Duration) -> Self {
+        Self {
+            interval,
+            clock,
+            last: clock(),
+            topics: HashMap::new(),
+            activities: HashMap::new(),
+        }
+    }
+    pub(crate) fn count_message(&mut self, topic: &Topic) {
+        self.topics
+            .entry(topic.clone())
+            .or_insert((topic.name(), 0))
+            .1 += 1;
+    }
+    pub(crate) fn count_handler(&mut self, activity: UncheckedActivityId) {
+        *self.activities.entry(activity).or_default() += 1;
+    }
+    /// Resets all counters and returns their summary, if the interval has passed.
+    pub(crate) fn beat(&mut self) -> Option<NutsHeartbeat> {
+        let now = (self.clock)();
+        let elapsed = now.checked_sub(self.last).unwrap_or_default();
+        if elapsed < self.interval {
+            return None;
+        }
+        self.last = now;
+        let mut topics: Vec<_> = self.topics.drain().map(|(_, v)| v).collect();
+        topics.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let mut activities: Vec<_> = self.activities.drain().collect();
+        activities.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        Some(NutsHeartbeat {
+            elapsed,
+            topics,
+            activities,
+        })
+    }
+}
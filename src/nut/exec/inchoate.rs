@@ -2,6 +2,8 @@
 //! To still be able to add new activities and subscriptions during that time, temporary
 //! structures are used to buffer additions. Theses are then merged in a deferred event.
 
+use crate::nut::activity::NewActivity;
+use crate::nut::bus::BusId;
 use crate::{Activity, ActivityContainer, ActivityId, DomainId, LifecycleStatus};
 
 pub(crate) struct InchoateActivityContainer {
@@ -11,14 +13,17 @@ pub(crate) struct InchoateActivityContainer {
 
 impl Default for InchoateActivityContainer {
     fn default() -> Self {
-        Self {
-            activities: Default::default(),
-            offset: 1, // for NotAnActivity
-        }
+        Self::new(BusId::default())
     }
 }
 
 impl InchoateActivityContainer {
+    pub(crate) fn new(bus: BusId) -> Self {
+        Self {
+            activities: ActivityContainer::empty(bus),
+            offset: 1, // for NotAnActivity
+        }
+    }
     pub(crate) fn inc_offset(&mut self) {
         debug_assert_eq!(self.activities.len(), 0);
         self.offset += 1;
@@ -40,7 +45,7 @@ impl InchoateActivityContainer {
 impl InchoateActivityContainer {
     pub(crate) fn add<A: Activity>(
         &mut self,
-        a: A,
+        a: NewActivity<A>,
         domain: DomainId,
         status: LifecycleStatus,
     ) -> ActivityId<A> {
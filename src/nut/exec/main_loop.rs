@@ -0,0 +1,103 @@
+//! A frame loop for native applications, which have no browser driving the updates.
+
+use crate::Phase;
+use std::time::Duration;
+
+/// Builtin message, published for [`Phase::Update`](enum.Phase.html#variant.Update) in every frame of a [`MainLoopBuilder`](struct.MainLoopBuilder.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameUpdate {
+    /// Time since the previous frame started, zero in the first frame.
+    pub delta: Duration,
+    /// The frame counter, as returned by [`nuts::current_tick`](fn.current_tick.html).
+    pub frame: u64,
+}
+
+/// Builtin message, published for [`Phase::Draw`](enum.Phase.html#variant.Draw) in every frame of a [`MainLoopBuilder`](struct.MainLoopBuilder.html).
+///
+/// It is delivered after all messages of the update phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameDraw {
+    /// Time since the previous frame started, zero in the first frame.
+    pub delta: Duration,
+    /// The frame counter, as returned by [`nuts::current_tick`](fn.current_tick.html).
+    pub frame: u64,
+}
+
+/// Drives nuts in regular frames, the native counterpart to a browser's animation frames.
+///
+/// Each frame advances the frame counter with [`nuts::tick`](fn.tick.html), calls all `on_frame` closures,
+/// and publishes [`FrameUpdate`](struct.FrameUpdate.html) and [`FrameDraw`](struct.FrameDraw.html) in their phases.
+///
+/// Use [`run`](#method.run) to let nuts own the loop,
+/// or call [`step`](#method.step) from the loop of a windowing library, such as winit.
+///
+/// ### Example
+/// ```rust
+/// use nuts::{FrameUpdate, MainLoopBuilder};
+/// struct Physics { steps: u64 }
+///
+/// let physics = nuts::new_activity(Physics { steps: 0 });
+/// physics.subscribe(|physics, frame: &FrameUpdate| {
+///     physics.steps += 1;
+///     if frame.frame == 3 {
+///         nuts::exit_main_loop();
+///     }
+/// });
+/// MainLoopBuilder::new(60)
+///     .on_frame(|delta| println!("{:?} since the last frame", delta))
+///     .run();
+/// ```
+pub struct MainLoopBuilder {
+    frame_time: Duration,
+    on_frame: Vec<Box<dyn FnMut(Duration)>>,
+}
+
+impl MainLoopBuilder {
+    /// A loop that targets the given number of frames per second.
+    ///
+    /// # Panics
+    /// Panics if `fps` is zero.
+    pub fn new(fps: u32) -> Self {
+        assert!(fps > 0, "A main loop needs at least one frame per second");
+        Self {
+            frame_time: Duration::from_secs(1) / fps,
+            on_frame: Vec::new(),
+        }
+    }
+    /// Adds a closure that is called at the start of each frame, with the time since the previous frame.
+    pub fn on_frame(mut self, f: impl FnMut(Duration) + 'static) -> Self {
+        self.on_frame.push(Box::new(f));
+        self
+    }
+    /// Executes a single frame, `delta` is the time since the previous frame.
+    ///
+    /// All messages published in the frame have been delivered when this returns.
+    pub fn step(&mut self, delta: Duration) {
+        let frame = crate::tick();
+        for f in &mut self.on_frame {
+            f(delta);
+        }
+        crate::publish_phased(Phase::Update, FrameUpdate { delta, frame });
+        crate::publish_phased(Phase::Draw, FrameDraw { delta, frame });
+    }
+    /// Executes frames until [`nuts::exit_main_loop`](fn.exit_main_loop.html) is called, sleeping between frames to keep the target rate.
+    ///
+    /// Not available on wasm, where the browser owns the loop.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run(mut self) {
+        use std::time::Instant;
+        crate::nut::take_exit_request();
+        let mut previous: Option<Instant> = None;
+        while !crate::nut::take_exit_request() {
+            let start = Instant::now();
+            let delta = previous.map_or(Duration::default(), |p| start - p);
+            previous = Some(start);
+            self.step(delta);
+            if let Some(rest) = self.frame_time.checked_sub(start.elapsed()) {
+                std::thread::sleep(rest);
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Cumulative message and handler statistics, compiled in with the `metrics` feature.
+
+use crate::UncheckedActivityId;
+use core::any::TypeId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Snapshot of the statistics nuts collects when compiled with the `metrics` feature.
+///
+/// Returned by [`nuts::metrics`](fn.metrics.html).
+/// Unlike [`NutsHeartbeat`](struct.NutsHeartbeat.html), counters are never reset, except by [`nuts::reset`](fn.reset.html).
+#[derive(Clone, Debug, Default)]
+pub struct NutsMetrics {
+    /// Number of messages broadcast per message type, including private messages.
+    pub messages: HashMap<TypeId, MessageMetrics>,
+    /// Number of subscription handlers executed per activity, and the time spent in them.
+    pub handlers: HashMap<UncheckedActivityId, HandlerMetrics>,
+}
+
+/// Statistics of a single message type, part of [`NutsMetrics`](struct.NutsMetrics.html).
+#[derive(Clone, Debug)]
+pub struct MessageMetrics {
+    /// Name of the message type
+    pub type_name: &'static str,
+    /// How many messages of this type have been broadcast
+    pub count: u64,
+}
+
+/// Statistics of the handlers of a single activity, part of [`NutsMetrics`](struct.NutsMetrics.html).
+#[derive(Clone, Debug)]
+pub struct HandlerMetrics {
+    /// Name of the activity type
+    pub type_name: &'static str,
+    /// How many times a handler of the activity has been executed
+    pub calls: u64,
+    /// Sum of the time spent inside the handlers of the activity
+    pub total_time: Duration,
+}
+
+impl NutsMetrics {
+    pub(crate) fn count_message(&mut self, type_id: TypeId, type_name: &'static str) {
+        self.messages
+            .entry(type_id)
+            .or_insert(MessageMetrics {
+                type_name,
+                count: 0,
+            })
+            .count += 1;
+    }
+    pub(crate) fn count_handler(
+        &mut self,
+        activity: UncheckedActivityId,
+        type_name: &'static str,
+        time: Duration,
+    ) {
+        let handler = self.handlers.entry(activity).or_insert(HandlerMetrics {
+            type_name,
+            calls: 0,
+            total_time: Duration::default(),
+        });
+        handler.calls += 1;
+        handler.total_time += time;
+    }
+}
+
+/// Measures the time spent in a handler, using `performance.now()` on the web.
+pub(crate) struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start: performance_now(),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn elapsed(&self) -> Duration {
+        let ms = (performance_now() - self.start).max(0.0);
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+}
+
+/// Milliseconds since the page has been loaded, or 0 outside of a browser window.
+#[cfg(all(target_arch = "wasm32", feature = "web-sys"))]
+fn performance_now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0.0, |performance| performance.now())
+}
+/// Without `web-sys`, there is no clock and all handlers take no time.
+#[cfg(all(target_arch = "wasm32", not(feature = "web-sys")))]
+fn performance_now() -> f64 {
+    0.0
+}
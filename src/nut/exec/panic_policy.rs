@@ -0,0 +1,84 @@
+use crate::nut::activity::TransitionCause;
+use crate::nut::iac::publish::BroadcastInfo;
+use crate::nut::iac::topic::Topic;
+use crate::nut::Nut;
+use crate::{LifecycleStatus, UncheckedActivityId};
+use core::any::Any;
+
+/// Defines what happens when a subscription handler panics, see [`nuts::set_panic_policy`](fn.set_panic_policy.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PanicPolicy {
+    /// The default. The panic unwinds through nuts into the caller of `publish`.
+    /// Nuts is left in an inconsistent state afterwards and should be reset before it is used again.
+    #[default]
+    Propagate,
+    /// The panic is caught, the activity is set to inactive and a [`SubscriberPanicked`](struct.SubscriberPanicked.html) message is published.
+    /// The remaining subscribers still receive the message.
+    RecoverAndReport,
+}
+
+/// Builtin message published when a subscription handler panics while [`PanicPolicy::RecoverAndReport`](enum.PanicPolicy.html) is active.
+#[derive(Clone, Debug)]
+pub struct SubscriberPanicked {
+    activity: UncheckedActivityId,
+    activity_type: &'static str,
+    message_type: &'static str,
+    panic_message: Option<String>,
+}
+
+impl SubscriberPanicked {
+    /// The activity whose handler panicked. It has been set to inactive, unless it has been restarted by its supervisor.
+    pub fn activity(&self) -> UncheckedActivityId {
+        self.activity
+    }
+    /// The name of the activity type whose handler panicked, as returned by `std::any::type_name`, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+    pub fn activity_type_name(&self) -> &'static str {
+        self.activity_type
+    }
+    /// The name of the message type that was handled, as returned by `std::any::type_name`, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+    pub fn message_type_name(&self) -> &'static str {
+        self.message_type
+    }
+    /// The message passed to `panic!`, if it was a string.
+    pub fn panic_message(&self) -> Option<&str> {
+        self.panic_message.as_deref()
+    }
+}
+
+impl Nut {
+    /// Restarts the activity if it is supervised, otherwise puts it to sleep, and reports the panic.
+    ///
+    /// only access after locking with executing flag
+    pub(crate) fn recover_from_panic(
+        &self,
+        activity: UncheckedActivityId,
+        topic: &Topic,
+        payload: &(dyn Any + Send),
+    ) {
+        let panic_message = payload
+            .downcast_ref::<&str>()
+            .map(|s| (*s).to_owned())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+        let activity_type = self
+            .activities
+            .try_borrow()
+            .map_or("<unknown>", |activities| activities.type_name(activity));
+        if !self.restart_supervised(activity, true) {
+            self.set_status(
+                activity,
+                LifecycleStatus::Inactive,
+                TransitionCause::PanicRecovery,
+            );
+        }
+        let report = SubscriberPanicked {
+            activity,
+            activity_type,
+            message_type: topic.name(),
+            panic_message,
+        };
+        self.push_deferred(
+            BroadcastInfo::global(report, Topic::public_message::<SubscriberPanicked>()).into(),
+        );
+    }
+}
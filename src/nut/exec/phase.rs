@@ -0,0 +1,76 @@
+//! Frame phases: messages published for a later phase wait until all earlier phases are done.
+
+use crate::nut::iac::publish::BroadcastInfo;
+use crate::nut::{diagnostics::OrReport, Nut};
+
+/// A step of a frame, used with [`nuts::publish_phased`](fn.publish_phased.html).
+///
+/// Messages of a phase are only delivered when no messages of earlier phases are left, see [`nuts::set_phase_order`](fn.set_phase_order.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Game logic, physics and other state changes. Runs first by default.
+    Update,
+    /// Rendering. Runs after `Update` by default.
+    Draw,
+    /// An application-defined phase. Runs after all phases listed in the phase order, unless it is listed itself.
+    Custom(&'static str),
+}
+
+/// Phased messages that have been published but not released to the queue yet
+pub(crate) struct PhaseQueues {
+    order: Vec<Phase>,
+    pending: Vec<(Phase, Vec<BroadcastInfo>)>,
+}
+
+impl Default for PhaseQueues {
+    fn default() -> Self {
+        Self {
+            order: vec![Phase::Update, Phase::Draw],
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl PhaseQueues {
+    pub(crate) fn set_order(&mut self, order: &[Phase]) {
+        self.order = order.to_vec();
+    }
+    pub(crate) fn hold(&mut self, phase: Phase, broadcast: BroadcastInfo) {
+        match self.pending.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, queue)) => queue.push(broadcast),
+            None => self.pending.push((phase, vec![broadcast])),
+        }
+    }
+    /// Takes all messages of the earliest phase that has any.
+    fn take_next(&mut self) -> Vec<BroadcastInfo> {
+        let order = &self.order;
+        let rank = |phase: &Phase| order.iter().position(|p| p == phase).unwrap_or(order.len());
+        // Phases with equal rank are released in the order they have first been used
+        let next = self
+            .pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(i, (phase, _))| (rank(phase), *i))
+            .map(|(i, _)| i);
+        match next {
+            Some(i) => self.pending.remove(i).1,
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Nut {
+    /// Queues the messages of the next phase.
+    /// Returns true iff anything has been queued.
+    ///
+    /// only access after locking with executing flag
+    pub(crate) fn release_next_phase(&self) -> bool {
+        let released = self.phases.try_borrow_mut().or_report().take_next();
+        let any = !released.is_empty();
+        for broadcast in released {
+            self.push_deferred(broadcast.into());
+        }
+        any
+    }
+}
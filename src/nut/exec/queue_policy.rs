@@ -0,0 +1,25 @@
+/// Limits the length of the deferred queue, see [`nuts::set_queue_policy`](fn.set_queue_policy.html).
+#[derive(Clone, Copy, Debug)]
+pub struct QueuePolicy {
+    /// The maximum number of events in the queue
+    pub max_len: usize,
+    /// What to do when an event would exceed the maximum
+    pub on_overflow: OnOverflow,
+}
+
+/// Reaction to an overflowing queue, part of a [`QueuePolicy`](struct.QueuePolicy.html).
+///
+/// Only messages are ever dropped. Internal events, such as new subscriptions or lifecycle changes, are always kept.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum OnOverflow {
+    /// Panic, to detect runaway feedback loops early.
+    Panic,
+    /// Drop the oldest queued message to make space, starting with the lowest [`Priority`](enum.Priority.html).
+    DropOldest,
+    /// Drop the message that would exceed the maximum.
+    DropNewest,
+    /// Keep the event and call the function with the current queue length.
+    /// The function must not call any nuts functions.
+    Callback(fn(usize)),
+}
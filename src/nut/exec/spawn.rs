@@ -0,0 +1,113 @@
+//! Futures spawned with `nuts::spawn`, polled at points of quiescence.
+
+use crate::nut::{diagnostics::OrReport, Nut, NUT};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// All spawned futures that have not completed, yet
+#[derive(Default)]
+pub(crate) struct Tasks {
+    tasks: Vec<Task>,
+    /// Set while tasks are polled, they are taken out of `tasks` during that time
+    polling: bool,
+    /// Set if a point of quiescence has been reached while tasks were polled
+    repoll: bool,
+}
+
+/// Wakes nuts to poll all spawned futures.
+///
+/// On the web, a poll is scheduled as a microtask. On other platforms, futures are only polled on the next point of quiescence.
+#[derive(Default)]
+struct NutsWaker {
+    scheduled: AtomicBool,
+}
+
+impl NutsWaker {
+    fn schedule_poll(&self) {
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async { NUT.with(Nut::poll_spawned) });
+    }
+}
+
+impl Wake for NutsWaker {
+    fn wake(self: Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::Relaxed) {
+            self.schedule_poll();
+        }
+    }
+}
+
+impl Nut {
+    /// Polls all spawned futures once, or more often if they cause further points of quiescence.
+    ///
+    /// Does nothing inside subscription handlers, they are polled after the handlers are done.
+    pub(crate) fn poll_spawned(&self) {
+        if !self.quiescent() {
+            return;
+        }
+        {
+            let mut tasks = self.spawned.try_borrow_mut().or_report();
+            if tasks.polling {
+                tasks.repoll = true;
+                return;
+            }
+            if tasks.tasks.is_empty() {
+                return;
+            }
+            tasks.polling = true;
+        }
+        let _guard = Polling(self);
+        let waker = Waker::from(Arc::new(NutsWaker::default()));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            // No borrow is held while polling, the futures usually call nuts
+            let batch = {
+                let mut tasks = self.spawned.try_borrow_mut().or_report();
+                tasks.repoll = false;
+                std::mem::take(&mut tasks.tasks)
+            };
+            let pending: Vec<Task> = batch
+                .into_iter()
+                .filter_map(|mut task| match task.as_mut().poll(&mut cx) {
+                    Poll::Ready(()) => None,
+                    Poll::Pending => Some(task),
+                })
+                .collect();
+            let mut tasks = self.spawned.try_borrow_mut().or_report();
+            let spawned_meanwhile = std::mem::replace(&mut tasks.tasks, pending);
+            tasks.tasks.extend(spawned_meanwhile);
+            if !tasks.repoll {
+                break;
+            }
+        }
+    }
+}
+
+/// Ends polling of spawned futures, also if a future panics.
+/// The futures of the interrupted batch are dropped in that case.
+struct Polling<'a>(&'a Nut);
+
+impl Drop for Polling<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut tasks) = self.0.spawned.try_borrow_mut() {
+            tasks.polling = false;
+            tasks.repoll = false;
+        }
+    }
+}
+
+pub(crate) fn spawn(f: impl Future<Output = ()> + 'static) {
+    NUT.with(|nut| {
+        nut.spawned
+            .try_borrow_mut()
+            .or_report()
+            .tasks
+            .push(Box::pin(f));
+        nut.poll_spawned();
+    })
+}
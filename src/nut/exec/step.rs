@@ -0,0 +1,84 @@
+//! Executing the deferred queue one event at a time, see `nuts::test_support`.
+
+use super::{Deferred, Priority, QueuedEvent};
+use crate::nut::{Nut, NUT};
+use core::sync::atomic::Ordering;
+
+/// Describes an event waiting in the queue of nuts, returned by [`nuts::test_support::pending_events`](fn.pending_events.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PendingEventInfo {
+    /// What the event does, for example `"broadcast"` or `"lifecycle change"`
+    pub kind: &'static str,
+    /// The name of the message type, for broadcasts only, not stable, see [`MessageMeta::type_name`](../struct.MessageMeta.html#method.type_name).
+    pub message: Option<&'static str>,
+    /// The priority the event has been queued with
+    pub priority: Priority,
+}
+
+impl Deferred {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Broadcast(_) => "broadcast",
+            Self::Subscription(_) => "subscription",
+            Self::Unsubscribe(_) => "unsubscribe",
+            Self::OnDeleteSubscription(_, _) => "on delete subscription",
+            Self::LifecycleChange(_) => "lifecycle change",
+            Self::RemoveActivity(_) => "remove activity",
+            Self::ReplaceActivity(_, _, _) => "replace activity",
+            Self::DomainStore(_) => "domain store",
+            Self::FlushInchoateActivities => "flush new activities",
+            Self::ActivityCall(_) => "activity call",
+            Self::ApplyDeliveryOrder(_) => "apply delivery order",
+            Self::Compact => "compact",
+        }
+    }
+}
+
+impl PendingEventInfo {
+    fn of(queued: &QueuedEvent) -> Self {
+        Self {
+            kind: queued.event.kind(),
+            message: match &queued.event {
+                Deferred::Broadcast(b) => Some(b.topic().name()),
+                _ => None,
+            },
+            priority: queued.priority,
+        }
+    }
+}
+
+impl Nut {
+    /// Executes the next queued event, if any.
+    /// Returns false if nothing has been executed, either because the queue is empty or because an event is executing already.
+    fn step(&self) -> bool {
+        if self.executing.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        let queued = self.deferred_events.pop();
+        let stepped = queued.is_some();
+        if let Some(queued) = queued {
+            self.exec_queued(queued.event, queued.barriers, queued.trace);
+            self.finish_exec();
+        }
+        self.executing.store(false, Ordering::Relaxed);
+        stepped
+    }
+}
+
+pub(crate) fn set_stepping(enabled: bool) {
+    NUT.with(|nut| {
+        nut.stepping.set(enabled);
+        if !enabled {
+            nut.catch_up_deferred_to_quiescence();
+        }
+    })
+}
+
+pub(crate) fn pending_events() -> Vec<PendingEventInfo> {
+    NUT.with(|nut| nut.deferred_events.map_all(PendingEventInfo::of))
+}
+
+pub(crate) fn step() -> bool {
+    NUT.with(Nut::step)
+}
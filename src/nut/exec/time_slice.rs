@@ -0,0 +1,80 @@
+//! Limit on the time spent in a single catch-up, set with `nuts::set_execution_budget`.
+
+use crate::nut::iac::publish::BroadcastInfo;
+use crate::nut::iac::topic::Topic;
+use crate::nut::Nut;
+use std::time::Duration;
+
+/// Builtin message published when nuts stopped executing queued events because the execution budget has been used up.
+///
+/// The remaining events stay queued and are executed the next time nuts catches up, for example when the next message is published from outside of handlers.
+/// See [`nuts::set_execution_budget`](fn.set_execution_budget.html).
+#[derive(Clone, Debug)]
+pub struct BudgetExceeded {
+    /// Time spent executing, as measured by the clock passed to `set_execution_budget`.
+    pub elapsed: Duration,
+    /// Number of events left in the queue.
+    pub remaining: usize,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct ExecutionBudget {
+    budget: Duration,
+    clock: fn() -> Duration,
+}
+
+/// The budget of the ongoing catch-up
+#[derive(Clone, Copy)]
+pub(crate) struct TimeSlice {
+    budget: ExecutionBudget,
+    start: Duration,
+}
+
+impl ExecutionBudget {
+    pub(crate) fn new(budget: Duration, clock: fn() -> Duration) -> Self {
+        Self { budget, clock }
+    }
+    pub(crate) fn start(self) -> TimeSlice {
+        TimeSlice {
+            budget: self,
+            start: (self.clock)(),
+        }
+    }
+}
+
+impl TimeSlice {
+    /// The time spent so far, if it exceeds the budget.
+    fn exceeded(&self) -> Option<Duration> {
+        let elapsed = (self.budget.clock)()
+            .checked_sub(self.start)
+            .unwrap_or_default();
+        Some(elapsed).filter(|elapsed| *elapsed >= self.budget.budget)
+    }
+}
+
+impl Nut {
+    /// Checks the budget after an event has been executed.
+    /// Returns true iff execution must stop, after delivering a `BudgetExceeded` notification.
+    ///
+    /// only access after locking with executing flag
+    pub(crate) fn time_slice_used_up(&self) -> bool {
+        let elapsed = match self.time_slice.get().and_then(|slice| slice.exceeded()) {
+            Some(elapsed) => elapsed,
+            None => return false,
+        };
+        if self.deferred_events.is_empty() {
+            return false;
+        }
+        // Only one notification per catch-up, events queued by its handlers wait like all others
+        self.time_slice.set(None);
+        let msg = BudgetExceeded {
+            elapsed,
+            remaining: self.deferred_events.len(),
+        };
+        self.unchecked_broadcast(BroadcastInfo::global(
+            msg,
+            Topic::public_message::<BudgetExceeded>(),
+        ));
+        true
+    }
+}
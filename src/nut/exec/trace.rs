@@ -0,0 +1,137 @@
+//! Opt-in tracing of events, to reconstruct why a cascade of messages happened.
+
+use crate::nut::exec::flow::FlowRecorder;
+use crate::nut::exec::Deferred;
+use crate::{LifecycleStatus, UncheckedActivityId};
+
+/// A structured event passed to the sink registered with [`nuts::enable_tracing`](fn.enable_tracing.html).
+///
+/// Every published message and lifecycle change receives a unique `id`.
+/// The `parent` is the id of the message (or lifecycle change) that was being processed when the event was caused,
+/// or `None` if it was caused from outside of nuts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TraceEvent {
+    /// A message has been published or sent to a private channel.
+    Publish {
+        /// Identifies the message
+        id: u64,
+        /// The event that caused the message
+        parent: Option<u64>,
+        /// The activity whose handler published the message, if any
+        publisher: Option<UncheckedActivityId>,
+        /// The name of the message type, not stable, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+        type_name: &'static str,
+    },
+    /// The event with the given id has been queued, because another event is executing right now.
+    Defer {
+        /// Identifies the queued event
+        id: u64,
+    },
+    /// A message has been delivered to a subscription handler of the receiver.
+    Deliver {
+        /// Identifies the message
+        id: u64,
+        /// The activity the handler belongs to
+        receiver: UncheckedActivityId,
+        /// The name of the message type, not stable, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+        type_name: &'static str,
+    },
+    /// The lifecycle status of an activity has been requested to change.
+    LifecycleChange {
+        /// Identifies the change
+        id: u64,
+        /// The event that caused the change
+        parent: Option<u64>,
+        /// The activity changing status
+        activity: UncheckedActivityId,
+        /// The new status
+        status: LifecycleStatus,
+    },
+}
+
+pub(crate) type TraceSink = Box<dyn Fn(&TraceEvent)>;
+
+/// Produces trace events, for the sink of `nuts::enable_tracing` and for the flow recorder of `nuts::debug`.
+#[derive(Default)]
+pub(crate) struct Tracer {
+    sink: Option<TraceSink>,
+    flow: Option<FlowRecorder>,
+    next_id: u64,
+    /// The traced event that is executing right now
+    current: Option<u64>,
+    /// The message type of the broadcast that is executing right now
+    current_type: &'static str,
+    /// The activity whose handler is executing right now
+    handler: Option<UncheckedActivityId>,
+}
+
+impl Tracer {
+    pub(crate) fn set_sink(&mut self, sink: Option<TraceSink>) {
+        self.sink = sink;
+    }
+    pub(crate) fn set_flow(&mut self, flow: Option<FlowRecorder>) {
+        self.flow = flow;
+    }
+    pub(crate) fn flow(&self) -> Option<&FlowRecorder> {
+        self.flow.as_ref()
+    }
+    /// True iff neither a sink nor a recorder is attached
+    pub(crate) fn is_idle(&self) -> bool {
+        self.sink.is_none() && self.flow.is_none()
+    }
+    fn emit(&mut self, event: TraceEvent) {
+        if let Some(flow) = &mut self.flow {
+            flow.record(&event);
+        }
+        if let Some(sink) = &self.sink {
+            sink(&event);
+        }
+    }
+    /// Reports a newly queued event and returns the trace id that it carries through the queue.
+    ///
+    /// Untraced events carry the id of their parent, hence everything they cause is attributed to the parent.
+    pub(crate) fn queue(&mut self, event: &Deferred, executing: bool) -> Option<u64> {
+        let parent = self.current;
+        let id = self.next_id;
+        let traced = match event {
+            Deferred::Broadcast(b) => TraceEvent::Publish {
+                id,
+                parent,
+                publisher: self.handler,
+                type_name: b.topic().name(),
+            },
+            Deferred::LifecycleChange(lc) => TraceEvent::LifecycleChange {
+                id,
+                parent,
+                activity: lc.activity(),
+                status: lc.status(),
+            },
+            _ => return parent,
+        };
+        self.next_id += 1;
+        self.emit(traced);
+        if executing {
+            self.emit(TraceEvent::Defer { id });
+        }
+        Some(id)
+    }
+    /// Marks the start of executing a queued event
+    pub(crate) fn execute(&mut self, trace: Option<u64>, event: Option<&Deferred>) {
+        self.current = trace;
+        self.handler = None;
+        if let Some(Deferred::Broadcast(b)) = event {
+            self.current_type = b.topic().name();
+        }
+    }
+    pub(crate) fn deliver(&mut self, receiver: UncheckedActivityId) {
+        self.handler = Some(receiver);
+        if let Some(id) = self.current {
+            self.emit(TraceEvent::Deliver {
+                id,
+                receiver,
+                type_name: self.current_type,
+            });
+        }
+    }
+}
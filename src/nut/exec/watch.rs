@@ -0,0 +1,67 @@
+//! Messages published with `nuts::publish_when`, waiting for a condition on a domain.
+
+use crate::nut::iac::publish::BroadcastInfo;
+use crate::nut::{diagnostics::OrReport, Nut};
+use crate::{DomainId, DomainState};
+
+type Predicate = Box<dyn Fn(&DomainState) -> bool>;
+
+struct Watched {
+    domain: DomainId,
+    predicate: Predicate,
+    broadcast: BroadcastInfo,
+}
+
+/// Held messages, in the order they have been published
+#[derive(Default)]
+pub(crate) struct WatchList {
+    watched: Vec<Watched>,
+}
+
+impl WatchList {
+    pub(crate) fn hold(
+        &mut self,
+        domain: DomainId,
+        predicate: Predicate,
+        broadcast: BroadcastInfo,
+    ) {
+        self.watched.push(Watched {
+            domain,
+            predicate,
+            broadcast,
+        });
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.watched.is_empty()
+    }
+}
+
+impl Nut {
+    /// Queues all held messages whose condition is true now.
+    /// Returns true iff anything has been queued.
+    ///
+    /// only access after locking with executing flag
+    pub(crate) fn release_watched(&self) -> bool {
+        let mut watch_list = self.watch_list.try_borrow_mut().or_report();
+        if watch_list.is_empty() {
+            return false;
+        }
+        let mut managed_state = self.managed_state.try_borrow_mut().or_report();
+        let (ready, waiting) = std::mem::take(&mut watch_list.watched)
+            .into_iter()
+            .partition::<Vec<_>, _>(|w| {
+                managed_state.prepare(w.domain);
+                managed_state
+                    .get_mut(w.domain)
+                    .is_some_and(|domain| (w.predicate)(domain))
+            });
+        watch_list.watched = waiting;
+        drop(managed_state);
+        drop(watch_list);
+        let any = !ready.is_empty();
+        for w in ready {
+            self.push_deferred(w.broadcast.into());
+        }
+        any
+    }
+}
@@ -6,8 +6,13 @@
 //!
 //! TODO: model for shared memory is planned for higher bandwidth communication.
 
+pub(crate) mod context;
+pub(crate) mod entity;
 pub(crate) mod filter;
 pub(crate) mod managed_state;
+pub(crate) mod order;
 pub(crate) mod publish;
+pub(crate) mod signal;
 pub(crate) mod subscription;
 pub(crate) mod topic;
+pub(crate) mod trait_topic;
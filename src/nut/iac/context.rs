@@ -0,0 +1,156 @@
+//! Context object handed to subscription handlers registered with `subscribe_ctx`.
+
+use crate::nut::exec::Deferred;
+use crate::nut::iac::publish::MessageMeta;
+use crate::nut::{OnceHandler, IMPOSSIBLE_ERR_MSG};
+use crate::*;
+use core::any::Any;
+use std::rc::Rc;
+
+/// Gives a subscription handler access to everything that is available while a message is delivered.
+///
+/// Handlers receive a `HandlerCtx` when registered with [`subscribe_ctx`](struct.ActivityId.html#method.subscribe_ctx).
+/// Besides the activity itself, it provides the domain (if any), information about the message,
+/// and ways to influence the ongoing dispatch.
+///
+/// ### Example
+/// ```rust
+/// struct MyActivity { received: usize }
+/// struct Ping;
+///
+/// let id = nuts::new_activity(MyActivity { received: 0 });
+/// id.subscribe_ctx(|ctx, _msg: &Ping| {
+///     ctx.activity().received += 1;
+///     assert!(ctx.meta().type_name().ends_with("Ping"));
+///     // No other subscriber will receive this message
+///     ctx.stop_propagation();
+/// });
+/// nuts::publish(Ping);
+/// ```
+pub struct HandlerCtx<'a, A: Activity> {
+    activity: &'a mut A,
+    domain: Option<&'a mut DomainState>,
+    meta: &'a MessageMeta,
+    id: ActivityId<A>,
+    stop_propagation: &'a mut bool,
+}
+
+impl<'a, A: Activity> HandlerCtx<'a, A> {
+    pub(crate) fn new(
+        activity: &'a mut A,
+        domain: Option<&'a mut DomainState>,
+        meta: &'a MessageMeta,
+        id: ActivityId<A>,
+        stop_propagation: &'a mut bool,
+    ) -> Self {
+        Self {
+            activity,
+            domain,
+            meta,
+            id,
+            stop_propagation,
+        }
+    }
+    /// The activity that received the message.
+    pub fn activity(&mut self) -> &mut A {
+        self.activity
+    }
+    /// The domain of the activity, or `None` if the activity has been registered without a domain.
+    pub fn domain(&mut self) -> Option<&mut DomainState> {
+        self.domain.as_deref_mut()
+    }
+    /// Information about the message that is currently being delivered.
+    pub fn meta(&self) -> &MessageMeta {
+        self.meta
+    }
+    /// The id of the activity that received the message.
+    pub fn id(&self) -> ActivityId<A> {
+        self.id
+    }
+    /// After the current handler returns, the message will not be delivered to any further subscribers.
+    pub fn stop_propagation(&mut self) {
+        *self.stop_propagation = true;
+    }
+    /// Publishes a new message. Same as `nuts::publish`.
+    pub fn publish<MSG: Any>(&self, msg: MSG) {
+        crate::publish(msg)
+    }
+    /// Forwards a message to the private channel of another activity.
+    pub fn forward_to<MSG: Any>(&self, receiver: impl Into<UncheckedActivityId>, msg: MSG) {
+        receiver.into().private_message(msg)
+    }
+    /// Schedules a closure with access to the activity.
+    ///
+    /// It will be executed after all events that are already queued up at this point, including messages
+    /// published by the current handler.
+    /// The closure is dropped without being called if the activity is deleted in the meantime.
+    pub fn defer<F>(&self, f: F)
+    where
+        F: FnOnce(&mut A) + 'static,
+    {
+        let index = self.id;
+        let closure: OnceHandler = Box::new(
+            move |activities: &mut ActivityContainer, _: &mut ManagedState| {
+                if activities.is_alive(index.id) {
+                    let a = activities[index]
+                        .downcast_mut::<A>()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    f(a)
+                }
+            },
+        );
+        crate::nut::push_deferred(Deferred::ActivityCall(closure));
+    }
+    /// Splits a long computation into steps, with other queued events executed in between.
+    ///
+    /// The continuation `f` is queued like a published message and called with the activity and `state`.
+    /// If it returns `Some(state)`, it is queued again with the new state, behind everything that has been queued in the meantime.
+    /// Returning `None` ends the computation.
+    /// The continuation is dropped without being called if the activity is deleted in the meantime.
+    ///
+    /// Call this at the end of the handler, the current invocation should return right after.
+    /// Note that all steps are still executed before the outermost call into nuts returns, the steps only interleave with other events.
+    ///
+    /// ### Example
+    /// ```rust
+    /// struct Indexer { indexed: Vec<u32> }
+    /// struct Reindex;
+    ///
+    /// let id = nuts::new_activity(Indexer { indexed: vec![] });
+    /// id.subscribe_ctx(|ctx, _: &Reindex| {
+    ///     ctx.yield_and_continue(0u32, |indexer: &mut Indexer, next| {
+    ///         indexer.indexed.push(next);
+    ///         if next < 99 { Some(next + 1) } else { None }
+    ///     });
+    /// });
+    /// nuts::publish(Reindex);
+    /// ```
+    pub fn yield_and_continue<S, F>(&self, state: S, f: F)
+    where
+        S: 'static,
+        F: Fn(&mut A, S) -> Option<S> + 'static,
+    {
+        queue_continuation(self.id, state, Rc::new(f));
+    }
+}
+
+fn queue_continuation<A, S, F>(id: ActivityId<A>, state: S, f: Rc<F>)
+where
+    A: Activity,
+    S: 'static,
+    F: Fn(&mut A, S) -> Option<S> + 'static,
+{
+    let closure: OnceHandler = Box::new(
+        move |activities: &mut ActivityContainer, _: &mut ManagedState| {
+            if activities.is_alive(id.id) {
+                let a = activities[id]
+                    .downcast_mut::<A>()
+                    .expect(IMPOSSIBLE_ERR_MSG);
+                if let Some(state) = f(a, state) {
+                    queue_continuation(id, state, f);
+                }
+            }
+        },
+    );
+    crate::nut::push_deferred(Deferred::ActivityCall(closure));
+}
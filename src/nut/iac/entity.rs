@@ -0,0 +1,6 @@
+/// Addresses a single entity, see [`nuts::publish_to_entity`](fn.publish_to_entity.html).
+///
+/// Entities are not managed by nuts, the number is chosen by the application.
+/// Typically, it is the index of the entity in a game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityId(pub u64);
@@ -1,25 +1,101 @@
+use crate::nut::iac::subscription::SubscriptionOptions;
 use crate::*;
 
 /// Defines under which circumstances a subscribing activity should be called.
 /// At the moment, the only filter option is to check the activity lifecycle state.
 /// The default filter will ignore events when the activity is inactive.
+///
+/// The filter also defines the priority of the subscription.
+/// Subscriptions with a higher priority are called first, subscriptions with equal priority are called in the order they have been registered.
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct SubscriptionFilter {
     /// Only call the subscribed closure when the activity is active.
     pub active_only: bool,
+    /// Subscriptions with higher priority are called before those with lower priority. Default is 0.
+    pub priority: i32,
+    /// The subscription group, if any. See [`in_group`](#method.in_group).
+    pub group: Option<String>,
+    /// Only receive messages published for this phase. See [`in_phase`](#method.in_phase).
+    pub phase: Option<Phase>,
 }
 
 impl Default for SubscriptionFilter {
     fn default() -> Self {
-        Self { active_only: true }
+        Self {
+            active_only: true,
+            priority: 0,
+            group: None,
+            phase: None,
+        }
     }
 }
 
 impl SubscriptionFilter {
     /// Create a new subscription filter that will ensure the activity always receives a message, even when inactive.
     pub fn no_filter() -> Self {
-        Self { active_only: false }
+        Self {
+            active_only: false,
+            ..Default::default()
+        }
+    }
+    /// Sets the priority of the subscription.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use nuts::SubscriptionFilter;
+    ///
+    /// struct Physics;
+    /// struct Rendering;
+    /// struct Tick;
+    ///
+    /// let rendering = nuts::new_activity(Rendering);
+    /// let physics = nuts::new_activity(Physics);
+    /// rendering.subscribe(|_, _: &Tick| println!("Draw"));
+    /// // Runs before the rendering, even though it has been registered later.
+    /// physics.subscribe_masked(SubscriptionFilter::default().with_priority(10), |_, _: &Tick| {
+    ///     println!("Step")
+    /// });
+    /// nuts::publish(Tick);
+    /// ```
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+    /// Adds the subscription to a named group.
+    ///
+    /// Groups select subscriptions that survive when the activity is replaced with [`ActivityId::replace`](struct.ActivityId.html#method.replace).
+    pub fn in_group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+    /// Restricts the subscription to messages published with [`nuts::publish_phased`](fn.publish_phased.html) for the given phase.
+    pub fn in_phase(mut self, phase: Phase) -> Self {
+        self.phase = Some(phase);
+        self
+    }
+    pub(crate) fn into_options(self) -> SubscriptionOptions {
+        SubscriptionOptions {
+            active_only: self.active_only,
+            priority: self.priority,
+            group: self.group,
+            phase: self.phase,
+        }
+    }
+    pub(crate) fn options(&self) -> SubscriptionOptions {
+        SubscriptionOptions {
+            active_only: self.active_only,
+            priority: self.priority,
+            group: self.group.clone(),
+            phase: self.phase,
+        }
+    }
+}
+
+/// A filter for messages of a single phase, same as `SubscriptionFilter::default().in_phase(phase)`.
+impl From<Phase> for SubscriptionFilter {
+    fn from(phase: Phase) -> Self {
+        Self::default().in_phase(phase)
     }
 }
 
@@ -5,41 +5,119 @@
 mod domain_id;
 mod domain_state;
 mod domain_store;
+mod typed_domain;
 
 use crate::nut::activity::Activity;
 use crate::nut::activity::ActivityContainer;
 use crate::nut::activity::ActivityId;
+use crate::nut::iac::context::HandlerCtx;
 use crate::nut::iac::filter::SubscriptionFilter;
-use crate::nut::Handler;
+use crate::nut::iac::publish::{MessageMeta, ResponseSlot};
 use crate::nut::IMPOSSIBLE_ERR_MSG;
+use crate::nut::{Handler, Nut};
+use crate::Phase;
 use core::any::Any;
 pub use domain_id::*;
 pub use domain_state::*;
 pub(crate) use domain_store::*;
+pub use typed_domain::TypedDomain;
 
 #[derive(Default)]
 pub(crate) struct ManagedState {
     domains: Vec<DomainState>,
+    /// The only domain that handlers may access, while a parallel-ready message is dispatched to it.
+    domain_group: Option<DomainId>,
     broadcast: Option<Box<dyn Any>>,
+    broadcast_meta: Option<MessageMeta>,
+    /// Where to store a reply to the current broadcast, if it is a request.
+    reply_to: Option<ResponseSlot>,
+    /// Set by a handler to prevent delivery of the current broadcast to further subscribers.
+    stop_propagation: bool,
+}
+
+/// Mutable access to a single domain, which must be the domain group while one is set.
+fn domain_in_group(
+    domains: &mut [DomainState],
+    group: Option<DomainId>,
+    index: usize,
+) -> &mut DomainState {
+    if let Some(group) = group {
+        assert_eq!(
+            group.index(),
+            Some(index),
+            "Handler of a parallel-ready message accessed a domain other than the domain of its activity"
+        );
+    }
+    &mut domains[index]
 }
 
 impl ManagedState {
     pub(crate) fn get_mut(&mut self, id: DomainId) -> Option<&mut DomainState> {
-        id.index().map(move |i| &mut self.domains[i])
+        let group = self.domain_group;
+        let domains = &mut self.domains;
+        id.index().map(move |i| domain_in_group(domains, group, i))
+    }
+    pub(crate) fn domains(&self) -> impl Iterator<Item = &DomainState> {
+        self.domains.iter()
+    }
+    /// Restricts domain access to a single domain, until called again with `None`.
+    pub(crate) fn set_domain_group(&mut self, group: Option<DomainId>) {
+        self.domain_group = group;
+    }
+    /// Like `get_mut` but by index, creating the domain and those before it if necessary.
+    #[cfg(feature = "serde")]
+    pub(crate) fn get_or_prepare_index(&mut self, index: usize) -> &mut DomainState {
+        while self.domains.len() <= index {
+            let id = DomainId::from_index(self.domains.len());
+            self.domains.push(DomainState::with_id(id));
+        }
+        &mut self.domains[index]
     }
     /// Fills all domains with default values. Must be called once or will panic when used.
     pub(crate) fn prepare(&mut self, id: DomainId) {
         if let Some(n) = id.index() {
             while self.domains.len() <= n {
-                self.domains.push(Default::default());
+                let id = DomainId::from_index(self.domains.len());
+                self.domains.push(DomainState::with_id(id));
             }
         }
     }
-    pub(crate) fn set_broadcast(&mut self, msg: Box<dyn Any>) {
+    pub(crate) fn set_broadcast(
+        &mut self,
+        msg: Box<dyn Any>,
+        meta: MessageMeta,
+        reply_to: Option<ResponseSlot>,
+    ) {
         self.broadcast = Some(msg);
+        self.broadcast_meta = Some(meta);
+        self.reply_to = reply_to;
+        self.stop_propagation = false;
+    }
+    /// The phase of the message that is currently being delivered, if any.
+    pub(crate) fn broadcast_phase(&self) -> Option<Phase> {
+        self.broadcast_meta.as_ref().and_then(MessageMeta::phase)
     }
-    pub(crate) fn clear_broadcast(&mut self) {
-        self.broadcast = None;
+    /// Returns the message, unless a private handler has taken it.
+    pub(crate) fn clear_broadcast(&mut self) -> Option<Box<dyn Any>> {
+        self.broadcast_meta = None;
+        self.reply_to = None;
+        self.broadcast.take()
+    }
+    /// Stores the reply if the current broadcast is a request. Otherwise, the reply is dropped.
+    fn reply<REPLY: Any>(&self, reply: REPLY) {
+        if let Some(slot) = self.reply_to {
+            Nut::with_response_tracker_mut(|rt| rt.set_reply(slot, Box::new(reply)));
+        }
+    }
+    /// The message that is currently being delivered, unless a private handler has taken it.
+    pub(crate) fn broadcast_ref(&self) -> Option<&dyn Any> {
+        self.broadcast.as_deref()
+    }
+    pub(crate) fn has_broadcast(&self) -> bool {
+        self.broadcast.is_some()
+    }
+    pub(crate) fn propagation_stopped(&self) -> bool {
+        self.stop_propagation
     }
     /// panics if runtime broadcast is not of static type A
     fn current_broadcast<A: Any>(&mut self) -> &mut A {
@@ -59,17 +137,31 @@ impl ManagedState {
             .downcast_mut()
             .expect(IMPOSSIBLE_ERR_MSG);
         let i = id.index().expect(IMPOSSIBLE_ERR_MSG);
-        let domain = &mut self.domains[i];
+        let domain = domain_in_group(&mut self.domains, self.domain_group, i);
         (msg, domain)
     }
-    fn take_current_broadcast<A: Any>(&mut self) -> Box<A> {
-        let msg = self
+    fn current_broadcast_with_context<A: Any>(
+        &mut self,
+        id: DomainId,
+    ) -> (&mut A, Option<&mut DomainState>, &MessageMeta, &mut bool) {
+        let msg: &mut A = self
             .broadcast
+            .as_mut()
+            .expect(IMPOSSIBLE_ERR_MSG)
+            .downcast_mut()
+            .expect(IMPOSSIBLE_ERR_MSG);
+        let domains = &mut self.domains;
+        let group = self.domain_group;
+        let domain = id.index().map(move |i| domain_in_group(domains, group, i));
+        let meta = self.broadcast_meta.as_ref().expect(IMPOSSIBLE_ERR_MSG);
+        (msg, domain, meta, &mut self.stop_propagation)
+    }
+    fn take_current_broadcast<A: Any>(&mut self) -> Box<A> {
+        self.broadcast
             .take()
             .expect(IMPOSSIBLE_ERR_MSG)
             .downcast()
-            .expect(IMPOSSIBLE_ERR_MSG);
-        msg
+            .expect(IMPOSSIBLE_ERR_MSG)
     }
     fn take_current_broadcast_and_borrow_domain<A: Any>(
         &mut self,
@@ -82,7 +174,7 @@ impl ManagedState {
             .downcast()
             .expect(IMPOSSIBLE_ERR_MSG);
         let i = id.index().expect("Activity has no domain");
-        let domain = &mut self.domains[i];
+        let domain = domain_in_group(&mut self.domains, self.domain_group, i);
         (msg, domain)
     }
 
@@ -119,11 +211,15 @@ impl ManagedState {
         Box::new(
             move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
                 if activities.filter(index, &filter) {
+                    let domain_id = activities.domain(index.into());
                     let a = activities[index]
                         .downcast_mut::<A>()
                         .expect(IMPOSSIBLE_ERR_MSG);
-                    let domain = &mut managed_state.domains
-                        [index.domain_index.index().expect(IMPOSSIBLE_ERR_MSG)];
+                    let domain = domain_in_group(
+                        &mut managed_state.domains,
+                        managed_state.domain_group,
+                        domain_id.index().expect(IMPOSSIBLE_ERR_MSG),
+                    );
                     f(a, domain)
                 }
             },
@@ -142,6 +238,48 @@ impl ManagedState {
             },
         )
     }
+    pub(crate) fn pack_closure_any<F>(f: F) -> Handler
+    where
+        F: Fn(&MessageMeta, &dyn Any) + 'static,
+    {
+        Box::new(
+            move |_activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                let msg = managed_state
+                    .broadcast
+                    .as_deref()
+                    .expect(IMPOSSIBLE_ERR_MSG);
+                let meta = managed_state
+                    .broadcast_meta
+                    .as_ref()
+                    .expect(IMPOSSIBLE_ERR_MSG);
+                f(meta, msg)
+            },
+        )
+    }
+    pub(crate) fn pack_closure_dyn<A, F>(
+        f: F,
+        index: ActivityId<A>,
+        filter: SubscriptionFilter,
+    ) -> Handler
+    where
+        A: Activity,
+        F: Fn(&mut A, &dyn Any) + 'static,
+    {
+        Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                if activities.filter(index, &filter) {
+                    let a = activities[index]
+                        .downcast_mut::<A>()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    let msg = managed_state
+                        .broadcast
+                        .as_deref()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    f(a, msg)
+                }
+            },
+        )
+    }
     pub(crate) fn pack_closure<A, F, MSG>(
         f: F,
         index: ActivityId<A>,
@@ -151,6 +289,70 @@ impl ManagedState {
         A: Activity,
         F: Fn(&mut A, &MSG) + 'static,
         MSG: Any,
+    {
+        Self::pack_closure_filtered(f, |_: &MSG| true, index, filter)
+    }
+    /// The predicate is checked before the activity is looked up.
+    pub(crate) fn pack_closure_filtered<A, F, P, MSG>(
+        f: F,
+        predicate: P,
+        index: ActivityId<A>,
+        filter: SubscriptionFilter,
+    ) -> Handler
+    where
+        A: Activity,
+        F: Fn(&mut A, &MSG) + 'static,
+        P: Fn(&MSG) -> bool + 'static,
+        MSG: Any,
+    {
+        Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                let msg = managed_state.current_broadcast();
+                if predicate(msg) && activities.filter(index, &filter) {
+                    let a = activities[index]
+                        .downcast_mut::<A>()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    f(a, msg)
+                }
+            },
+        )
+    }
+    pub(crate) fn pack_closure_paired<A, F, G, MSG>(
+        active: F,
+        inactive: G,
+        index: ActivityId<A>,
+    ) -> Handler
+    where
+        A: Activity,
+        F: Fn(&mut A, &MSG) + 'static,
+        G: Fn(&mut A, &MSG) + 'static,
+        MSG: Any,
+    {
+        Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                let is_active = activities.status(index.into()).is_active();
+                let a = activities[index]
+                    .downcast_mut::<A>()
+                    .expect(IMPOSSIBLE_ERR_MSG);
+                let msg = managed_state.current_broadcast();
+                if is_active {
+                    active(a, msg)
+                } else {
+                    inactive(a, msg)
+                }
+            },
+        )
+    }
+    pub(crate) fn pack_responder<A, F, MSG, REPLY>(
+        f: F,
+        index: ActivityId<A>,
+        filter: SubscriptionFilter,
+    ) -> Handler
+    where
+        A: Activity,
+        F: Fn(&mut A, &MSG) -> REPLY + 'static,
+        MSG: Any,
+        REPLY: Any,
     {
         Box::new(
             move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
@@ -159,7 +361,33 @@ impl ManagedState {
                         .downcast_mut::<A>()
                         .expect(IMPOSSIBLE_ERR_MSG);
                     let msg = managed_state.current_broadcast();
-                    f(a, msg)
+                    let reply = f(a, msg);
+                    managed_state.reply(reply);
+                }
+            },
+        )
+    }
+    pub(crate) fn pack_closure_ctx<A, F, MSG>(
+        f: F,
+        index: ActivityId<A>,
+        filter: SubscriptionFilter,
+    ) -> Handler
+    where
+        A: Activity,
+        F: Fn(&mut HandlerCtx<A>, &MSG) + 'static,
+        MSG: Any,
+    {
+        Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                if activities.filter(index, &filter) {
+                    let domain_id = activities.domain(index.into());
+                    let a = activities[index]
+                        .downcast_mut::<A>()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    let (msg, domain, meta, stop_propagation) =
+                        managed_state.current_broadcast_with_context(domain_id);
+                    let mut ctx = HandlerCtx::new(a, domain, meta, index, stop_propagation);
+                    f(&mut ctx, msg)
                 }
             },
         )
@@ -208,6 +436,30 @@ impl ManagedState {
             },
         )
     }
+    pub(crate) fn pack_owned_responder<A, F, MSG, REPLY>(
+        f: F,
+        index: ActivityId<A>,
+        filter: SubscriptionFilter,
+    ) -> Handler
+    where
+        A: Activity,
+        F: Fn(&mut A, MSG) -> REPLY + 'static,
+        MSG: Any,
+        REPLY: Any,
+    {
+        Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                if activities.filter(index, &filter) {
+                    let a = activities[index]
+                        .downcast_mut::<A>()
+                        .expect(IMPOSSIBLE_ERR_MSG);
+                    let msg = managed_state.take_current_broadcast();
+                    let reply = f(a, *msg);
+                    managed_state.reply(reply);
+                }
+            },
+        )
+    }
     pub(crate) fn pack_domained_closure<A, F, MSG>(
         f: F,
         index: ActivityId<A>,
@@ -221,11 +473,11 @@ impl ManagedState {
         Box::new(
             move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
                 if activities.filter(index, &filter) {
+                    let domain_id = activities.domain(index.into());
                     let a = activities[index]
                         .downcast_mut::<A>()
                         .expect(IMPOSSIBLE_ERR_MSG);
-                    let (msg, domain) =
-                        managed_state.current_broadcast_and_domain(index.domain_index);
+                    let (msg, domain) = managed_state.current_broadcast_and_domain(domain_id);
                     f(a, domain, msg)
                 }
             },
@@ -244,11 +496,11 @@ impl ManagedState {
         Box::new(
             move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
                 if activities.filter(index, &filter) {
+                    let domain_id = activities.domain(index.into());
                     let a = activities[index]
                         .downcast_mut::<A>()
                         .expect(IMPOSSIBLE_ERR_MSG);
-                    let (msg, domain) =
-                        managed_state.current_broadcast_and_domain(index.domain_index);
+                    let (msg, domain) = managed_state.current_broadcast_and_domain(domain_id);
                     f(a, domain, msg)
                 }
             },
@@ -267,11 +519,12 @@ impl ManagedState {
         Box::new(
             move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
                 if activities.filter(index, &filter) {
+                    let domain_id = activities.domain(index.into());
                     let a = activities[index]
                         .downcast_mut::<A>()
                         .expect(IMPOSSIBLE_ERR_MSG);
                     let (msg, domain) =
-                        managed_state.take_current_broadcast_and_borrow_domain(index.domain_index);
+                        managed_state.take_current_broadcast_and_borrow_domain(domain_id);
                     f(a, domain, *msg)
                 }
             },
@@ -1,6 +1,7 @@
 use core::any::{Any, TypeId};
 use std::collections::{hash_map::Entry, HashMap};
 
+use super::DomainId;
 use crate::nut::IMPOSSIBLE_ERR_MSG;
 
 /// Stores passive data that can be accessed in event handlers of multiple activities.
@@ -22,10 +23,41 @@ pub struct DomainState {
     // Indirection to Vec is used here to allow for safe internal mutability without falling back to RefCells.
     // (RefCells are uneasy to use from outside AND the runtime hit is larger)
     objects: Vec<Box<dyn Any>>,
+    /// Type names of `objects`, for diagnostics
+    type_names: Vec<&'static str>,
     index_map: HashMap<TypeId, usize>,
+    /// The domain this state belongs to, to look up pending stores
+    id: DomainId,
+}
+
+/// Defines `try_get_N_mut` for N types, on top of `DomainState::try_get_disjoint_mut`.
+macro_rules! try_get_n_mut {
+    ($name:ident, $n:literal, $($T:ident),+) => {
+        #[doc = concat!("Same as [`try_get_2_mut`](#method.try_get_2_mut) but for ", $n, " types.")]
+        /// # Panics
+        /// Panics if the same type is requested twice.
+        #[allow(clippy::type_complexity)]
+        pub fn $name<$($T: Any),+>(&mut self) -> ($(Option<&mut $T>,)+) {
+            let mut objects = self
+                .try_get_disjoint_mut(&[$(TypeId::of::<$T>()),+])
+                .into_iter();
+            ($(
+                objects
+                    .next()
+                    .expect(IMPOSSIBLE_ERR_MSG)
+                    .map(|obj| obj.downcast_mut::<$T>().expect(IMPOSSIBLE_ERR_MSG)),
+            )+)
+        }
+    };
 }
 
 impl DomainState {
+    pub(crate) fn with_id(id: DomainId) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
     /// Stores a value in the domain.
     // @ START-DOC DOMAIN_STORE
     /// Only one instance per type id can be stored inside a domain.
@@ -42,6 +74,7 @@ impl DomainState {
             Entry::Vacant(entry) => {
                 entry.insert(self.objects.len());
                 self.objects.push(Box::new(obj));
+                self.type_names.push(std::any::type_name::<T>());
             }
         }
     }
@@ -51,7 +84,12 @@ impl DomainState {
     /// Used for delayed stores to domains.
     ///
     /// This variant is slightly less efficient as it will allocate another Box if the value was already in the domain.
-    pub(crate) fn store_unchecked(&mut self, id: TypeId, obj: Box<dyn Any>) {
+    pub(crate) fn store_unchecked(
+        &mut self,
+        id: TypeId,
+        obj: Box<dyn Any>,
+        type_name: &'static str,
+    ) {
         match self.index_map.entry(id) {
             Entry::Occupied(entry) => {
                 self.objects[*entry.get()] = obj;
@@ -59,9 +97,45 @@ impl DomainState {
             Entry::Vacant(entry) => {
                 entry.insert(self.objects.len());
                 self.objects.push(obj);
+                self.type_names.push(type_name);
             }
         }
     }
+    /// The number of stores of type `T` to this domain that have been delayed and not executed yet.
+    ///
+    /// Stores are delayed when [`nuts::store_to_domain`](fn.store_to_domain.html) is called inside a subscription handler.
+    /// This is meant for diagnostics, the stored values land before messages published after them are delivered.
+    pub fn pending<T: Any>(&self) -> usize {
+        crate::nut::pending_domain_stores(self.id, TypeId::of::<T>())
+    }
+    /// Removes the value of the specified type from the domain and returns it, if it exists.
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        let index = self.index_map.remove(&TypeId::of::<T>())?;
+        let obj = self.objects.swap_remove(index);
+        self.type_names.swap_remove(index);
+        if let Some(moved) = self.objects.get(index) {
+            // The last object has been moved into the gap
+            self.index_map.insert(moved.as_ref().type_id(), index);
+        }
+        Some(*obj.downcast().expect(IMPOSSIBLE_ERR_MSG))
+    }
+    /// Non-generic variant of `try_get`, used for snapshots.
+    #[cfg(feature = "serde")]
+    pub(crate) fn get_unchecked(&self, id: TypeId) -> Option<&dyn Any> {
+        self.index_map
+            .get(&id)
+            .map(|index| self.objects[*index].as_ref())
+    }
+    /// Iterates the types of all stored values, in the order they have been stored first.
+    ///
+    /// Removing a value moves the last stored value into its place.
+    pub fn iter_types(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.objects.iter().map(|obj| obj.as_ref().type_id())
+    }
+    /// Names of the types of all stored values.
+    pub(crate) fn type_names(&self) -> &[&'static str] {
+        &self.type_names
+    }
     /// Returns a reference to a value of the specified type, if such a value has previously been stored to the domain.
     #[allow(clippy::unwrap_used)]
     pub fn try_get<T: Any>(&self) -> Option<&T> {
@@ -78,6 +152,14 @@ impl DomainState {
             None
         }
     }
+    /// Returns a mutable reference to the value of the specified type, storing the default value first if it is missing.
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn get_mut_or_default<T: Any + Default>(&mut self) -> &mut T {
+        if !self.index_map.contains_key(&TypeId::of::<T>()) {
+            self.store(T::default());
+        }
+        self.try_get_mut().unwrap()
+    }
     /// Return two mutable references to domain objects
     #[allow(clippy::unwrap_used)]
     pub fn try_get_2_mut<T1: Any, T2: Any>(&mut self) -> (Option<&mut T1>, Option<&mut T2>) {
@@ -109,6 +191,30 @@ impl DomainState {
             Some(t2.as_mut().downcast_mut().unwrap()),
         )
     }
+    try_get_n_mut!(try_get_3_mut, 3, T1, T2, T3);
+    try_get_n_mut!(try_get_4_mut, 4, T1, T2, T3, T4);
+    try_get_n_mut!(try_get_5_mut, 5, T1, T2, T3, T4, T5);
+    try_get_n_mut!(try_get_6_mut, 6, T1, T2, T3, T4, T5, T6);
+    try_get_n_mut!(try_get_7_mut, 7, T1, T2, T3, T4, T5, T6, T7);
+    try_get_n_mut!(try_get_8_mut, 8, T1, T2, T3, T4, T5, T6, T7, T8);
+    /// Mutable references to the values of all given types, in the same order.
+    fn try_get_disjoint_mut(&mut self, types: &[TypeId]) -> Vec<Option<&mut dyn Any>> {
+        for (i, t) in types.iter().enumerate() {
+            for other in &types[i + 1..] {
+                assert_ne(*t, *other);
+            }
+        }
+        let index_map = &self.index_map;
+        let mut objects: Vec<Option<&mut Box<dyn Any>>> =
+            self.objects.iter_mut().map(Some).collect();
+        types
+            .iter()
+            .map(|t| {
+                let index = index_map.get(t)?;
+                objects[*index].take().map(|obj| obj.as_mut())
+            })
+            .collect()
+    }
     /// Returns a reference to a value of the specified type, taken from the domain.
     /// # Panics
     /// Panics if object of that type has not been stored previously.
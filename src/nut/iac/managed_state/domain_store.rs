@@ -1,23 +1,50 @@
-use crate::debug::DebugTypeName;
-use crate::nut::{Nut, IMPOSSIBLE_ERR_MSG};
+use crate::nut::exec::Deferred;
+use crate::nut::iac::publish::ResponseSlot;
+use crate::nut::{diagnostics::OrReport, Nut};
 use crate::DomainId;
 use core::any::{Any, TypeId};
+use std::collections::hash_map::Entry;
 
 pub(crate) struct DomainStoreData {
     domain: DomainId,
     id: TypeId,
     data: Box<dyn Any>,
-    #[allow(dead_code)]
-    type_name: DebugTypeName,
+    type_name: &'static str,
 }
 impl Nut {
+    /// Queues the store and counts it as pending until it has been executed.
+    pub(crate) fn push_domain_store(&self, d: DomainStoreData, notify: Option<ResponseSlot>) {
+        *self
+            .pending_stores
+            .try_borrow_mut()
+            .or_report()
+            .entry((d.domain, d.id))
+            .or_default() += 1;
+        let event = Deferred::DomainStore(d);
+        match notify {
+            Some(slot) => self.push_deferred_with_barrier(event, slot),
+            None => self.push_deferred(event),
+        }
+    }
     pub fn exec_domain_store(&self, d: DomainStoreData) {
-        self.managed_state
+        if let Entry::Occupied(mut pending) = self
+            .pending_stores
             .try_borrow_mut()
-            .expect(IMPOSSIBLE_ERR_MSG)
+            .or_report()
+            .entry((d.domain, d.id))
+        {
+            if *pending.get() <= 1 {
+                pending.remove();
+            } else {
+                *pending.get_mut() -= 1;
+            }
+        }
+        let mut managed_state = self.managed_state.try_borrow_mut().or_report();
+        managed_state.prepare(d.domain);
+        managed_state
             .get_mut(d.domain)
             .expect("Domain ID invalid")
-            .store_unchecked(d.id, d.data);
+            .store_unchecked(d.id, d.data, d.type_name);
     }
 }
 
@@ -27,7 +54,7 @@ impl DomainStoreData {
             domain,
             id: TypeId::of::<DATA>(),
             data: Box::new(data),
-            type_name: DebugTypeName::new::<DATA>(),
+            type_name: std::any::type_name::<DATA>(),
         }
     }
 }
@@ -35,6 +62,6 @@ impl DomainStoreData {
 #[cfg(debug_assertions)]
 impl std::fmt::Debug for DomainStoreData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Storing {:?} to the domain", self.type_name)
+        write!(f, "Storing {} to the domain", self.type_name)
     }
 }
@@ -0,0 +1,74 @@
+use crate::nut::exec::Deferred;
+use crate::*;
+use core::any::Any;
+use std::marker::PhantomData;
+
+/// Handle to a domained activity that accesses one type of domain data directly.
+///
+/// Created with [`nuts::new_domained_activity_typed`](fn.new_domained_activity_typed.html).
+/// On creation, the default value of `T` is stored to the domain unless a value of type `T` is already present.
+/// Handlers registered through this handle receive `&mut T` instead of the full [`DomainState`](struct.DomainState.html),
+/// so there is no lookup that can fail at runtime.
+pub struct TypedDomain<A, T> {
+    id: ActivityId<A, WithDomain>,
+    phantom: PhantomData<T>,
+}
+
+impl<A: Activity, T: Any + Default> TypedDomain<A, T> {
+    pub(crate) fn new(id: ActivityId<A, WithDomain>) -> Self {
+        let index: UncheckedActivityId = id.into();
+        crate::nut::push_deferred(Deferred::ActivityCall(Box::new(
+            move |activities: &mut ActivityContainer, managed_state: &mut ManagedState| {
+                let domain = activities.domain(index);
+                managed_state.prepare(domain);
+                if let Some(domain) = managed_state.get_mut(domain) {
+                    domain.get_mut_or_default::<T>();
+                }
+            },
+        )));
+        Self {
+            id,
+            phantom: PhantomData,
+        }
+    }
+    /// The id of the activity, for all other operations on it.
+    pub fn id(&self) -> ActivityId<A, WithDomain> {
+        self.id
+    }
+    /// Same as [`ActivityId::subscribe_domained`](struct.ActivityId.html#method.subscribe_domained) but receives the typed domain value.
+    ///
+    /// If the value has been removed from the domain in the meantime, the default value is stored again before calling the handler.
+    pub fn subscribe<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut T, &MSG) + 'static,
+        MSG: Any,
+    {
+        self.id
+            .subscribe_domained(move |a, domain, msg| f(a, domain.get_mut_or_default(), msg))
+    }
+    /// Same as [`subscribe`](#method.subscribe) but gives mutable access to the message object.
+    pub fn subscribe_mut<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut T, &mut MSG) + 'static,
+        MSG: Any,
+    {
+        self.id
+            .subscribe_domained_mut(move |a, domain, msg| f(a, domain.get_mut_or_default(), msg))
+    }
+    /// Same as [`ActivityId::private_domained_channel`](struct.ActivityId.html#method.private_domained_channel) but receives the typed domain value.
+    pub fn private_channel<F, MSG>(&self, f: F) -> SubscriptionId
+    where
+        F: Fn(&mut A, &mut T, MSG) + 'static,
+        MSG: Any,
+    {
+        self.id
+            .private_domained_channel(move |a, domain, msg| f(a, domain.get_mut_or_default(), msg))
+    }
+}
+
+impl<A, T> Copy for TypedDomain<A, T> {}
+impl<A, T> Clone for TypedDomain<A, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
@@ -0,0 +1,53 @@
+//! Order of delivery among subscriptions of equal priority, set per topic with `nuts::set_topic_order`.
+
+use super::subscription::Subscription;
+use super::topic::Topic;
+use crate::nut::exec::Deferred;
+use crate::nut::{diagnostics::OrReport, Nut};
+use crate::UncheckedActivityId;
+use std::cmp::Ordering;
+
+/// Defines in which order subscriptions of the same message type and priority receive a message.
+///
+/// Subscriptions with a higher priority are always called first, see [`SubscriptionFilter::with_priority`](struct.SubscriptionFilter.html#method.with_priority).
+/// Set it per message type with [`nuts::set_topic_order`](fn.set_topic_order.html).
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub enum DeliveryOrder {
+    /// In the order the subscriptions have been registered. This is the default.
+    #[default]
+    Registration,
+    /// By the name of the activity type, as returned by `std::any::type_name`, then in registration order.
+    TypeName,
+    /// By comparing the subscribing activities, subscriptions that compare equal stay in registration order.
+    Custom(fn(UncheckedActivityId, UncheckedActivityId) -> Ordering),
+}
+
+impl Nut {
+    /// Sorts the subscriptions of the topic, unless no order has been set and they are in registration order already.
+    ///
+    /// only access while no broadcast is ongoing
+    pub(crate) fn apply_delivery_order(&self, topic: &Topic) {
+        let order = match self.subscriptions.order(topic) {
+            Some(order) => order,
+            None => return,
+        };
+        let activities = self.activities.try_borrow().or_report();
+        let compare = |a: &Subscription, b: &Subscription| match order {
+            DeliveryOrder::Registration => Ordering::Equal,
+            DeliveryOrder::TypeName => activities
+                .type_name(a.activity)
+                .cmp(activities.type_name(b.activity)),
+            DeliveryOrder::Custom(cmp) => cmp(a.activity, b.activity),
+        };
+        self.subscriptions.sort_shared(topic, compare);
+    }
+    pub(crate) fn set_delivery_order(&self, topic: Topic, order: DeliveryOrder) {
+        self.subscriptions.set_order(topic.clone(), order);
+        if self.quiescent() {
+            self.apply_delivery_order(&topic);
+        } else {
+            self.push_deferred(Deferred::ApplyDeliveryOrder(topic));
+        }
+    }
+}
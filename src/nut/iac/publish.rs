@@ -1,29 +1,144 @@
 pub(crate) use broadcast::BroadcastInfo;
 
 mod broadcast;
+mod buffer;
+mod error;
+mod intercept;
+mod mailbox;
+mod meta;
+mod pool;
+mod proxy;
+mod record;
+mod replay;
 mod response;
+mod undelivered;
+pub(crate) use buffer::ActivityBuffers;
+pub use error::PublishError;
+pub use intercept::InterceptDecision;
+pub(crate) use intercept::Interceptor;
+pub use mailbox::MailboxStats;
+pub(crate) use mailbox::Mailboxes;
+pub use meta::MessageMeta;
+pub(crate) use pool::MessagePool;
+pub use proxy::NutsProxy;
+pub(crate) use proxy::RemoteInbox;
+pub(crate) use record::Recorder;
+pub use record::Recording;
+pub(crate) use replay::ReplayBuffers;
+pub(crate) use response::NutsResponse;
+pub use response::RequestError;
 pub(crate) use response::ResponseTracker;
 pub(crate) use response::Slot as ResponseSlot;
+pub(crate) use undelivered::UndeliveredMessages;
 
-use crate::nut::Nut;
+use crate::nut::{diagnostics::OrReport, exec::Deferred, Nut};
 use crate::*;
 use core::any::Any;
 
-use self::response::NutsResponse;
-
 impl Nut {
     pub(crate) fn broadcast(&self, broadcast: BroadcastInfo) {
-        self.deferred_events.push(broadcast.into());
+        self.broadcast_with_priority(broadcast, Priority::Normal);
+    }
+    pub(crate) fn broadcast_with_priority(&self, broadcast: BroadcastInfo, priority: Priority) {
+        self.accept(&broadcast, false);
+        self.push_deferred_with_priority(broadcast.into(), priority);
         self.catch_up_deferred_to_quiescence();
     }
+    /// Records the message and forwards it to the worker, if it is published.
+    ///
+    /// Called once per message, when it is accepted, no matter if it is queued right away or held back by a phase or a condition.
+    pub(crate) fn accept(&self, broadcast: &BroadcastInfo, batched: bool) {
+        self.record(broadcast, batched);
+        #[cfg(feature = "web-worker")]
+        self.forward_to_worker(broadcast);
+    }
+    /// Removes all published messages of type `MSG` that have not been delivered, yet.
+    /// Messages that no subscription has received come first, followed by those still in the queue.
+    pub(crate) fn drain_topic<MSG: Any>(&self) -> Vec<MSG> {
+        let mut drained = self.undelivered.try_borrow_mut().or_report().take::<MSG>();
+        let queued = self
+            .take_deferred_where(|deferred| match deferred {
+                Deferred::Broadcast(b) => b.is_published::<MSG>(),
+                _ => false,
+            })
+            .into_iter()
+            .filter_map(|deferred| match deferred {
+                Deferred::Broadcast(b) => Some(b.into_message()),
+                _ => None,
+            });
+        drained.extend(queued);
+        drained
+    }
+    /// Queues all messages before delivering the first.
+    pub(crate) fn publish_iter<MSG: Any>(&self, msgs: impl IntoIterator<Item = MSG>) {
+        for (i, msg) in msgs.into_iter().enumerate() {
+            let broadcast = BroadcastInfo::global(msg, Topic::public_message::<MSG>());
+            self.accept(&broadcast, i > 0);
+            self.push_deferred(broadcast.into());
+        }
+        self.catch_up_deferred_to_quiescence();
+    }
+    /// Returns a proxy to the inbox for other threads, creating the inbox on first use.
+    pub(crate) fn remote_handle(&self) -> NutsProxy {
+        let inbox = self
+            .remote_inbox
+            .try_borrow_mut()
+            .or_report()
+            .get_or_insert_with(Default::default)
+            .clone();
+        NutsProxy::new(inbox)
+    }
+    /// Moves all messages sent from other threads to the deferred queue.
+    /// Returns true iff at least one message has been queued.
+    pub(crate) fn queue_remote_messages(&self) -> bool {
+        let inbox = self.remote_inbox.try_borrow().or_report();
+        let broadcasts = match inbox.as_ref() {
+            Some(inbox) => std::mem::take(&mut *inbox.lock().unwrap_or_else(|e| e.into_inner())),
+            None => return false,
+        };
+        let any = !broadcasts.is_empty();
+        for make_broadcast in broadcasts {
+            self.push_deferred(make_broadcast().into());
+        }
+        any
+    }
     pub(crate) fn publish_and_await<MSG: Any>(&self, msg: MSG) -> NutsResponse {
         let broadcast = BroadcastInfo::global(msg, Topic::public_message::<MSG>());
         let ticket = Nut::with_response_tracker_mut(|rt| rt.allocate());
         let future = NutsResponse::new(&ticket);
-        self.deferred_events
-            .push(nut::exec::Deferred::BroadcastAwaitingResponse(
-                broadcast, ticket,
-            ));
+        self.push_deferred_with_barrier(broadcast.into(), ticket);
+        self.catch_up_deferred_to_quiescence();
+        future
+    }
+    /// Like `publish_and_await` but responders can store a reply, which the future resolves to.
+    pub(crate) fn request<MSG: Any>(&self, msg: MSG) -> NutsResponse {
+        let ticket = Nut::with_response_tracker_mut(|rt| rt.allocate());
+        let broadcast =
+            BroadcastInfo::global(msg, Topic::public_message::<MSG>()).with_reply_to(ticket);
+        let future = NutsResponse::new(&ticket);
+        self.push_deferred_with_barrier(broadcast.into(), ticket);
+        self.catch_up_deferred_to_quiescence();
+        future
+    }
+    /// Like `request` but the message is sent privately to a single activity.
+    pub(crate) fn request_from<MSG: Any>(
+        &self,
+        msg: MSG,
+        id: UncheckedActivityId,
+        timeout: Option<u64>,
+    ) -> NutsResponse {
+        let deadline = timeout.map(|ticks| self.tick.get().saturating_add(ticks));
+        let ticket = Nut::with_response_tracker_mut(|rt| {
+            let ticket = rt.allocate();
+            if let Some(deadline) = deadline {
+                rt.set_deadline(ticket, deadline);
+            }
+            ticket
+        });
+        let broadcast =
+            BroadcastInfo::local(msg, id, Topic::private_message::<MSG>()).with_reply_to(ticket);
+        let future = NutsResponse::new(&ticket);
+        self.push_deferred_with_barrier(broadcast.into(), ticket);
         self.catch_up_deferred_to_quiescence();
         future
     }
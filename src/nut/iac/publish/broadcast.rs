@@ -1,13 +1,21 @@
 use crate::debug::DebugTypeName;
-use crate::nut::{iac::subscription::Subscription, Nut};
+use crate::nut::diagnostics::OrReport;
+use crate::nut::iac::publish::{MessageMeta, ResponseSlot};
+use crate::nut::iac::subscription::{Subscription, SubscriptionContainer};
+use crate::nut::{Nut, IMPOSSIBLE_ERR_MSG};
+use crate::PanicPolicy;
 use crate::*;
 use core::any::{Any, TypeId};
 use std::cell::RefMut;
+use std::collections::HashMap;
 
 pub(crate) struct BroadcastInfo {
     address: BroadcastAddress,
     msg: Box<dyn Any>,
+    meta: MessageMeta,
     topic: Topic,
+    /// Set for requests that expect a reply
+    reply_to: Option<ResponseSlot>,
     #[allow(dead_code)]
     type_name: DebugTypeName,
 }
@@ -16,14 +24,24 @@ enum BroadcastAddress {
     Local(UncheckedActivityId),
     LocalByType(TypeId),
     Global,
+    /// A single subscription, used to replay cached messages
+    Subscription(UncheckedActivityId, usize),
+    /// All activities of a domain
+    Domain(DomainId),
 }
 
 impl BroadcastInfo {
     pub(crate) fn global<MSG: Any>(msg: MSG, topic: Topic) -> Self {
+        Self::global_boxed::<MSG>(Box::new(msg), topic)
+    }
+    /// `msg` must hold a `MSG`
+    pub(crate) fn global_boxed<MSG: Any>(msg: Box<dyn Any>, topic: Topic) -> Self {
         BroadcastInfo {
             address: BroadcastAddress::Global,
-            msg: Box::new(msg),
+            msg,
+            meta: MessageMeta::new::<MSG>(&topic),
             topic,
+            reply_to: None,
             type_name: DebugTypeName::new::<MSG>(),
         }
     }
@@ -31,7 +49,30 @@ impl BroadcastInfo {
         BroadcastInfo {
             address: BroadcastAddress::Local(id),
             msg: Box::new(msg),
+            meta: MessageMeta::new::<MSG>(&topic),
+            topic,
+            reply_to: None,
+            type_name: DebugTypeName::new::<MSG>(),
+        }
+    }
+    /// Only the given subscription receives the message.
+    pub(crate) fn subscription<MSG: Any>(msg: MSG, id: &SubscriptionId) -> Self {
+        BroadcastInfo {
+            address: BroadcastAddress::Subscription(id.activity, id.index),
+            msg: Box::new(msg),
+            meta: MessageMeta::new::<MSG>(&id.topic),
+            topic: id.topic.clone(),
+            reply_to: None,
+            type_name: DebugTypeName::new::<MSG>(),
+        }
+    }
+    pub(crate) fn domain<MSG: Any>(msg: MSG, domain: DomainId, topic: Topic) -> Self {
+        BroadcastInfo {
+            address: BroadcastAddress::Domain(domain),
+            msg: Box::new(msg),
+            meta: MessageMeta::new::<MSG>(&topic),
             topic,
+            reply_to: None,
             type_name: DebugTypeName::new::<MSG>(),
         }
     }
@@ -39,32 +80,164 @@ impl BroadcastInfo {
         BroadcastInfo {
             address: BroadcastAddress::LocalByType(TypeId::of::<RECV>()),
             msg: Box::new(msg),
+            meta: MessageMeta::new::<MSG>(&topic),
             topic,
+            reply_to: None,
             type_name: DebugTypeName::new::<MSG>(),
         }
     }
 }
 
+impl BroadcastInfo {
+    pub(crate) fn in_phase(mut self, phase: Phase) -> Self {
+        self.meta.set_phase(phase);
+        self
+    }
+    pub(crate) fn parallel_ready(mut self) -> Self {
+        self.meta.set_parallel_ready();
+        self
+    }
+    pub(crate) fn with_reply_to(mut self, slot: ResponseSlot) -> Self {
+        self.reply_to = Some(slot);
+        self
+    }
+    /// For interceptors
+    pub(crate) fn parts_mut(&mut self) -> (&MessageMeta, &mut dyn Any) {
+        (&self.meta, self.msg.as_mut())
+    }
+    /// Delivers the message to a single activity instead of its original receivers.
+    pub(crate) fn reroute(&mut self, id: UncheckedActivityId) {
+        self.address = BroadcastAddress::Local(id);
+    }
+    /// True iff this is a private message that is addressed to the activity.
+    pub(crate) fn is_private_for<A: Any>(&self, id: UncheckedActivityId) -> bool {
+        self.topic.unqiue_per_activity()
+            && match self.address {
+                BroadcastAddress::Local(receiver) => receiver == id,
+                BroadcastAddress::LocalByType(t) => t == TypeId::of::<A>(),
+                BroadcastAddress::Global
+                | BroadcastAddress::Subscription(..)
+                | BroadcastAddress::Domain(_) => false,
+            }
+    }
+    /// The message, if it is addressed to all subscribers of its topic
+    pub(crate) fn published_message(&self) -> Option<&dyn Any> {
+        match self.address {
+            BroadcastAddress::Global => Some(self.msg.as_ref()),
+            _ => None,
+        }
+    }
+    pub(crate) fn phase(&self) -> Option<Phase> {
+        self.meta.phase()
+    }
+    pub(crate) fn topic(&self) -> &Topic {
+        &self.topic
+    }
+    pub(crate) fn message_type_id(&self) -> TypeId {
+        self.meta.type_id()
+    }
+    /// True iff this is a published (not private) message of type `MSG`.
+    pub(crate) fn is_published<MSG: Any>(&self) -> bool {
+        matches!(self.address, BroadcastAddress::Global)
+            && self.topic == Topic::public_message::<MSG>()
+    }
+    /// panics if the message is not of type `MSG`
+    pub(crate) fn into_message<MSG: Any>(self) -> MSG {
+        *self.msg.downcast().expect(IMPOSSIBLE_ERR_MSG)
+    }
+}
+
 impl Nut {
     /// only access after locking with executing flag
-    pub(crate) fn unchecked_broadcast(&self, broadcast: BroadcastInfo) {
+    pub(crate) fn unchecked_broadcast(&self, mut broadcast: BroadcastInfo) {
+        if !self.intercept(&mut broadcast) {
+            return;
+        }
+        #[cfg(debug_assertions)]
+        self.record_dispatch(broadcast.topic.name());
         let mut managed_state = self.managed_state.borrow_mut();
-        managed_state.set_broadcast(broadcast.msg);
-        if let Some(handlers) = self.subscriptions.get().get(&broadcast.topic) {
-            match self.receiver_id(&broadcast.address) {
+        if let Some(heartbeat) = self.heartbeat.try_borrow_mut().or_report().as_mut() {
+            heartbeat.count_message(&broadcast.topic);
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .try_borrow_mut()
+            .or_report()
+            .count_message(broadcast.message_type_id(), broadcast.topic.name());
+        if let BroadcastAddress::Global = broadcast.address {
+            self.replay_buffers
+                .try_borrow_mut()
+                .or_report()
+                .record(&broadcast.topic, broadcast.msg.as_ref());
+        }
+        let replayed_to = match broadcast.address {
+            BroadcastAddress::Subscription(_, index) => Some(index),
+            _ => None,
+        };
+        let domain = match broadcast.address {
+            BroadcastAddress::Domain(domain) => Some(domain),
+            _ => None,
+        };
+        let type_id = broadcast.meta.type_id();
+        let parallel_ready = broadcast.meta.is_parallel_ready();
+        let bufferable = self
+            .activity_buffers
+            .try_borrow()
+            .or_report()
+            .is_bufferable(type_id);
+        managed_state.set_broadcast(broadcast.msg, broadcast.meta, broadcast.reply_to);
+        let subscriptions = self.subscriptions.get();
+        let unheard = matches!(broadcast.address, BroadcastAddress::Global)
+            && self.undelivered.try_borrow().or_report().is_kept(type_id)
+            && subscriptions
+                .get(&broadcast.topic)
+                .map_or(true, SubscriptionContainer::is_empty);
+        if let (Some(taps), None) = (subscriptions.get(&Topic::Wildcard), replayed_to) {
+            for sub in taps.shared_subscriptions() {
+                self.call_subscriber(sub, &broadcast.topic, &mut managed_state);
+            }
+        }
+        let receiver = self.receiver_id(&broadcast.address);
+        if let Some(handlers) = subscriptions.get(&broadcast.topic) {
+            match receiver {
+                None if parallel_ready => {
+                    self.dispatch_grouped_by_domain(
+                        handlers.shared_subscriptions(),
+                        &broadcast.topic,
+                        bufferable.then_some(type_id),
+                        &mut managed_state,
+                    );
+                }
                 None => {
                     for sub in handlers.shared_subscriptions() {
-                        self.call_subscriber(sub, &mut managed_state);
+                        if let Some(domain) = domain {
+                            if self.activities.borrow().domain(sub.activity) != domain {
+                                continue;
+                            }
+                        }
+                        if bufferable {
+                            self.hold_if_buffering(sub, &broadcast.topic, type_id, &managed_state);
+                        }
+                        self.call_subscriber(sub, &broadcast.topic, &mut managed_state);
+                        if managed_state.propagation_stopped() {
+                            break;
+                        }
                     }
                 }
                 Some(id) => {
                     if broadcast.topic.unqiue_per_activity() {
                         if let Some(sub) = handlers.private_subscription(id) {
-                            self.call_subscriber(sub, &mut managed_state);
+                            self.call_subscriber(sub, &broadcast.topic, &mut managed_state);
                         }
                     } else {
-                        for sub in handlers.shared_subscriptions_of_single_activity(id) {
-                            self.call_subscriber(sub, &mut managed_state);
+                        for sub in handlers
+                            .shared_subscriptions_of_single_activity(id)
+                            .filter(|sub| replayed_to.is_none() || replayed_to == Some(sub.index))
+                        {
+                            self.call_subscriber(sub, &broadcast.topic, &mut managed_state);
+                            if managed_state.propagation_stopped() {
+                                break;
+                            }
                         }
                     }
                 }
@@ -72,18 +245,146 @@ impl Nut {
             #[cfg(debug_assertions)]
             self.active_activity_name.set(None);
         }
-        managed_state.clear_broadcast();
+        if receiver.is_none() && matches!(broadcast.topic, Topic::PublicMessage(_)) {
+            self.deliver_to_traits(type_id, domain, &subscriptions, &mut managed_state);
+        }
+        if broadcast.topic.unqiue_per_activity() {
+            if let Some(id) = receiver {
+                // Private channels take ownership of the message, unless filtered
+                let consumed = !managed_state.has_broadcast();
+                self.mailboxes
+                    .try_borrow_mut()
+                    .or_report()
+                    .count_delivery(id, consumed);
+            }
+        }
+        let unused = match managed_state.clear_broadcast() {
+            Some(msg) if unheard => self.undelivered.try_borrow_mut().or_report().hold(msg),
+            msg => msg,
+        };
+        if let Some(msg) = unused {
+            self.message_pool.try_borrow_mut().or_report().recycle(msg);
+        }
+        #[cfg(debug_assertions)]
+        self.finish_dispatch();
     }
-    fn call_subscriber(&self, sub: &Subscription, managed_state: &mut RefMut<ManagedState>) {
+    fn call_subscriber(
+        &self,
+        sub: &Subscription,
+        topic: &Topic,
+        managed_state: &mut RefMut<ManagedState>,
+    ) {
+        if let Some(phase) = sub.options.phase {
+            if managed_state.broadcast_phase() != Some(phase) {
+                return;
+            }
+        }
+        if let Some(group) = &sub.options.group {
+            if self.disabled_tags.try_borrow().or_report().contains(group) {
+                return;
+            }
+        }
         #[cfg(debug_assertions)]
         self.active_activity_name.set(Some(sub.type_name));
+        if let Some(heartbeat) = self.heartbeat.try_borrow_mut().or_report().as_mut() {
+            heartbeat.count_handler(sub.activity);
+        }
+        if let Some(tracer) = self.tracer.try_borrow_mut().or_report().as_mut() {
+            tracer.deliver(sub.activity);
+        }
         let f = &sub.handler;
-        f(&mut self.activities.borrow_mut(), managed_state);
+        #[cfg(feature = "metrics")]
+        let stopwatch = crate::nut::exec::metrics::Stopwatch::start();
+        match self.panic_policy.get() {
+            PanicPolicy::Propagate => f(&mut self.activities.borrow_mut(), managed_state),
+            PanicPolicy::RecoverAndReport => {
+                let call = std::panic::AssertUnwindSafe(|| {
+                    f(&mut self.activities.borrow_mut(), managed_state)
+                });
+                if let Err(payload) = std::panic::catch_unwind(call) {
+                    self.recover_from_panic(sub.activity, topic, payload.as_ref());
+                }
+            }
+        }
+        #[cfg(feature = "metrics")]
+        {
+            let type_name = self.activities.borrow().type_name(sub.activity);
+            self.metrics.try_borrow_mut().or_report().count_handler(
+                sub.activity,
+                type_name,
+                stopwatch.elapsed(),
+            );
+        }
+    }
+    /// Delivers a published message to subscriptions of the traits its type has been registered for, after all others.
+    fn deliver_to_traits(
+        &self,
+        type_id: TypeId,
+        domain: Option<DomainId>,
+        subscriptions: &HashMap<Topic, SubscriptionContainer>,
+        managed_state: &mut RefMut<ManagedState>,
+    ) {
+        let traits = self
+            .trait_registry
+            .try_borrow()
+            .or_report()
+            .traits_of(type_id);
+        for trait_id in traits {
+            let topic = Topic::public_message_dyn(trait_id);
+            for sub in subscriptions
+                .get(&topic)
+                .into_iter()
+                .flat_map(SubscriptionContainer::shared_subscriptions)
+            {
+                if managed_state.propagation_stopped() {
+                    return;
+                }
+                if let Some(domain) = domain {
+                    if self.activities.borrow().domain(sub.activity) != domain {
+                        continue;
+                    }
+                }
+                self.call_subscriber(sub, &topic, managed_state);
+            }
+        }
+        #[cfg(debug_assertions)]
+        self.active_activity_name.set(None);
+    }
+    /// Calls all handlers of one domain before those of the next domain, activities without a domain last.
+    ///
+    /// While the handlers of a domain are running, accessing any other domain panics.
+    /// Messages of a `bufferable` type are held for buffering activities, as in a normal broadcast.
+    fn dispatch_grouped_by_domain<'a>(
+        &self,
+        subscriptions: impl Iterator<Item = &'a Subscription>,
+        topic: &Topic,
+        bufferable: Option<TypeId>,
+        managed_state: &mut RefMut<ManagedState>,
+    ) {
+        let mut grouped: Vec<(DomainId, &Subscription)> = {
+            let activities = self.activities.try_borrow().or_report();
+            subscriptions
+                .map(|sub| (activities.domain(sub.activity), sub))
+                .collect()
+        };
+        // Stable, so the priority order within each domain is kept
+        grouped.sort_by_key(|(domain, _)| domain.index().unwrap_or(usize::MAX));
+        for (domain, sub) in grouped {
+            if let Some(type_id) = bufferable {
+                self.hold_if_buffering(sub, topic, type_id, managed_state);
+            }
+            managed_state.set_domain_group(Some(domain));
+            self.call_subscriber(sub, topic, managed_state);
+            managed_state.set_domain_group(None);
+            if managed_state.propagation_stopped() {
+                break;
+            }
+        }
     }
     fn receiver_id(&self, address: &BroadcastAddress) -> Option<UncheckedActivityId> {
         match address {
-            BroadcastAddress::Global => None,
-            BroadcastAddress::Local(id) => Some(*id),
+            BroadcastAddress::Global | BroadcastAddress::Domain(_) => None,
+            BroadcastAddress::Local(id) | BroadcastAddress::Subscription(id, _) => Some(*id),
             BroadcastAddress::LocalByType(t) => self.activities.borrow().id_lookup(*t),
         }
     }
@@ -95,6 +396,16 @@ impl std::fmt::Debug for BroadcastInfo {
         match self.address {
             BroadcastAddress::Global => write!(f, "published message of type {:?}", self.type_name),
             BroadcastAddress::Local(_) => write!(f, "{:?} event", self.topic),
+            BroadcastAddress::Domain(_) => {
+                write!(
+                    f,
+                    "message of type {:?} (published to a domain)",
+                    self.type_name
+                )
+            }
+            BroadcastAddress::Subscription(..) => {
+                write!(f, "replayed message of type {:?}", self.type_name)
+            }
             BroadcastAddress::LocalByType(_) => {
                 write!(f, "message of type {:?} (sent privately)", self.type_name)
             }
@@ -0,0 +1,129 @@
+//! Messages held back for activities with status `LifecycleStatus::Buffering`, delivered once they are active again.
+
+use super::BroadcastInfo;
+use crate::nut::exec::fifo::Priority;
+use crate::nut::iac::managed_state::ManagedState;
+use crate::nut::iac::subscription::{Subscription, SubscriptionId};
+use crate::nut::iac::topic::Topic;
+use crate::nut::{diagnostics::OrReport, Nut, IMPOSSIBLE_ERR_MSG};
+use crate::{LifecycleStatus, Phase, UncheckedActivityId};
+use core::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+
+/// Number of messages held per activity, unless changed with `ActivityId::set_buffer_capacity`
+const DEFAULT_CAPACITY: usize = 64;
+
+type CopyFn = fn(&dyn Any, &SubscriptionId) -> BroadcastInfo;
+
+fn copy<MSG: Any + Clone>(msg: &dyn Any, id: &SubscriptionId) -> BroadcastInfo {
+    let msg = msg.downcast_ref::<MSG>().expect(IMPOSSIBLE_ERR_MSG);
+    BroadcastInfo::subscription(msg.clone(), id)
+}
+
+/// Message types registered with `nuts::make_bufferable` and the messages held per activity
+#[derive(Default)]
+pub(crate) struct ActivityBuffers {
+    types: HashMap<TypeId, CopyFn>,
+    capacities: HashMap<UncheckedActivityId, usize>,
+    held: HashMap<UncheckedActivityId, VecDeque<BroadcastInfo>>,
+}
+
+impl ActivityBuffers {
+    pub(crate) fn make_bufferable<MSG: Any + Clone>(&mut self) {
+        self.types.insert(TypeId::of::<MSG>(), copy::<MSG>);
+    }
+    pub(crate) fn is_bufferable(&self, type_id: TypeId) -> bool {
+        self.types.contains_key(&type_id)
+    }
+    /// Drops the oldest held messages that no longer fit.
+    pub(crate) fn set_capacity(&mut self, id: UncheckedActivityId, capacity: usize) {
+        self.capacities.insert(id, capacity);
+        if let Some(queue) = self.held.get_mut(&id) {
+            while queue.len() > capacity {
+                queue.pop_front();
+            }
+        }
+    }
+    fn hold(
+        &mut self,
+        type_id: TypeId,
+        msg: &dyn Any,
+        id: &SubscriptionId,
+        phase: Option<Phase>,
+    ) -> Option<()> {
+        let copy = self.types.get(&type_id)?;
+        let capacity = self
+            .capacities
+            .get(&id.activity)
+            .copied()
+            .unwrap_or(DEFAULT_CAPACITY);
+        if capacity == 0 {
+            return None;
+        }
+        let queue = self.held.entry(id.activity).or_default();
+        if queue.len() == capacity {
+            queue.pop_front();
+        }
+        let broadcast = copy(msg, id);
+        queue.push_back(match phase {
+            Some(phase) => broadcast.in_phase(phase),
+            None => broadcast,
+        });
+        Some(())
+    }
+    fn take(&mut self, id: UncheckedActivityId) -> VecDeque<BroadcastInfo> {
+        self.held.remove(&id).unwrap_or_default()
+    }
+    pub(crate) fn forget_held(&mut self, id: UncheckedActivityId) {
+        self.held.remove(&id);
+    }
+    pub(crate) fn forget(&mut self, id: UncheckedActivityId) {
+        self.held.remove(&id);
+        self.capacities.remove(&id);
+    }
+}
+
+impl Nut {
+    /// Keeps a copy of the message that is being delivered, if the subscription would miss it only because its activity is buffering.
+    pub(crate) fn hold_if_buffering(
+        &self,
+        sub: &Subscription,
+        topic: &Topic,
+        type_id: TypeId,
+        managed_state: &ManagedState,
+    ) {
+        if !sub.options.active_only
+            || self
+                .activities
+                .try_borrow()
+                .or_report()
+                .status(sub.activity)
+                != LifecycleStatus::Buffering
+        {
+            return;
+        }
+        if sub.options.phase.is_some() && sub.options.phase != managed_state.broadcast_phase() {
+            return;
+        }
+        if let Some(msg) = managed_state.broadcast_ref() {
+            let id = SubscriptionId {
+                topic: topic.clone(),
+                activity: sub.activity,
+                index: sub.index,
+            };
+            self.activity_buffers.try_borrow_mut().or_report().hold(
+                type_id,
+                msg,
+                &id,
+                sub.options.phase,
+            );
+        }
+    }
+    /// Queues the messages held for the activity ahead of all other messages, oldest first.
+    pub(crate) fn release_held(&self, id: UncheckedActivityId) {
+        let held = self.activity_buffers.try_borrow_mut().or_report().take(id);
+        for broadcast in held {
+            self.push_deferred_with_priority(broadcast.into(), Priority::High);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Checks for the fallible variants of publishing, `nuts::try_publish` and `nuts::try_send_to`.
+
+use crate::nut::exec::Deferred;
+use crate::nut::iac::topic::Topic;
+use crate::nut::{diagnostics::OrReport, Nut};
+use crate::{SubscriptionId, UncheckedActivityId};
+use core::any::{Any, TypeId};
+
+/// Reason why [`nuts::try_publish`](fn.try_publish.html) or [`nuts::try_send_to`](fn.try_send_to.html) did not send a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PublishError {
+    /// No activity would receive the message.
+    NoReceiver,
+    /// The receiving activity exists but has no private channel for the message type.
+    NoPrivateChannel,
+    /// The queue is at the maximum length of the [`QueuePolicy`](struct.QueuePolicy.html).
+    QueueFull,
+}
+
+impl std::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoReceiver => write!(f, "no activity receives the message"),
+            Self::NoPrivateChannel => {
+                write!(f, "the receiver has no private channel for the message")
+            }
+            Self::QueueFull => write!(f, "the message queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for PublishError {}
+
+impl Nut {
+    /// Ok iff a published message of type `MSG` would be queued and received by at least one subscription.
+    ///
+    /// Subscriptions that are still queued count as well, they are added before the message is delivered.
+    pub(crate) fn check_publish<MSG: Any>(&self) -> Result<(), PublishError> {
+        let mut topics = vec![Topic::public_message::<MSG>(), Topic::Wildcard];
+        let traits = self
+            .trait_registry
+            .try_borrow()
+            .or_report()
+            .traits_of(TypeId::of::<MSG>());
+        topics.extend(traits.into_iter().map(Topic::public_message_dyn));
+        let subscribed = {
+            let subscriptions = self.subscriptions.get();
+            topics.iter().any(|topic| {
+                matches!(subscriptions.get(topic), Some(s) if s.shared_subscriptions().next().is_some())
+            })
+        };
+        if !subscribed && !self.queued_subscription(|id| topics.contains(&id.topic)) {
+            return Err(PublishError::NoReceiver);
+        }
+        self.check_queue_space()
+    }
+    /// Ok iff a message of type `MSG` sent to `RECV` would be queued and has a private channel to go to.
+    ///
+    /// Inside subscription handlers, the receiver cannot be looked up.
+    /// It is only checked that some activity has a private channel for `MSG`, the message is dropped on delivery if that is not `RECV`.
+    pub(crate) fn check_send<RECV: Any, MSG: Any>(&self) -> Result<(), PublishError> {
+        let topic = Topic::private_message::<MSG>();
+        let receiver = match self.activities.try_borrow() {
+            Ok(activities) => Some(
+                activities
+                    .id_lookup(TypeId::of::<RECV>())
+                    .ok_or(PublishError::NoReceiver)?,
+            ),
+            Err(_) => None,
+        };
+        let has_channel = |id: UncheckedActivityId| {
+            matches!(self.subscriptions.get().get(&topic), Some(s) if s.private_subscription(id).is_some())
+                || self.queued_subscription(|sub| sub.topic == topic && sub.activity == id)
+        };
+        let any_channel = || {
+            matches!(self.subscriptions.get().get(&topic), Some(s) if s.private_subscriptions().next().is_some())
+                || self.queued_subscription(|sub| sub.topic == topic)
+        };
+        let ok = match receiver {
+            Some(id) => has_channel(id),
+            None => any_channel(),
+        };
+        if !ok {
+            return Err(PublishError::NoPrivateChannel);
+        }
+        self.check_queue_space()
+    }
+    fn queued_subscription(&self, pred: impl Fn(&SubscriptionId) -> bool) -> bool {
+        self.any_deferred(|event| matches!(event, Deferred::Subscription(sub) if pred(sub.id())))
+    }
+    fn check_queue_space(&self) -> Result<(), PublishError> {
+        match self.queue_policy.get() {
+            Some(policy) if self.deferred_events.len() >= policy.max_len => {
+                Err(PublishError::QueueFull)
+            }
+            _ => Ok(()),
+        }
+    }
+}
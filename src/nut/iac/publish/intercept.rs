@@ -0,0 +1,46 @@
+//! Interceptors registered with `nuts::add_interceptor`, which see every message before it is delivered.
+
+use super::{BroadcastInfo, MessageMeta};
+use crate::nut::{diagnostics::OrReport, Nut};
+use crate::UncheckedActivityId;
+use core::any::Any;
+use std::rc::Rc;
+
+/// Returned by interceptors registered with [`nuts::add_interceptor`](fn.add_interceptor.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum InterceptDecision {
+    /// Continue with the next interceptor and then deliver the message, including all changes made to it.
+    Deliver,
+    /// Discard the message. Later interceptors and all subscribers do not see it.
+    Drop,
+    /// Deliver the message only to the subscriptions of the given activity, instead of all subscribers.
+    RerouteTo(UncheckedActivityId),
+}
+
+pub(crate) type Interceptor = Rc<dyn Fn(&MessageMeta, &mut dyn Any) -> InterceptDecision>;
+
+impl Nut {
+    /// Runs all interceptors on the broadcast, in registration order.
+    /// Returns false if the broadcast has been dropped.
+    ///
+    /// only access after locking with executing flag
+    pub(crate) fn intercept(&self, broadcast: &mut BroadcastInfo) -> bool {
+        let interceptors = {
+            let interceptors = self.interceptors.try_borrow().or_report();
+            if interceptors.is_empty() {
+                return true;
+            }
+            interceptors.clone()
+        };
+        for interceptor in interceptors {
+            let (meta, msg) = broadcast.parts_mut();
+            match interceptor(meta, msg) {
+                InterceptDecision::Deliver => {}
+                InterceptDecision::Drop => return false,
+                InterceptDecision::RerouteTo(id) => broadcast.reroute(id),
+            }
+        }
+        true
+    }
+}
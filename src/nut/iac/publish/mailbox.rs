@@ -0,0 +1,68 @@
+use crate::nut::exec::{Deferred, QueuedEvent};
+use crate::nut::Nut;
+use crate::UncheckedActivityId;
+use core::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Statistics about the private messages of an activity.
+///
+/// Private messages (sent with `send_to` or `private_message`) are queued like all other messages.
+/// Producers can use these numbers to react to the load of the receiver, for example by skipping a frame.
+/// Obtained through [`ActivityId::mailbox_stats`](struct.ActivityId.html#method.mailbox_stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MailboxStats {
+    /// Private messages addressed to the activity that wait in the queue right now.
+    pub queued: usize,
+    /// Private messages that have been handled by the activity so far.
+    pub processed: usize,
+    /// Private messages addressed to the activity that have not been handled, because no private channel was registered for them or because the activity was inactive.
+    pub dropped: usize,
+}
+
+/// Delivery counters for private messages, by activity
+#[derive(Default)]
+pub(crate) struct Mailboxes {
+    counters: HashMap<UncheckedActivityId, MailboxStats>,
+}
+
+impl Mailboxes {
+    /// Drops the counters of activities for which `dead` returns true.
+    pub(crate) fn compact(&mut self, dead: impl Fn(UncheckedActivityId) -> bool) {
+        self.counters.retain(|id, _| !dead(*id));
+        self.counters.shrink_to_fit();
+    }
+    pub(crate) fn count_delivery(&mut self, id: UncheckedActivityId, consumed: bool) {
+        let stats = self.counters.entry(id).or_default();
+        if consumed {
+            stats.processed += 1;
+        } else {
+            stats.dropped += 1;
+        }
+    }
+}
+
+impl Nut {
+    pub(crate) fn mailbox_stats<A: Any>(&self, id: UncheckedActivityId) -> MailboxStats {
+        let mut stats = self
+            .mailboxes
+            .borrow()
+            .counters
+            .get(&id)
+            .copied()
+            .unwrap_or_default();
+        stats.queued = self.count_queued_private::<A>(id, None);
+        stats
+    }
+    pub(crate) fn mailbox_len<A: Any, MSG: Any>(&self, id: UncheckedActivityId) -> usize {
+        self.count_queued_private::<A>(id, Some(TypeId::of::<MSG>()))
+    }
+    fn count_queued_private<A: Any>(&self, id: UncheckedActivityId, msg: Option<TypeId>) -> usize {
+        self.deferred_events
+            .count(|queued: &QueuedEvent| match queued.event() {
+                Deferred::Broadcast(b) => {
+                    b.is_private_for::<A>(id) && msg.map_or(true, |t| b.message_type_id() == t)
+                }
+                _ => false,
+            })
+    }
+}
@@ -0,0 +1,62 @@
+use crate::nut::iac::topic::Topic;
+use crate::Phase;
+use core::any::{Any, TypeId};
+
+/// Information about the message that is currently being delivered.
+///
+/// Available to subscription handlers through [`HandlerCtx::meta`](struct.HandlerCtx.html#method.meta).
+#[derive(Clone, Debug)]
+pub struct MessageMeta {
+    type_name: &'static str,
+    type_id: TypeId,
+    private: bool,
+    tick: u64,
+    phase: Option<Phase>,
+    parallel_ready: bool,
+}
+
+impl MessageMeta {
+    pub(crate) fn new<MSG: Any>(topic: &Topic) -> Self {
+        Self {
+            type_name: std::any::type_name::<MSG>(),
+            type_id: TypeId::of::<MSG>(),
+            private: topic.unqiue_per_activity(),
+            tick: crate::nut::current_tick(),
+            phase: None,
+            parallel_ready: false,
+        }
+    }
+    pub(crate) fn set_phase(&mut self, phase: Phase) {
+        self.phase = Some(phase);
+    }
+    pub(crate) fn set_parallel_ready(&mut self) {
+        self.parallel_ready = true;
+    }
+    /// The name of the message type, as returned by `std::any::type_name`.
+    ///
+    /// Only use this for debugging purposes, the exact value is not guaranteed to be stable.
+    /// The same applies to all type names that nuts reports, for example in traces, flow graphs and panic reports.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+    /// The type identifier of the message.
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+    /// True iff the message has been sent to a private channel.
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+    /// The value of the frame counter when the message has been published, see [`nuts::tick`](fn.tick.html).
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+    /// The phase the message has been published for with [`nuts::publish_phased`](fn.publish_phased.html), if any.
+    pub fn phase(&self) -> Option<Phase> {
+        self.phase
+    }
+    /// True iff the message has been published with [`nuts::publish_parallel_ready`](fn.publish_parallel_ready.html).
+    pub fn is_parallel_ready(&self) -> bool {
+        self.parallel_ready
+    }
+}
@@ -0,0 +1,40 @@
+//! Recycled message boxes for `nuts::publish_copy`, so that steady streams of small messages do not allocate.
+
+use crate::nut::IMPOSSIBLE_ERR_MSG;
+use core::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Maximum number of free boxes kept per message type
+const MAX_POOLED: usize = 32;
+
+/// Free boxes per message type, only for types that have been published with `nuts::publish_copy`
+#[derive(Default)]
+pub(crate) struct MessagePool {
+    free: HashMap<TypeId, Vec<Box<dyn Any>>>,
+}
+
+impl MessagePool {
+    /// Puts the message in a recycled box, if one is available.
+    pub(crate) fn boxed<MSG: Any + Copy>(&mut self, msg: MSG) -> Box<dyn Any> {
+        match self.free.entry(TypeId::of::<MSG>()).or_default().pop() {
+            Some(mut boxed) => {
+                *boxed.downcast_mut::<MSG>().expect(IMPOSSIBLE_ERR_MSG) = msg;
+                boxed
+            }
+            None => Box::new(msg),
+        }
+    }
+    /// Number of free boxes currently kept for the message type
+    #[cfg(test)]
+    pub(crate) fn free_boxes<MSG: Any>(&self) -> usize {
+        self.free.get(&TypeId::of::<MSG>()).map_or(0, Vec::len)
+    }
+    /// Keeps the box of a delivered message for reuse, if its type is pooled.
+    pub(crate) fn recycle(&mut self, msg: Box<dyn Any>) {
+        if let Some(free) = self.free.get_mut(&msg.as_ref().type_id()) {
+            if free.len() < MAX_POOLED {
+                free.push(msg);
+            }
+        }
+    }
+}
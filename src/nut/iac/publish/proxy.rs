@@ -0,0 +1,50 @@
+use super::BroadcastInfo;
+use crate::nut::iac::topic::Topic;
+use core::any::Any;
+use std::sync::{Arc, Mutex};
+
+/// Creates the broadcast on the thread that owns the nut.
+pub(crate) type RemoteBroadcast = Box<dyn FnOnce() -> BroadcastInfo + Send>;
+
+/// Messages sent through a proxy, waiting to be picked up by the thread that owns the nut.
+pub(crate) type RemoteInbox = Arc<Mutex<Vec<RemoteBroadcast>>>;
+
+/// A handle to publish messages from other threads.
+///
+/// Create it with [`nuts::remote_handle`](fn.remote_handle.html) on the thread that owns the activities.
+/// Messages published through the proxy are queued and delivered on the owning thread,
+/// the next time nuts executes there or when [`nuts::drain_remote`](fn.drain_remote.html) is called.
+#[derive(Clone)]
+pub struct NutsProxy {
+    inbox: RemoteInbox,
+}
+
+impl NutsProxy {
+    pub(crate) fn new(inbox: RemoteInbox) -> Self {
+        Self { inbox }
+    }
+    /// Queues a message to be published on the thread that owns the nut.
+    pub fn publish<MSG: Any + Send>(&self, msg: MSG) {
+        self.push(Box::new(move || {
+            BroadcastInfo::global(msg, Topic::public_message::<MSG>())
+        }));
+    }
+    /// Queues a message to be sent to the private channel of an activity on the thread that owns the nut.
+    /// Equivalent to `nuts::send_to::<RECEIVER, MSG>(msg)` on that thread.
+    pub fn send_to<RECEIVER: Any, MSG: Any + Send>(&self, msg: MSG) {
+        self.push(Box::new(move || {
+            BroadcastInfo::local_by_type::<RECEIVER, MSG>(msg, Topic::private_message::<MSG>())
+        }));
+    }
+    fn push(&self, broadcast: RemoteBroadcast) {
+        // A poisoned lock only means another sender panicked, the queue itself is still valid
+        let mut inbox = self.inbox.lock().unwrap_or_else(|e| e.into_inner());
+        inbox.push(broadcast);
+    }
+}
+
+impl std::fmt::Debug for NutsProxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NutsProxy")
+    }
+}
@@ -0,0 +1,135 @@
+//! Recordings of published messages, see `nuts::start_recording`.
+
+use super::BroadcastInfo;
+use crate::nut::iac::topic::Topic;
+use crate::nut::{diagnostics::OrReport, Nut};
+use crate::Phase;
+use core::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// A message captured by `nuts::start_recording`
+struct RecordedMessage {
+    msg: Box<dyn Any>,
+    type_name: &'static str,
+    copy: CopyFn,
+    /// Published with `nuts::publish_phased`
+    phase: Option<Phase>,
+    /// Queued together with the previous message, by `nuts::publish_iter`
+    batched: bool,
+}
+
+/// Messages published while recording, in publish order.
+///
+/// Created with [`nuts::stop_recording`](fn.stop_recording.html) and played back with [`nuts::replay`](fn.replay.html).
+#[derive(Default)]
+pub struct Recording {
+    messages: Vec<RecordedMessage>,
+}
+
+impl Recording {
+    /// Number of recorded messages
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+    /// True iff no message has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+    /// Names of the types of the recorded messages, in publish order, as returned by `std::any::type_name`, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+    pub fn type_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.messages.iter().map(|recorded| recorded.type_name)
+    }
+}
+
+impl std::fmt::Debug for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.type_names()).finish()
+    }
+}
+
+/// Clones a message of a recordable type
+type CloneFn = fn(&dyn Any) -> Box<dyn Any>;
+/// Creates a broadcast of a copy of a recorded message
+type CopyFn = fn(&dyn Any) -> BroadcastInfo;
+
+/// Message types registered with `nuts::record_type` and the ongoing recording, if any
+#[derive(Default)]
+pub(crate) struct Recorder {
+    types: HashMap<TypeId, (CloneFn, CopyFn)>,
+    recording: Option<Recording>,
+}
+
+#[allow(clippy::unwrap_used)]
+fn clone_message<MSG: Any + Clone>(msg: &dyn Any) -> Box<dyn Any> {
+    Box::new(msg.downcast_ref::<MSG>().unwrap().clone())
+}
+
+#[allow(clippy::unwrap_used)]
+fn copy_broadcast<MSG: Any + Clone>(msg: &dyn Any) -> BroadcastInfo {
+    let msg = msg.downcast_ref::<MSG>().unwrap().clone();
+    BroadcastInfo::global(msg, Topic::public_message::<MSG>())
+}
+
+impl Recorder {
+    pub(crate) fn register<MSG: Any + Clone>(&mut self) {
+        self.types.insert(
+            TypeId::of::<MSG>(),
+            (clone_message::<MSG>, copy_broadcast::<MSG>),
+        );
+    }
+    pub(crate) fn start(&mut self) {
+        self.recording = Some(Recording::default());
+    }
+    pub(crate) fn stop(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+    fn record(&mut self, broadcast: &BroadcastInfo, batched: bool) {
+        if let (Some(recording), Some(msg)) = (&mut self.recording, broadcast.published_message()) {
+            if let Some((clone, copy)) = self.types.get(&broadcast.message_type_id()) {
+                recording.messages.push(RecordedMessage {
+                    msg: clone(msg),
+                    type_name: broadcast.topic().name(),
+                    copy: *copy,
+                    phase: broadcast.phase(),
+                    batched,
+                });
+            }
+        }
+    }
+}
+
+impl Nut {
+    /// Adds the message to the ongoing recording, if it has been published outside of handlers and its type is recordable.
+    ///
+    /// Messages published inside handlers are not recorded, they are published again by the handlers when the recording is replayed.
+    /// Set `batched` for messages that are queued together with the previous one.
+    pub(crate) fn record(&self, broadcast: &BroadcastInfo, batched: bool) {
+        if self.quiescent() {
+            self.recorder
+                .try_borrow_mut()
+                .or_report()
+                .record(broadcast, batched);
+        }
+    }
+    /// Publishes a copy of every recorded message, in the original order and with the original batches.
+    ///
+    /// Phased messages are held for their phase again, conditions of `nuts::publish_when` are not recorded.
+    pub(crate) fn replay(&self, recording: &Recording) {
+        for (i, recorded) in recording.messages.iter().enumerate() {
+            let broadcast = (recorded.copy)(recorded.msg.as_ref());
+            match recorded.phase {
+                Some(phase) => self
+                    .phases
+                    .try_borrow_mut()
+                    .or_report()
+                    .hold(phase, broadcast.in_phase(phase)),
+                None => self.push_deferred(broadcast.into()),
+            }
+            let batch_continues =
+                matches!(recording.messages.get(i + 1), Some(next) if next.batched);
+            if !batch_continues {
+                self.catch_up_deferred_to_quiescence();
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Caches of the latest published messages per topic, replayed to new subscribers.
+
+use super::BroadcastInfo;
+use crate::nut::iac::subscription::SubscriptionId;
+use crate::nut::iac::topic::Topic;
+use crate::nut::{diagnostics::OrReport, Nut};
+use core::any::Any;
+use std::collections::{HashMap, VecDeque};
+
+/// Type-erased cache of one message type
+trait ReplayBuffer {
+    fn set_capacity(&mut self, capacity: usize);
+    fn record(&mut self, msg: &dyn Any);
+    fn replay(&self, id: &SubscriptionId) -> Vec<BroadcastInfo>;
+}
+
+struct LastN<MSG> {
+    capacity: usize,
+    messages: VecDeque<MSG>,
+}
+
+impl<MSG: Any + Clone> ReplayBuffer for LastN<MSG> {
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.messages.len() > capacity {
+            self.messages.pop_front();
+        }
+    }
+    fn record(&mut self, msg: &dyn Any) {
+        if let Some(msg) = msg.downcast_ref::<MSG>() {
+            if self.messages.len() == self.capacity {
+                self.messages.pop_front();
+            }
+            self.messages.push_back(msg.clone());
+        }
+    }
+    fn replay(&self, id: &SubscriptionId) -> Vec<BroadcastInfo> {
+        self.messages
+            .iter()
+            .map(|msg| BroadcastInfo::subscription(msg.clone(), id))
+            .collect()
+    }
+}
+
+/// Replay buffers of all topics configured with `nuts::retain_last`
+#[derive(Default)]
+pub(crate) struct ReplayBuffers {
+    buffers: HashMap<Topic, Box<dyn ReplayBuffer>>,
+}
+
+impl ReplayBuffers {
+    /// A capacity of 0 removes the buffer.
+    pub(crate) fn retain_last<MSG: Any + Clone>(&mut self, n: usize) {
+        let topic = Topic::public_message::<MSG>();
+        if n == 0 {
+            self.buffers.remove(&topic);
+            return;
+        }
+        self.buffers
+            .entry(topic)
+            .or_insert_with(|| {
+                Box::new(LastN::<MSG> {
+                    capacity: n,
+                    messages: VecDeque::new(),
+                })
+            })
+            .set_capacity(n);
+    }
+    pub(crate) fn record(&mut self, topic: &Topic, msg: &dyn Any) {
+        if let Some(buffer) = self.buffers.get_mut(topic) {
+            buffer.record(msg);
+        }
+    }
+    fn replay(&self, id: &SubscriptionId) -> Vec<BroadcastInfo> {
+        self.buffers
+            .get(&id.topic)
+            .map(|buffer| buffer.replay(id))
+            .unwrap_or_default()
+    }
+}
+
+impl Nut {
+    /// Queues the cached messages of the topic for the new subscription.
+    ///
+    /// Returns true iff anything has been queued.
+    pub(crate) fn queue_replay(&self, id: &SubscriptionId) -> bool {
+        let replay = self.replay_buffers.try_borrow().or_report().replay(id);
+        let queued = !replay.is_empty();
+        for broadcast in replay {
+            self.push_deferred(broadcast.into());
+        }
+        queued
+    }
+}
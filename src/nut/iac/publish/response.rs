@@ -1,70 +1,218 @@
-use std::{future::Future, task::Poll};
+use core::any::Any;
+use std::{collections::HashMap, future::Future, task::Poll};
 
 use crate::nut::Nut;
 
 #[derive(Default)]
 pub(crate) struct ResponseTracker {
-    slots: Vec<SlotState>,
+    slots: Vec<(u64, SlotState)>,
+    /// Values set by responders, by slot index
+    replies: HashMap<usize, Box<dyn Any>>,
+    /// Ticks at which pending responses time out, by slot index
+    deadlines: HashMap<usize, u64>,
+    /// Generation of the next allocated slot, continues counting after a reset
+    next_generation: u64,
 }
 
 enum SlotState {
     Available,
-    Occupied,
+    /// Counts the deferred events that still have to be executed before the response is ready.
+    Occupied(usize),
+    /// Like `Occupied` but the future has been dropped, the slot is freed when the events are done.
+    Abandoned(usize),
     Done,
 }
 
-pub(crate) struct Slot(usize);
+/// A slot index together with the generation it has been allocated in.
+/// Slots of an older generation, for example from before a reset, are stale and ignored.
+#[derive(Clone, Copy)]
+pub(crate) struct Slot {
+    index: usize,
+    generation: u64,
+}
 
 #[allow(clippy::single_match)]
 impl ResponseTracker {
+    /// An empty tracker that never hands out slots of a generation used by this one.
+    pub(crate) fn successor(&self) -> Self {
+        Self {
+            next_generation: self.next_generation,
+            ..Default::default()
+        }
+    }
     pub fn allocate(&mut self) -> Slot {
+        let generation = self.next_generation;
+        self.next_generation += 1;
         for (i, slot) in self.slots.iter_mut().enumerate() {
-            match slot {
+            match slot.1 {
                 SlotState::Available => {
-                    *slot = SlotState::Occupied;
-                    return Slot(i);
+                    *slot = (generation, SlotState::Occupied(0));
+                    return Slot {
+                        index: i,
+                        generation,
+                    };
                 }
                 _ => {}
             }
         }
         let i = self.slots.len();
-        self.slots.push(SlotState::Occupied);
-        Slot(i)
+        self.slots.push((generation, SlotState::Occupied(0)));
+        Slot {
+            index: i,
+            generation,
+        }
+    }
+    /// The state of the slot, `None` if the slot is stale.
+    fn state(&mut self, slot: Slot) -> Option<&mut SlotState> {
+        match self.slots.get_mut(slot.index) {
+            Some((generation, state)) if *generation == slot.generation => Some(state),
+            _ => None,
+        }
+    }
+    /// Registers one more deferred event that must be executed before the response is ready.
+    pub fn add_pending(&mut self, slot: Slot) {
+        match self.state(slot) {
+            Some(SlotState::Occupied(pending)) | Some(SlotState::Abandoned(pending)) => {
+                *pending += 1
+            }
+            Some(_) => panic!("Corrupted futures State"),
+            None => {}
+        }
     }
-    pub fn done(&mut self, slot: &Slot) {
-        self.slots[slot.0] = SlotState::Done;
+    /// Marks one deferred event as executed. The response is ready once no more events are pending.
+    pub fn resolve_pending(&mut self, slot: Slot) {
+        match self.state(slot) {
+            Some(SlotState::Occupied(pending)) | Some(SlotState::Abandoned(pending))
+                if *pending > 1 =>
+            {
+                *pending -= 1
+            }
+            Some(state @ SlotState::Occupied(_)) => *state = SlotState::Done,
+            Some(SlotState::Abandoned(_)) => {
+                self.free(slot.index);
+            }
+            Some(_) => panic!("Corrupted futures State"),
+            None => {}
+        }
     }
-    fn free(&mut self, index: usize) {
-        self.slots[index] = SlotState::Available;
+    /// Stores the reply to a request. Only the first reply is kept.
+    pub fn set_reply(&mut self, slot: Slot, reply: Box<dyn Any>) {
+        if self.state(slot).is_some() {
+            self.replies.entry(slot.index).or_insert(reply);
+        }
+    }
+    /// The response resolves without a reply if it is still pending once the frame counter reaches `tick`.
+    pub fn set_deadline(&mut self, slot: Slot, tick: u64) {
+        if self.state(slot).is_some() {
+            self.deadlines.insert(slot.index, tick);
+        }
+    }
+    /// The future of the slot has been dropped before it resolved.
+    /// Stale slots are ignored.
+    fn abandon(&mut self, slot: Slot) {
+        let state = match self.state(slot) {
+            Some(state) => state,
+            None => return,
+        };
+        match *state {
+            SlotState::Occupied(pending) => *state = SlotState::Abandoned(pending),
+            SlotState::Done => {
+                self.free(slot.index);
+            }
+            _ => {}
+        }
+    }
+    fn free(&mut self, index: usize) -> Option<Box<dyn Any>> {
+        self.slots[index].1 = SlotState::Available;
+        self.deadlines.remove(&index);
+        self.replies.remove(&index)
     }
 }
 
 pub struct NutsResponse {
-    index: usize,
+    slot: Slot,
+    /// Set once the future returned `Ready`, the slot is freed at that point
+    resolved: bool,
+    /// Set if the future resolved because its deadline has passed
+    timed_out: bool,
 }
 
 impl NutsResponse {
     pub(crate) fn new(slot: &Slot) -> Self {
-        Self { index: slot.0 }
+        Self {
+            slot: *slot,
+            resolved: false,
+            timed_out: false,
+        }
+    }
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out
     }
 }
 
 impl Future for NutsResponse {
-    type Output = ();
+    /// The reply, if any responder has been called
+    type Output = Option<Box<dyn Any>>;
 
     fn poll(
-        self: std::pin::Pin<&mut Self>,
+        mut self: std::pin::Pin<&mut Self>,
         _cx: &mut std::task::Context<'_>,
     ) -> Poll<Self::Output> {
-        Nut::with_response_tracker_mut(|response_tracker| {
-            match response_tracker.slots[self.index] {
-                SlotState::Available => panic!("Corrupted futures State"),
-                SlotState::Occupied => Poll::Pending,
-                SlotState::Done => {
-                    response_tracker.free(self.index);
-                    Poll::Ready(())
+        let slot = self.slot;
+        let tick = crate::nut::current_tick();
+        let mut timed_out = false;
+        let poll = Nut::with_response_tracker_mut(|response_tracker| {
+            let deadline = response_tracker.deadlines.get(&slot.index).copied();
+            match response_tracker.state(slot) {
+                // The slot has been dropped by a reset, nothing will ever respond
+                None => Poll::Ready(None),
+                Some(SlotState::Available) | Some(SlotState::Abandoned(_)) => {
+                    panic!("Corrupted futures State")
                 }
+                Some(SlotState::Occupied(_)) if matches!(deadline, Some(deadline) if tick >= deadline) =>
+                {
+                    // Events still pending free the slot once they are done
+                    response_tracker.abandon(slot);
+                    timed_out = true;
+                    Poll::Ready(None)
+                }
+                Some(SlotState::Occupied(_)) => Poll::Pending,
+                Some(SlotState::Done) => Poll::Ready(response_tracker.free(slot.index)),
             }
-        })
+        });
+        self.resolved = poll.is_ready();
+        self.timed_out = timed_out;
+        poll
     }
 }
+
+impl Drop for NutsResponse {
+    fn drop(&mut self) {
+        if !self.resolved {
+            let slot = self.slot;
+            Nut::try_with_response_tracker_mut(|response_tracker| response_tracker.abandon(slot));
+        }
+    }
+}
+
+/// Reason why a request sent with [`ActivityId::request`](struct.ActivityId.html#method.request) did not produce a reply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RequestError {
+    /// The request has been processed but no private responder answered it with a reply of the expected type.
+    /// This is also the case if the activity was inactive or has been deleted.
+    NoReply,
+    /// The request was still pending when the timeout expired.
+    Timeout,
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoReply => write!(f, "the request has not been answered"),
+            Self::Timeout => write!(f, "the request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
@@ -0,0 +1,40 @@
+//! Published messages that no subscription has received, kept until `nuts::drain_topic` takes them.
+
+use crate::nut::IMPOSSIBLE_ERR_MSG;
+use core::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Undelivered messages per message type, only for types configured with `nuts::keep_undelivered`
+#[derive(Default)]
+pub(crate) struct UndeliveredMessages {
+    kept: HashMap<TypeId, Vec<Box<dyn Any>>>,
+}
+
+impl UndeliveredMessages {
+    pub(crate) fn keep<MSG: Any>(&mut self) {
+        self.kept.entry(TypeId::of::<MSG>()).or_default();
+    }
+    pub(crate) fn is_kept(&self, type_id: TypeId) -> bool {
+        self.kept.contains_key(&type_id)
+    }
+    /// Stores the message, if its type is kept. Otherwise, the message is returned.
+    pub(crate) fn hold(&mut self, msg: Box<dyn Any>) -> Option<Box<dyn Any>> {
+        match self.kept.get_mut(&msg.as_ref().type_id()) {
+            Some(held) => {
+                held.push(msg);
+                None
+            }
+            None => Some(msg),
+        }
+    }
+    /// Removes all held messages of the type, oldest first.
+    pub(crate) fn take<MSG: Any>(&mut self) -> Vec<MSG> {
+        self.kept
+            .get_mut(&TypeId::of::<MSG>())
+            .map(std::mem::take)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|msg| *msg.downcast().expect(IMPOSSIBLE_ERR_MSG))
+            .collect()
+    }
+}
@@ -0,0 +1,117 @@
+//! Signals: values that publish a message whenever they change.
+//!
+//! Domain watches: builtin activities that poll a domain value and publish a message when it has changed.
+
+use crate::*;
+use core::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Published by a [`Signal`](struct.Signal.html) when its value changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Changed<T> {
+    /// The value before the change
+    pub old: T,
+    /// The value after the change
+    pub new: T,
+}
+
+/// A value that publishes [`Changed<T>`](struct.Changed.html) whenever it is set to something different.
+///
+/// The latest value is also stored in the domain of the signal, where domained subscriptions can read it.
+/// Create it with [`nuts::signal`](fn.signal.html).
+///
+/// Clones of a signal share the same value.
+pub struct Signal<T> {
+    value: Rc<RefCell<T>>,
+    domain: DomainId,
+}
+
+impl<T: PartialEq + Clone + Any> Signal<T> {
+    pub(crate) fn new(domain: DomainId, initial: T) -> Self {
+        crate::nut::write_domain_by_id(domain, initial.clone());
+        Self {
+            value: Rc::new(RefCell::new(initial)),
+            domain,
+        }
+    }
+    /// Updates the value.
+    ///
+    /// If it differs from the previous value, the new value is stored to the domain and `Changed<T>` is published.
+    /// Otherwise, nothing happens.
+    pub fn set(&self, value: T) {
+        if *self.value.borrow() == value {
+            return;
+        }
+        let old = self.value.replace(value.clone());
+        crate::nut::write_domain_by_id(self.domain, value.clone());
+        crate::publish(Changed { old, new: value });
+    }
+    /// A copy of the current value.
+    pub fn get(&self) -> T {
+        self.value.borrow().clone()
+    }
+}
+
+impl<T> Clone for Signal<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            domain: self.domain,
+        }
+    }
+}
+
+type OnChange<T> = Box<dyn Fn(&T, &T)>;
+
+/// Builtin activity that compares a domain value to its previous value every few frames, see [`nuts::watch_domain`](fn.watch_domain.html).
+pub struct DomainWatch<T> {
+    last: Option<T>,
+    period: u64,
+    on_change: OnChange<T>,
+}
+
+impl<T: PartialEq + Clone + Any> DomainWatch<T> {
+    pub(crate) fn new(period: u64, on_change: impl Fn(&T, &T) + 'static) -> Self {
+        assert!(
+            period > 0,
+            "A domain watch needs a period of at least one frame"
+        );
+        Self {
+            last: None,
+            period,
+            on_change: Box::new(on_change),
+        }
+    }
+    fn poll(&mut self, domain: &DomainState, frame: u64) {
+        if frame % self.period != 0 {
+            return;
+        }
+        let current = match domain.try_get::<T>() {
+            Some(value) => value,
+            None => return,
+        };
+        match self.last.as_mut() {
+            Some(last) if last != current => {
+                let old = std::mem::replace(last, current.clone());
+                (self.on_change)(&old, current);
+                crate::publish(Changed {
+                    old,
+                    new: current.clone(),
+                });
+            }
+            Some(_) => {}
+            None => self.last = Some(current.clone()),
+        }
+    }
+}
+
+pub(crate) fn watch_domain<T: PartialEq + Clone + Any>(
+    domain: &impl DomainEnumeration,
+    period: u64,
+    on_change: impl Fn(&T, &T) + 'static,
+) -> ActivityId<DomainWatch<T>, WithDomain> {
+    let id = crate::new_domained_activity_checked(DomainWatch::new(period, on_change), domain);
+    id.subscribe_domained(|watch, domain, frame: &FrameUpdate| watch.poll(domain, frame.frame));
+    id
+}
@@ -1,45 +1,149 @@
-use super::{managed_state::ManagedState, topic::Topic};
+use super::{
+    managed_state::{DomainId, ManagedState},
+    publish::BroadcastInfo,
+    topic::{MessageType, Topic},
+};
 use crate::{
     debug::DebugTypeName,
-    nut::{exec::Deferred, Handler, Nut, IMPOSSIBLE_ERR_MSG},
-    ActivityId, UncheckedActivityId,
+    nut::{diagnostics::OrReport, exec::Deferred, Handler, Nut},
+    ActivityId, DeliveryOrder, Phase, UncheckedActivityId,
 };
 use core::cell::Ref;
 use std::{
-    any::Any,
-    cell::RefCell,
+    any::{Any, TypeId},
+    cell::{Cell, RefCell},
     collections::HashMap,
-    ops::{Index, IndexMut},
 };
 
 #[derive(Default)]
 pub(crate) struct Subscriptions {
     subscriptions: RefCell<HashMap<Topic, SubscriptionContainer>>,
+    /// Topics with a delivery order set by `nuts::set_topic_order`
+    orders: RefCell<HashMap<Topic, DeliveryOrder>>,
+    /// Used to give each subscription a unique number
+    next_index: Cell<usize>,
 }
 
-/// Handlers stored per Activity
-#[derive(Default)]
-pub(crate) struct SubscriptionContainer {
-    data: HashMap<usize, ActivityTopicSubscriptions>,
+/// Handle to a single subscription, returned when subscribing.
+///
+/// Use it with [`ActivityId::unsubscribe`](struct.ActivityId.html#method.unsubscribe) to remove the subscription again.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SubscriptionId {
+    pub(crate) topic: Topic,
+    pub(crate) activity: UncheckedActivityId,
+    pub(crate) index: usize,
+}
+
+/// A subscription without an activity, returned by [`nuts::subscribe`](fn.subscribe.html) and friends.
+///
+/// The subscription stays registered when the handle is dropped, call [`unsubscribe`](#method.unsubscribe) to remove it.
+#[derive(Debug)]
+pub struct SubscriptionHandle {
+    id: SubscriptionId,
 }
 
-/// Handlers per type per activity
+impl SubscriptionHandle {
+    pub(crate) fn new(id: SubscriptionId) -> Self {
+        Self { id }
+    }
+    /// Removes the subscription.
+    ///
+    /// If called while a broadcast is ongoing, the removal is deferred, like with [`ActivityId::unsubscribe`](struct.ActivityId.html#method.unsubscribe).
+    pub fn unsubscribe(self) {
+        crate::nut::unsubscribe(self.id);
+    }
+}
+
+/// Handlers stored per topic
 #[derive(Default)]
-pub(crate) struct ActivityTopicSubscriptions {
+pub(crate) struct SubscriptionContainer {
+    /// Sorted by priority (highest first), then in registration order
     shared: Vec<Subscription>,
-    private: Option<Subscription>,
+    /// At most one per activity, by activity index
+    private: HashMap<usize, Subscription>,
+}
+
+/// Builtin message published when the last subscription of a message type has been removed.
+///
+/// This happens when the last subscriber calls [`unsubscribe`](struct.ActivityId.html#method.unsubscribe) or when it is deleted.
+/// Producers can listen to it to stop producing messages that nobody receives anymore.
+#[derive(Clone, Debug)]
+pub struct TopicOrphaned {
+    message_type: MessageType,
+    private: bool,
+}
+
+impl TopicOrphaned {
+    /// The name of the message type, as returned by `std::any::type_name`, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+    pub fn type_name(&self) -> &'static str {
+        self.message_type.name
+    }
+    /// True iff the orphaned topic is for messages of type `MSG`.
+    pub fn is<MSG: Any>(&self) -> bool {
+        self.message_type.id == TypeId::of::<MSG>()
+    }
+    /// True iff the orphaned topic is for private messages. (Sent with `send_to` or `private_message`)
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+}
+
+/// Builtin message published when a handler registered with [`subscribe_fallible`](struct.ActivityId.html#method.subscribe_fallible) returns an error.
+///
+/// Subscribe to it to handle errors of all activities in a central place.
+#[derive(Debug)]
+pub struct HandlerError {
+    activity: &'static str,
+    message: &'static str,
+    error: Box<dyn std::error::Error>,
+}
+
+impl HandlerError {
+    pub(crate) fn new<A: Any, MSG: Any>(error: Box<dyn std::error::Error>) -> Self {
+        Self {
+            activity: std::any::type_name::<A>(),
+            message: std::any::type_name::<MSG>(),
+            error,
+        }
+    }
+    /// The name of the activity type whose handler failed, as returned by `std::any::type_name`, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+    pub fn activity_type_name(&self) -> &'static str {
+        self.activity
+    }
+    /// The name of the message type that was handled, as returned by `std::any::type_name`, see [`MessageMeta::type_name`](struct.MessageMeta.html#method.type_name).
+    pub fn message_type_name(&self) -> &'static str {
+        self.message
+    }
+    /// The error returned by the handler.
+    pub fn error(&self) -> &(dyn std::error::Error + 'static) {
+        self.error.as_ref()
+    }
+}
+
+/// Properties of a subscription that are defined when subscribing, see `SubscriptionFilter::options`.
+#[derive(Clone, Default)]
+pub(crate) struct SubscriptionOptions {
+    pub(crate) active_only: bool,
+    pub(crate) priority: i32,
+    pub(crate) group: Option<String>,
+    pub(crate) phase: Option<Phase>,
 }
 
 pub(crate) struct Subscription {
+    pub(crate) index: usize,
+    pub(crate) activity: UncheckedActivityId,
+    pub(crate) options: SubscriptionOptions,
     pub(crate) handler: Handler,
     #[allow(dead_code)]
     pub(crate) type_name: DebugTypeName,
 }
 
+pub(crate) type OnDeleteWithDomain = Box<dyn FnOnce(Box<dyn Any>, DomainId, &mut ManagedState)>;
+
 pub(crate) enum OnDelete {
     None,
     Simple(Box<dyn FnOnce(Box<dyn Any>)>),
-    WithDomain(Box<dyn FnOnce(Box<dyn Any>, &mut ManagedState)>),
+    WithDomain(OnDeleteWithDomain),
 }
 
 impl Nut {
@@ -48,103 +152,288 @@ impl Nut {
         topic: Topic,
         id: ActivityId<A>,
         closure: Handler,
-    ) {
+        options: SubscriptionOptions,
+    ) -> SubscriptionId {
+        self.check_bus(id.into());
         let type_name = DebugTypeName::new::<A>();
+        let sub_id = self.subscriptions.allocate_id(topic, id.into());
         if self.quiescent() {
             self.subscriptions
-                .force_push_closure(topic, id, closure, type_name);
+                .force_push_closure(sub_id.clone(), closure, type_name, options);
+            self.apply_delivery_order(&sub_id.topic);
+            if self.queue_replay(&sub_id) {
+                self.catch_up_deferred_to_quiescence();
+            }
         } else {
-            let sub = NewSubscription::new(topic, id, closure, type_name);
-            self.deferred_events.push(Deferred::Subscription(sub));
+            let sub = NewSubscription::new(sub_id.clone(), closure, type_name, options);
+            self.push_deferred(Deferred::Subscription(sub));
+        }
+        sub_id
+    }
+    pub(crate) fn unsubscribe(&self, id: SubscriptionId) {
+        if self.quiescent() {
+            self.unchecked_unsubscribe(&id);
+        } else {
+            self.push_deferred(Deferred::Unsubscribe(id));
+        }
+    }
+    /// only access while no broadcast is ongoing
+    pub(crate) fn unchecked_unsubscribe(&self, id: &SubscriptionId) {
+        if self.subscriptions.remove(id) {
+            self.publish_orphaned(&id.topic);
+        }
+    }
+    /// Removes all subscriptions of a deleted activity.
+    /// only access while no broadcast is ongoing
+    pub(crate) fn unsubscribe_all(&self, activity: UncheckedActivityId) {
+        for topic in self.subscriptions.remove_activity(activity) {
+            self.publish_orphaned(&topic);
+        }
+    }
+    /// Swaps the data of an activity and drops its subscriptions, except for those in the listed groups.
+    /// only access while no broadcast is ongoing
+    pub(crate) fn replace_activity(
+        &self,
+        activity: UncheckedActivityId,
+        data: Box<dyn Any>,
+        keep_groups: &[String],
+    ) {
+        self.activities
+            .try_borrow_mut()
+            .or_report()
+            .replace(activity, data);
+        for topic in self
+            .subscriptions
+            .remove_activity_except(activity, keep_groups)
+        {
+            self.publish_orphaned(&topic);
+        }
+    }
+    pub(crate) fn publish_orphaned(&self, topic: &Topic) {
+        if let Some(message_type) = topic.message_type() {
+            let msg = TopicOrphaned {
+                message_type,
+                private: topic.unqiue_per_activity(),
+            };
+            self.broadcast(BroadcastInfo::global(
+                msg,
+                Topic::public_message::<TopicOrphaned>(),
+            ));
         }
     }
 }
 
 impl Subscriptions {
-    pub(crate) fn exec_new_subscription(&self, sub: NewSubscription) {
-        self.force_push_closure(sub.topic, sub.id, sub.closure, sub.type_name);
+    pub(crate) fn exec_new_subscription(&self, sub: NewSubscription) -> SubscriptionId {
+        self.force_push_closure(sub.id.clone(), sub.closure, sub.type_name, sub.options);
+        sub.id
+    }
+    fn allocate_id(&self, topic: Topic, activity: UncheckedActivityId) -> SubscriptionId {
+        let index = self.next_index.get();
+        self.next_index.set(index + 1);
+        SubscriptionId {
+            topic,
+            activity,
+            index,
+        }
     }
     fn force_push_closure(
         &self,
-        topic: Topic,
-        id: impl Into<UncheckedActivityId>,
+        id: SubscriptionId,
         handler: Handler,
         type_name: DebugTypeName,
+        options: SubscriptionOptions,
     ) {
-        let id = id.into();
-        let private = topic.unqiue_per_activity();
-        let subs = &mut self
-            .subscriptions
-            .try_borrow_mut()
-            .expect(IMPOSSIBLE_ERR_MSG);
-        let subs_per_activity = &mut subs.entry(topic).or_insert_with(Default::default)[id];
+        let private = id.topic.unqiue_per_activity();
+        let subs = &mut self.subscriptions.try_borrow_mut().or_report();
+        let container = subs.entry(id.topic).or_default();
+        let sub = Subscription {
+            index: id.index,
+            activity: id.activity,
+            options,
+            handler,
+            type_name,
+        };
 
         if private {
-            subs_per_activity.private = Some(Subscription { handler, type_name });
+            container.private.insert(id.activity.index, sub);
         } else {
-            subs_per_activity
+            let priority = sub.options.priority;
+            let pos = container
                 .shared
-                .push(Subscription { handler, type_name });
+                .iter()
+                .position(|other| other.options.priority < priority)
+                .unwrap_or(container.shared.len());
+            container.shared.insert(pos, sub);
+        }
+    }
+    /// Removes a single subscription. Nothing happens if the subscription does not exist (anymore).
+    ///
+    /// Returns true iff the topic has no subscriptions left afterwards, while it had some before.
+    pub(crate) fn remove(&self, id: &SubscriptionId) -> bool {
+        let subs = &mut self.subscriptions.try_borrow_mut().or_report();
+        if let Some(container) = subs.get_mut(&id.topic) {
+            container.shared.retain(|sub| sub.index != id.index);
+            if container
+                .private
+                .get(&id.activity.index)
+                .is_some_and(|sub| sub.index == id.index)
+            {
+                container.private.remove(&id.activity.index);
+            }
+            if container.is_empty() {
+                subs.remove(&id.topic);
+                return true;
+            }
         }
+        false
+    }
+    /// Removes all subscriptions of an activity and returns the topics that have no subscriptions left.
+    pub(crate) fn remove_activity(&self, activity: UncheckedActivityId) -> Vec<Topic> {
+        self.remove_activity_except(activity, &[])
     }
-    pub(crate) fn get(&self) -> Ref<HashMap<Topic, SubscriptionContainer>> {
+    /// Removes all subscriptions of an activity, except those in one of the listed groups.
+    pub(crate) fn remove_activity_except(
+        &self,
+        activity: UncheckedActivityId,
+        keep_groups: &[String],
+    ) -> Vec<Topic> {
+        let removed = |sub: &Subscription| {
+            sub.activity == activity
+                && !sub
+                    .options
+                    .group
+                    .as_ref()
+                    .is_some_and(|group| keep_groups.contains(group))
+        };
+        let subs = &mut self.subscriptions.try_borrow_mut().or_report();
+        let mut orphaned = vec![];
+        subs.retain(|topic, container| {
+            let before = container.shared.len() + container.private.len();
+            container.shared.retain(|sub| !removed(sub));
+            if container.private.get(&activity.index).is_some_and(removed) {
+                container.private.remove(&activity.index);
+            }
+            let after = container.shared.len() + container.private.len();
+            if before > after && after == 0 {
+                orphaned.push(topic.clone());
+                false
+            } else {
+                true
+            }
+        });
+        orphaned
+    }
+    /// Removes all subscriptions for which `dead` returns true, returns the topics that have no subscriptions left.
+    /// Also releases memory that is not needed anymore.
+    pub(crate) fn compact(&self, dead: impl Fn(UncheckedActivityId) -> bool) -> Vec<Topic> {
+        let subs = &mut self.subscriptions.try_borrow_mut().or_report();
+        let mut orphaned = vec![];
+        subs.retain(|topic, container| {
+            container.shared.retain(|sub| !dead(sub.activity));
+            container.private.retain(|_, sub| !dead(sub.activity));
+            if container.is_empty() {
+                orphaned.push(topic.clone());
+                return false;
+            }
+            container.shared.shrink_to_fit();
+            container.private.shrink_to_fit();
+            true
+        });
+        subs.shrink_to_fit();
+        orphaned
+    }
+    /// Removes all subscriptions and restarts numbering.
+    pub(crate) fn clear(&self) {
+        self.subscriptions.take();
+        self.orders.take();
+        self.next_index.set(0);
+    }
+    pub(crate) fn get(&self) -> Ref<'_, HashMap<Topic, SubscriptionContainer>> {
         self.subscriptions.borrow()
     }
+    /// The delivery order, if one has been set for the topic
+    pub(crate) fn order(&self, topic: &Topic) -> Option<DeliveryOrder> {
+        self.orders.try_borrow().or_report().get(topic).copied()
+    }
+    pub(crate) fn set_order(&self, topic: Topic, order: DeliveryOrder) {
+        self.orders
+            .try_borrow_mut()
+            .or_report()
+            .insert(topic, order);
+    }
+    /// Sorts shared subscriptions by priority, then with `compare`, then in registration order.
+    pub(crate) fn sort_shared(
+        &self,
+        topic: &Topic,
+        mut compare: impl FnMut(&Subscription, &Subscription) -> std::cmp::Ordering,
+    ) {
+        if let Some(container) = self
+            .subscriptions
+            .try_borrow_mut()
+            .or_report()
+            .get_mut(topic)
+        {
+            container.shared.sort_by(|a, b| {
+                b.options
+                    .priority
+                    .cmp(&a.options.priority)
+                    .then_with(|| compare(a, b))
+                    .then(a.index.cmp(&b.index))
+            });
+        }
+    }
 }
 
 impl SubscriptionContainer {
     pub fn shared_subscriptions(&self) -> impl Iterator<Item = &Subscription> {
-        self.data.values().flat_map(|f| f.shared.iter())
+        self.shared.iter()
     }
     pub fn shared_subscriptions_of_single_activity(
         &self,
         id: UncheckedActivityId,
     ) -> impl Iterator<Item = &Subscription> {
-        self.data
-            .get(&id.index)
-            .into_iter()
-            .flat_map(|f| f.shared.iter())
+        self.shared.iter().filter(move |sub| sub.activity == id)
+    }
+    /// In no particular order
+    pub fn private_subscriptions(&self) -> impl Iterator<Item = &Subscription> {
+        self.private.values()
     }
     pub fn private_subscription(&self, id: UncheckedActivityId) -> Option<&Subscription> {
-        self.data
-            .get(&id.index)
-            .map(|f| f.private.as_ref())
-            .flatten()
+        self.private.get(&id.index)
     }
-}
-impl Index<UncheckedActivityId> for SubscriptionContainer {
-    type Output = ActivityTopicSubscriptions;
-    fn index(&self, id: UncheckedActivityId) -> &Self::Output {
-        &self.data[&id.index]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shared.is_empty() && self.private.is_empty()
     }
-}
-impl IndexMut<UncheckedActivityId> for SubscriptionContainer {
-    fn index_mut(&mut self, id: UncheckedActivityId) -> &mut Self::Output {
-        self.data.entry(id.index).or_insert_with(Default::default)
+    pub(crate) fn len(&self) -> usize {
+        self.shared.len() + self.private.len()
     }
 }
 
 pub(crate) struct NewSubscription {
-    topic: Topic,
-    id: UncheckedActivityId,
+    id: SubscriptionId,
     closure: Handler,
     type_name: DebugTypeName,
+    options: SubscriptionOptions,
 }
 
 impl NewSubscription {
     fn new(
-        topic: Topic,
-        id: impl Into<UncheckedActivityId>,
+        id: SubscriptionId,
         closure: Handler,
         type_name: DebugTypeName,
+        options: SubscriptionOptions,
     ) -> Self {
         Self {
-            topic,
-            id: id.into(),
+            id,
             closure,
             type_name,
+            options,
         }
     }
+    pub(crate) fn id(&self) -> &SubscriptionId {
+        &self.id
+    }
 }
 
 #[cfg(debug_assertions)]
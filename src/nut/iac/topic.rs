@@ -1,14 +1,43 @@
+use crate::EntityId;
 use core::any::{Any, TypeId};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 /// A topic for messages that can be published and subscribed to
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) enum Topic {
     /// Topic for a builtin event
     BuiltinEvent(BuiltinEvent),
-    /// Topic for a message type, where type is a Rust type (core::any::TypeId). Many receivers can coexists for each published message.
-    PublicMessage(TypeId),
-    /// Topic for a message type, where type is a Rust type (core::any::TypeId). Only one receiver can exist per activity and each message must be sent to exactly one activity.
-    PrivateMessage(TypeId),
+    /// Topic for a message type, where type is a Rust type (`core::any::TypeId`). Many receivers can coexists for each published message.
+    PublicMessage(MessageType),
+    /// Topic for a message type, where type is a Rust type (`core::any::TypeId`). Only one receiver can exist per activity and each message must be sent to exactly one activity.
+    PrivateMessage(MessageType),
+    /// Like `PrivateMessage`, with one independent channel per key.
+    KeyedPrivateMessage(MessageType, ChannelKey),
+    /// Like `PublicMessage`, for messages addressed to a single entity. Only subscriptions of that entity receive them.
+    EntityMessage(MessageType, EntityId),
+    /// Messages published by name with `nuts::publish_dynamic`, the name is interned when it is subscribed to.
+    #[cfg(feature = "dynamic-messages")]
+    DynamicMessage(&'static str),
+    /// Subscriptions to this topic receive all messages, before the regular subscribers. Nothing is ever published to it directly.
+    Wildcard,
+}
+
+/// Identifies a message type. The name is only carried along for diagnostics, comparisons use the `TypeId` only.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MessageType {
+    pub(crate) id: TypeId,
+    pub(crate) name: &'static str,
+}
+
+/// The key of a keyed private channel, compared with the `Eq` implementation of the original key.
+/// Keys of different types are never equal.
+#[derive(Clone)]
+pub(crate) struct ChannelKey {
+    hash: u64,
+    key: Rc<dyn Any>,
+    eq: fn(&dyn Any, &dyn Any) -> bool,
 }
 
 /// Builtin events are messages without payload that are used internally.
@@ -18,6 +47,8 @@ pub(crate) enum BuiltinEvent {
     Enter,
     /// On status change to inactive / deleted
     Leave,
+    /// On any status change, with a `LifecycleTransition` payload
+    Transition,
 }
 
 impl Topic {
@@ -27,16 +58,150 @@ impl Topic {
     pub(crate) fn leave() -> Self {
         Self::BuiltinEvent(BuiltinEvent::Leave)
     }
+    pub(crate) fn transition() -> Self {
+        Self::BuiltinEvent(BuiltinEvent::Transition)
+    }
     pub(crate) fn public_message<T: Any>() -> Self {
-        Self::PublicMessage(TypeId::of::<T>())
+        Self::PublicMessage(MessageType::of::<T>())
+    }
+    /// Topic for published messages of a type that is only known at runtime
+    pub(crate) fn public_message_dyn(id: TypeId) -> Self {
+        Self::PublicMessage(MessageType {
+            id,
+            name: "<dynamic>",
+        })
+    }
+    pub(crate) fn entity_message<T: Any>(entity: EntityId) -> Self {
+        Self::EntityMessage(MessageType::of::<T>(), entity)
     }
     pub(crate) fn private_message<T: Any>() -> Self {
-        Self::PrivateMessage(TypeId::of::<T>())
+        Self::PrivateMessage(MessageType::of::<T>())
+    }
+    pub(crate) fn keyed_private_message<T: Any>(key: impl Hash + Eq + Any) -> Self {
+        Self::KeyedPrivateMessage(MessageType::of::<T>(), ChannelKey::new(key))
+    }
+    /// For subscriptions, interns the name.
+    #[cfg(feature = "dynamic-messages")]
+    pub(crate) fn dynamic_message(name: &str) -> Self {
+        Self::DynamicMessage(intern(name))
+    }
+    /// For publishing, `None` if no subscription to the name has ever been registered, which means the message has no receiver.
+    ///
+    /// Only subscribed names are interned, so that publishing arbitrary names does not leak memory.
+    #[cfg(feature = "dynamic-messages")]
+    pub(crate) fn published_dynamic_message(name: &str) -> Option<Self> {
+        interned(name).map(Self::DynamicMessage)
+    }
+    /// The message type of the topic, `None` for builtin events and dynamic messages
+    pub(crate) fn message_type(&self) -> Option<MessageType> {
+        match self {
+            Self::BuiltinEvent(_) | Self::Wildcard => None,
+            #[cfg(feature = "dynamic-messages")]
+            Self::DynamicMessage(_) => None,
+            Self::PublicMessage(t)
+            | Self::PrivateMessage(t)
+            | Self::KeyedPrivateMessage(t, _)
+            | Self::EntityMessage(t, _) => Some(*t),
+        }
+    }
+    /// A readable name for diagnostics, not guaranteed to be stable.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::BuiltinEvent(BuiltinEvent::Enter) => "nuts::Enter",
+            Self::BuiltinEvent(BuiltinEvent::Leave) => "nuts::Leave",
+            Self::BuiltinEvent(BuiltinEvent::Transition) => "nuts::Transition",
+            Self::PublicMessage(t)
+            | Self::PrivateMessage(t)
+            | Self::KeyedPrivateMessage(t, _)
+            | Self::EntityMessage(t, _) => t.name,
+            Self::Wildcard => "*",
+            #[cfg(feature = "dynamic-messages")]
+            Self::DynamicMessage(name) => name,
+        }
     }
     pub(crate) fn unqiue_per_activity(&self) -> bool {
         match self {
-            Self::BuiltinEvent(_) | Self::PublicMessage(_) => false,
-            Self::PrivateMessage(_) => true,
+            Self::BuiltinEvent(_)
+            | Self::PublicMessage(_)
+            | Self::EntityMessage(..)
+            | Self::Wildcard => false,
+            #[cfg(feature = "dynamic-messages")]
+            Self::DynamicMessage(_) => false,
+            Self::PrivateMessage(_) | Self::KeyedPrivateMessage(..) => true,
         }
     }
 }
+
+impl ChannelKey {
+    fn new<K: Hash + Eq + Any>(key: K) -> Self {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        Self {
+            hash: hasher.finish(),
+            key: Rc::new(key),
+            eq: |a, b| a.downcast_ref::<K>() == b.downcast_ref::<K>(),
+        }
+    }
+}
+
+impl PartialEq for ChannelKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+            && self.key.as_ref().type_id() == other.key.as_ref().type_id()
+            && (self.eq)(self.key.as_ref(), other.key.as_ref())
+    }
+}
+impl Eq for ChannelKey {}
+impl Hash for ChannelKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+impl std::fmt::Debug for ChannelKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ChannelKey({:#x})", self.hash)
+    }
+}
+
+thread_local!(
+    #[cfg(feature = "dynamic-messages")]
+    static NAMES: std::cell::RefCell<std::collections::HashSet<&'static str>> = Default::default()
+);
+
+/// Returns a `'static` copy of the name, allocating only the first time a name is seen.
+/// Diagnostics hold topic names as `&'static str`, which requires that dynamic names live forever.
+#[cfg(feature = "dynamic-messages")]
+fn intern(name: &str) -> &'static str {
+    interned(name).unwrap_or_else(|| {
+        let interned: &'static str = Box::leak(name.into());
+        NAMES.with(|names| names.borrow_mut().insert(interned));
+        interned
+    })
+}
+
+/// The copy of the name created by `intern`, if any.
+#[cfg(feature = "dynamic-messages")]
+fn interned(name: &str) -> Option<&'static str> {
+    NAMES.with(|names| names.borrow().get(name).copied())
+}
+
+impl MessageType {
+    pub(crate) fn of<T: Any>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+        }
+    }
+}
+
+impl PartialEq for MessageType {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+impl Eq for MessageType {}
+impl std::hash::Hash for MessageType {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
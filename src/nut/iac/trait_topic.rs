@@ -0,0 +1,68 @@
+//! Subscriptions to all published messages that implement a trait, see `nuts::register_trait`.
+
+use crate::nut::{diagnostics::OrReport, NUT};
+use core::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Casts a message to the trait object type `T`
+type Cast<T> = Rc<dyn Fn(&dyn Any) -> Option<&T>>;
+
+/// Message types registered with `nuts::register_trait`
+#[derive(Default)]
+pub(crate) struct TraitRegistry {
+    /// Trait object types per message type
+    traits: HashMap<TypeId, Vec<TypeId>>,
+    /// A `Cast<T>` per trait object type and message type
+    casts: HashMap<(TypeId, TypeId), Box<dyn Any>>,
+}
+
+/// Only used to fix the signature of the closure.
+fn cast_fn<T: ?Sized, F: Fn(&dyn Any) -> Option<&T>>(f: F) -> F {
+    f
+}
+
+impl TraitRegistry {
+    fn register<T: ?Sized + 'static, MSG: Any>(&mut self, cast: fn(&MSG) -> &T) {
+        let traits = self.traits.entry(TypeId::of::<MSG>()).or_default();
+        if !traits.contains(&TypeId::of::<T>()) {
+            traits.push(TypeId::of::<T>());
+        }
+        let cast: Cast<T> = Rc::new(cast_fn(move |msg| msg.downcast_ref::<MSG>().map(cast)));
+        self.casts
+            .insert((TypeId::of::<T>(), TypeId::of::<MSG>()), Box::new(cast));
+    }
+    /// The trait object types the message type has been registered for
+    pub(crate) fn traits_of(&self, message_type: TypeId) -> Vec<TypeId> {
+        self.traits.get(&message_type).cloned().unwrap_or_default()
+    }
+    fn cast<T: ?Sized + 'static>(&self, message_type: TypeId) -> Option<Cast<T>> {
+        self.casts
+            .get(&(TypeId::of::<T>(), message_type))
+            .and_then(|cast| cast.downcast_ref::<Cast<T>>())
+            .cloned()
+    }
+}
+
+pub(crate) fn register<T: ?Sized + 'static, MSG: Any>(cast: fn(&MSG) -> &T) {
+    NUT.with(|nut| {
+        nut.trait_registry
+            .try_borrow_mut()
+            .or_report()
+            .register(cast)
+    })
+}
+
+/// Calls `f` with the message as trait object, if its type has been registered for the trait.
+pub(crate) fn with_trait_object<T: ?Sized + 'static>(msg: &dyn Any, f: impl FnOnce(&T)) {
+    // The registry is not borrowed while `f` runs, it may register more types
+    let cast = NUT.with(|nut| {
+        nut.trait_registry
+            .try_borrow()
+            .or_report()
+            .cast::<T>(msg.type_id())
+    });
+    if let Some(obj) = cast.and_then(|cast| cast(msg)) {
+        f(obj)
+    }
+}
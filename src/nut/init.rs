@@ -0,0 +1,49 @@
+//! Initialization with guaranteed direct access to the nut.
+
+use crate::*;
+use core::any::Any;
+
+/// Gives access to the nut during initialization, see [`nuts::init_with`](fn.init_with.html).
+///
+/// All calls through `Setup` take effect immediately, they are never deferred.
+pub struct Setup {
+    _private: (),
+}
+
+impl Setup {
+    pub(crate) fn new() -> Self {
+        Self { _private: () }
+    }
+    /// Same as [`nuts::new_activity`](fn.new_activity.html)
+    pub fn new_activity<A: Activity>(&mut self, activity: A) -> ActivityId<A> {
+        crate::new_activity(activity)
+    }
+    /// Same as [`nuts::new_domained_activity`](fn.new_domained_activity.html)
+    pub fn new_domained_activity<A, D>(&mut self, activity: A, domain: &D) -> ActivityId<A>
+    where
+        A: Activity,
+        D: DomainEnumeration,
+    {
+        crate::new_domained_activity(activity, domain)
+    }
+    /// Same as [`nuts::new_domained_activity_checked`](fn.new_domained_activity_checked.html)
+    pub fn new_domained_activity_checked<A, D>(
+        &mut self,
+        activity: A,
+        domain: &D,
+    ) -> ActivityId<A, WithDomain>
+    where
+        A: Activity,
+        D: DomainEnumeration,
+    {
+        crate::new_domained_activity_checked(activity, domain)
+    }
+    /// Same as [`nuts::store_to_domain`](fn.store_to_domain.html)
+    pub fn store_to_domain<D, T>(&mut self, domain: &D, data: T)
+    where
+        D: DomainEnumeration,
+        T: Any,
+    {
+        crate::store_to_domain(domain, data)
+    }
+}
@@ -0,0 +1,110 @@
+//! Runtime metadata about the nut, for debugging tools.
+
+use crate::nut::activity::ActivityContainer;
+use crate::nut::iac::topic::Topic;
+use crate::nut::Nut;
+use crate::{LifecycleStatus, UncheckedActivityId};
+
+/// A snapshot of the activities, subscriptions, and domains currently registered.
+///
+/// Created by [`nuts::inspect`](fn.inspect.html).
+/// All names are type names as returned by `std::any::type_name`. Only use them for debugging purposes, they are not guaranteed to be stable.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct NutsInspection {
+    /// All activities that have not been deleted, in the order they have been created.
+    pub activities: Vec<ActivityInspection>,
+    /// Number of subscriptions per topic, identified by the name of the message type.
+    /// Sorted by name.
+    pub subscriptions: Vec<(&'static str, usize)>,
+    /// Number of events waiting in the deferred queue.
+    pub queue_len: usize,
+    /// Names of the types stored in each domain, indexed by domain.
+    pub domains: Vec<Vec<&'static str>>,
+}
+
+/// Part of [`NutsInspection`](struct.NutsInspection.html), describes a single activity.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ActivityInspection {
+    /// The ID of the activity
+    pub id: UncheckedActivityId,
+    /// The name of the activity type
+    pub type_name: &'static str,
+    /// The current lifecycle status
+    pub status: LifecycleStatus,
+}
+
+impl ActivityInspection {
+    fn new(activities: &ActivityContainer, id: UncheckedActivityId) -> Self {
+        Self {
+            id,
+            type_name: activities.type_name(id),
+            status: activities.status(id),
+        }
+    }
+}
+
+impl Nut {
+    pub(crate) fn inspect(&self) -> Option<NutsInspection> {
+        let activities = self.activities.try_borrow().ok()?;
+        let managed_state = self.managed_state.try_borrow().ok()?;
+        let activities = (1..activities.len())
+            .map(|index| activities.id(index))
+            .filter(|id| activities.is_alive(*id))
+            .map(|id| ActivityInspection::new(&activities, id))
+            .collect();
+        let mut subscriptions: Vec<_> = self
+            .subscriptions
+            .get()
+            .iter()
+            .map(|(topic, container)| (topic.name(), container.len()))
+            .collect();
+        subscriptions.sort_unstable();
+        let domains = managed_state
+            .domains()
+            .map(|domain| domain.type_names().to_vec())
+            .collect();
+        Some(NutsInspection {
+            activities,
+            subscriptions,
+            queue_len: self.deferred_events.len(),
+            domains,
+        })
+    }
+}
+
+impl Nut {
+    /// Activities with a subscription to the public or the private topic of the message type.
+    pub(crate) fn subscribers_of(
+        &self,
+        public: &Topic,
+        private: &Topic,
+    ) -> Option<Vec<ActivityInspection>> {
+        let activities = self.activities.try_borrow().ok()?;
+        let subscriptions = self.subscriptions.get();
+        let shared = subscriptions
+            .get(public)
+            .into_iter()
+            .flat_map(|container| container.shared_subscriptions());
+        let mut private: Vec<_> = subscriptions
+            .get(private)
+            .into_iter()
+            .flat_map(|container| container.private_subscriptions())
+            .collect();
+        private.sort_by_key(|sub| sub.activity.index);
+        let mut ids: Vec<UncheckedActivityId> = Vec::new();
+        for sub in shared.chain(private) {
+            // Subscriptions without activity belong to index 0
+            if sub.activity.index != 0 && !ids.contains(&sub.activity) {
+                ids.push(sub.activity);
+            }
+        }
+        Some(
+            ids.into_iter()
+                .filter(|id| activities.is_alive(*id))
+                .map(|id| ActivityInspection::new(&activities, id))
+                .collect(),
+        )
+    }
+}
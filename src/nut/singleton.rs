@@ -0,0 +1,95 @@
+//! App-global objects that belong to no domain, see `nuts::singleton`.
+
+use super::{diagnostics::OrReport, Nut, NUT};
+use core::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type SingletonInit = Box<dyn FnOnce() -> Box<dyn Any>>;
+
+enum SingletonSlot {
+    Pending(SingletonInit),
+    Initializing,
+    Ready(Box<dyn Any>),
+}
+
+/// All registered singletons, in registration order
+#[derive(Default)]
+pub(crate) struct Singletons {
+    slots: Vec<(TypeId, Rc<RefCell<SingletonSlot>>)>,
+}
+
+impl Singletons {
+    fn get(&self, t: TypeId) -> Option<Rc<RefCell<SingletonSlot>>> {
+        self.slots
+            .iter()
+            .find(|(id, _)| *id == t)
+            .map(|(_, slot)| slot.clone())
+    }
+}
+
+impl Drop for Singletons {
+    /// Singletons are dropped in reverse order of registration, so they may depend on those registered before them.
+    fn drop(&mut self) {
+        while let Some(slot) = self.slots.pop() {
+            drop(slot);
+        }
+    }
+}
+
+impl Nut {
+    fn singleton_slot<T: Any>(&self) -> Option<Rc<RefCell<SingletonSlot>>> {
+        self.singletons
+            .try_borrow()
+            .or_report()
+            .get(TypeId::of::<T>())
+    }
+}
+
+pub(crate) fn register<T: Any>(init: impl FnOnce() -> T + 'static) -> bool {
+    NUT.with(|nut| {
+        let mut singletons = nut.singletons.try_borrow_mut().or_report();
+        if singletons.get(TypeId::of::<T>()).is_some() {
+            return false;
+        }
+        let init: SingletonInit = Box::new(move || Box::new(init()));
+        singletons.slots.push((
+            TypeId::of::<T>(),
+            Rc::new(RefCell::new(SingletonSlot::Pending(init))),
+        ));
+        true
+    })
+}
+
+pub(crate) fn with<T: Any, R>(f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    // No borrow of the nut is held while the singleton is initialized or accessed
+    let slot = NUT.with(Nut::singleton_slot::<T>)?;
+    let pending = match &*slot.borrow() {
+        SingletonSlot::Pending(_) => true,
+        SingletonSlot::Initializing => panic!(
+            "Singleton {} accessed during its own initialization",
+            std::any::type_name::<T>()
+        ),
+        SingletonSlot::Ready(_) => false,
+    };
+    if pending {
+        let init = match slot.replace(SingletonSlot::Initializing) {
+            SingletonSlot::Pending(init) => init,
+            _ => panic!("{}", super::IMPOSSIBLE_ERR_MSG),
+        };
+        let value = init();
+        *slot.borrow_mut() = SingletonSlot::Ready(value);
+    }
+    let mut slot = slot.try_borrow_mut().unwrap_or_else(|_| {
+        panic!(
+            "Singleton {} is already borrowed",
+            std::any::type_name::<T>()
+        )
+    });
+    match &mut *slot {
+        SingletonSlot::Ready(value) => {
+            Some(f(value.downcast_mut().expect(super::IMPOSSIBLE_ERR_MSG)))
+        }
+        _ => panic!("{}", super::IMPOSSIBLE_ERR_MSG),
+    }
+}
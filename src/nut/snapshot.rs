@@ -0,0 +1,146 @@
+//! Saving and restoring domain values, compiled in with the `serde` feature.
+
+use super::{diagnostics::OrReport, NUT};
+use core::any::{Any, TypeId};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serialized values, the outer index is the domain index.
+type SnapshotData = Vec<Vec<(String, Vec<u8>)>>;
+
+/// A domain value type registered with `nuts::register_serializable`
+struct SerializableType {
+    type_id: TypeId,
+    /// The name given on registration, which identifies values in a snapshot
+    name: &'static str,
+    serialize: fn(&dyn Any) -> bincode::Result<Vec<u8>>,
+    deserialize: fn(&[u8]) -> bincode::Result<Box<dyn Any>>,
+}
+
+/// All types registered with `nuts::register_serializable`
+#[derive(Default)]
+pub(crate) struct SerializableTypes {
+    types: Vec<SerializableType>,
+}
+
+/// Returned by [`nuts::snapshot_domains`](fn.snapshot_domains.html) and [`nuts::restore_domains`](fn.restore_domains.html).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// Called inside a subscription handler, where domains are not accessible.
+    InsideHandler,
+    /// A value could not be serialized, or the snapshot could not be deserialized.
+    Encoding(bincode::Error),
+    /// The snapshot contains values under a name that has not been registered with `nuts::register_serializable`.
+    UnknownType(String),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InsideHandler => write!(
+                f,
+                "domains are not accessible inside a subscription handler"
+            ),
+            Self::Encoding(e) => write!(f, "invalid domain snapshot: {}", e),
+            Self::UnknownType(name) => write!(
+                f,
+                "domain snapshot contains values of type {:?}, which has not been registered",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InsideHandler | Self::UnknownType(_) => None,
+            Self::Encoding(e) => Some(e),
+        }
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self {
+        Self::Encoding(e)
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+pub(crate) fn serialize<T: Any + Serialize>(obj: &dyn Any) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(obj.downcast_ref::<T>().unwrap())
+}
+
+fn deserialize<T: Any + DeserializeOwned>(bytes: &[u8]) -> bincode::Result<Box<dyn Any>> {
+    Ok(Box::new(bincode::deserialize::<T>(bytes)?))
+}
+
+pub(crate) fn register<T: Any + Serialize + DeserializeOwned>(name: &'static str) -> bool {
+    NUT.with(|nut| {
+        let mut registry = nut.serializable_types.try_borrow_mut().or_report();
+        if registry
+            .types
+            .iter()
+            .any(|t| t.type_id == TypeId::of::<T>() || t.name == name)
+        {
+            return false;
+        }
+        registry.types.push(SerializableType {
+            type_id: TypeId::of::<T>(),
+            name,
+            serialize: serialize::<T>,
+            deserialize: deserialize::<T>,
+        });
+        true
+    })
+}
+
+pub(crate) fn snapshot() -> Result<Vec<u8>, SnapshotError> {
+    NUT.with(|nut| {
+        let managed_state = nut
+            .managed_state
+            .try_borrow()
+            .map_err(|_| SnapshotError::InsideHandler)?;
+        let registry = nut.serializable_types.try_borrow().or_report();
+        let mut data = SnapshotData::new();
+        for domain in managed_state.domains() {
+            let mut values = Vec::new();
+            for t in &registry.types {
+                if let Some(obj) = domain.get_unchecked(t.type_id) {
+                    values.push((t.name.to_owned(), (t.serialize)(obj)?));
+                }
+            }
+            data.push(values);
+        }
+        Ok(bincode::serialize(&data)?)
+    })
+}
+
+pub(crate) fn restore(bytes: &[u8]) -> Result<(), SnapshotError> {
+    NUT.with(|nut| {
+        let mut managed_state = nut
+            .managed_state
+            .try_borrow_mut()
+            .map_err(|_| SnapshotError::InsideHandler)?;
+        let registry = nut.serializable_types.try_borrow().or_report();
+        let data: SnapshotData = bincode::deserialize(bytes)?;
+        // Decode everything before the first store, so that a broken snapshot leaves the domains untouched
+        let mut decoded = Vec::new();
+        for (index, values) in data.iter().enumerate() {
+            for (name, value) in values {
+                let t = registry
+                    .types
+                    .iter()
+                    .find(|t| t.name == name)
+                    .ok_or_else(|| SnapshotError::UnknownType(name.clone()))?;
+                decoded.push((index, t, (t.deserialize)(value)?));
+            }
+        }
+        for (index, t, obj) in decoded {
+            managed_state
+                .get_or_prepare_index(index)
+                .store_unchecked(t.type_id, obj, t.name);
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,156 @@
+//! Forwarding published messages to and from a Web Worker, compiled in with the `web-worker` feature.
+
+use super::iac::publish::BroadcastInfo;
+use super::iac::topic::Topic;
+use super::{diagnostics::OrReport, Nut, NUT};
+use core::any::{Any, TypeId};
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+/// A message type registered with `nuts::bridge`
+struct BridgedType {
+    type_id: TypeId,
+    name: &'static str,
+    serialize: fn(&dyn Any) -> bincode::Result<Vec<u8>>,
+    receive: fn(&[u8]) -> bincode::Result<BroadcastInfo>,
+}
+
+type OnMessage = Closure<dyn FnMut(MessageEvent)>;
+
+/// The other side of the bridge
+enum Port {
+    /// Used on the main thread
+    Worker(Worker),
+    /// Used inside the worker
+    Main(DedicatedWorkerGlobalScope),
+}
+
+impl Port {
+    fn post_message(&self, msg: &JsValue) -> Result<(), JsValue> {
+        match self {
+            Self::Worker(worker) => worker.post_message(msg),
+            Self::Main(scope) => scope.post_message(msg),
+        }
+    }
+    fn set_onmessage(&self, f: Option<&js_sys::Function>) {
+        match self {
+            Self::Worker(worker) => worker.set_onmessage(f),
+            Self::Main(scope) => scope.set_onmessage(f),
+        }
+    }
+}
+
+/// Registered types and the connection, if any
+#[derive(Default)]
+pub(crate) struct Bridge {
+    types: Vec<BridgedType>,
+    connection: Option<(Port, OnMessage)>,
+}
+
+impl Drop for Bridge {
+    /// The callback must not outlive its closure.
+    fn drop(&mut self) {
+        if let Some((port, _)) = &self.connection {
+            port.set_onmessage(None);
+        }
+    }
+}
+
+fn receive<T: Any + DeserializeOwned>(bytes: &[u8]) -> bincode::Result<BroadcastInfo> {
+    let msg = bincode::deserialize::<T>(bytes)?;
+    Ok(BroadcastInfo::global(msg, Topic::public_message::<T>()))
+}
+
+impl Nut {
+    /// Sends a published message of a bridged type to the other side.
+    pub(crate) fn forward_to_worker(&self, broadcast: &BroadcastInfo) {
+        let msg = match broadcast.published_message() {
+            Some(msg) => msg,
+            None => return,
+        };
+        let bridge = self.bridge.try_borrow().or_report();
+        let port = match &bridge.connection {
+            Some((port, _)) => port,
+            None => return,
+        };
+        if let Some(t) = bridge.types.iter().find(|t| t.type_id == msg.type_id()) {
+            let sent = (t.serialize)(msg)
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+                .and_then(|bytes| {
+                    let data = js_sys::Array::of2(
+                        &JsValue::from_str(t.name),
+                        &js_sys::Uint8Array::from(&bytes[..]),
+                    );
+                    port.post_message(&data)
+                });
+            if let Err(_e) = sent {
+                debug_print!("Dropped message {} for the worker: {:?}", t.name, _e);
+            }
+        }
+    }
+    /// Publishes a message received from the other side, without forwarding it back.
+    fn receive_from_worker(&self, data: &JsValue) {
+        let data: &js_sys::Array = match data.dyn_ref() {
+            Some(array) => array,
+            None => return,
+        };
+        let name = data.get(0).as_string().unwrap_or_default();
+        let bytes = js_sys::Uint8Array::new(&data.get(1)).to_vec();
+        let broadcast = {
+            let bridge = self.bridge.try_borrow().or_report();
+            match bridge.types.iter().find(|t| t.name == name) {
+                Some(t) => (t.receive)(&bytes),
+                None => return,
+            }
+        };
+        match broadcast {
+            Ok(broadcast) => {
+                self.push_deferred(broadcast.into());
+                self.catch_up_deferred_to_quiescence();
+            }
+            Err(_e) => {
+                debug_print!("Dropped message {} from the worker: {}", name, _e);
+            }
+        }
+    }
+}
+
+pub(crate) fn register<T: Any + Serialize + DeserializeOwned>() -> bool {
+    NUT.with(|nut| {
+        let mut bridge = nut.bridge.try_borrow_mut().or_report();
+        if bridge.types.iter().any(|t| t.type_id == TypeId::of::<T>()) {
+            return false;
+        }
+        bridge.types.push(BridgedType {
+            type_id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+            serialize: super::snapshot::serialize::<T>,
+            receive: receive::<T>,
+        });
+        true
+    })
+}
+
+fn connect(port: Port) {
+    let on_message = Closure::wrap(Box::new(|event: MessageEvent| {
+        NUT.with(|nut| nut.receive_from_worker(&event.data()))
+    }) as Box<dyn FnMut(MessageEvent)>);
+    NUT.with(|nut| {
+        let mut bridge = nut.bridge.try_borrow_mut().or_report();
+        // Cleared before the new handler is installed, the old port may be the same worker
+        if let Some((old, _)) = bridge.connection.take() {
+            old.set_onmessage(None);
+        }
+        port.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        bridge.connection = Some((port, on_message));
+    })
+}
+
+pub(crate) fn connect_worker(worker: Worker) {
+    connect(Port::Worker(worker))
+}
+
+pub(crate) fn connect_main_thread(scope: DedicatedWorkerGlobalScope) {
+    connect(Port::Main(scope))
+}
@@ -1,11 +1,18 @@
 mod base_tests;
+mod context_tests;
+#[cfg(feature = "derive")]
+mod derive_tests;
 mod domain_tests;
 mod inchoate_tests;
 mod lifecycle_tests;
+mod response_tests;
 
 use crate::*;
 use std::cell::Cell;
+use std::future::Future;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
 
 #[derive(Clone)]
 struct TestActivity {
@@ -37,5 +44,21 @@ domain_enum!(TestDomains);
 
 struct TestUpdateMsg;
 struct TestForInt(usize);
+#[derive(Clone)]
 struct TestMessage(u32);
 struct TestMessageNoClone;
+
+/// Records values in test handlers, for later inspection.
+type Log<T> = Rc<std::cell::RefCell<Vec<T>>>;
+
+struct NoopWaker;
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Polls a future exactly once, without an executor.
+fn poll_once<F: Future + ?Sized>(f: std::pin::Pin<&mut F>) -> Poll<F::Output> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    f.poll(&mut cx)
+}
@@ -43,7 +43,7 @@ fn domained_activity() {
     let a = TestActivity::new();
     let d = TestDomains::DomainA;
     crate::store_to_domain(&d, 7usize);
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.subscribe_domained(|_activity, domain, _msg: &TestUpdateMsg| {
         let x: usize = *domain.get();
         assert_eq!(7, x);
@@ -89,7 +89,7 @@ fn owned_domained_message() {
     let counter = a.shared_counter_ref();
     let d = TestDomains::DomainA;
     crate::store_to_domain(&d, 7usize);
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.private_domained_channel(|activity, domain, _msg: TestMessageNoClone| {
         let x: usize = *domain.get();
         assert_eq!(7, x);
@@ -180,3 +180,1718 @@ fn multi_subscriber_private_message() {
     crate::send_to::<TestActivity, _>(TestMessageNoClone);
     assert_eq!(1, counter.get()); // Make sure subscription of correct type has been called exactly once
 }
+
+#[test]
+fn subscribe_variant() {
+    enum TestEvent {
+        Add(u32),
+        Ignore,
+    }
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_variant(
+        |event: &TestEvent| match event {
+            TestEvent::Add(n) => Some(n),
+            TestEvent::Ignore => None,
+        },
+        |activity, n| activity.inc(*n),
+    );
+    crate::publish(TestEvent::Add(3));
+    crate::publish(TestEvent::Ignore);
+    crate::publish(TestEvent::Add(4));
+    assert_eq!(7, counter.get());
+}
+
+#[test]
+fn keyed_private_channels() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.private_channel(|activity, _msg: TestMessageNoClone| activity.inc(1));
+    id.private_channel_keyed("a", |activity, _msg: TestMessageNoClone| activity.inc(10));
+    id.private_channel_keyed("b", |activity, _msg: TestMessageNoClone| activity.inc(100));
+    crate::send_to_keyed::<TestActivity, _, _>("b", TestMessageNoClone);
+    assert_eq!(100, counter.get());
+    crate::send_to_keyed::<TestActivity, _, _>("a", TestMessageNoClone);
+    assert_eq!(110, counter.get());
+    crate::send_to::<TestActivity, _>(TestMessageNoClone);
+    assert_eq!(111, counter.get());
+    crate::send_to_keyed::<TestActivity, _, _>("c", TestMessageNoClone);
+    assert_eq!(111, counter.get());
+    // Keys are compared by value and type, not by hash
+    id.private_channel_keyed(1u32, |activity, _msg: TestMessageNoClone| {
+        activity.inc(1000)
+    });
+    crate::send_to_keyed::<TestActivity, _, _>(1u64, TestMessageNoClone);
+    crate::send_to_keyed::<TestActivity, _, _>(String::from("a"), TestMessageNoClone);
+    assert_eq!(111, counter.get());
+    crate::send_to_keyed::<TestActivity, _, _>(1u32, TestMessageNoClone);
+    assert_eq!(1111, counter.get());
+}
+
+#[test]
+fn activity_instances() {
+    let a = TestActivity::new();
+    let b = TestActivity::new();
+    let counter_a = a.shared_counter_ref();
+    let counter_b = b.shared_counter_ref();
+    let id_a = crate::new_activity(a);
+    let id_b = crate::new_activity(b);
+    for id in [id_a, id_b] {
+        id.private_channel(|activity, msg: TestForInt| activity.inc(msg.0 as u32));
+    }
+    assert_eq!(
+        Some(vec![id_a.into(), id_b.into()]),
+        crate::instances_of::<TestActivity>()
+    );
+
+    id_b.private_message(TestForInt(2));
+    crate::send_to_instance(id_b.into(), TestForInt(3));
+    crate::send_to::<TestActivity, _>(TestForInt(1));
+    assert_eq!(1, counter_a.get());
+    assert_eq!(5, counter_b.get());
+
+    // A queued message keeps its receiver when an earlier instance is deleted
+    crate::new_activity(()).subscribe(move |_, _: &TestUpdateMsg| {
+        id_b.private_message(TestForInt(2));
+        id_a.set_status(LifecycleStatus::Deleted);
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(
+        Some(vec![id_b.into()]),
+        crate::instances_of::<TestActivity>()
+    );
+    assert_eq!(1, counter_a.get());
+    assert_eq!(7, counter_b.get());
+}
+
+#[test]
+fn with_activity_recovers_from_panic() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, _: &TestUpdateMsg| activity.inc(1));
+    let result = std::panic::catch_unwind(|| {
+        crate::with_activity(|_: &mut TestActivity| panic!("closure failed"))
+    });
+    assert!(result.is_err());
+    assert_eq!(Ok(()), crate::publish_sync(TestUpdateMsg));
+    assert_eq!(1, counter.get());
+}
+
+#[test]
+fn keyed_activities() {
+    let id = crate::new_activity_keyed("counter", TestActivity::new());
+    assert_eq!(Some(id.into()), crate::lookup_key("counter"));
+    assert_eq!(None, crate::lookup_key("other"));
+
+    let result = std::panic::catch_unwind(|| crate::new_activity_keyed("counter", ()));
+    assert!(result.is_err());
+
+    id.set_status(LifecycleStatus::Deleted);
+    assert_eq!(None, crate::lookup_key("counter"));
+    let new_id = crate::new_activity_keyed("counter", ());
+    assert_eq!(Some(new_id.into()), crate::lookup_key("counter"));
+}
+
+#[test]
+#[cfg(feature = "dynamic-messages")]
+fn dynamic_messages() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_dynamic("add", |activity, value| {
+        activity.inc(value.as_u64().unwrap_or_default() as u32)
+    });
+    id.subscribe(|activity, _: &serde_json::Value| activity.inc(100));
+    crate::publish_dynamic("add", serde_json::json!(3));
+    crate::publish_dynamic("other", serde_json::json!(5));
+    assert_eq!(3, counter.get());
+    // Only subscribed names are kept
+    use crate::nut::iac::topic::Topic;
+    assert!(Topic::published_dynamic_message("add").is_some());
+    assert!(Topic::published_dynamic_message("other").is_none());
+    let subscriptions = crate::inspect()
+        .expect("not inside a handler")
+        .subscriptions;
+    assert!(subscriptions.contains(&("add", 1)));
+}
+
+#[test]
+fn drain_and_republish() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    let drained: Log<TestMessage> = Default::default();
+    let drained_clone = drained.clone();
+    id.subscribe(move |_, _: &TestUpdateMsg| {
+        crate::publish(TestMessage(1));
+        crate::publish(TestMessage(2));
+        drained_clone
+            .borrow_mut()
+            .extend(crate::drain_topic::<TestMessage>());
+    });
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+
+    crate::publish(TestUpdateMsg);
+    assert_eq!(0, counter.get());
+    assert_eq!(2, drained.borrow().len());
+
+    crate::publish_iter(drained.borrow_mut().drain(..));
+    assert_eq!(3, counter.get());
+}
+
+#[test]
+fn drain_undelivered() {
+    crate::keep_undelivered::<TestMessage>();
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(2));
+    // Not kept
+    crate::publish(TestForInt(3));
+    assert!(crate::drain_topic::<TestForInt>().is_empty());
+
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    // Delivered messages are not kept
+    crate::publish(TestMessage(4));
+    assert_eq!(4, counter.get());
+
+    let pending = crate::drain_topic::<TestMessage>();
+    assert_eq!(
+        vec![1, 2],
+        pending.iter().map(|msg| msg.0).collect::<Vec<_>>()
+    );
+    assert!(crate::drain_topic::<TestMessage>().is_empty());
+    crate::publish_iter(pending);
+    assert_eq!(7, counter.get());
+}
+
+#[test]
+fn unsubscribe() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    let sub = id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.subscribe(|activity, _msg: &TestMessage| activity.inc(100));
+    crate::publish(TestMessage(1));
+    assert_eq!(101, counter.get());
+    id.unsubscribe(sub);
+    crate::publish(TestMessage(1));
+    assert_eq!(201, counter.get());
+}
+
+#[test]
+fn unsubscribe_inside_publish() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    let sub = id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.subscribe(move |_, _msg: &TestUpdateMsg| {
+        id.unsubscribe(sub.clone());
+        // Removal is deferred but still takes effect before this message
+        crate::publish(TestMessage(1));
+    });
+    crate::publish(TestUpdateMsg);
+    crate::publish(TestMessage(1));
+    assert_eq!(0, counter.get());
+}
+
+#[test]
+fn heartbeat() {
+    use std::time::Duration;
+    thread_local!(static NOW: Cell<u64> = const { Cell::new(0) });
+    fn clock() -> Duration {
+        Duration::from_millis(NOW.with(Cell::get))
+    }
+
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    let beats: Log<NutsHeartbeat> = Default::default();
+    let beats_clone = beats.clone();
+    crate::subscribe(move |beat: &NutsHeartbeat| beats_clone.borrow_mut().push(beat.clone()));
+
+    crate::enable_heartbeat(Duration::from_millis(100), clock);
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(1));
+    assert!(beats.borrow().is_empty());
+
+    NOW.with(|now| now.set(150));
+    crate::publish(TestMessage(1));
+    assert_eq!(3, counter.get());
+    assert_eq!(1, beats.borrow().len());
+    let beat = beats.borrow()[0].clone();
+    assert_eq!(Duration::from_millis(150), beat.elapsed);
+    assert_eq!(1, beat.topics.len());
+    assert!(beat.topics[0].0.ends_with("TestMessage"));
+    assert_eq!(3, beat.topics[0].1);
+    assert_eq!(vec![(UncheckedActivityId::from(id), 3)], beat.activities);
+
+    crate::disable_heartbeat();
+    NOW.with(|now| now.set(300));
+    crate::publish(TestMessage(1));
+    assert_eq!(1, beats.borrow().len());
+}
+
+#[test]
+fn contract() {
+    crate::assert_nuts_semantics!();
+}
+
+#[test]
+fn step_through_queue() {
+    use crate::test_support;
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| {
+        activity.inc(msg.0);
+        crate::publish(TestUpdateMsg);
+    });
+    id.subscribe(|activity, _: &TestUpdateMsg| activity.inc(10));
+
+    test_support::set_stepping(true);
+    while test_support::step() {}
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(2));
+    let pending = test_support::pending_events();
+    assert_eq!(2, pending.len());
+    assert_eq!("broadcast", pending[0].kind);
+    assert_eq!(
+        Some(std::any::type_name::<TestMessage>()),
+        pending[0].message
+    );
+    assert_eq!(0, counter.get());
+
+    assert!(test_support::step());
+    assert_eq!(1, counter.get());
+    assert_eq!(
+        Err(ExecError::Stepping),
+        crate::publish_sync(TestMessage(100))
+    );
+    let pending = test_support::pending_events();
+    assert_eq!(
+        vec![
+            Some(std::any::type_name::<TestMessage>()),
+            Some(std::any::type_name::<TestUpdateMsg>())
+        ],
+        pending.iter().map(|e| e.message).collect::<Vec<_>>()
+    );
+
+    test_support::set_stepping(false);
+    assert!(test_support::pending_events().is_empty());
+    assert!(!test_support::step());
+    assert_eq!(23, counter.get());
+}
+
+#[test]
+#[cfg(feature = "metrics")]
+fn metrics() {
+    use core::any::TypeId;
+    let a = TestActivity::new();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.subscribe(|activity, _: &TestUpdateMsg| activity.inc(1));
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(1));
+    crate::publish(TestUpdateMsg);
+
+    let metrics = crate::metrics();
+    let messages = &metrics.messages[&TypeId::of::<TestMessage>()];
+    assert!(messages.type_name.ends_with("TestMessage"));
+    assert_eq!(2, messages.count);
+    assert_eq!(1, metrics.messages[&TypeId::of::<TestUpdateMsg>()].count);
+    let handlers = &metrics.handlers[&UncheckedActivityId::from(id)];
+    assert!(handlers.type_name.ends_with("TestActivity"));
+    assert_eq!(3, handlers.calls);
+
+    crate::reset();
+    assert!(crate::metrics().messages.is_empty());
+}
+
+#[test]
+fn publish_from_other_thread() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.private_channel(|activity, msg: TestForInt| activity.inc(msg.0 as u32 * 100));
+
+    let proxy = crate::remote_handle();
+    std::thread::spawn(move || {
+        proxy.publish(TestMessage(1));
+        proxy.send_to::<TestActivity, _>(TestForInt(1));
+    })
+    .join()
+    .expect("thread panicked");
+    assert_eq!(0, counter.get());
+    crate::drain_remote();
+    assert_eq!(101, counter.get());
+
+    // Picked up by the next local publish
+    let proxy = crate::remote_handle();
+    std::thread::spawn(move || proxy.publish(TestMessage(1)))
+        .join()
+        .expect("thread panicked");
+    crate::publish(TestMessage(10));
+    assert_eq!(112, counter.get());
+}
+
+#[test]
+fn init_with() {
+    assert!(!crate::is_initialized());
+    let (id, counter) = crate::init_with(|setup| {
+        setup.store_to_domain(&TestDomains::DomainA, 5u32);
+        let a = TestActivity::new();
+        let counter = a.shared_counter_ref();
+        (
+            setup.new_domained_activity_checked(a, &TestDomains::DomainA),
+            counter,
+        )
+    });
+    assert!(crate::is_initialized());
+    id.subscribe_domained(|activity, domain, _msg: &TestUpdateMsg| {
+        activity.inc(*domain.get::<u32>())
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(5, counter.get());
+}
+
+#[test]
+#[should_panic]
+fn init_with_twice() {
+    crate::init_with(|_| {});
+    crate::init_with(|_| {});
+}
+
+#[test]
+fn subscription_priority() {
+    let order: Log<u32> = Default::default();
+    let ids: Vec<_> = (0..3).map(|_| crate::new_activity(())).collect();
+    for (i, priority) in [(1, 0), (2, 5), (3, 0), (4, -1), (5, 5)] {
+        let order = order.clone();
+        let filter = SubscriptionFilter::default().with_priority(priority);
+        ids[i as usize % 3].subscribe_masked(filter, move |_, _: &TestUpdateMsg| {
+            order.borrow_mut().push(i)
+        });
+    }
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![2, 5, 1, 3, 4], *order.borrow());
+}
+
+#[test]
+fn subscribe_any() {
+    let seen: Log<(bool, Option<u32>)> = Default::default();
+    let seen_clone = seen.clone();
+    crate::subscribe_any(move |meta, msg| {
+        let value = msg.downcast_ref::<TestMessage>().map(|m| m.0);
+        seen_clone.borrow_mut().push((meta.is_private(), value));
+    });
+    let id = crate::new_activity(TestActivity::new());
+    id.private_channel(|_, _: TestMessage| {});
+
+    crate::publish(TestMessage(1));
+    crate::publish(TestUpdateMsg);
+    id.private_message(TestMessage(2));
+    assert_eq!(
+        vec![(false, Some(1)), (false, None), (true, Some(2))],
+        *seen.borrow()
+    );
+}
+
+#[test]
+fn mailbox_stats() {
+    let a = TestActivity::new();
+    let id = crate::new_activity(a);
+    id.private_channel(|_, _: TestMessage| {});
+    let lens: Log<(usize, usize, MailboxStats)> = Default::default();
+    let lens_clone = lens.clone();
+    crate::subscribe(move |_: &TestUpdateMsg| {
+        id.private_message(TestMessage(1));
+        crate::send_to::<TestActivity, _>(TestMessage(2));
+        id.private_message(TestForInt(3));
+        lens_clone.borrow_mut().push((
+            id.mailbox_len::<TestMessage>(),
+            id.mailbox_len::<TestForInt>(),
+            id.mailbox_stats(),
+        ));
+    });
+    crate::publish(TestUpdateMsg);
+    let stats = MailboxStats {
+        queued: 3,
+        processed: 0,
+        dropped: 0,
+    };
+    assert_eq!(vec![(2, 1, stats)], *lens.borrow());
+    let stats = MailboxStats {
+        queued: 0,
+        processed: 2,
+        dropped: 1,
+    };
+    assert_eq!(stats, id.mailbox_stats());
+}
+
+#[test]
+fn replace_activity_keeps_groups() {
+    let log: Log<(&str, u32)> = Default::default();
+    let id = crate::new_activity(1u32);
+    let filter = SubscriptionFilter::default().in_group("render");
+    let log_clone = log.clone();
+    id.subscribe_masked(filter, move |n, _: &TestUpdateMsg| {
+        log_clone.borrow_mut().push(("render", *n))
+    });
+    let log_clone = log.clone();
+    id.subscribe(move |n, _: &TestUpdateMsg| log_clone.borrow_mut().push(("plain", *n)));
+    crate::publish(TestUpdateMsg);
+    id.replace(2, &["render"]);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(
+        vec![("render", 1), ("plain", 1), ("render", 2)],
+        *log.borrow()
+    );
+}
+
+#[test]
+fn hot_swap_activity() {
+    let log: Log<u32> = Default::default();
+    let id = crate::new_activity(1u32);
+    let log_clone = log.clone();
+    id.subscribe(move |n, _: &TestUpdateMsg| log_clone.borrow_mut().push(*n));
+    id.set_status(LifecycleStatus::Inactive);
+    assert_eq!(Ok(1), id.replace_activity(2));
+
+    crate::publish(TestUpdateMsg);
+    id.set_status(LifecycleStatus::Active);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![2], *log.borrow());
+
+    let log_clone = log.clone();
+    id.subscribe(move |_, _: &TestForInt| {
+        log_clone.borrow_mut().push(0);
+        assert_eq!(Err(ExecError::InsideHandler), id.replace_activity(3));
+    });
+    crate::publish(TestForInt(0));
+    id.set_status(LifecycleStatus::Deleted);
+    assert_eq!(Err(ExecError::MissingActivity), id.replace_activity(4));
+    assert_eq!(vec![2, 0], *log.borrow());
+}
+
+#[test]
+fn read_activity() {
+    assert_eq!(
+        None,
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    counter.set(3);
+    assert_eq!(
+        Some(3),
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+
+    let inside: Log<Option<u32>> = Default::default();
+    let inside_clone = inside.clone();
+    id.subscribe(move |_, _: &TestUpdateMsg| {
+        inside_clone
+            .borrow_mut()
+            .push(crate::read_activity(|a: &TestActivity| a.counter.get()))
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![None], *inside.borrow());
+}
+
+#[test]
+fn subscribe_owned() {
+    let received: Log<Vec<u32>> = Default::default();
+    for _ in 0..2 {
+        let received = received.clone();
+        let id = crate::new_activity(());
+        id.subscribe_owned(move |_, mut msg: Vec<u32>| {
+            msg.push(received.borrow().len() as u32);
+            received.borrow_mut().push(msg);
+        });
+    }
+    crate::publish(vec![7u32]);
+    assert_eq!(vec![vec![7, 0], vec![7, 1]], *received.borrow());
+}
+
+#[test]
+fn subscribe_filtered() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_filtered(
+        |msg: &TestMessage| msg.0 > 2,
+        |activity, msg: &TestMessage| activity.inc(msg.0),
+    );
+    for i in 1..=4 {
+        crate::publish(TestMessage(i));
+    }
+    assert_eq!(7, counter.get());
+}
+
+#[test]
+fn retain_last() {
+    crate::retain_last::<TestMessage>(2);
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    for i in 1..=3 {
+        crate::publish(TestMessage(i));
+    }
+    assert_eq!(6, counter.get());
+
+    // Only the new subscription receives the last two messages
+    let late: Log<u32> = Default::default();
+    let late_clone = late.clone();
+    id.subscribe(move |_, msg: &TestMessage| late_clone.borrow_mut().push(msg.0));
+    assert_eq!(vec![2, 3], *late.borrow());
+    assert_eq!(6, counter.get());
+
+    crate::retain_last::<TestMessage>(0);
+    let late_clone = late.clone();
+    id.subscribe(move |_, msg: &TestMessage| late_clone.borrow_mut().push(msg.0));
+    assert_eq!(vec![2, 3], *late.borrow());
+}
+
+#[test]
+fn phased_publish() {
+    let log: Log<&str> = Default::default();
+    let id = crate::new_activity(());
+    let log_clone = log.clone();
+    id.subscribe_masked(
+        SubscriptionFilter::default().in_phase(Phase::Draw),
+        move |_, _: &TestUpdateMsg| log_clone.borrow_mut().push("draw"),
+    );
+    let log_clone = log.clone();
+    id.subscribe_masked(
+        SubscriptionFilter::default().in_phase(Phase::Update),
+        move |_, _: &TestUpdateMsg| {
+            log_clone.borrow_mut().push("update");
+            crate::publish(TestMessage(0));
+        },
+    );
+    let log_clone = log.clone();
+    id.subscribe(move |_, _: &TestMessage| log_clone.borrow_mut().push("caused by update"));
+
+    id.subscribe(|_, _: &TestForInt| {
+        crate::publish_phased(Phase::Draw, TestUpdateMsg);
+        crate::publish_phased(Phase::Update, TestUpdateMsg);
+    });
+
+    crate::publish(TestForInt(0));
+    assert_eq!(vec!["update", "caused by update", "draw"], *log.borrow());
+
+    log.borrow_mut().clear();
+    crate::set_phase_order(&[Phase::Draw, Phase::Update]);
+    crate::publish(TestForInt(0));
+    assert_eq!(vec!["draw", "update", "caused by update"], *log.borrow());
+}
+
+#[test]
+fn singletons() {
+    struct Dropped(&'static str, Log<&'static str>);
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+    struct Second(Dropped);
+
+    let log: Log<&str> = Default::default();
+    let log_clone = log.clone();
+    assert!(crate::singleton(move || {
+        log_clone.borrow_mut().push("init first");
+        Dropped("first", log_clone.clone())
+    }));
+    let log_clone = log.clone();
+    assert!(crate::singleton(move || {
+        // Initialization may access other singletons
+        crate::with_singleton(|first: &mut Dropped| first.0);
+        Second(Dropped("second", log_clone))
+    }));
+    assert!(crate::singleton(|| 0u32));
+    assert!(!crate::singleton(|| 1u32));
+    assert!(log.borrow().is_empty());
+
+    assert_eq!(
+        Some("second"),
+        crate::with_singleton(|s: &mut Second| s.0 .0)
+    );
+    assert_eq!(vec!["init first"], *log.borrow());
+    assert_eq!(Some(0), crate::with_singleton(|n: &mut u32| *n));
+    assert_eq!(None, crate::with_singleton(|_: &mut i32| ()));
+
+    // Dropped in reverse order of registration
+    crate::reset();
+    assert_eq!(vec!["init first", "second", "first"], *log.borrow());
+}
+
+#[test]
+fn idle_handlers() {
+    let log: Log<&str> = Default::default();
+    let log_clone = log.clone();
+    crate::on_idle(move || {
+        log_clone.borrow_mut().push("idle");
+        crate::publish(TestUpdateMsg);
+    });
+    let log_clone = log.clone();
+    crate::subscribe(move |_: &TestUpdateMsg| {
+        log_clone.borrow_mut().push("update");
+        assert!(!crate::run_idle());
+    });
+    crate::publish(TestUpdateMsg);
+    assert!(crate::run_idle());
+    assert_eq!(vec!["update", "idle", "update"], *log.borrow());
+}
+
+#[test]
+fn subscribe_fallible() {
+    let errors: Log<(String, &str)> = Default::default();
+    let errors_clone = errors.clone();
+    crate::subscribe(move |e: &HandlerError| {
+        errors_clone
+            .borrow_mut()
+            .push((e.error().to_string(), e.message_type_name()))
+    });
+    let id = crate::new_activity(());
+    id.subscribe_fallible(|_, msg: &TestForInt| {
+        if msg.0 > 1 {
+            Err(std::fmt::Error)
+        } else {
+            Ok(())
+        }
+    });
+    crate::publish(TestForInt(1));
+    crate::publish(TestForInt(2));
+    assert_eq!(
+        vec![(
+            std::fmt::Error.to_string(),
+            std::any::type_name::<TestForInt>()
+        )],
+        *errors.borrow()
+    );
+}
+
+#[test]
+fn recover_from_panic() {
+    crate::set_panic_policy(PanicPolicy::RecoverAndReport);
+    let reports: Log<(UncheckedActivityId, Option<String>)> = Default::default();
+    let reports_clone = reports.clone();
+    crate::subscribe(move |report: &SubscriberPanicked| {
+        reports_clone
+            .borrow_mut()
+            .push((report.activity(), report.panic_message().map(str::to_owned)))
+    });
+    let faulty = crate::new_activity(());
+    faulty.subscribe(|_, _: &TestUpdateMsg| panic!("faulty handler"));
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let healthy = crate::new_activity(a);
+    healthy.subscribe(|activity, _: &TestUpdateMsg| activity.inc(1));
+
+    crate::publish(TestUpdateMsg);
+    assert_eq!(1, counter.get());
+    assert_eq!(
+        vec![(faulty.into(), Some("faulty handler".to_owned()))],
+        *reports.borrow()
+    );
+
+    // The faulty activity has been set to inactive, everything else keeps working
+    crate::publish(TestUpdateMsg);
+    assert_eq!(2, counter.get());
+    assert_eq!(1, reports.borrow().len());
+}
+
+#[test]
+fn inspect() {
+    let d = TestDomains::DomainA;
+    crate::store_to_domain(&d, 1u32);
+    let id = crate::new_domained_activity_checked(TestActivity::new(), &d);
+    id.subscribe(|_, _: &TestUpdateMsg| {});
+    id.private_channel(|_, _: TestMessage| {});
+    crate::new_activity(()).subscribe(|_, _: &TestUpdateMsg| {
+        assert!(crate::inspect().is_none());
+    });
+    id.set_status(LifecycleStatus::Inactive);
+
+    let inspection = crate::inspect().expect("called outside of handlers");
+    let activities: Vec<_> = inspection
+        .activities
+        .iter()
+        .map(|a| (a.type_name, a.status))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                std::any::type_name::<TestActivity>(),
+                LifecycleStatus::Inactive
+            ),
+            ("()", LifecycleStatus::Active)
+        ],
+        activities
+    );
+    assert_eq!(
+        vec![
+            (std::any::type_name::<TestMessage>(), 1),
+            (std::any::type_name::<TestUpdateMsg>(), 2)
+        ],
+        inspection.subscriptions
+    );
+    assert_eq!(0, inspection.queue_len);
+    assert_eq!(vec!["u32"], inspection.domains[0]);
+    crate::publish(TestUpdateMsg);
+}
+
+#[test]
+fn tracing() {
+    let trace: Log<TraceEvent> = Default::default();
+    let trace_clone = trace.clone();
+    crate::enable_tracing(move |event| trace_clone.borrow_mut().push(*event));
+    let id = crate::new_activity(());
+    id.subscribe(|_, _: &TestUpdateMsg| crate::publish(TestForInt(1)));
+    id.subscribe(move |_, _: &TestForInt| id.set_status(LifecycleStatus::Inactive));
+    crate::publish(TestUpdateMsg);
+    crate::disable_tracing();
+    crate::publish(TestUpdateMsg);
+
+    let receiver = UncheckedActivityId::from(id);
+    assert_eq!(
+        vec![
+            TraceEvent::Publish {
+                id: 0,
+                parent: None,
+                publisher: None,
+                type_name: std::any::type_name::<TestUpdateMsg>(),
+            },
+            TraceEvent::Deliver {
+                id: 0,
+                receiver,
+                type_name: std::any::type_name::<TestUpdateMsg>(),
+            },
+            TraceEvent::Publish {
+                id: 1,
+                parent: Some(0),
+                publisher: Some(receiver),
+                type_name: std::any::type_name::<TestForInt>(),
+            },
+            TraceEvent::Defer { id: 1 },
+            TraceEvent::Deliver {
+                id: 1,
+                receiver,
+                type_name: std::any::type_name::<TestForInt>(),
+            },
+            TraceEvent::LifecycleChange {
+                id: 2,
+                parent: Some(1),
+                activity: receiver,
+                status: LifecycleStatus::Inactive,
+            },
+            TraceEvent::Defer { id: 2 },
+            TraceEvent::Publish {
+                id: 3,
+                parent: Some(2),
+                publisher: None,
+                type_name: "nuts::Leave",
+            },
+            TraceEvent::Defer { id: 3 },
+        ],
+        *trace.borrow()
+    );
+}
+
+#[test]
+fn flow_graph() {
+    crate::debug::start_flow_capture();
+    let a = crate::new_activity(TestActivity::new());
+    a.subscribe(|_, _: &TestUpdateMsg| crate::publish(TestForInt(1)));
+    let b = crate::new_activity(());
+    b.subscribe(|_, _: &TestForInt| {});
+    crate::publish(TestUpdateMsg);
+    crate::publish(TestUpdateMsg);
+
+    let graph = crate::debug::export_flow_graph();
+    let names: Vec<_> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(
+        vec![
+            "()",
+            std::any::type_name::<TestActivity>(),
+            std::any::type_name::<TestForInt>(),
+            std::any::type_name::<TestUpdateMsg>(),
+        ],
+        names
+    );
+    let edges: Vec<_> = graph
+        .edges
+        .iter()
+        .map(|e| (e.from, e.to, e.count))
+        .collect();
+    assert_eq!(vec![(1, 2, 2), (2, 0, 2), (3, 1, 2)], edges);
+    assert!(graph.to_dot().contains("    n1 -> n2 [label=\"2\"];\n"));
+    assert!(graph.to_mermaid().contains("    n3 -->|2| n1\n"));
+
+    crate::debug::stop_flow_capture();
+    assert_eq!(
+        crate::debug::FlowGraph::default(),
+        crate::debug::export_flow_graph()
+    );
+}
+
+#[test]
+fn borrow_scope() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|a, msg: &TestForInt| a.inc(msg.0 as u32 * 100));
+    id.subscribe(move |_, _: &TestUpdateMsg| {
+        assert_eq!(Err(ExecError::InsideHandler), id.borrow_scope(|_| ()));
+    });
+
+    let result = id.borrow_scope(|scope| {
+        for i in 1..=3 {
+            scope.with(|a| a.inc(i));
+        }
+        crate::publish(TestForInt(1));
+        assert_eq!(Err(ExecError::InsideHandler), id.borrow_scope(|_| ()));
+        scope.with(|a| a.counter.get())
+    });
+    assert_eq!(Ok(6), result);
+    assert_eq!(106, counter.get());
+    crate::publish(TestUpdateMsg);
+
+    id.set_status(LifecycleStatus::Deleted);
+    assert_eq!(Err(ExecError::MissingActivity), id.borrow_scope(|_| ()));
+}
+
+#[test]
+fn subscribe_dyn() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_dyn(core::any::TypeId::of::<TestForInt>(), |a, msg| {
+        let msg: &TestForInt = msg.downcast_ref().expect("wrong type");
+        a.inc(msg.0 as u32);
+    });
+    id.subscribe(|a, msg: &TestForInt| a.inc(msg.0 as u32 * 10));
+    crate::publish(TestForInt(2));
+    crate::publish(TestMessage(100));
+    assert_eq!(22, counter.get());
+}
+
+#[test]
+fn topic_budget() {
+    let log: Log<u32> = Default::default();
+    let log_clone = log.clone();
+    let id = crate::new_activity(());
+    id.subscribe(move |_, msg: &TestForInt| log_clone.borrow_mut().push(msg.0 as u32));
+    let log_clone = log.clone();
+    id.subscribe(move |_, _: &TestUpdateMsg| log_clone.borrow_mut().push(0));
+    crate::set_topic_budget::<TestForInt>(2);
+
+    crate::publish_iter((1..=5).map(TestForInt));
+    assert_eq!(vec![1, 2], *log.borrow());
+    // Postponed messages do not keep nuts busy
+    crate::on_idle(|| {});
+    assert!(crate::run_idle());
+    // Postponed messages stay ahead of new messages
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![1, 2, 3, 4, 0], *log.borrow());
+    crate::pump();
+    assert_eq!(vec![1, 2, 3, 4, 0, 5], *log.borrow());
+    crate::clear_topic_budget::<TestForInt>();
+    crate::publish_iter((6..=8).map(TestForInt));
+    assert_eq!(vec![1, 2, 3, 4, 0, 5, 6, 7, 8], *log.borrow());
+}
+
+#[test]
+fn queue_policy() {
+    let log: Log<u32> = Default::default();
+    let log_clone = log.clone();
+    let id = crate::new_activity(());
+    id.subscribe(move |_, msg: &TestForInt| log_clone.borrow_mut().push(msg.0 as u32));
+    id.subscribe(|_, _: &TestUpdateMsg| crate::publish_iter((1..=4).map(TestForInt)));
+
+    crate::set_queue_policy(Some(QueuePolicy {
+        max_len: 2,
+        on_overflow: OnOverflow::DropOldest,
+    }));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![3, 4], *log.borrow());
+
+    log.borrow_mut().clear();
+    crate::set_queue_policy(Some(QueuePolicy {
+        max_len: 2,
+        on_overflow: OnOverflow::DropNewest,
+    }));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![1, 2], *log.borrow());
+}
+
+#[test]
+#[should_panic]
+fn queue_policy_panic() {
+    crate::set_queue_policy(Some(QueuePolicy {
+        max_len: 2,
+        on_overflow: OnOverflow::Panic,
+    }));
+    crate::new_activity(())
+        .subscribe(|_, _: &TestUpdateMsg| crate::publish_iter((1..=4).map(TestForInt)));
+    crate::publish(TestUpdateMsg);
+}
+
+#[test]
+#[should_panic(expected = "Queued events: 0")]
+fn borrow_conflict_report() {
+    use crate::nut::diagnostics::OrReport;
+    let cell = std::cell::RefCell::new(());
+    let _borrowed = cell.borrow_mut();
+    cell.try_borrow().or_report();
+}
+
+#[test]
+fn activity_template() {
+    let template = crate::template(
+        |n: u32| n,
+        |id| {
+            id.subscribe(|n, msg: &TestForInt| *n += msg.0 as u32);
+        },
+    );
+    let ids: Vec<_> = (1..=3).map(|n| template.spawn(n * 10)).collect();
+    crate::publish(TestForInt(1));
+    ids[1].set_status(LifecycleStatus::Inactive);
+    crate::publish(TestForInt(1));
+    let values: Log<u32> = Default::default();
+    for id in ids {
+        let values = values.clone();
+        id.on_delete(move |n| values.borrow_mut().push(n));
+        id.set_status(LifecycleStatus::Deleted);
+    }
+    assert_eq!(vec![12, 21, 32], *values.borrow());
+}
+
+#[test]
+fn interceptors() {
+    let a = TestActivity::new();
+    let b = TestActivity::new();
+    let counter_a = a.shared_counter_ref();
+    let counter_b = b.shared_counter_ref();
+    let a = crate::new_activity(a);
+    let b = crate::new_activity(b);
+    a.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    b.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+
+    let b_id: UncheckedActivityId = b.into();
+    crate::add_interceptor(move |_, msg| match msg.downcast_mut::<TestMessage>() {
+        Some(TestMessage(0)) => InterceptDecision::Drop,
+        Some(TestMessage(n)) if *n >= 100 => InterceptDecision::RerouteTo(b_id),
+        _ => InterceptDecision::Deliver,
+    });
+    // Sees the changes of the first interceptor
+    crate::add_interceptor(|_, msg| {
+        if let Some(msg) = msg.downcast_mut::<TestMessage>() {
+            msg.0 *= 2;
+        }
+        InterceptDecision::Deliver
+    });
+
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(0));
+    crate::publish(TestMessage(100));
+    assert_eq!(2, counter_a.get());
+    assert_eq!(202, counter_b.get());
+}
+
+#[test]
+fn record_and_replay() {
+    let build = || {
+        let log: Log<u32> = Default::default();
+        let l = log.clone();
+        let id = crate::new_activity(());
+        id.subscribe(move |_, msg: &TestMessage| {
+            l.borrow_mut().push(msg.0);
+            if msg.0 > 0 && msg.0 < 10 {
+                crate::publish(TestMessage(msg.0 * 10));
+            }
+        });
+        log
+    };
+    crate::record_type::<TestMessage>();
+    let original = build();
+    crate::publish(TestMessage(0));
+    crate::start_recording();
+    crate::publish(TestMessage(1));
+    crate::publish(TestUpdateMsg);
+    crate::publish_iter(vec![TestMessage(2), TestMessage(30)]);
+    crate::publish_phased(Phase::Draw, TestMessage(4));
+    let recording = crate::stop_recording().expect("recording started");
+    crate::publish(TestMessage(3));
+    assert!(crate::stop_recording().is_none());
+    assert_eq!(4, recording.len());
+
+    crate::reset();
+    let replayed = build();
+    let phases: Log<Option<Phase>> = Default::default();
+    let p = phases.clone();
+    crate::new_activity(()).subscribe_ctx(move |ctx, msg: &TestMessage| {
+        if msg.0 == 4 {
+            p.borrow_mut().push(ctx.meta().phase());
+        }
+    });
+    crate::replay(&recording);
+    assert_eq!(vec![1, 10, 2, 30, 20, 4, 40], *replayed.borrow());
+    assert_eq!(vec![Some(Phase::Draw)], *phases.borrow());
+    assert_eq!(original.borrow()[1..8], replayed.borrow()[..]);
+}
+
+#[test]
+fn record_held_messages() {
+    crate::record_type::<TestMessage>();
+    crate::start_recording();
+    crate::publish(TestMessage(1));
+    crate::publish_phased(Phase::Update, TestMessage(2));
+    let ready = |domain: &DomainState| domain.try_get::<usize>().is_some();
+    crate::publish_when(&TestDomains::DomainA, ready, TestMessage(3));
+    let recording = crate::stop_recording().expect("recording started");
+    assert_eq!(3, recording.len());
+
+    crate::reset();
+    let received: Log<u32> = Default::default();
+    let log = received.clone();
+    crate::subscribe(move |msg: &TestMessage| log.borrow_mut().push(msg.0));
+    crate::replay(&recording);
+    assert_eq!(vec![1, 2, 3], *received.borrow());
+}
+
+#[test]
+fn lazy_activity() {
+    let constructed = Rc::new(Cell::new(0));
+    let c = constructed.clone();
+    let id = crate::new_lazy_activity(move || {
+        c.set(c.get() + 1);
+        TestActivity::new()
+    });
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(0, constructed.get());
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(2));
+    assert_eq!(1, constructed.get());
+    assert_eq!(
+        Some(3),
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+
+    // Deleting an unused lazy activity does not construct it
+    let c = constructed.clone();
+    let id = crate::new_lazy_activity(move || {
+        c.set(c.get() + 1);
+    });
+    id.set_status(LifecycleStatus::Deleted);
+    assert_eq!(1, constructed.get());
+}
+
+#[test]
+fn paired_subscription() {
+    let active: Log<u32> = Default::default();
+    let inactive: Log<u32> = Default::default();
+    let (a, i) = (active.clone(), inactive.clone());
+    let id = crate::new_activity(());
+    id.subscribe_paired(
+        move |_, msg: &TestMessage| a.borrow_mut().push(msg.0),
+        move |_, msg: &TestMessage| i.borrow_mut().push(msg.0),
+    );
+    crate::publish(TestMessage(1));
+    id.set_status(LifecycleStatus::Inactive);
+    crate::publish(TestMessage(2));
+    id.set_status(LifecycleStatus::Active);
+    crate::publish(TestMessage(3));
+    assert_eq!(vec![1, 3], *active.borrow());
+    assert_eq!(vec![2], *inactive.borrow());
+}
+
+#[test]
+fn fallible_publish() {
+    assert_eq!(
+        Err(PublishError::NoReceiver),
+        crate::try_publish(TestMessage(1))
+    );
+    assert_eq!(
+        Err(PublishError::NoReceiver),
+        crate::try_send_to::<TestActivity, _>(TestMessage(1))
+    );
+    let id = crate::new_activity(TestActivity::new());
+    assert_eq!(
+        Err(PublishError::NoPrivateChannel),
+        crate::try_send_to::<TestActivity, _>(TestMessage(1))
+    );
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.private_channel(|activity, msg: TestMessage| activity.inc(msg.0 * 10));
+    assert_eq!(Ok(()), crate::try_publish(TestMessage(1)));
+    assert_eq!(
+        Ok(()),
+        crate::try_send_to::<TestActivity, _>(TestMessage(2))
+    );
+    assert_eq!(
+        Some(21),
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+
+    let inside: Log<Result<(), PublishError>> = Default::default();
+    let log = inside.clone();
+    id.subscribe(move |_, _: &TestUpdateMsg| {
+        log.borrow_mut()
+            .push(crate::try_send_to::<TestActivity, _>(TestMessage(3)));
+        log.borrow_mut()
+            .push(crate::try_send_to::<TestActivity, _>(TestForInt(0)));
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(
+        vec![Ok(()), Err(PublishError::NoPrivateChannel)],
+        *inside.borrow()
+    );
+    assert_eq!(
+        Some(51),
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+
+    crate::set_queue_policy(Some(QueuePolicy {
+        max_len: 0,
+        on_overflow: OnOverflow::DropNewest,
+    }));
+    assert_eq!(
+        Err(PublishError::QueueFull),
+        crate::try_publish(TestMessage(1))
+    );
+}
+
+#[test]
+fn fallible_publish_queued_and_trait_subscribers() {
+    trait Named {
+        fn name(&self) -> &'static str;
+    }
+    impl Named for TestMessage {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+    }
+    let results: Log<Result<(), PublishError>> = Default::default();
+    let log = results.clone();
+    let id = crate::new_activity(());
+    id.subscribe(move |_, _: &TestUpdateMsg| {
+        // Subscriptions added inside a handler are queued
+        let other = crate::new_activity(TestActivity::new());
+        other.subscribe(|activity, msg: &TestForInt| activity.inc(msg.0 as u32));
+        other.private_channel(|activity, _msg: TestMessageNoClone| activity.inc(100));
+        log.borrow_mut().push(crate::try_publish(TestForInt(1)));
+        log.borrow_mut()
+            .push(crate::try_send_to::<TestActivity, _>(TestMessageNoClone));
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![Ok(()), Ok(())], *results.borrow());
+    assert_eq!(
+        Some(101),
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+
+    assert_eq!(
+        Err(PublishError::NoReceiver),
+        crate::try_publish(TestMessage(1))
+    );
+    crate::register_trait::<dyn Named, TestMessage>(|msg| msg);
+    id.subscribe_trait::<dyn Named, _>(|_, msg| assert_eq!("test", msg.name()));
+    assert_eq!(Ok(()), crate::try_publish(TestMessage(1)));
+}
+
+#[test]
+fn subscribe_once() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_once(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.set_status(LifecycleStatus::Inactive);
+    crate::publish(TestMessage(1));
+    id.set_status(LifecycleStatus::Active);
+    crate::publish(TestMessage(2));
+    crate::publish(TestMessage(4));
+    assert_eq!(2, counter.get());
+    let inspection = crate::inspect().expect("not inside a handler");
+    assert!(inspection
+        .subscriptions
+        .iter()
+        .filter(|(name, _)| name.ends_with("TestMessage"))
+        .all(|(_, n)| *n == 0));
+}
+
+#[test]
+fn main_loop() {
+    let log: Log<&'static str> = Default::default();
+    let (update, draw, frame) = (log.clone(), log.clone(), log.clone());
+    let id = crate::new_activity(());
+    id.subscribe(move |_, msg: &FrameDraw| {
+        draw.borrow_mut().push("draw");
+        if msg.frame == 2 {
+            crate::exit_main_loop();
+        }
+    });
+    id.subscribe(move |_, _: &FrameUpdate| update.borrow_mut().push("update"));
+    MainLoopBuilder::new(1000)
+        .on_frame(move |_| frame.borrow_mut().push("frame"))
+        .run();
+    assert_eq!(
+        vec!["frame", "update", "draw", "frame", "update", "draw"],
+        *log.borrow()
+    );
+    assert_eq!(2, crate::current_tick());
+}
+
+#[test]
+fn isolated_bus() {
+    let bus = crate::Bus::new();
+    let a = TestActivity::new();
+    let bus_counter = a.shared_counter_ref();
+    let id = bus.new_activity(a);
+    bus.enter(|| {
+        id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+        // Handlers run inside the bus, so this stays on it
+        id.subscribe(|_, _: &TestUpdateMsg| crate::publish(TestMessage(10)));
+    });
+    let a = TestActivity::new();
+    let default_counter = a.shared_counter_ref();
+    crate::new_activity(a).subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+
+    bus.publish(TestMessage(1));
+    bus.publish(TestUpdateMsg);
+    crate::publish(TestMessage(100));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(11, bus_counter.get());
+    assert_eq!(100, default_counter.get());
+}
+
+#[test]
+fn bus_ids_outside_of_enter() {
+    let bus = crate::Bus::new();
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = bus.enter(|| {
+        crate::store_to_domain(&TestDomains::DomainA, 7u32);
+        crate::new_domained_activity_checked(a, &TestDomains::DomainA)
+    });
+    // Outside of `enter`, the id still acts on its own bus
+    id.subscribe_domained(|activity, domain, _: &TestUpdateMsg| activity.inc(*domain.get::<u32>()));
+    let default_id = crate::new_activity(());
+    bus.enter(|| {
+        // Inside `enter`, ids of the default bus still act on the default bus
+        default_id.subscribe(|_, msg: &TestMessage| assert_eq!(5, msg.0));
+        assert_eq!(Some(vec![id.into()]), crate::instances_of::<TestActivity>());
+    });
+    assert_eq!(Some(vec![]), crate::instances_of::<TestActivity>());
+    crate::publish(TestMessage(5));
+
+    // The future is polled inside the bus, even though it is polled outside of `enter`
+    let response = bus.enter(|| crate::publish_awaiting_response(TestUpdateMsg));
+    let mut response = Box::pin(bus.enter_future(response));
+    assert!(poll_once(response.as_mut()).is_ready());
+    assert_eq!(7, counter.get());
+}
+
+#[test]
+#[should_panic(expected = "after its bus has been reset or dropped")]
+fn id_of_dropped_bus() {
+    let bus = crate::Bus::new();
+    let id = bus.new_activity(());
+    drop(bus);
+    id.subscribe(|_, _: &TestMessage| {});
+}
+
+#[test]
+fn message_priorities() {
+    let log: Log<u32> = Default::default();
+    let l = log.clone();
+    let id = crate::new_activity(());
+    id.subscribe(move |_, msg: &TestMessage| l.borrow_mut().push(msg.0));
+    id.subscribe(|_, _: &TestUpdateMsg| {
+        crate::publish_with_priority(TestMessage(1), Priority::Low);
+        crate::publish(TestMessage(2));
+        crate::publish_with_priority(TestMessage(3), Priority::High);
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![3, 2, 1], *log.borrow());
+
+    // Lower priorities are not starved
+    log.borrow_mut().clear();
+    id.subscribe(|_, _: &TestForInt| {
+        crate::publish_with_priority(TestMessage(0), Priority::Low);
+        for i in 1..=20 {
+            crate::publish_with_priority(TestMessage(i), Priority::High);
+        }
+    });
+    crate::publish(TestForInt(0));
+    let low_position = log.borrow().iter().position(|n| *n == 0);
+    assert_eq!(Some(16), low_position);
+}
+
+#[test]
+fn subscription_handle() {
+    let order: Log<&'static str> = Default::default();
+    let log = order.clone();
+    let low = crate::subscribe(move |_: &TestUpdateMsg| log.borrow_mut().push("low"));
+    let log = order.clone();
+    let high = crate::subscribe_masked(
+        SubscriptionFilter::default().with_priority(1),
+        move |_: &TestUpdateMsg| log.borrow_mut().push("high"),
+    );
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["high", "low"], *order.borrow());
+
+    high.unsubscribe();
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["high", "low", "low"], *order.borrow());
+    low.unsubscribe();
+    crate::publish(TestUpdateMsg);
+    assert_eq!(3, order.borrow().len());
+}
+
+#[test]
+fn with_activity() {
+    assert_eq!(
+        Err(ExecError::MissingActivity),
+        crate::with_activity(|_: &mut TestActivity| ())
+    );
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|a: &mut TestActivity, _: &TestUpdateMsg| a.inc(10));
+
+    // Messages published inside are delivered afterwards
+    let result = crate::with_activity(|a: &mut TestActivity| {
+        a.inc(1);
+        crate::publish(TestUpdateMsg);
+        counter.get()
+    });
+    assert_eq!(Ok(1), result);
+    assert_eq!(11, counter.get());
+
+    id.subscribe(|_: &mut TestActivity, _: &TestForInt| {
+        assert_eq!(
+            Err(ExecError::InsideHandler),
+            crate::with_activity(|_: &mut TestActivity| ())
+        );
+    });
+    crate::publish(TestForInt(0));
+}
+
+#[test]
+fn weak_subscription() {
+    let received: Log<u32> = Default::default();
+    let guard = std::rc::Rc::new(received.clone());
+    crate::subscribe_weak(&guard, |log, msg: &TestMessage| {
+        log.borrow_mut().push(msg.0)
+    });
+    crate::publish(TestMessage(1));
+    drop(guard);
+    crate::publish(TestMessage(2));
+    assert_eq!(vec![1], *received.borrow());
+    let inspection = crate::inspect().expect("not inside a handler");
+    assert!(inspection
+        .subscriptions
+        .iter()
+        .filter(|(name, _)| name.ends_with("TestMessage"))
+        .all(|(_, n)| *n == 0));
+}
+
+#[test]
+fn publish_sync() {
+    let received: Log<u32> = Default::default();
+    let log = received.clone();
+    crate::subscribe(move |msg: &TestMessage| log.borrow_mut().push(msg.0));
+    crate::subscribe(|_: &TestUpdateMsg| {
+        assert_eq!(
+            Err(ExecError::InsideHandler),
+            crate::publish_sync(TestMessage(2))
+        );
+    });
+    assert_eq!(Ok(()), crate::publish_sync(TestMessage(1)));
+    assert_eq!(vec![1], *received.borrow());
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![1], *received.borrow());
+}
+
+#[test]
+fn tagged_subscriptions() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|a: &mut TestActivity, _: &TestUpdateMsg| a.inc(1));
+    id.subscribe_tagged("optional", |a: &mut TestActivity, _: &TestUpdateMsg| {
+        a.inc(10)
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(11, counter.get());
+
+    crate::set_tag_enabled("optional", false);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(12, counter.get());
+
+    crate::set_tag_enabled("optional", true);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(23, counter.get());
+}
+
+#[test]
+fn execution_budget() {
+    use std::time::Duration;
+    thread_local!(static CLOCK: Cell<Duration> = const { Cell::new(Duration::ZERO) });
+    fn clock() -> Duration {
+        CLOCK.with(Cell::get)
+    }
+    let received: Log<usize> = Default::default();
+    let log = received.clone();
+    crate::subscribe(move |msg: &TestForInt| {
+        CLOCK.with(|c| c.set(c.get() + Duration::from_millis(1)));
+        log.borrow_mut().push(msg.0);
+        if msg.0 > 0 {
+            crate::publish(TestForInt(msg.0 - 1));
+        }
+    });
+    let exceeded: Log<usize> = Default::default();
+    let log = exceeded.clone();
+    crate::subscribe(move |msg: &BudgetExceeded| log.borrow_mut().push(msg.remaining));
+
+    crate::set_execution_budget(Duration::from_millis(3), clock);
+    crate::publish(TestForInt(5));
+    assert_eq!(vec![5, 4, 3], *received.borrow());
+    assert_eq!(vec![1], *exceeded.borrow());
+    assert_eq!(
+        Err(ExecError::EventsPending),
+        crate::publish_sync(TestForInt(0))
+    );
+
+    // The next catch-up continues where the last one stopped
+    crate::clear_execution_budget();
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![5, 4, 3, 2, 1, 0], *received.borrow());
+}
+
+#[test]
+fn topic_order() {
+    struct Beta;
+    struct Alpha;
+    let order: Log<&'static str> = Default::default();
+    let log = order.clone();
+    let beta = crate::new_activity(Beta);
+    beta.subscribe(move |_, _: &TestUpdateMsg| log.borrow_mut().push("beta"));
+    let log = order.clone();
+    let alpha = crate::new_activity(Alpha);
+    alpha.subscribe(move |_, _: &TestUpdateMsg| log.borrow_mut().push("alpha"));
+
+    crate::set_topic_order::<TestUpdateMsg>(DeliveryOrder::TypeName);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["alpha", "beta"], *order.borrow());
+
+    // Applies to later subscriptions, too
+    order.borrow_mut().clear();
+    let log = order.clone();
+    crate::new_activity(()).subscribe(move |_, _: &TestUpdateMsg| log.borrow_mut().push("unit"));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["unit", "alpha", "beta"], *order.borrow());
+
+    order.borrow_mut().clear();
+    crate::set_topic_order::<TestUpdateMsg>(DeliveryOrder::Registration);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["beta", "alpha", "unit"], *order.borrow());
+}
+
+trait Describe {
+    fn describe(&self) -> u32;
+}
+impl Describe for TestMessage {
+    fn describe(&self) -> u32 {
+        self.0
+    }
+}
+impl Describe for TestForInt {
+    fn describe(&self) -> u32 {
+        self.0 as u32 * 10
+    }
+}
+
+#[test]
+fn trait_subscription() {
+    let seen: Log<u32> = Default::default();
+    let log = seen.clone();
+    crate::new_activity(TestActivity::new())
+        .subscribe_trait::<dyn Describe, _>(move |_, msg| log.borrow_mut().push(msg.describe()));
+    crate::register_trait::<dyn Describe, TestMessage>(|msg| msg);
+
+    crate::publish(TestMessage(1));
+    crate::publish(TestForInt(2));
+    crate::register_trait::<dyn Describe, TestForInt>(|msg| msg);
+    crate::publish(TestForInt(3));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![1, 30], *seen.borrow());
+}
+
+#[test]
+fn entity_subscription() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    for entity in 0..10 {
+        id.subscribe_entity(EntityId(entity), move |activity, msg: &TestForInt| {
+            assert_eq!(entity as usize, msg.0);
+            activity.inc(1);
+        });
+    }
+    id.subscribe(|_, _: &TestForInt| panic!("Entity messages are not published to all"));
+
+    crate::publish_to_entity(EntityId(3), TestForInt(3));
+    crate::publish_to_entity(EntityId(7), TestForInt(7));
+    crate::publish_to_entity(EntityId(11), TestForInt(11));
+    assert_eq!(2, counter.get());
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn panic_context() {
+    let seen: Log<PanicContext> = Default::default();
+    let seen_clone = seen.clone();
+    let id = crate::new_activity(TestActivity::new());
+    id.subscribe(move |_, _: &TestMessage| {
+        crate::publish(TestUpdateMsg);
+        seen_clone.borrow_mut().push(crate::panic_context());
+    });
+    crate::publish(TestForInt(0));
+    crate::publish(TestMessage(1));
+
+    let context = seen.borrow_mut().pop().expect("handler not called");
+    assert_eq!(
+        Some(std::any::type_name::<TestActivity>()),
+        context.activity
+    );
+    assert_eq!(Some(std::any::type_name::<TestMessage>()), context.message);
+    assert_eq!(Some(1), context.queue_len);
+    assert_eq!(
+        vec![
+            std::any::type_name::<TestForInt>(),
+            std::any::type_name::<TestMessage>()
+        ],
+        context.recent_topics
+    );
+    assert_eq!(None, crate::panic_context().message);
+}
+
+#[test]
+fn activity_builder() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::activity(a)
+        .domain(&TestDomains::DomainA)
+        .start_inactive()
+        .subscribe(|activity, msg: &TestMessage| activity.inc(msg.0))
+        .subscribe_masked(
+            SubscriptionFilter::no_filter(),
+            |activity, msg: &TestForInt| activity.inc(msg.0 as u32),
+        )
+        .subscribe_domained(|activity, domain, _: &TestUpdateMsg| {
+            activity.inc(*domain.get::<u32>())
+        })
+        .private_channel(|activity, msg: TestMessage| activity.inc(msg.0 * 100))
+        .build();
+    crate::store_to_domain(&TestDomains::DomainA, 1000u32);
+
+    crate::publish(TestMessage(1));
+    assert_eq!(0, counter.get());
+    id.set_status(LifecycleStatus::Active);
+    crate::publish(TestMessage(1));
+    crate::publish(TestForInt(10));
+    crate::publish(TestUpdateMsg);
+    id.private_message(TestMessage(2));
+    assert_eq!(1211, counter.get());
+}
+
+#[test]
+fn subscribers_of() {
+    let a = crate::new_activity(TestActivity::new());
+    let b = crate::new_activity(0u32);
+    let c = crate::new_activity(String::new());
+    crate::subscribe(|_: &TestMessage| {});
+    b.subscribe(|_, _: &TestMessage| {});
+    a.subscribe_masked(
+        SubscriptionFilter::default().with_priority(1),
+        |_, _: &TestMessage| {},
+    );
+    a.subscribe(|_, _: &TestMessage| {});
+    c.private_channel(|_, _: TestMessage| {});
+    b.private_channel(|_, _: TestMessage| {});
+    c.set_status(LifecycleStatus::Inactive);
+
+    let subscribers = crate::subscribers_of::<TestMessage>().expect("not inside a handler");
+    let ids: Vec<UncheckedActivityId> = subscribers.iter().map(|s| s.id).collect();
+    let expected: Vec<UncheckedActivityId> = vec![a.into(), b.into(), c.into()];
+    assert_eq!(expected, ids);
+    assert!(subscribers[1].type_name.ends_with("u32"));
+    assert_eq!(LifecycleStatus::Inactive, subscribers[2].status);
+    assert!(crate::subscribers_of::<TestForInt>()
+        .expect("not inside a handler")
+        .is_empty());
+}
+
+#[test]
+fn publish_copy_reuses_boxes() {
+    #[derive(Clone, Copy)]
+    struct Tiny(u32);
+    let seen: Log<u32> = Default::default();
+    let seen_clone = seen.clone();
+    crate::subscribe(move |msg: &Tiny| seen_clone.borrow_mut().push(msg.0));
+    assert_eq!(0, crate::nut::pooled_boxes::<Tiny>());
+
+    crate::publish_copy(Tiny(1));
+    assert_eq!(1, crate::nut::pooled_boxes::<Tiny>());
+    crate::publish_copy(Tiny(2));
+    crate::publish_copy(Tiny(3));
+    assert_eq!(1, crate::nut::pooled_boxes::<Tiny>());
+
+    // Once the type is pooled, boxes from `publish` are recycled, too
+    crate::publish(Tiny(4));
+    assert_eq!(2, crate::nut::pooled_boxes::<Tiny>());
+    assert_eq!(vec![1, 2, 3, 4], *seen.borrow());
+}
+
+#[test]
+fn api_conversion_traits() {
+    use crate::api::{AsDomainState, AsMessageMeta};
+    fn add(mut domain: impl AsDomainState, n: u32) {
+        *domain
+            .as_domain_state()
+            .expect("activity has a domain")
+            .get_mut::<u32>() += n;
+    }
+    fn type_name(meta: impl AsMessageMeta) -> &'static str {
+        meta.as_message_meta().type_name()
+    }
+    let names: Log<&'static str> = Default::default();
+    let names_clone = names.clone();
+    crate::store_to_domain(&TestDomains::DomainA, 0u32);
+    let id = crate::new_domained_activity_checked((), &TestDomains::DomainA);
+    id.subscribe_domained(|_, domain, _: &TestUpdateMsg| add(domain, 1));
+    id.subscribe_ctx(move |ctx, _: &TestUpdateMsg| {
+        names_clone.borrow_mut().push(type_name(&*ctx));
+        add(ctx, 10);
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(
+        Some(11),
+        crate::take_from_domain::<_, u32>(&TestDomains::DomainA)
+    );
+    assert!(names.borrow()[0].ends_with("TestUpdateMsg"));
+
+    let filter: SubscriptionFilter = Phase::Update.into();
+    assert_eq!(Some(Phase::Update), filter.phase);
+}
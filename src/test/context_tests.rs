@@ -0,0 +1,111 @@
+//! Test suite for handlers registered with a `HandlerCtx`.
+use super::*;
+
+#[test]
+fn ctx_stop_propagation() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_ctx(|ctx, msg: &TestMessage| {
+        ctx.activity().inc(msg.0);
+        ctx.stop_propagation();
+    });
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0 * 100));
+    crate::publish(TestMessage(1));
+    assert_eq!(1, counter.get());
+}
+
+#[test]
+fn ctx_domain_and_meta() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let d = TestDomains::DomainA;
+    crate::store_to_domain(&d, 7usize);
+    let id = crate::new_domained_activity_checked(a, &d);
+    id.subscribe_ctx(move |ctx, _: &TestUpdateMsg| {
+        assert!(!ctx.meta().is_private());
+        assert_eq!(
+            ctx.meta().type_id(),
+            core::any::TypeId::of::<TestUpdateMsg>()
+        );
+        assert_eq!(UncheckedActivityId::from(ctx.id()), id.into());
+        let x: usize = *ctx.domain().expect("domain missing").get();
+        ctx.activity().inc(x as u32);
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(7, counter.get());
+
+    let id = crate::new_activity(());
+    id.subscribe_ctx(|ctx, _: &TestUpdateMsg| assert!(ctx.domain().is_none()));
+    crate::publish(TestUpdateMsg);
+}
+
+#[test]
+fn ctx_defer() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe_ctx(|ctx, _: &TestUpdateMsg| {
+        ctx.publish(TestMessage(1));
+        ctx.defer(|activity: &mut TestActivity| {
+            // The message published before has been delivered already
+            assert_eq!(1, activity.counter.get());
+            activity.inc(10);
+        });
+    });
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(11, counter.get());
+}
+
+#[test]
+fn ctx_yield_and_continue() {
+    let log: Log<String> = Default::default();
+    let log_clone = log.clone();
+    let id = crate::new_activity(());
+    id.subscribe_ctx(move |ctx, _: &TestUpdateMsg| {
+        let log = log_clone.clone();
+        ctx.yield_and_continue(0u32, move |_, step| {
+            log.borrow_mut().push(format!("step {}", step));
+            crate::publish(TestMessage(step));
+            if step < 2 {
+                Some(step + 1)
+            } else {
+                None
+            }
+        });
+    });
+    let log_clone = log.clone();
+    id.subscribe(move |_, msg: &TestMessage| {
+        log_clone.borrow_mut().push(format!("message {}", msg.0))
+    });
+    crate::publish(TestUpdateMsg);
+    // Messages published by a step are delivered before the next step
+    assert_eq!(
+        vec![
+            "step 0",
+            "message 0",
+            "step 1",
+            "message 1",
+            "step 2",
+            "message 2"
+        ],
+        *log.borrow()
+    );
+}
+
+#[test]
+fn ctx_meta_tick() {
+    let ticks: Log<u64> = Default::default();
+    let ticks_clone = ticks.clone();
+    let id = crate::new_activity(());
+    id.subscribe_ctx(move |ctx, _: &TestUpdateMsg| {
+        ticks_clone.borrow_mut().push(ctx.meta().tick())
+    });
+    crate::publish(TestUpdateMsg);
+    crate::tick();
+    assert_eq!(2, crate::tick());
+    crate::publish(TestUpdateMsg);
+    assert_eq!(2, crate::current_tick());
+    assert_eq!(vec![0, 2], *ticks.borrow());
+}
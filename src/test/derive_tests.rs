@@ -0,0 +1,47 @@
+use super::*;
+
+struct Player {
+    moved: u32,
+    teleported: u32,
+}
+struct Move(u32);
+struct Teleport(u32);
+
+#[crate::subscriber]
+impl Player {
+    #[subscribe]
+    fn on_move(&mut self, msg: &Move) {
+        self.moved += msg.0;
+    }
+    #[subscribe]
+    fn on_teleport(&mut self, msg: &mut Teleport) {
+        self.teleported += msg.0;
+        msg.0 = 0;
+    }
+    // Fits the signature of a handler, but is not marked
+    #[allow(dead_code)]
+    fn on_other_move(&mut self, msg: &Move) {
+        self.moved += 100 * msg.0;
+    }
+}
+
+#[test]
+fn register_marked_methods() {
+    let id = crate::new_activity(Player {
+        moved: 0,
+        teleported: 0,
+    });
+    id.register_all();
+    let seen: Log<u32> = Default::default();
+    let seen_clone = seen.clone();
+    crate::subscribe(move |msg: &Teleport| seen_clone.borrow_mut().push(msg.0));
+
+    crate::publish(Move(2));
+    crate::publish(Teleport(3));
+    assert_eq!(
+        Some((2, 3)),
+        crate::read_activity(|p: &Player| (p.moved, p.teleported))
+    );
+    // `on_teleport` is a mutable subscription, it runs before the shared one
+    assert_eq!(vec![0], *seen.borrow());
+}
@@ -6,7 +6,7 @@ fn store_to_domain_inside_activity() {
     let a = TestActivity::new();
     let d = TestDomains::DomainA;
     crate::store_to_domain(&d, 7usize);
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.subscribe_domained(|_activity, domain, msg: &TestForInt| {
         let x: usize = *domain.get();
         assert_eq!(msg.0, x);
@@ -34,3 +34,338 @@ fn store_to_domain_inside_activity() {
     // Check update has been completed
     crate::publish(TestForInt(9));
 }
+
+#[test]
+fn move_to_domain() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    crate::store_to_domain(&TestDomains::DomainA, 1u32);
+    crate::store_to_domain(&TestDomains::_DomainB, 10u32);
+    let id = crate::new_domained_activity_checked(a, &TestDomains::DomainA);
+    id.subscribe_domained(|activity, domain, _msg: &TestUpdateMsg| {
+        activity.inc(*domain.get::<u32>());
+    });
+    id.subscribe(move |_, _msg: &TestMessage| {
+        id.move_to_domain(&TestDomains::DomainA);
+        // The move is queued before this message, which therefore sees the new domain
+        crate::publish(TestUpdateMsg);
+    });
+
+    crate::publish(TestUpdateMsg);
+    assert_eq!(1, counter.get());
+    id.move_to_domain(&TestDomains::_DomainB);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(11, counter.get());
+    crate::publish(TestMessage(0));
+    assert_eq!(12, counter.get());
+}
+
+#[test]
+fn signal_publishes_on_change() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_domained_activity_checked(a, &TestDomains::DomainA);
+    id.subscribe_domained(|activity, domain, change: &Changed<u32>| {
+        assert_eq!(change.new, *domain.get::<u32>());
+        activity.inc(change.new - change.old);
+    });
+
+    let signal = crate::signal(&TestDomains::DomainA, 1u32);
+    signal.set(1);
+    assert_eq!(0, counter.get());
+    signal.set(3);
+    assert_eq!(2, counter.get());
+    signal.clone().set(3);
+    assert_eq!(2, counter.get());
+    assert_eq!(3, signal.get());
+}
+
+#[test]
+fn watch_domain_value() {
+    let d = TestDomains::DomainA;
+    let seen: Log<(u32, u32)> = Default::default();
+    let seen_clone = seen.clone();
+    crate::subscribe(move |change: &Changed<u32>| {
+        seen_clone.borrow_mut().push((change.old, change.new))
+    });
+    let calls = Rc::new(Cell::new(0));
+    let calls_clone = calls.clone();
+    crate::watch_domain(&d, 2, move |_: &u32, _: &u32| {
+        calls_clone.set(calls_clone.get() + 1)
+    });
+    let mut main_loop = crate::MainLoopBuilder::new(60);
+
+    crate::store_to_domain(&d, 1u32);
+    main_loop.step(Default::default());
+    main_loop.step(Default::default());
+    crate::store_to_domain(&d, 2u32);
+    main_loop.step(Default::default());
+    assert!(seen.borrow().is_empty());
+    main_loop.step(Default::default());
+    assert_eq!(vec![(1, 2)], *seen.borrow());
+    main_loop.step(Default::default());
+    main_loop.step(Default::default());
+    assert_eq!(1, calls.get());
+}
+
+#[test]
+fn typed_domain() {
+    #[derive(Default)]
+    struct Score(u32);
+
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let typed = crate::new_domained_activity_typed::<_, _, Score>(a, &TestDomains::DomainA);
+    typed.subscribe(|activity, score, msg: &TestMessage| {
+        score.0 += msg.0;
+        activity.inc(score.0);
+    });
+    typed
+        .id()
+        .subscribe_domained(|_, domain, _msg: &TestUpdateMsg| {
+            assert_eq!(3, domain.get::<Score>().0);
+        });
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(2));
+    assert_eq!(4, counter.get());
+    crate::publish(TestUpdateMsg);
+}
+
+#[test]
+fn remove_from_domain() {
+    let d = TestDomains::DomainA;
+    crate::store_to_domain(&d, 1u32);
+    crate::store_to_domain(&d, 2u64);
+    crate::store_to_domain(&d, 3usize);
+    assert_eq!(Some(1u32), crate::take_from_domain(&d));
+    assert_eq!(None, crate::take_from_domain::<_, u32>(&d));
+
+    let id = crate::new_domained_activity_checked((), &d);
+    id.subscribe_domained(|_, domain, _: &TestUpdateMsg| {
+        assert_eq!(None, domain.try_get::<u32>());
+        assert_eq!(2u64, *domain.get::<u64>());
+        assert_eq!(3usize, *domain.get::<usize>());
+        assert_eq!(Some(3usize), domain.remove());
+        // Deferred removal returns nothing
+        assert_eq!(
+            None,
+            crate::take_from_domain::<_, u64>(&TestDomains::DomainA)
+        );
+        assert_eq!(2u64, *domain.get::<u64>());
+    });
+    crate::publish(TestUpdateMsg);
+    assert_eq!(None, crate::take_from_domain::<_, u64>(&d));
+    assert_eq!(None, crate::take_from_domain::<_, usize>(&d));
+}
+
+#[test]
+fn store_to_domain_now_and_deferred() {
+    let d = TestDomains::DomainA;
+    assert_eq!(Ok(()), crate::store_to_domain_now(&d, 1u32));
+    assert_eq!(Some(1u32), crate::take_from_domain(&d));
+
+    let id = crate::new_domained_activity_checked((), &d);
+    id.subscribe_domained(|_, domain, _: &TestUpdateMsg| {
+        assert_eq!(
+            Err(ExecError::InsideHandler),
+            crate::store_to_domain_now(&TestDomains::DomainA, 2u32)
+        );
+        crate::store_to_domain_deferred(&TestDomains::DomainA, 3u32);
+        assert_eq!(None, domain.try_get::<u32>());
+        crate::publish(TestForInt(3));
+    });
+    id.subscribe_domained(|_, domain, msg: &TestForInt| {
+        assert_eq!(msg.0 as u32, *domain.get::<u32>());
+    });
+    crate::publish(TestUpdateMsg);
+    crate::store_to_domain_deferred(&d, 4u32);
+    assert_eq!(Some(4u32), crate::take_from_domain(&d));
+}
+
+#[test]
+fn publish_to_domain() {
+    let a = TestActivity::new();
+    let b = TestActivity::new();
+    let c = TestActivity::new();
+    let counter_a = a.shared_counter_ref();
+    let counter_b = b.shared_counter_ref();
+    let counter_c = c.shared_counter_ref();
+    let a = crate::new_domained_activity(a, &TestDomains::DomainA);
+    let b = crate::new_domained_activity(b, &TestDomains::_DomainB);
+    let c = crate::new_activity(c);
+    for id in [a, b, c].iter() {
+        id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    }
+    crate::publish_to_domain(&TestDomains::DomainA, TestMessage(1));
+    crate::publish_to_domain(&TestDomains::_DomainB, TestMessage(10));
+    crate::publish(TestMessage(100));
+    assert_eq!(101, counter_a.get());
+    assert_eq!(110, counter_b.get());
+    assert_eq!(100, counter_c.get());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshot_and_restore_domains() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Score {
+        points: u32,
+        name: String,
+    }
+    let a = TestDomains::DomainA;
+    let b = TestDomains::_DomainB;
+    assert!(crate::register_serializable::<Score>("score"));
+    assert!(!crate::register_serializable::<Score>("other"));
+    assert!(!crate::register_serializable::<u32>("score"));
+    crate::store_to_domain(
+        &a,
+        Score {
+            points: 1,
+            name: "a".to_owned(),
+        },
+    );
+    crate::store_to_domain(
+        &b,
+        Score {
+            points: 2,
+            name: "b".to_owned(),
+        },
+    );
+    crate::store_to_domain(&a, 5u64);
+    let snapshot = crate::snapshot_domains().expect("not inside a handler");
+
+    crate::store_to_domain(
+        &a,
+        Score {
+            points: 10,
+            name: "x".to_owned(),
+        },
+    );
+    crate::store_to_domain(&b, 10u8);
+    crate::store_to_domain(&a, 50u64);
+    assert!(crate::restore_domains(&snapshot[..1]).is_err());
+    crate::restore_domains(&snapshot).expect("valid snapshot");
+
+    // Unregistered types are neither saved nor restored
+    assert_eq!(Some(50u64), crate::take_from_domain(&a));
+    assert_eq!(Some(10u8), crate::take_from_domain(&b));
+    assert_eq!(
+        Some(Score {
+            points: 1,
+            name: "a".to_owned()
+        }),
+        crate::take_from_domain(&a)
+    );
+    assert_eq!(
+        Some(Score {
+            points: 2,
+            name: "b".to_owned()
+        }),
+        crate::take_from_domain(&b)
+    );
+
+    // After a reset, the name is unknown until it is registered again
+    crate::reset();
+    assert!(matches!(
+        crate::restore_domains(&snapshot),
+        Err(crate::SnapshotError::UnknownType(name)) if name == "score"
+    ));
+    assert!(crate::register_serializable::<Score>("score"));
+    crate::restore_domains(&snapshot).expect("valid snapshot");
+    assert_eq!(
+        Some(1),
+        crate::take_from_domain::<_, Score>(&a).map(|s| s.points)
+    );
+
+    let id = crate::new_activity(());
+    id.subscribe(|_, _: &TestUpdateMsg| {
+        assert!(matches!(
+            crate::snapshot_domains(),
+            Err(crate::SnapshotError::InsideHandler)
+        ));
+    });
+    crate::publish(TestUpdateMsg);
+}
+
+#[test]
+fn domain_bulk_access() {
+    let mut domain = DomainState::default();
+    domain.store(1u8);
+    domain.store(2u16);
+    domain.store(3u32);
+    domain.store(4u64);
+    assert_eq!(
+        vec![
+            std::any::TypeId::of::<u8>(),
+            std::any::TypeId::of::<u16>(),
+            std::any::TypeId::of::<u32>(),
+            std::any::TypeId::of::<u64>()
+        ],
+        domain.iter_types().collect::<Vec<_>>()
+    );
+
+    let (a, b, c, d) = domain.try_get_4_mut::<u64, u8, i32, u32>();
+    *a.expect("stored") += 10;
+    *b.expect("stored") += 10;
+    assert!(c.is_none());
+    *d.expect("stored") += 10;
+    assert_eq!(
+        (Some(&mut 11), Some(&mut 2), Some(&mut 13)),
+        domain.try_get_3_mut::<u8, u16, u32>()
+    );
+    assert_eq!(14u64, *domain.get::<u64>());
+}
+
+#[test]
+fn parallel_ready_grouped_by_domain() {
+    let order: Log<&'static str> = Default::default();
+    let log = order.clone();
+    crate::new_domained_activity_checked((), &TestDomains::_DomainB)
+        .subscribe_domained(move |_, _, _: &TestUpdateMsg| log.borrow_mut().push("B"));
+    let log = order.clone();
+    crate::new_activity(()).subscribe(move |_, _: &TestUpdateMsg| log.borrow_mut().push("-"));
+    let log = order.clone();
+    crate::new_domained_activity_checked((), &TestDomains::DomainA)
+        .subscribe_domained(move |_, _, _: &TestUpdateMsg| log.borrow_mut().push("A"));
+
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["B", "-", "A"], *order.borrow());
+    order.borrow_mut().clear();
+    crate::publish_parallel_ready(TestUpdateMsg);
+    assert_eq!(vec!["A", "B", "-"], *order.borrow());
+}
+
+#[test]
+fn publish_when_condition_holds() {
+    let received: Log<u32> = Default::default();
+    let log = received.clone();
+    crate::subscribe(move |msg: &TestMessage| log.borrow_mut().push(msg.0));
+    let ready = |domain: &DomainState| domain.try_get::<usize>().is_some();
+    crate::publish_when(&TestDomains::DomainA, ready, TestMessage(1));
+    crate::publish_when(&TestDomains::DomainA, ready, TestMessage(2));
+    assert!(received.borrow().is_empty());
+
+    // Stored inside a handler
+    crate::subscribe(|_: &TestUpdateMsg| crate::store_to_domain(&TestDomains::DomainA, 0usize));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec![1, 2], *received.borrow());
+
+    // Stored outside of activities
+    crate::publish_when(&TestDomains::_DomainB, ready, TestMessage(3));
+    crate::store_to_domain(&TestDomains::_DomainB, 0usize);
+    assert_eq!(vec![1, 2, 3], *received.borrow());
+}
+
+#[test]
+fn publish_when_store_now() {
+    let received: Log<u32> = Default::default();
+    let log = received.clone();
+    crate::subscribe(move |msg: &TestMessage| log.borrow_mut().push(msg.0));
+    let ready = |domain: &DomainState| domain.try_get::<usize>().is_some();
+    crate::publish_when(&TestDomains::DomainA, ready, TestMessage(1));
+    assert_eq!(
+        Ok(()),
+        crate::store_to_domain_now(&TestDomains::DomainA, 0usize)
+    );
+    assert_eq!(vec![1], *received.borrow());
+}
@@ -1,4 +1,5 @@
 //! Testing creation and managing of activities while a broadcast is inflight.
+#![allow(clippy::unwrap_used, clippy::doc_markdown)]
 use super::*;
 
 struct Main;
@@ -58,7 +59,7 @@ fn create_inchoate_domained_activity_and_subscribe() {
     crate::store_to_domain(&d, 7usize);
 
     main.subscribe(move |_, _: &Main| {
-        let id = crate::new_domained_activity(a.clone(), &d);
+        let id = crate::new_domained_activity_checked(a.clone(), &d);
         id.subscribe_domained(|activity: &mut TestActivity, domain, _: &TestUpdateMsg| {
             let x: usize = *domain.get();
             assert_eq!(7, x);
@@ -84,14 +85,14 @@ fn create_inchoate_domained_activity_and_subscribe_and_publish() {
     let d = TestDomains::DomainA;
     crate::store_to_domain(&d, 7usize);
 
-    let bid = crate::new_domained_activity(b, &d);
+    let bid = crate::new_domained_activity_checked(b, &d);
     bid.subscribe_domained(|_activity, domain, msg: &TestForInt| {
         let x: usize = *domain.get();
         assert_eq!(msg.0, x);
     });
 
     main.subscribe(move |_, _: &Main| {
-        let id = crate::new_domained_activity(a.clone(), &d);
+        let id = crate::new_domained_activity_checked(a.clone(), &d);
         id.subscribe_domained(|activity: &mut TestActivity, domain, _: &TestUpdateMsg| {
             let x: usize = *domain.get();
             assert_eq!(7, x);
@@ -118,7 +119,7 @@ fn create_inchoate_domained_activity_and_subscribe_and_publish_to_inchoate_activ
     crate::store_to_domain(&d, 7usize);
 
     main.subscribe(move |_, _: &Main| {
-        let id = crate::new_domained_activity(a.clone(), &d);
+        let id = crate::new_domained_activity_checked(a.clone(), &d);
         id.subscribe_domained(|_activity, domain, msg: &TestForInt| {
             let x: usize = *domain.get();
             assert_eq!(msg.0, x);
@@ -141,6 +142,8 @@ fn create_inchoate_domained_activity_and_subscribe_and_publish_to_inchoate_activ
 }
 
 #[test]
+// Covers the deprecated methods with domain access on ids without `WithDomain`
+#[allow(deprecated)]
 fn queue_message_and_add_inchoate_subscriber() {
     let main = crate::new_activity(());
     let a = TestActivity::new();
@@ -208,7 +211,7 @@ fn delete_inchoate_activity_with_subscriber_and_on_delete() {
 
     main.subscribe(move |_, _: &Main| {
         crate::publish(TestForInt(7));
-        let id = crate::new_domained_activity(a.clone(), &d);
+        let id = crate::new_domained_activity_checked(a.clone(), &d);
         id.subscribe_domained(|_activity, _domain, _msg: &TestMessage| {
             panic!("Activity should be deleted by now, why is the subscriber called?")
         });
@@ -237,7 +240,7 @@ fn on_enter_and_leave_and_delete_for_inchoate_activity() {
     let aid_slot_clone = aid_slot.clone();
 
     main.subscribe(move |_, _: &Main| {
-        let id = crate::new_domained_activity(a.clone(), &d);
+        let id = crate::new_domained_activity_checked(a.clone(), &d);
         id.on_enter_domained(|activity: &mut TestActivity, domain| {
             let x: usize = *domain.get();
             assert_eq!(7, x);
@@ -255,7 +258,7 @@ fn on_enter_and_leave_and_delete_for_inchoate_activity() {
         });
         id.set_status(LifecycleStatus::Inactive);
         id.set_status(LifecycleStatus::Active);
-        aid_slot.set(Some(id));
+        aid_slot.set(Some(id.without_domain()));
     });
 
     assert_eq!(counter.get(), 0);
@@ -282,14 +285,14 @@ fn create_inchoate_domained_activity_and_subscribe_and_publish_privately() {
     let d = TestDomains::DomainA;
     crate::store_to_domain(&d, 7usize);
 
-    let bid = crate::new_domained_activity(b, &d);
+    let bid = crate::new_domained_activity_checked(b, &d);
     bid.private_domained_channel(|_activity, domain, msg: &TestForInt| {
         let x: usize = *domain.get();
         assert_eq!(msg.0, x);
     });
 
     main.private_channel(move |_, _: Main| {
-        let id = crate::new_domained_activity(a.clone(), &d);
+        let id = crate::new_domained_activity_checked(a.clone(), &d);
         id.private_domained_channel(|activity: &mut TestActivity, domain, _: TestUpdateMsg| {
             let x: usize = *domain.get();
             assert_eq!(7, x);
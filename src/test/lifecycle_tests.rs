@@ -77,7 +77,7 @@ fn on_delete_domained() {
     let d = TestDomains::DomainA;
     crate::store_to_domain(&d, 7usize);
 
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.on_delete_domained(|a, domain| {
         let x: usize = *domain.get();
         assert_eq!(7, x);
@@ -108,7 +108,7 @@ fn delete_twice() {
 fn activate_after_delete() {
     let a = TestActivity::new();
     let d = TestDomains::DomainA;
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.set_status(LifecycleStatus::Deleted);
     id.set_status(LifecycleStatus::Active);
 }
@@ -120,7 +120,7 @@ fn call_subscription_after_delete() {
     let counter = a.shared_counter_ref();
     let d = TestDomains::DomainA;
 
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.subscribe_domained(|a, _domain, _msg: &TestMessage| a.inc(1));
     assert_eq!(0, counter.get()); // Make sure subscription has not been called, yet
 
@@ -166,7 +166,7 @@ fn subscribe_after_delete() {
     let counter = a.shared_counter_ref();
     let d = TestDomains::DomainA;
 
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.set_status(LifecycleStatus::Deleted);
 
     id.subscribe_domained(|a, _domain, _msg: &TestMessage| a.inc(1));
@@ -182,7 +182,7 @@ fn delete_with_on_leave() {
     let counter = a.shared_counter_ref();
     let d = TestDomains::DomainA;
 
-    let id = crate::new_domained_activity(a, &d);
+    let id = crate::new_domained_activity_checked(a, &d);
     id.on_leave_domained(|a, _domain| a.inc(1));
 
     assert_eq!(0, counter.get());
@@ -203,3 +203,450 @@ fn create_new_after_delete() {
 
     assert_ne!(num_a, num_b);
 }
+
+#[test]
+fn enter_leave_with_cause() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+
+    id.on_enter_with_cause(|activity, cause| {
+        assert_eq!(*cause, TransitionCause::Manual);
+        activity.inc(1);
+    });
+    id.on_leave_with_cause(|activity, cause| {
+        assert_eq!(*cause, TransitionCause::Manual);
+        activity.inc(10);
+    });
+
+    id.set_status(LifecycleStatus::Inactive);
+    assert_eq!(counter.get(), 10);
+    id.set_status(LifecycleStatus::Active);
+    assert_eq!(counter.get(), 11);
+}
+
+#[test]
+fn delete_removes_subscriptions_and_reports_orphaned_topics() {
+    let orphaned: Log<&'static str> = Default::default();
+    let orphaned_clone = orphaned.clone();
+    crate::subscribe(move |msg: &TopicOrphaned| {
+        assert!(msg.is::<TestMessage>() || msg.is::<TestForInt>());
+        orphaned_clone.borrow_mut().push(msg.type_name());
+    });
+
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    let b = crate::new_activity(());
+    let sub = b.subscribe(|_, _msg: &TestMessage| {});
+    b.subscribe(|_, _msg: &TestForInt| {});
+
+    id.set_status(LifecycleStatus::Deleted);
+    assert!(orphaned.borrow().is_empty());
+    b.unsubscribe(sub);
+    assert_eq!(1, orphaned.borrow().len());
+    assert!(orphaned.borrow()[0].ends_with("TestMessage"));
+    crate::publish(TestMessage(1));
+    assert_eq!(0, counter.get());
+
+    b.set_status(LifecycleStatus::Deleted);
+    assert_eq!(2, orphaned.borrow().len());
+    assert!(orphaned.borrow()[1].ends_with("TestForInt"));
+}
+
+#[test]
+fn compact_removes_late_subscriptions() {
+    let orphaned: Log<&'static str> = Default::default();
+    let orphaned_clone = orphaned.clone();
+    crate::subscribe(move |msg: &TopicOrphaned| orphaned_clone.borrow_mut().push(msg.type_name()));
+    let id = crate::new_activity(TestActivity::new());
+    id.subscribe(|_, _: &TestUpdateMsg| {});
+    let other = crate::new_activity(());
+    other.subscribe(move |_, _: &TestUpdateMsg| {
+        id.set_status(LifecycleStatus::Deleted);
+        crate::publish(TestForInt(0));
+    });
+    other.subscribe(move |_, _: &TestForInt| {
+        // Arrives after the activity has been removed
+        id.subscribe(|_, _: &TestMessage| panic!("activity is deleted"));
+    });
+    crate::publish(TestUpdateMsg);
+    let count = |name: &str| {
+        crate::inspect()
+            .expect("not inside a handler")
+            .subscriptions
+            .iter()
+            .filter(|(topic, _)| topic.ends_with(name))
+            .map(|(_, n)| *n)
+            .sum::<usize>()
+    };
+    assert_eq!(1, count("TestMessage"));
+    assert!(orphaned.borrow().is_empty());
+
+    crate::compact();
+    assert_eq!(0, count("TestMessage"));
+    assert_eq!(1, count("TestUpdateMsg"));
+    assert_eq!(1, orphaned.borrow().len());
+    assert!(orphaned.borrow()[0].ends_with("TestMessage"));
+    crate::publish(TestMessage(1));
+}
+
+#[test]
+fn on_transition() {
+    let log: Log<(u32, LifecycleTransition)> = Default::default();
+    let supervisor = crate::new_activity(());
+    let child = crate::new_activity(1u32);
+    supervisor.supervise(child, RestartPolicy::Always, || 2u32);
+    let log_clone = log.clone();
+    child.on_transition(move |n, transition| log_clone.borrow_mut().push((*n, transition.clone())));
+
+    child.set_status(LifecycleStatus::Inactive);
+    child.set_status(LifecycleStatus::Inactive);
+    child.set_status(LifecycleStatus::Deleted);
+    let transition = |from, to, cause| LifecycleTransition { from, to, cause };
+    assert_eq!(
+        vec![
+            (
+                1,
+                transition(
+                    LifecycleStatus::Active,
+                    LifecycleStatus::Inactive,
+                    TransitionCause::Manual
+                )
+            ),
+            (
+                2,
+                transition(
+                    LifecycleStatus::Inactive,
+                    LifecycleStatus::Inactive,
+                    TransitionCause::SupervisorRestart
+                )
+            ),
+        ],
+        *log.borrow()
+    );
+
+    supervisor.set_status(LifecycleStatus::Deleted);
+    assert_eq!(
+        (
+            2,
+            transition(
+                LifecycleStatus::Inactive,
+                LifecycleStatus::Deleted,
+                TransitionCause::ParentCascade(supervisor.into())
+            )
+        ),
+        log.borrow()[2]
+    );
+}
+
+#[test]
+fn on_start() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    crate::store_to_domain(&TestDomains::DomainA, 10u32);
+    let id = crate::new_domained_activity_checked(a, &TestDomains::DomainA);
+    id.set_status(LifecycleStatus::Inactive);
+    id.on_start(|activity| activity.inc(1));
+    assert_eq!(1, counter.get());
+    id.on_start_domained(|activity, domain| activity.inc(*domain.get::<u32>()));
+    assert_eq!(11, counter.get());
+
+    // Activities created inside handlers start after they have been added
+    let b = TestActivity::new();
+    let counter_b = b.shared_counter_ref();
+    id.subscribe_masked(
+        SubscriptionFilter::no_filter(),
+        move |_, _: &TestUpdateMsg| {
+            let new_id = crate::new_activity(b.clone());
+            new_id.on_start(|activity| activity.inc(1));
+        },
+    );
+    crate::publish(TestUpdateMsg);
+    assert_eq!(1, counter_b.get());
+    crate::publish(TestUpdateMsg);
+    assert_eq!(2, counter_b.get());
+    assert_eq!(11, counter.get());
+}
+
+#[test]
+fn reset() {
+    crate::store_to_domain(&TestDomains::DomainA, 1u32);
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    id.on_delete(|activity| activity.inc(100));
+    crate::init_with(|_| {});
+
+    crate::reset();
+    crate::publish(TestMessage(1));
+    assert_eq!(0, counter.get());
+    assert!(!crate::is_initialized());
+    assert_eq!(
+        None,
+        crate::take_from_domain::<_, u32>(&TestDomains::DomainA)
+    );
+
+    // Works like a fresh start
+    let b = TestActivity::new();
+    let counter_b = b.shared_counter_ref();
+    let new_id = crate::new_activity(b);
+    assert_eq!(id.id.index, new_id.id.index);
+    // The slot is reused, but the old id is told apart from the new one
+    assert_ne!(id.id, new_id.id);
+    new_id.subscribe(|activity, msg: &TestMessage| activity.inc(msg.0));
+    crate::publish(TestMessage(1));
+    assert_eq!(1, counter_b.get());
+}
+
+#[test]
+#[should_panic(expected = "after its bus has been reset")]
+fn id_from_before_reset() {
+    let id = crate::new_activity(());
+    crate::reset();
+    id.set_status(LifecycleStatus::Inactive);
+}
+
+#[test]
+fn shutdown() {
+    let order: Log<u32> = Default::default();
+    let order_clone = order.clone();
+    crate::subscribe(move |msg: &TestMessage| order_clone.borrow_mut().push(msg.0));
+    let mut counters = vec![];
+    for i in 0..3 {
+        let a = TestActivity::new();
+        counters.push(a.shared_counter_ref());
+        let id = crate::new_activity(a);
+        id.on_leave_with_cause(move |_, cause| {
+            assert_eq!(TransitionCause::Shutdown, *cause);
+            // Published messages are delivered before the reset
+            crate::publish(TestMessage(i));
+        });
+        id.on_delete(move |activity| activity.inc(1));
+    }
+    crate::shutdown();
+    assert_eq!(vec![2, 1, 0], *order.borrow());
+    assert!(counters.iter().all(|c| c.get() == 1));
+}
+
+#[test]
+fn group_status() {
+    let log: Log<(u32, TransitionCause)> = Default::default();
+    let mut group = ActivityGroup::new("screen");
+    for i in 0..3u32 {
+        let id = crate::new_activity(i);
+        let log = log.clone();
+        id.on_leave_with_cause(move |i, cause| log.borrow_mut().push((*i, cause.clone())));
+        group.add(id);
+    }
+    let deleted = group.members()[1];
+    deleted.set_status(LifecycleStatus::Deleted);
+    log.borrow_mut().clear();
+
+    group.set_status(LifecycleStatus::Inactive);
+    let cause = TransitionCause::GroupChange("screen".to_owned());
+    assert_eq!(vec![(0, cause.clone()), (2, cause)], *log.borrow());
+}
+
+#[test]
+fn request_delete() {
+    let a = TestActivity::new();
+    let counter = a.shared_counter_ref();
+    let id = crate::new_activity(a);
+    id.subscribe(|a, _: &TestUpdateMsg| a.inc(1));
+    let observer = crate::new_activity(true);
+    observer.subscribe(|keep_alive, msg: &AboutToDelete<TestActivity>| {
+        if *keep_alive {
+            msg.veto();
+        }
+    });
+
+    id.request_delete();
+    crate::publish(TestUpdateMsg);
+    assert_eq!(1, counter.get());
+
+    observer.subscribe(|keep_alive, _: &TestUpdateMsg| *keep_alive = false);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(2, counter.get());
+    id.request_delete();
+    crate::publish(TestUpdateMsg);
+    assert_eq!(2, counter.get());
+    // Requesting again after deletion does nothing
+    id.request_delete();
+}
+
+#[test]
+fn supervised_restart() {
+    crate::set_panic_policy(PanicPolicy::RecoverAndReport);
+    let supervisor = crate::new_activity(());
+    let child = crate::new_activity(0u32);
+    supervisor.supervise(child, RestartPolicy::Always, || 0u32);
+    child.subscribe(|n, msg: &TestMessage| {
+        *n += msg.0;
+        assert!(*n < 10, "overflow");
+    });
+    let read = || crate::read_activity(|n: &u32| *n);
+
+    crate::publish(TestMessage(5));
+    assert_eq!(Some(5), read());
+    // A panic restarts the child, which stays active and subscribed
+    crate::publish(TestMessage(5));
+    assert_eq!(Some(0), read());
+    crate::publish(TestMessage(3));
+    assert_eq!(Some(3), read());
+
+    // Deletion restarts the child, too
+    child.set_status(LifecycleStatus::Deleted);
+    assert_eq!(Some(0), read());
+    crate::publish(TestMessage(4));
+    assert_eq!(Some(4), read());
+
+    // Deleting the supervisor deletes the child
+    supervisor.set_status(LifecycleStatus::Deleted);
+    assert_eq!(None, read());
+}
+
+#[test]
+fn buffering_status() {
+    crate::make_bufferable::<TestMessage>();
+    let id = crate::new_activity(Vec::<u32>::new());
+    id.subscribe(|received, msg: &TestMessage| received.push(msg.0));
+    let read = || crate::read_activity(|received: &Vec<u32>| received.clone());
+
+    id.set_status(LifecycleStatus::Buffering);
+    crate::publish(TestMessage(1));
+    crate::publish(TestMessage(2));
+    assert_eq!(Some(vec![]), read());
+    id.set_status(LifecycleStatus::Active);
+    assert_eq!(Some(vec![1, 2]), read());
+
+    // Only the latest messages are kept when the buffer is full
+    id.set_buffer_capacity(2);
+    id.set_status(LifecycleStatus::Buffering);
+    for i in 3..=6 {
+        crate::publish(TestMessage(i));
+    }
+    id.set_status(LifecycleStatus::Active);
+    assert_eq!(Some(vec![1, 2, 5, 6]), read());
+
+    // Going to sleep drops the held messages
+    id.set_status(LifecycleStatus::Buffering);
+    crate::publish(TestMessage(7));
+    id.set_status(LifecycleStatus::Inactive);
+    id.set_status(LifecycleStatus::Active);
+    assert_eq!(Some(vec![1, 2, 5, 6]), read());
+}
+
+#[test]
+fn buffering_parallel_ready() {
+    crate::make_bufferable::<TestMessage>();
+    let id = crate::new_activity(Vec::<u32>::new());
+    id.subscribe(|received, msg: &TestMessage| received.push(msg.0));
+    let read = || crate::read_activity(|received: &Vec<u32>| received.clone());
+
+    id.set_status(LifecycleStatus::Buffering);
+    crate::publish_parallel_ready(TestMessage(1));
+    assert_eq!(Some(vec![]), read());
+    id.set_status(LifecycleStatus::Active);
+    assert_eq!(Some(vec![1]), read());
+}
+
+#[test]
+fn state_machine() {
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum State {
+        Menu,
+        Game,
+        Paused,
+    }
+    let machine = ActivityStateMachine::new("test", State::Menu);
+    let menu = crate::new_activity(TestActivity::new());
+    let game = crate::new_activity(());
+    machine.bind(State::Menu, menu);
+    machine.bind(State::Game, game);
+    machine.bind(State::Paused, game);
+    let status = |id: UncheckedActivityId| {
+        crate::inspect()
+            .expect("not inside a handler")
+            .activities
+            .into_iter()
+            .find(|a| a.id == id)
+            .map(|a| a.status)
+    };
+    assert_eq!(Some(LifecycleStatus::Active), status(menu.into()));
+    assert_eq!(Some(LifecycleStatus::Inactive), status(game.into()));
+
+    let transitions: Log<(State, State)> = Default::default();
+    let log = transitions.clone();
+    machine.on_transition(State::Menu, State::Game, move || {
+        log.borrow_mut().push((State::Menu, State::Game))
+    });
+    // The state can be changed from inside handlers
+    let handle = machine.clone();
+    menu.subscribe(move |_, _: &TestUpdateMsg| handle.set(State::Game));
+    crate::publish(TestUpdateMsg);
+    assert_eq!(State::Game, machine.state());
+    assert_eq!(Some(LifecycleStatus::Inactive), status(menu.into()));
+    assert_eq!(Some(LifecycleStatus::Active), status(game.into()));
+    assert_eq!(vec![(State::Menu, State::Game)], *transitions.borrow());
+
+    // Bound to both states, stays active
+    machine.set(State::Paused);
+    assert_eq!(Some(LifecycleStatus::Active), status(game.into()));
+    assert_eq!(1, transitions.borrow().len());
+}
+
+#[test]
+fn state_machine_changed_by_lifecycle_handler() {
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum State {
+        Loading,
+        Menu,
+    }
+    let machine = ActivityStateMachine::new("test", State::Menu);
+    let loading = crate::new_activity(());
+    let menu = crate::new_activity(());
+    machine.bind(State::Loading, loading);
+    machine.bind(State::Menu, menu);
+    let status = |id: UncheckedActivityId| {
+        crate::inspect()
+            .expect("not inside a handler")
+            .activities
+            .into_iter()
+            .find(|a| a.id == id)
+            .map(|a| a.status)
+    };
+    // Loading finishes right away
+    let handle = machine.clone();
+    loading.on_enter(move |_| handle.set(State::Menu));
+    machine.set(State::Loading);
+    assert_eq!(State::Menu, machine.state());
+    assert_eq!(Some(LifecycleStatus::Active), status(menu.into()));
+    assert_eq!(Some(LifecycleStatus::Inactive), status(loading.into()));
+}
+
+#[test]
+fn state_machine_tags() {
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum State {
+        Menu,
+        Game,
+    }
+    let machine = ActivityStateMachine::new("test", State::Menu);
+    let received: Log<&'static str> = Default::default();
+    let log = received.clone();
+    crate::new_activity(()).subscribe_tagged("game-input", move |_, _: &TestUpdateMsg| {
+        log.borrow_mut().push("input")
+    });
+    machine.bind_tag(State::Game, "game-input");
+    crate::publish(TestUpdateMsg);
+    assert!(received.borrow().is_empty());
+    machine.set(State::Game);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(vec!["input"], *received.borrow());
+    machine.set(State::Menu);
+    crate::publish(TestUpdateMsg);
+    assert_eq!(1, received.borrow().len());
+}
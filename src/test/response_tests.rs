@@ -0,0 +1,246 @@
+//! Test suite for awaiting responses of published messages.
+use super::*;
+use std::cell::RefCell;
+use std::pin::Pin;
+
+struct Start;
+struct Awaited;
+struct Follow;
+
+type PendingResponse = Rc<RefCell<Option<Pin<Box<dyn Future<Output = ()>>>>>>;
+type PendingRequest = Pin<Box<dyn Future<Output = Result<u32, RequestError>>>>;
+
+#[test]
+fn response_waits_for_transitive_events() {
+    let d = TestDomains::DomainA;
+    crate::store_to_domain(&d, 0usize);
+    let response: PendingResponse = Default::default();
+
+    let main = crate::new_domained_activity_checked((), &d);
+    let response_clone = response.clone();
+    main.subscribe(move |_, _: &Start| {
+        let mut future = Box::pin(crate::publish_awaiting_response(Awaited));
+        // Published message is queued, it cannot be ready, yet.
+        assert!(poll_once(future.as_mut()).is_pending());
+        *response_clone.borrow_mut() = Some(future);
+    });
+    main.subscribe(move |_, _: &Awaited| {
+        crate::publish(Follow);
+        crate::store_to_domain(&d, 1usize);
+    });
+    let response_clone = response.clone();
+    main.subscribe_domained(move |_, domain, _: &Follow| {
+        // Handlers of `Awaited` are done but the events queued by them are not
+        let mut future = response_clone.borrow_mut();
+        let future = future.as_mut().expect("future missing");
+        assert!(poll_once(future.as_mut()).is_pending());
+        assert_eq!(0usize, *domain.get::<usize>());
+    });
+
+    crate::publish(Start);
+    let mut future = response.borrow_mut().take().expect("future missing");
+    assert!(poll_once(future.as_mut()).is_ready());
+}
+
+/// Publishes `Start`, which stores a future awaiting `Awaited` in the returned slot.
+fn await_inside_handler() -> PendingResponse {
+    let response: PendingResponse = Default::default();
+    let response_clone = response.clone();
+    let id = crate::new_activity(());
+    id.subscribe(move |_, _: &Start| {
+        let mut future = Box::pin(crate::publish_awaiting_response(Awaited));
+        assert!(poll_once(future.as_mut()).is_pending());
+        *response_clone.borrow_mut() = Some(future);
+    });
+    id.subscribe(|_, _: &Awaited| {});
+    crate::publish(Start);
+    response
+}
+
+#[test]
+fn response_survives_reset() {
+    let stale = await_inside_handler().borrow_mut().take();
+    crate::reset();
+    let response = await_inside_handler();
+    // Must not free the slot of the new response, which may have the same index
+    drop(stale);
+    let mut future = response.borrow_mut().take().expect("future missing");
+    assert!(poll_once(future.as_mut()).is_ready());
+}
+
+#[test]
+fn request_reply() {
+    struct Question(u32);
+    let id = crate::new_activity(TestActivity::new());
+    id.subscribe_responder(|activity, msg: &Question| {
+        activity.inc(1);
+        msg.0 * 2
+    });
+    id.subscribe_responder(|_, _: &Question| 0u32);
+
+    let mut future = Box::pin(crate::request::<_, u32>(Question(21)));
+    assert_eq!(Poll::Ready(Some(42)), poll_once(future.as_mut()));
+
+    // Wrong reply type
+    let mut future = Box::pin(crate::request::<_, String>(Question(1)));
+    assert_eq!(Poll::Ready(None), poll_once(future.as_mut()));
+
+    // No responder
+    let mut future = Box::pin(crate::request::<_, u32>(Start));
+    assert_eq!(Poll::Ready(None), poll_once(future.as_mut()));
+
+    // Plain publish drops the reply
+    crate::publish(Question(1));
+}
+
+#[test]
+fn mutate_domain() {
+    struct Volume(u32);
+    let d = TestDomains::DomainA;
+    crate::store_to_domain(&d, Volume(1));
+
+    let mut future = Box::pin(crate::mutate(&d, |v: &mut Volume| {
+        v.0 += 1;
+        v.0
+    }));
+    assert_eq!(Poll::Ready(Some(2)), poll_once(future.as_mut()));
+    let mut future = Box::pin(crate::mutate(&d, |_: &mut String| ()));
+    assert_eq!(Poll::Ready(None), poll_once(future.as_mut()));
+
+    // Deferred inside handlers
+    let pending: Log<Pin<Box<dyn Future<Output = Option<u32>>>>> = Default::default();
+    let pending_clone = pending.clone();
+    let id = crate::new_domained_activity_checked((), &d);
+    id.subscribe_domained(move |_, domain, _: &Start| {
+        let future = crate::mutate(&TestDomains::DomainA, |v: &mut Volume| {
+            v.0 *= 10;
+            v.0
+        });
+        pending_clone.borrow_mut().push(Box::pin(future));
+        assert_eq!(3, domain.get::<Volume>().0);
+    });
+    // Dropping the future does not prevent the call
+    drop(crate::mutate(&d, |v: &mut Volume| v.0 += 1));
+    crate::publish(Start);
+    let mut future = pending.borrow_mut().pop().expect("no future");
+    assert_eq!(Poll::Ready(Some(30)), poll_once(future.as_mut()));
+}
+
+#[test]
+fn private_request() {
+    struct Question(u32);
+    let id = crate::new_activity(TestActivity::new());
+    id.private_responder(|activity, msg: Question| {
+        activity.inc(1);
+        msg.0 * 2
+    });
+    // Public messages are not received by private responders
+    crate::publish(Question(1));
+    assert_eq!(
+        Some(0),
+        crate::read_activity(|a: &TestActivity| a.counter.get())
+    );
+
+    let mut future = Box::pin(id.request::<_, u32>(Question(21)));
+    assert_eq!(Poll::Ready(Ok(42)), poll_once(future.as_mut()));
+    let mut future = Box::pin(id.request::<_, String>(Question(1)));
+    assert_eq!(
+        Poll::Ready(Err(RequestError::NoReply)),
+        poll_once(future.as_mut())
+    );
+
+    // Requests sent inside a handler can time out before they are delivered
+    let pending: Log<PendingRequest> = Default::default();
+    let pending_clone = pending.clone();
+    let main = crate::new_activity(());
+    main.subscribe(move |_, _: &Start| {
+        let mut future = Box::pin(id.request_with_timeout::<_, u32>(Question(5), 1));
+        assert!(poll_once(future.as_mut()).is_pending());
+        crate::tick();
+        assert_eq!(
+            Poll::Ready(Err(RequestError::Timeout)),
+            poll_once(future.as_mut())
+        );
+        pending_clone
+            .borrow_mut()
+            .push(Box::pin(id.request_with_timeout(Question(7), 1)));
+    });
+    crate::publish(Start);
+    let mut future = pending.borrow_mut().pop().expect("no future");
+    assert_eq!(Poll::Ready(Ok(14)), poll_once(future.as_mut()));
+}
+
+#[test]
+fn store_and_notify() {
+    let d = TestDomains::DomainA;
+    let mut future = Box::pin(crate::store_to_domain_and_notify(&d, 1usize));
+    assert!(poll_once(future.as_mut()).is_ready());
+
+    let response: PendingResponse = Default::default();
+    let response_clone = response.clone();
+    let id = crate::new_domained_activity_checked((), &d);
+    id.subscribe_domained(move |_, domain, _: &Start| {
+        let mut future = Box::pin(crate::store_to_domain_and_notify(&d, 2usize));
+        assert!(poll_once(future.as_mut()).is_pending());
+        assert_eq!(1, domain.pending::<usize>());
+        assert_eq!(0, domain.pending::<u32>());
+        assert_eq!(1usize, *domain.get::<usize>());
+        *response_clone.borrow_mut() = Some(future);
+        crate::publish(Follow);
+    });
+    id.subscribe_domained(|_, domain, _: &Follow| {
+        assert_eq!(0, domain.pending::<usize>());
+        assert_eq!(2usize, *domain.get::<usize>());
+    });
+    crate::publish(Start);
+    let mut future = response.borrow_mut().take().expect("future missing");
+    assert!(poll_once(future.as_mut()).is_ready());
+    // Executed stores leave no counter behind
+    assert_eq!(0, crate::nut::pending_store_entries());
+}
+
+#[test]
+#[cfg(feature = "wasm-futures")]
+fn spawn_polls_at_quiescence() {
+    let log: Log<&'static str> = Default::default();
+    let id = crate::new_activity(());
+    let l = log.clone();
+    id.subscribe(move |_, _: &Start| {
+        let task_log = l.clone();
+        crate::spawn(async move {
+            task_log.borrow_mut().push("task started");
+            crate::publish_awaiting_response(Awaited).await;
+            task_log.borrow_mut().push("task resumed");
+        });
+        l.borrow_mut().push("handler done");
+    });
+    let l = log.clone();
+    id.subscribe(move |_, _: &Awaited| {
+        l.borrow_mut().push("awaited");
+        crate::publish(Follow);
+    });
+    let l = log.clone();
+    id.subscribe(move |_, _: &Follow| l.borrow_mut().push("follow"));
+    crate::publish(Start);
+    assert_eq!(
+        vec![
+            "handler done",
+            "task started",
+            "awaited",
+            "follow",
+            "task resumed"
+        ],
+        *log.borrow()
+    );
+}
+
+#[test]
+#[cfg(feature = "wasm-futures")]
+fn spawn_after_panicking_task() {
+    let polled = Rc::new(Cell::new(false));
+    let result = std::panic::catch_unwind(|| crate::spawn(async { panic!("task failed") }));
+    assert!(result.is_err());
+    let p = polled.clone();
+    crate::spawn(async move { p.set(true) });
+    assert!(polled.get());
+}
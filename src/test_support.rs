@@ -0,0 +1,59 @@
+//! Step-by-step execution of queued events, for assertions in the middle of a cascade of messages.
+//!
+//! Only available with the `test-utils` feature.
+//!
+//! Usually, nuts executes everything that has been queued before a call like `nuts::publish` returns.
+//! After [`set_stepping(true)`](fn.set_stepping.html), events stay in the queue instead,
+//! until they are executed one by one with [`step`](fn.step.html).
+//!
+//! ### Example
+//! ```rust
+//! # #[cfg(feature = "test-utils")] {
+//! use nuts::test_support;
+//!
+//! struct Ping;
+//! struct Pong;
+//! let a = nuts::new_activity(());
+//! a.subscribe(|_, _: &Ping| nuts::publish(Pong));
+//! a.subscribe(|_, _: &Pong| {});
+//!
+//! test_support::set_stepping(true);
+//! // Subscriptions are queued events as well
+//! while test_support::step() {}
+//!
+//! nuts::publish(Ping);
+//! assert_eq!(test_support::pending_events().len(), 1);
+//! assert!(test_support::step());
+//! // Delivering `Ping` has queued `Pong`
+//! let pending = test_support::pending_events();
+//! assert_eq!(pending[0].message, Some(std::any::type_name::<Pong>()));
+//!
+//! // Runs everything that is left
+//! test_support::set_stepping(false);
+//! assert!(test_support::pending_events().is_empty());
+//! # }
+//! ```
+
+pub use crate::nut::exec::step::PendingEventInfo;
+
+/// Turns step mode on or off.
+///
+/// While it is on, nuts does not execute queued events on its own, not even new subscriptions.
+/// Turning it off executes all events that are still queued.
+/// A call to `nuts::reset` turns it off, too.
+pub fn set_stepping(enabled: bool) {
+    crate::nut::exec::step::set_stepping(enabled)
+}
+
+/// Lists all queued events, the highest priority first and in queue order within one priority.
+pub fn pending_events() -> Vec<PendingEventInfo> {
+    crate::nut::exec::step::pending_events()
+}
+
+/// Executes exactly one queued event, even if step mode is off.
+///
+/// Events published during its execution are queued and not executed before the next step.
+/// Returns false if the queue is empty, or if called from inside a subscription handler.
+pub fn step() -> bool {
+    crate::nut::exec::step::step()
+}